@@ -0,0 +1,84 @@
+//! Times every engine on small programs that stress different parts of
+//! them. Run with `make bench`, which builds the `jit` feature so that
+//! `jit` is more than the tree-walker.
+//!
+//! To show a change is faster, save a baseline before it with
+//! `make bench BENCH_ARGS="--save-baseline before"` and compare after it
+//! with `make bench BENCH_ARGS="--baseline before"`. `cargo test --benches`
+//! runs each of them once instead, checking every engine gives the value
+//! expected.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rinha::{Engine, File, Fuel, InterpreterConfig, Value};
+
+const FIB: &str = "
+let fib = fn (n) => {
+  if (n < 2) { n } else { fib(n - 1) + fib(n - 2) }
+};
+fib(30)
+";
+
+const CONCAT: &str = r#"
+let concat = fn (n, acc) => {
+  if (n == 0) { acc } else { concat(n - 1, acc + "x") }
+};
+concat(2000, "")
+"#;
+
+const TUPLES: &str = "
+let churn = fn (n, pair) => {
+  if (n == 0) {
+    first(pair) + second(pair)
+  } else {
+    churn(n - 1, (second(pair), first(pair) + 1))
+  }
+};
+churn(10000, (0, 0))
+";
+
+/// `let x0 = 0; let x1 = x0 + 1; ...`, `length` bindings deep.
+fn let_chain(length: usize) -> String {
+    let mut source = String::from("let x0 = 0;\n");
+    for i in 1..length {
+        source.push_str(&format!("let x{} = x{} + 1;\n", i, i - 1));
+    }
+    source.push_str(&format!("x{}\n", length - 1));
+    source
+}
+
+fn run(file: &File, engine: Engine) -> Value {
+    let mut lines = Vec::new();
+    engine
+        .run(
+            file,
+            false,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            &mut lines,
+        )
+        .unwrap()
+}
+
+fn bench(c: &mut Criterion, name: &str, source: &str, expected: &str) {
+    let file: File = rinha::parse(source, "bench.rinha").unwrap();
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+    for engine in Engine::ALL {
+        let value = run(&file, engine).to_string();
+        assert_eq!(value, expected, "{} with {}", name, engine.name());
+        group.bench_function(BenchmarkId::from_parameter(engine.name()), |b| {
+            b.iter(|| run(&file, engine))
+        });
+    }
+    group.finish();
+}
+
+fn engines(c: &mut Criterion) {
+    bench(c, "fib", FIB, "832040");
+    bench(c, "let_chain", &let_chain(1000), "999");
+    bench(c, "concat", CONCAT, &"x".repeat(2000));
+    bench(c, "tuples", TUPLES, "10000");
+}
+
+criterion_group!(benches, engines);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+fn main() {
+    // Node.js addons leave the symbols of the runtime for the process
+    // loading them to provide, which some linkers have to be told.
+    #[cfg(feature = "napi")]
+    napi_build::setup();
+
+    // Keeps the header of the C functions in step with their definitions.
+    #[cfg(feature = "capi")]
+    {
+        println!("cargo:rerun-if-changed=src/capi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let config =
+            cbindgen::Config::from_file("cbindgen.toml").expect("Falha ao ler cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/capi.rs")
+            .generate()
+            .expect("Falha ao gerar o cabeçalho C")
+            .write_to_file("include/rinha.h");
+    }
+}
@@ -0,0 +1,33 @@
+//! Feeds arbitrary bytes to the JSON AST loader and runs whatever it
+//! accepts. Loading may fail and programs may stop with runtime errors,
+//! but nothing may panic.
+//!
+//! Run it with `cargo +nightly fuzz run json_ast` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rinha::{lint, memory, optimizer, typecheck, Engine, Fuel, InterpreterConfig};
+
+#[global_allocator]
+static ALLOCATOR: memory::Counting = memory::Counting;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(json) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(file) = rinha::parse_json(json) else {
+        return;
+    };
+    let _ = typecheck::check(&file);
+    let _ = lint::lint(&file, InterpreterConfig::STRICT);
+    let _ = file.expression.to_source();
+    let file = optimizer::optimize(&file);
+    // Tuples are copied on every use, so a few nested lets can build
+    // values far larger than the input; stop those early.
+    memory::set_limit(Some(memory::allocated() + (256 << 20)));
+    for engine in [Engine::Tree, Engine::Vm] {
+        let mut lines = Vec::new();
+        let _ = engine.run(&file, true, InterpreterConfig::STRICT, Fuel::limited(10_000), &mut lines);
+    }
+});
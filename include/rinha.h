@@ -0,0 +1,50 @@
+/* Written by cbindgen from src/capi.rs; do not edit. */
+
+#ifndef RINHA_H
+#define RINHA_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * How a run of [`rinha_run_json`] went. Strings that do not apply are
+ * null.
+ */
+typedef struct RinhaResult {
+  /**
+   * Every line the program printed, each ending in a line break.
+   */
+  char *output;
+  /**
+   * The value of the program as `print` shows it, unless it failed.
+   */
+  char *value;
+  /**
+   * Why the program did not run or what stopped it, if anything did.
+   */
+  char *error;
+} RinhaResult;
+
+/**
+ * Runs the program in the JSON AST `json`. The result belongs to the
+ * caller, who gives it back with [`rinha_free_result`].
+ *
+ * # Safety
+ *
+ * `json` must be null or point to a string ending with a NUL byte.
+ */
+struct RinhaResult *rinha_run_json(const char *json);
+
+/**
+ * Frees a result of [`rinha_run_json`] with its strings.
+ *
+ * # Safety
+ *
+ * `result` must be null or a result of [`rinha_run_json`] not yet freed.
+ */
+void rinha_free_result(struct RinhaResult *result);
+
+#endif  /* RINHA_H */
@@ -0,0 +1,22 @@
+// Builds the addon with cargo when the package is installed and copies it
+// next to this file as rinha.node, the name index.js loads.
+
+const { execFileSync } = require("child_process");
+const fs = require("fs");
+const path = require("path");
+
+const crate = path.join(__dirname, "..");
+execFileSync(
+  "cargo",
+  ["build", "--release", "--lib", "--features", "napi"],
+  { cwd: crate, stdio: "inherit" },
+);
+
+const library = {
+  darwin: "librinha.dylib",
+  win32: "rinha.dll",
+}[process.platform] || "librinha.so";
+fs.copyFileSync(
+  path.join(crate, "target", "release", library),
+  path.join(__dirname, "rinha.node"),
+);
@@ -0,0 +1,17 @@
+/** What `run` returns. */
+export interface Run {
+  /** Every line the program printed, each ending in a line break. */
+  stdout: string;
+  /**
+   * The value of the program: integers, strings and booleans as
+   * themselves, tuples as arrays of two, and closures as `null`.
+   */
+  value: unknown;
+}
+
+/**
+ * Runs `program`, given as Rinha source or as a JSON AST. Throws an error
+ * with the code `InvalidArg` when the program does not parse, and
+ * `GenericFailure` when it fails.
+ */
+export function run(program: string): Run;
@@ -0,0 +1,63 @@
+// Host shim for modules built with `rinha build --target wasm`.
+//
+// In node: `node runtime/rinha.mjs program.wasm`. In a browser, import
+// `instantiate` and pass it the module bytes and a function receiving each
+// printed line.
+//
+// Calls that are not in tail position use the host stack, so deeply
+// recursive programs may need a larger one, e.g. `node --stack-size=65500`.
+
+const ERRORS = [
+  "type mismatch",
+  "unbound variable",
+  "wrong number of arguments",
+  "integer overflow",
+  "division by zero",
+  "no pattern matches the value",
+  "records are not supported by this target",
+  "references are not supported by this target",
+];
+
+export class RinhaError extends Error {
+  constructor(kind, start, end) {
+    super(ERRORS[kind] ?? `unknown error ${kind}`);
+    this.start = start;
+    this.end = end;
+  }
+}
+
+export async function instantiate(bytes, print) {
+  const decoder = new TextDecoder();
+  let memory;
+  const imports = {
+    rinha: {
+      print(ptr, len) {
+        print(decoder.decode(new Uint8Array(memory.buffer, ptr, len)));
+      },
+      error(kind, start, end) {
+        throw new RinhaError(kind, start, end);
+      },
+    },
+  };
+  const { instance } = await WebAssembly.instantiate(bytes, imports);
+  memory = instance.exports.memory;
+  return instance;
+}
+
+if (typeof process !== "undefined" && import.meta.url === `file://${process.argv[1]}`) {
+  const { readFile } = await import("node:fs/promises");
+  const path = process.argv[2];
+  if (!path) {
+    console.error("usage: node rinha.mjs <program.wasm>");
+    process.exit(2);
+  }
+  const instance = await instantiate(await readFile(path), (line) => console.log(line));
+  try {
+    instance.exports.main();
+  } catch (error) {
+    if (!(error instanceof RinhaError)) throw error;
+    console.log(`error: ${error.message}`);
+    console.error(`  --> ${path}:${error.start}..${error.end}`);
+    process.exit(1);
+  }
+}
@@ -0,0 +1,58 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use num_bigint::{BigInt, Sign};
+
+use crate::error::RuntimeError;
+use crate::types::{BinaryOp, Location};
+use crate::value::{type_mismatch, Value};
+
+pub fn is_big(value: &Value) -> bool {
+    matches!(value, Value::BigInt(_))
+}
+
+fn to_big(value: &Value, location: &Location) -> Result<BigInt, RuntimeError> {
+    match value {
+        Value::Number(num) => Ok(BigInt::from(*num)),
+        Value::BigInt(num) => Ok((**num).clone()),
+        _ => Err(type_mismatch("int", value, location)),
+    }
+}
+
+/// Narrows `num` back to a machine integer whenever it fits one.
+pub fn normalize(num: BigInt) -> Value {
+    match i64::try_from(&num) {
+        Ok(num) => Value::Number(num),
+        Err(_) => Value::BigInt(Rc::new(num)),
+    }
+}
+
+pub fn arithmetic(
+    left: &Value,
+    right: &Value,
+    op: &BinaryOp,
+    location: &Location,
+) -> Result<Value, RuntimeError> {
+    let left_val = to_big(left, location)?;
+    let right_val = to_big(right, location)?;
+    let divides = matches!(op, BinaryOp::Div | BinaryOp::Rem);
+    if divides && right_val.sign() == Sign::NoSign {
+        return Err(RuntimeError::DivisionByZero {
+            location: location.clone(),
+        });
+    }
+
+    let result = match op {
+        BinaryOp::Add => left_val + right_val,
+        BinaryOp::Sub => left_val - right_val,
+        BinaryOp::Mul => left_val * right_val,
+        BinaryOp::Div => left_val / right_val,
+        BinaryOp::Rem => left_val % right_val,
+        _ => unreachable!("not an arithmetic operator"),
+    };
+    Ok(normalize(result))
+}
+
+pub fn compare(left: &Value, right: &Value, location: &Location) -> Result<Ordering, RuntimeError> {
+    Ok(to_big(left, location)?.cmp(&to_big(right, location)?))
+}
@@ -0,0 +1,150 @@
+//! The functions bound in the global environment with `--extensions`.
+//!
+//! Besides the lists of [`crate::list`], programs get functions for
+//! strings, `read_line` to read from [`crate::input`], `random` to draw
+//! from [`crate::random`] and `now` to time themselves. Strings are indexed
+//! by character, starting at 0.
+
+/// Builtins that give a different result each call, which is never cached
+/// nor computed ahead of time.
+pub const EFFECTS: [&str; 3] = ["read_line", "random", "now"];
+
+use std::rc::Rc;
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use crate::error::RuntimeError;
+use crate::input;
+use crate::list::{self, List};
+use crate::random;
+use crate::symbol::Symbol;
+use crate::types::Location;
+use crate::value::{self, assert_int, type_mismatch, Native, Value};
+
+fn assert_string<'a>(value: &'a Value, location: &Location) -> Result<&'a str, RuntimeError> {
+    match value {
+        Value::String(s) => Ok(s),
+        value => Err(type_mismatch("string", value, location)),
+    }
+}
+
+/// Checks that `value` is an index into `string` no greater than `limit`
+/// characters.
+fn assert_index(
+    value: &Value,
+    string: &str,
+    limit: usize,
+    location: &Location,
+) -> Result<usize, RuntimeError> {
+    let index = assert_int(value, location)?;
+    match usize::try_from(index) {
+        Ok(i) if i <= limit => Ok(i),
+        _ => Err(RuntimeError::IndexOutOfRange {
+            index,
+            length: string.chars().count(),
+            location: location.clone(),
+        }),
+    }
+}
+
+fn str_length(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let string = assert_string(&arguments[0], location)?;
+    Ok(Value::Number(string.chars().count() as i64))
+}
+
+/// The characters from `start` up to, but not including, `end`. Both may
+/// be the length of the string, and the slice is empty when `end` is not
+/// after `start`.
+fn str_slice(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let string = assert_string(&arguments[0], location)?;
+    let length = string.chars().count();
+    let start = assert_index(&arguments[1], string, length, location)?;
+    let end = assert_index(&arguments[2], string, length, location)?;
+    let slice = string
+        .chars()
+        .skip(start)
+        .take(end.saturating_sub(start))
+        .collect::<String>();
+    Ok(Value::string(slice))
+}
+
+fn str_to_int(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let string = assert_string(&arguments[0], location)?;
+    let invalid = || RuntimeError::InvalidInt {
+        text: string.to_string(),
+        location: location.clone(),
+    };
+    #[cfg(feature = "bigint")]
+    {
+        let num = string.parse().map_err(|_| invalid())?;
+        Ok(crate::bigint::normalize(num))
+    }
+    #[cfg(not(feature = "bigint"))]
+    {
+        Ok(Value::Number(string.parse().map_err(|_| invalid())?))
+    }
+}
+
+fn int_to_str(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::Number(num) => Ok(Value::string(num.to_string())),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(num) => Ok(Value::string(num.to_string())),
+        value => Err(type_mismatch("int", value, location)),
+    }
+}
+
+fn char_at(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let string = assert_string(&arguments[0], location)?;
+    let length = string.chars().count();
+    let index = assert_index(&arguments[1], string, length.saturating_sub(1), location)?;
+    match string.chars().nth(index) {
+        Some(c) => Ok(Value::string(c)),
+        None => Err(RuntimeError::IndexOutOfRange {
+            index: index as i64,
+            length,
+            location: location.clone(),
+        }),
+    }
+}
+
+/// When the builtins were first bound, which `now()` counts from.
+static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Milliseconds since [`START`], or `clock` when it is fixed.
+fn now(clock: Option<u64>) -> Value {
+    let ms = clock.unwrap_or_else(|| START.elapsed().as_millis() as u64);
+    Value::Number(ms as i64)
+}
+
+/// The names bound by `--extensions`, with `now()` giving `clock` when it
+/// is fixed.
+pub fn globals(clock: Option<u64>) -> Vec<(Symbol, Value)> {
+    let natives = [
+        Native::new("head", 1, list::head),
+        Native::new("tail", 1, list::tail),
+        Native::new("cons", 2, list::cons),
+        Native::new("length", 1, list::length),
+        Native::new("str_length", 1, str_length),
+        Native::new("str_slice", 3, str_slice),
+        Native::new("str_to_int", 1, str_to_int),
+        Native::new("int_to_str", 1, int_to_str),
+        Native::new("char_at", 2, char_at),
+        Native::new("read_line", 0, input::read_line).with_effects(),
+        Native::new("random", 1, random::random).with_effects(),
+        Native::new("now", 0, move |_, _| Ok(now(clock))).with_effects(),
+    ];
+    LazyLock::force(&START);
+    let mut globals: Vec<(Symbol, Value)> = natives
+        .into_iter()
+        .map(|native| {
+            let name = Symbol::intern(&native.name);
+            (
+                name,
+                Value::Closure(value::Closure::Native(Rc::new(native))),
+            )
+        })
+        .collect();
+    globals.push((Symbol::intern("nil"), Value::List(List::default())));
+    globals
+}
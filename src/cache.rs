@@ -0,0 +1,180 @@
+//! Compiled programs kept on disk between runs, for `--cache`.
+//!
+//! Judging the same submission many times parses, checks, optimizes and
+//! compiles the same input every time. A [`Cache`] stores the bytecode of
+//! an input under a hash of its name, its contents and the options that
+//! change what it compiles to, so later runs load the bytecode instead.
+//! The contents are stored with the bytecode, and a program is only loaded
+//! back for the same contents, so two inputs whose keys collide are
+//! compiled again rather than mixed up.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::Program;
+use crate::parser::Syntax;
+
+/// What a file of the cache holds.
+#[derive(Deserialize)]
+struct Entry {
+    contents: String,
+    program: Program,
+}
+
+/// An [`Entry`] to write, borrowing what it holds.
+#[derive(Serialize)]
+struct NewEntry<'a> {
+    contents: &'a str,
+    program: &'a Program,
+}
+
+/// The 64-bit FNV-1a hash, which unlike the hashers of the standard
+/// library gives the same keys with every version of Rust.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    /// Adds `bytes`, preceded by their length so that consecutive fields
+    /// cannot run into each other.
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// A directory of compiled programs.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Cache {
+        Cache { dir: dir.into() }
+    }
+
+    /// Identifies the program compiled from `contents`, read from the file
+    /// `filename`. Bytecode holds the filename in the locations of errors,
+    /// depends on whether the input is JSON, on the syntax it is parsed
+    /// with and on whether builtins are bound, and may change with every
+    /// version of rinha.
+    pub fn key(
+        filename: &str,
+        contents: &str,
+        json: bool,
+        syntax: Syntax,
+        extensions: bool,
+    ) -> String {
+        let mut hasher = Fnv::new();
+        hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.write(filename.as_bytes());
+        hasher.write(contents.as_bytes());
+        let flags = [
+            json,
+            syntax.records,
+            syntax.refs,
+            syntax.modules,
+            extensions,
+        ];
+        hasher.write(&flags.map(u8::from));
+        format!("{:016x}", hasher.0)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension("json")
+    }
+
+    /// The program stored under `key`, if there is one that can be read and
+    /// it was compiled from `contents`.
+    pub fn load(&self, key: &str, contents: &str) -> Option<Program> {
+        let program = fs::read_to_string(self.path(key))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Entry>(&json).ok())
+            .filter(|entry| entry.contents == contents)
+            .map(|entry| entry.program);
+        match program {
+            Some(_) => tracing::debug!(key, "cache hit"),
+            None => tracing::debug!(key, "cache miss"),
+        }
+        program
+    }
+
+    /// Stores `program`, compiled from `contents`, under `key`, creating the
+    /// directory if needed.
+    pub fn store(&self, key: &str, contents: &str, program: &Program) -> io::Result<()> {
+        let json =
+            serde_json::to_string(&NewEntry { contents, program }).map_err(io::Error::other)?;
+        fs::create_dir_all(&self.dir)?;
+        // Written whole under another name first, so that a run reading the
+        // cache at the same time never sees half a program.
+        let partial = self.dir.join(format!("{}.partial", key));
+        fs::write(&partial, json)?;
+        fs::rename(&partial, self.path(key))
+    }
+}
+
+/// How the constants of a [`Program`] are stored: literals as themselves
+/// and builtins by name, to be bound again when loaded.
+pub(crate) mod constants {
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::builtins;
+    use crate::list::List;
+    use crate::symbol::Symbol;
+    use crate::value::{Closure, Value};
+
+    #[derive(Deserialize, Serialize)]
+    enum Constant {
+        Int(i64),
+        Str(String),
+        Bool(bool),
+        Nil,
+        Builtin(Symbol),
+    }
+
+    pub fn serialize<S: Serializer>(values: &[Value], serializer: S) -> Result<S::Ok, S::Error> {
+        let constants = values
+            .iter()
+            .map(|value| match value {
+                Value::Number(n) => Ok(Constant::Int(*n)),
+                Value::String(s) => Ok(Constant::Str(s.to_string())),
+                Value::Boolean(b) => Ok(Constant::Bool(*b)),
+                Value::List(list) if list.is_empty() => Ok(Constant::Nil),
+                Value::Closure(Closure::Native(native)) => {
+                    Ok(Constant::Builtin(Symbol::intern(&native.name)))
+                }
+                value => Err(S::Error::custom(format!("cannot store {:?}", value))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        constants.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Value>, D::Error> {
+        // A fixed clock is only for programs run from the library, which
+        // never go through the cache.
+        let globals = builtins::globals(None);
+        Vec::<Constant>::deserialize(deserializer)?
+            .into_iter()
+            .map(|constant| match constant {
+                Constant::Int(n) => Ok(Value::Number(n)),
+                Constant::Str(s) => Ok(Value::string(s)),
+                Constant::Bool(b) => Ok(Value::Boolean(b)),
+                Constant::Nil => Ok(Value::List(List::default())),
+                Constant::Builtin(name) => globals
+                    .iter()
+                    .find(|(global, _)| *global == name)
+                    .map(|(_, value)| value.clone())
+                    .ok_or_else(|| D::Error::custom(format!("unknown builtin {}", name))),
+            })
+            .collect()
+    }
+}
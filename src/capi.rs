@@ -0,0 +1,87 @@
+//! Functions for C programs, for the `capi` feature.
+//!
+//! Judges written in C or C++ link with the dynamic library and include
+//! `include/rinha.h`, which declares what is here. Every string crossing
+//! over is UTF-8 and ends with a NUL byte.
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+use crate::output;
+
+/// How a run of [`rinha_run_json`] went. Strings that do not apply are
+/// null.
+#[repr(C)]
+pub struct RinhaResult {
+    /// Every line the program printed, each ending in a line break.
+    pub output: *mut c_char,
+    /// The value of the program as `print` shows it, unless it failed.
+    pub value: *mut c_char,
+    /// Why the program did not run or what stopped it, if anything did.
+    pub error: *mut c_char,
+}
+
+/// `text` as a C string. NUL bytes, which would end it early, are left
+/// out.
+fn c_string(text: String) -> *mut c_char {
+    let text = CString::new(text).unwrap_or_else(|err| {
+        let mut bytes = err.into_vec();
+        bytes.retain(|&byte| byte != 0);
+        CString::new(bytes).expect("the NUL bytes were removed")
+    });
+    text.into_raw()
+}
+
+fn run(json: &str, output: &mut Vec<String>) -> Result<String, String> {
+    let file = crate::load_json(json)?;
+    crate::run_loaded(&file, output)
+        .map(|value| value.to_string())
+        .map_err(|err| err.to_string())
+}
+
+/// Runs the program in the JSON AST `json`. The result belongs to the
+/// caller, who gives it back with [`rinha_free_result`].
+///
+/// # Safety
+///
+/// `json` must be null or point to a string ending with a NUL byte.
+#[no_mangle]
+pub unsafe extern "C" fn rinha_run_json(json: *const c_char) -> *mut RinhaResult {
+    let mut lines = Vec::new();
+    let result = if json.is_null() {
+        Err("the program is null".to_string())
+    } else {
+        match CStr::from_ptr(json).to_str() {
+            Ok(json) => run(json, &mut lines),
+            Err(err) => Err(format!("the program is not UTF-8: {}", err)),
+        }
+    };
+    let output = output::joined(&lines);
+    let (value, error) = match result {
+        Ok(value) => (c_string(value), ptr::null_mut()),
+        Err(error) => (ptr::null_mut(), c_string(error)),
+    };
+    Box::into_raw(Box::new(RinhaResult {
+        output: c_string(output),
+        value,
+        error,
+    }))
+}
+
+/// Frees a result of [`rinha_run_json`] with its strings.
+///
+/// # Safety
+///
+/// `result` must be null or a result of [`rinha_run_json`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn rinha_free_result(result: *mut RinhaResult) {
+    if result.is_null() {
+        return;
+    }
+    let result = Box::from_raw(result);
+    for text in [result.output, result.value, result.error] {
+        if !text.is_null() {
+            drop(CString::from_raw(text));
+        }
+    }
+}
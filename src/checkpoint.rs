@@ -0,0 +1,361 @@
+//! Programs stopped by the VM kept on disk, for `--checkpoint` and
+//! `--resume`.
+//!
+//! A checkpoint holds the compiled program together with the stack and the
+//! frames of the VM when it stopped. Values are stored once each, however
+//! many places hold them, so a loaded checkpoint shares values the way the
+//! stopped program did, and refs that hold themselves load as cycles again.
+//! Lists are stored whole, without the tails they share.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::builtins;
+use crate::compiler::Program;
+use crate::gc;
+use crate::list::List;
+use crate::stats;
+use crate::symbol::Symbol;
+use crate::value::{self, Value};
+use crate::vm::{self, Frame, Snapshot};
+
+/// A value as stored: inline when it has no identity of its own, and
+/// otherwise the index of its [`Object`].
+#[derive(Deserialize, Serialize)]
+enum Stored {
+    Number(i64),
+    BigInt(String),
+    Boolean(bool),
+    Native(Symbol),
+    Object(usize),
+}
+
+#[derive(Deserialize, Serialize)]
+enum Object {
+    String(String),
+    Tuple(Stored, Stored),
+    List(Vec<Stored>),
+    Record(Vec<(Symbol, Stored)>),
+    Ref(Stored),
+    Closure {
+        function: usize,
+        captures: Vec<Stored>,
+    },
+    Partial {
+        callee: Stored,
+        arguments: Vec<Stored>,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+struct StoredFrame {
+    /// Object of the closure running in the frame.
+    closure: usize,
+    ip: usize,
+    base: usize,
+}
+
+/// What a checkpoint file holds; `P` is the program, borrowed when saving.
+#[derive(Deserialize, Serialize)]
+struct Checkpoint<P> {
+    /// Version of rinha that wrote the checkpoint, the only one that can
+    /// load it.
+    version: String,
+    program: P,
+    objects: Vec<Option<Object>>,
+    stack: Vec<Stored>,
+    frames: Vec<StoredFrame>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+#[derive(Default)]
+struct Writer {
+    /// Objects by the address of the allocation they were stored from.
+    ids: HashMap<usize, usize>,
+    /// Filled in once their contents are stored, which for a ref may hold
+    /// the ref itself.
+    objects: Vec<Option<Object>>,
+}
+
+impl Writer {
+    /// The object stored for the allocation at `address`, with whether it
+    /// is new and still has to be filled in.
+    fn object(&mut self, address: usize) -> (usize, bool) {
+        if let Some(&id) = self.ids.get(&address) {
+            return (id, false);
+        }
+        let id = self.objects.len();
+        self.objects.push(None);
+        self.ids.insert(address, id);
+        (id, true)
+    }
+
+    fn closure(&mut self, closure: &Rc<vm::Closure>) -> io::Result<usize> {
+        let (id, new) = self.object(address(closure));
+        if new {
+            let captures = closure
+                .captures
+                .iter()
+                .map(|value| self.value(value))
+                .collect::<io::Result<_>>()?;
+            self.objects[id] = Some(Object::Closure {
+                function: closure.function,
+                captures,
+            });
+        }
+        Ok(id)
+    }
+
+    fn value(&mut self, value: &Value) -> io::Result<Stored> {
+        let (id, new) = match value {
+            Value::Number(n) => return Ok(Stored::Number(*n)),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => return Ok(Stored::BigInt(n.to_string())),
+            Value::Boolean(b) => return Ok(Stored::Boolean(*b)),
+            Value::Closure(value::Closure::Native(native)) => {
+                return Ok(Stored::Native(Symbol::intern(&native.name)));
+            }
+            Value::Closure(value::Closure::Bytecode(closure)) => {
+                return Ok(Stored::Object(self.closure(closure)?));
+            }
+            Value::Closure(value::Closure::Tree(_)) => {
+                return Err(invalid("closures of the tree-walker cannot be stored"));
+            }
+            Value::Closure(value::Closure::Partial(partial)) => self.object(address(partial)),
+            Value::String(s) => self.object(address(s)),
+            Value::Tuple(pair) => self.object(address(pair)),
+            Value::Record(fields) => self.object(address(fields)),
+            Value::Ref(cell) => self.object(address(cell)),
+            // A list is known by its first node, and all empty lists are
+            // the same.
+            Value::List(list) => match list.iter().next() {
+                Some(head) => self.object(head as *const Value as usize),
+                None => self.object(0),
+            },
+        };
+        if !new {
+            return Ok(Stored::Object(id));
+        }
+        let object = match value {
+            Value::String(s) => Object::String(s.to_string()),
+            Value::Tuple(pair) => Object::Tuple(self.value(&pair.0)?, self.value(&pair.1)?),
+            Value::Record(fields) => Object::Record(
+                value::sorted_fields(fields)
+                    .into_iter()
+                    .map(|(name, value)| Ok((name, self.value(value)?)))
+                    .collect::<io::Result<_>>()?,
+            ),
+            Value::Ref(cell) => Object::Ref(self.value(&cell.borrow())?),
+            Value::List(list) => Object::List(
+                list.iter()
+                    .map(|v| self.value(v))
+                    .collect::<io::Result<_>>()?,
+            ),
+            Value::Closure(value::Closure::Partial(partial)) => Object::Partial {
+                callee: self.value(&Value::Closure(partial.callee.clone()))?,
+                arguments: partial
+                    .arguments
+                    .iter()
+                    .map(|v| self.value(v))
+                    .collect::<io::Result<_>>()?,
+            },
+            _ => unreachable!("values without identity were stored inline"),
+        };
+        self.objects[id] = Some(object);
+        Ok(Stored::Object(id))
+    }
+}
+
+struct Reader {
+    objects: Vec<Option<Object>>,
+    loaded: Vec<Option<Value>>,
+    globals: Vec<(Symbol, Value)>,
+    functions: usize,
+}
+
+impl Reader {
+    fn value(&mut self, stored: &Stored) -> io::Result<Value> {
+        match stored {
+            Stored::Number(n) => Ok(Value::Number(*n)),
+            #[cfg(feature = "bigint")]
+            Stored::BigInt(n) => {
+                let n = n.parse().map_err(|_| invalid("invalid big integer"))?;
+                Ok(Value::BigInt(Rc::new(n)))
+            }
+            #[cfg(not(feature = "bigint"))]
+            Stored::BigInt(_) => Err(invalid("big integers need the bigint feature")),
+            Stored::Boolean(b) => Ok(Value::Boolean(*b)),
+            Stored::Native(name) => self
+                .globals
+                .iter()
+                .find(|(global, _)| global == name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| invalid(format!("unknown builtin {}", name))),
+            Stored::Object(id) => self.object(*id),
+        }
+    }
+
+    fn object(&mut self, id: usize) -> io::Result<Value> {
+        if let Some(Some(value)) = self.loaded.get(id) {
+            return Ok(value.clone());
+        }
+        let object = self
+            .objects
+            .get_mut(id)
+            .and_then(Option::take)
+            .ok_or_else(|| invalid(format!("missing object {}", id)))?;
+        let value = match object {
+            Object::String(s) => Value::string(s),
+            Object::Tuple(first, second) => Value::tuple(self.value(&first)?, self.value(&second)?),
+            Object::List(elements) => {
+                let mut list = List::default();
+                for element in elements.iter().rev() {
+                    list = List::cons(self.value(element)?, list);
+                }
+                Value::List(list)
+            }
+            Object::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, value)| Ok((*name, self.value(value)?)))
+                    .collect::<io::Result<_>>()?;
+                Value::Record(Rc::new(fields))
+            }
+            Object::Ref(_) => unreachable!("refs are made before anything else"),
+            Object::Closure { function, captures } => {
+                if function >= self.functions {
+                    return Err(invalid(format!("unknown function {}", function)));
+                }
+                let captures = captures
+                    .iter()
+                    .map(|stored| self.value(stored))
+                    .collect::<io::Result<_>>()?;
+                stats::closure_created();
+                let closure = vm::Closure { function, captures };
+                Value::Closure(value::Closure::Bytecode(Rc::new(closure)))
+            }
+            Object::Partial { callee, arguments } => {
+                let callee = match &self.value(&callee)? {
+                    Value::Closure(
+                        callee @ (value::Closure::Bytecode(_) | value::Closure::Native(_)),
+                    ) => callee.clone(),
+                    _ => return Err(invalid(format!("object {} applies no function", id))),
+                };
+                let arguments = arguments
+                    .iter()
+                    .map(|stored| self.value(stored))
+                    .collect::<io::Result<_>>()?;
+                let partial = value::Partial { callee, arguments };
+                Value::Closure(value::Closure::Partial(Rc::new(partial)))
+            }
+        };
+        self.loaded[id] = Some(value.clone());
+        Ok(value)
+    }
+
+    fn closure(&mut self, id: usize) -> io::Result<Rc<vm::Closure>> {
+        match &self.object(id)? {
+            Value::Closure(value::Closure::Bytecode(closure)) => Ok(closure.clone()),
+            _ => Err(invalid(format!("object {} is not a closure", id))),
+        }
+    }
+}
+
+/// Writes `program`, stopped at `snapshot`, to `path`.
+pub fn save(path: impl AsRef<Path>, program: &Program, snapshot: &Snapshot) -> io::Result<()> {
+    let mut writer = Writer::default();
+    let stack = snapshot
+        .stack
+        .iter()
+        .map(|value| writer.value(value))
+        .collect::<io::Result<_>>()?;
+    let frames = snapshot
+        .frames
+        .iter()
+        .map(|frame| {
+            Ok(StoredFrame {
+                closure: writer.closure(&frame.closure)?,
+                ip: frame.ip,
+                base: frame.base,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    let checkpoint = Checkpoint {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        program,
+        objects: writer.objects,
+        stack,
+        frames,
+    };
+    let json = serde_json::to_string(&checkpoint)?;
+    // Written whole under another name first, so that being stopped again
+    // while writing never leaves half a checkpoint behind.
+    let path = path.as_ref();
+    let partial = path.with_extension("partial");
+    fs::write(&partial, json)?;
+    fs::rename(&partial, path)
+}
+
+/// Reads a checkpoint written by [`save`], giving back the program and the
+/// snapshot to run it from.
+pub fn load(path: impl AsRef<Path>) -> io::Result<(Program, Snapshot)> {
+    let checkpoint: Checkpoint<Program> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if checkpoint.version != env!("CARGO_PKG_VERSION") {
+        return Err(invalid(format!(
+            "written by rinha {}, not {}",
+            checkpoint.version,
+            env!("CARGO_PKG_VERSION")
+        )));
+    }
+    let program = checkpoint.program;
+    let mut reader = Reader {
+        loaded: vec![None; checkpoint.objects.len()],
+        objects: checkpoint.objects,
+        // Checkpoints are only written by `rinha`, which reads the real
+        // clock.
+        globals: builtins::globals(None),
+        functions: program.functions.len(),
+    };
+    // Every cycle goes through a ref, so making them all, empty, before
+    // anything else lets each value be loaded after what it holds.
+    let mut refs = Vec::new();
+    for (id, object) in reader.objects.iter_mut().enumerate() {
+        if let Some(Object::Ref(stored)) = object.take_if(|o| matches!(o, Object::Ref(_))) {
+            let reference = gc::new_ref(Value::Boolean(false));
+            reader.loaded[id] = Some(reference.clone());
+            refs.push((reference, stored));
+        }
+    }
+    let stack = checkpoint
+        .stack
+        .iter()
+        .map(|stored| reader.value(stored))
+        .collect::<io::Result<_>>()?;
+    let frames = checkpoint
+        .frames
+        .iter()
+        .map(|frame| {
+            let closure = reader.closure(frame.closure)?;
+            Ok(Frame::new(closure, frame.ip, frame.base))
+        })
+        .collect::<io::Result<_>>()?;
+    for (reference, stored) in refs {
+        let value = reader.value(&stored)?;
+        if let Value::Ref(cell) = &reference {
+            *cell.borrow_mut() = value;
+        }
+    }
+    Ok((program, Snapshot { stack, frames }))
+}
@@ -0,0 +1,1394 @@
+use std::path::Path;
+use std::time::Duration;
+
+use rinha::color;
+use rinha::config::Coercion;
+use rinha::depth;
+use rinha::parser::Syntax;
+use rinha::{Engine, InterpreterConfig};
+
+pub const USAGE: &str = "\
+usage: rinha [run] [options] <file>
+       rinha eval [options] <source>
+       rinha repl [--no-memo]
+       rinha test [--engine=<e>] <dir>
+       rinha fmt [--from-json | --check] <file>
+       rinha emit-ast <file>
+       rinha pack [-o <out>] <file>
+       rinha unpack [-o <out>] <file>
+       rinha check <file>
+       rinha lint <file>
+       rinha stats <file>
+       rinha highlight [--html] <file>
+       rinha debug [--no-memo] <file>
+       rinha difftest [--no-memo] [--extensions] [--max-steps <n>] <file>
+       rinha serve [--engine=<e>] [--no-memo] [--extensions] [--max-steps <n>]
+       rinha http [--listen <addr>] [--engine=<e>] [--no-memo] [--extensions]
+                  [--max-steps <n>] [--timeout <seconds>]
+       rinha lsp
+       rinha build --target <t> [-o <out>] <file>
+
+arguments:
+  <file>        program to run, or `-` to read it from stdin
+
+options:
+  --ast-json    read the input as a JSON AST
+  --source      read the input as .rinha source code
+  --packed      read the input as an AST made by `rinha pack`
+  --engine=<e>  execution engine: `tree` (default), `vm` or `jit`
+  --no-memo     do not cache the results of calls to pure functions
+  --strict      run the language exactly as specified, and refuse
+                `--extensions`
+  --coerce <c>  which values `+` joins to strings: `spec` (integers),
+                `booleans` or `all`
+  --closure-eq <m>
+                `identity` (default) compares closures with `==` by
+                identity, `error` stops the program instead
+  --closure-print <m>
+                `show` (default) prints closures as `<#closure>`, `error`
+                stops the program instead
+  --color <when>
+                color errors and the value of the program: `auto`
+                (default) when writing to a terminal, `always` or `never`
+  --extensions  bind builtin functions for lists and strings
+  --extensions <names>
+                also turn on the extensions in the comma-separated list
+                <names>: the syntax of `records`, `refs` and `modules`,
+                and `partial` application of functions
+  --max-steps <n>
+                stop with an error after <n> function calls
+  --max-depth <n>
+                stop with an error when more than <n> calls are active at
+                once (default: 1000000)
+  --timeout <seconds>
+                stop with an error once the program has run for this long
+  --max-memory <MB>
+                stop with an error once rinha uses more heap than this
+  --seed <n>    make `random` give out the same numbers on every run
+  --line-buffered
+                write each printed line right away instead of in blocks
+  --quiet       print nothing but what the program prints and its errors
+  --json-result print what the program printed, its result, the time it
+                took and whether it failed as one JSON object
+  --parallel    evaluate independent operands on several threads
+  --watch       run the program again whenever its files change
+  --show-opt    print the program after optimization instead of running it
+  --show-passes print the program before and after each optimizer pass
+                instead of running it
+  --peval       evaluate calls that only use literals and functions before
+                running the program
+  --lint        print the warnings of `rinha lint` before running
+  --trace[=<out>]
+                log the value of every evaluated term to stderr, or to the
+                file <out>
+  --stats       print counts of allocations, environment copies and live
+                closures to stderr after running
+  --report      print the resources the program used to stderr as JSON
+                after running
+  --profile     print how often each function was called and how long
+                it took to stderr after running
+  --flamegraph <out>
+                profile the program like `--profile` and write its stacks
+                of calls to <out> in the folded format of flamegraph tools
+  --log-level <filter>
+                log events of the interpreter at this level or above to
+                stderr: `error`, `warn`, `info` (default), `debug` or
+                `trace`, or the directives of `RUST_LOG`
+  --log-json    log events as one JSON object per line
+  --cache <dir> with `--engine=vm`, keep the compiled program in <dir> and
+                reuse it when the same input is run again
+  --checkpoint <file>
+                when stopped by SIGTERM, save the running program to <file>
+                instead of losing it
+  --resume <file>
+                continue the program saved in the checkpoint <file>, in
+                place of the input file
+  -h, --help    show this message
+
+`rinha run <file>` is the same as `rinha <file>`.
+When no format flag is given, files ending in `.rinha` are parsed as
+source, files ending in `.rinhab` are read as packed ASTs and everything
+else (including stdin) is read as a JSON AST.
+Before running, build and debug, the program is checked for empty names,
+functions that name a parameter twice and calls that give a known
+function the wrong number of arguments; all of them are reported and
+nothing runs.
+A function bound by `let` can call itself by name, even from inside
+other functions. As in the specification it cannot see the bindings that
+follow it, so functions calling one another take each other as
+arguments.
+
+`rinha eval` runs the `.rinha` source given as its last argument, as in
+`rinha eval 'let x = 2; print(x * 21)'`, with the same options as a file.
+Errors point into `<eval>`. `--ast-json` reads the argument as a JSON AST
+instead, and `--cache` and `--resume` do not apply.
+
+The REPL evaluates one expression at a time with the tree-walker. Lines
+starting with `{` are read as JSON AST terms, and `let name = value`
+entered on its own keeps `name` defined for the following lines. Errors
+point into `<repl:n>`, the nth input, and quote it like errors in files.
+
+The `jit` engine compiles functions that only compute with integers and
+booleans to native code and interprets everything else. It needs rinha to
+be built with the `jit` feature and otherwise runs the tree-walker.
+Ctrl-c stops programs at their next call, but not inside native code; a
+second ctrl-c kills rinha right away.
+
+With `--trace`, each line of the log shows the kind and span of a term
+followed by its value, indented and prefixed by the number of calls active
+when it started. Tracing always runs the tree-walker.
+
+`--profile` also runs the tree-walker. The time of a function includes the
+functions it calls, and recursive calls are only timed once.
+`--flamegraph` writes a line for each stack of calls the program made,
+its functions named after where they are defined and followed by the
+microseconds spent in the innermost one, leaving out the functions it
+called. A function calling itself counts as one frame, so recursion
+stays flat. Draw it with `inferno-flamegraph out.folded > out.svg` or
+open it in speedscope.
+
+`--parallel` runs the tree-walker, evaluating the second operand of an
+operator or a tuple on another thread while the first one is evaluated,
+when the second one makes calls and can never print. Programs print the
+same as without it, but the cost of sending operands between threads
+only pays off for programs that do a lot of independent work, such as
+naive `fib`. It has no effect together with `--max-steps`.
+
+`--watch`, as in `rinha run --watch <file>`, clears the screen and runs
+the program again each time its file, or with `--extensions modules` a
+file it imports, changes, until interrupted; a change saved during a run
+starts the next one once it ends. Every run is a separate process with
+the other options. It needs rinha to be built with the `watch` feature.
+
+`--peval` runs every call that only uses literals and functions bound by
+`let` around it, such as `fib(30)`, before the program starts, and puts
+its value in its place. A call that prints, fails, gives a function or
+makes more than 100000 calls is left to run with the program. Together
+with `--show-opt`, it shows the program that would run.
+
+The optimizer inlines small helper functions, binds pure terms that are
+evaluated twice, such as `fib(n - 1) + fib(n - 1)`, to a name computed
+once, and drops unused bindings. `--show-passes` prints the program
+after parsing and after each of these passes, under a comment naming it.
+
+When the tree-walker stops with an error, it also prints the calls that
+were active, innermost first. A call in tail position replaces the call
+that made it, so loops written as tail recursion show up only once.
+
+`rinha debug` runs a program with the tree-walker and stops before its
+first term to read commands: `break <offset>` stops at the first term
+starting at or after a byte offset of the source, `step`, `next` and
+`continue` resume it, `print <name>` shows a binding and `backtrace` lists
+the active calls. `help` lists every command.
+
+`rinha difftest` runs a program with every engine, after optimizing it
+like a normal run, and compares what they print and the value or error
+they end with. It exits with status 1 and shows the first line where they
+differ when they disagree. Without the `jit` feature the `jit` engine is
+the tree-walker, so only `tree` and `vm` are really compared.
+
+`rinha serve` runs many programs in one process. It reads one program per
+line of stdin, either a JSON AST document or `.rinha` source encoded in
+base64, runs each on its own and answers each line with a line holding
+the JSON object of `--json-result`. Programs that cannot be read, or
+that fail the checks made before running, are answered with the status
+`error` as well, the `result` listing every problem found.
+
+`rinha http` answers `POST /run` requests over HTTP, on 0.0.0.0:8080
+unless `--listen` gives another address. The body of a request is a JSON
+AST document, and the response is the JSON object of `--json-result`, with
+status 400 when the body is not a valid AST or fails the checks made
+before running, and 413 when it is larger than 16 MB. With `--timeout`, a
+program still running after that long is interrupted and answered with
+its error, and the server goes on to the next request. It needs rinha to
+be built with the `server` feature.
+
+`rinha lsp` speaks the Language Server Protocol over stdin and stdout.
+Editors get parse and type errors as diagnostics, the inferred type of a
+name on hover and go-to-definition for names bound by `let` and by
+function parameters. Editors send only what changed, and only the
+top-level bindings an edit touches are parsed again, so diagnostics keep
+up on large files. It needs rinha to be built with the `lsp` feature.
+
+`--extensions` adds lists to the language. `nil` is the empty list,
+`cons(x, list)` adds `x` in front of `list`, `head` and `tail` take a
+non-empty list apart and `length` counts its elements. Lists print as
+`[1, 2, 3]`. For strings, `str_length(s)` counts the characters of `s`,
+`char_at(s, i)` and `str_slice(s, start, end)` take characters by their
+index from 0, and `str_to_int` and `int_to_str` convert between strings
+and integers. `read_line()` reads a line of stdin, without its line
+ending, and stops the program with an error at the end of the input.
+`random(n)` gives an integer from 0 up to, but not including, `n`,
+different on every run unless `--seed` is given. `now()` gives the
+milliseconds since the program started, for programs that time
+themselves.
+These names are bound like any other, so programs that define them still
+run unchanged.
+
+`let (a, b) = value; next` binds `a` and `b` to the elements of the tuple
+`value` in `next`, stopping the program with an error when `value` is not a
+tuple.
+
+`match (value) { pattern => result, ... }` evaluates the result of the
+first arm whose pattern matches the value. Patterns are integer, string
+and boolean literals, tuples of patterns such as `(x, _)`, `_`, which
+matches anything, and names, which match anything and are bound to it in
+the result. A value that no arm matches stops the program with an error,
+and `rinha lint` warns about matches that miss some values and about arms
+that are never reached. `match` only starts a match when a parenthesized
+value and `{` follow it, so programs using it as a name run unchanged.
+
+`--extensions records` also adds records to the language. `{ x: 1, y: 2 }`
+builds a record and `point.x` reads one of its fields, stopping the program
+with an error when the record has no such field. Records print as
+`{x: 1, y: 2}` and are equal when they have the same fields with equal
+values.
+
+`--extensions refs` adds mutable references, which let functions share
+state without passing it through their arguments and results.
+`ref(value)` makes a new reference holding `value`, `!r` reads what `r`
+holds and `r := value` replaces it, giving back `value`. References print
+as `<#ref>` and are only equal to themselves. Calls that use a reference
+are never memoized. `rinha check` gives a reference a single type for its
+whole life. Values that hold themselves through references, such as a
+function stored in a reference it uses, are freed once nothing else
+holds them.
+
+`--extensions modules` splits programs across files. `import(path)`, with
+a string literal as `path`, is the value of the program in that file,
+found next to the file that imports it; JSON ASTs can be imported too.
+Every imported file runs once, before the program that imports it,
+however many times it is imported.
+A program that binds the name `import` itself calls its own function.
+
+Extensions can be combined, as in `--extensions records,refs`. `rinha
+fmt`, `emit-ast`, `check` and `lint` accept the syntax of every extension,
+and `check` and `lint` follow imports.
+The C and WebAssembly targets stop with an error on records and
+references.
+
+Without `--extensions`, rinha runs the language as the specification
+describes it, as the competition does, and `+` fails when either side is
+a boolean. With any `--extensions`, `+` also joins booleans to strings
+as `true` and `false`. `--strict` makes the first choice explicit, so a
+script meant for judging fails rather than quietly running extensions.
+`--coerce` picks what `+` joins to strings either way; with `all`, any
+value is joined as `print` shows it. Values other than integers are
+only joined next to a string, so `1 + true` fails with every choice.
+With `--extensions partial`, calling a function with fewer arguments
+than it takes gives a function waiting for the rest, so `add(1)(2)` is
+`add(1, 2)`; without, it fails as the specification says. `print(a, b)`
+likewise needs `--extensions`, and prints its values on one line,
+separated by spaces, giving the last one.
+
+Judges disagree on closures, so `--closure-eq` and `--closure-print`
+choose what comparing and printing them does, inside tuples, lists and
+records too. The defaults follow the specification, which prints a
+closure as `<#closure>`; it leaves comparing closures open, and by
+default a closure only equals itself.
+
+Printed lines are written to stdout in blocks, which is much faster for
+programs that print a lot. With `--line-buffered`, or with `--timeout`,
+which may stop a program before its output is written, every line is
+written as soon as it is printed.
+
+Colored errors and warnings name their kind in red or yellow and
+underline the code they point at. The value a program ends with is then
+shown as `print` shows it, in green, rather than as a debugging dump.
+`--color auto` leaves out colors when the `NO_COLOR` environment variable
+is set.
+
+Ctrl-c stops a running program at its next call instead of killing
+rinha, so the lines it printed are still written. The error that follows
+tells how many calls the program made and where it stopped, and rinha
+exits with status 130.
+
+With `--quiet`, stdout holds exactly the lines the program printed, as
+judges that compare outputs expect: the line with its value is left out,
+and so are the notes rinha writes to stderr, such as warnings about the
+cache. Errors are still reported. It cannot be combined with
+`--json-result`.
+
+With `--json-result`, rinha prints a single JSON object once the program
+stops, with the fields `stdout`, holding every printed line, `result`, the
+value of the program as `print` shows it or the message of the error that
+stopped it, `value`, the same value as JSON, `elapsed_ms`, the time the
+program ran, and `status`, `ok` or `error`. In `value`, tuples are arrays
+of two elements, records are objects, integers too large for 64 bits are
+strings, and functions, refs and errors are `null`. Errors while reading
+the program are reported as usual.
+
+With `--report`, the last line rinha writes to stderr, whether the
+program succeeds or fails, is a JSON object with the fields `wall_ms`,
+the time the program ran, `peak_rss_kb`, the most memory the process held
+(`null` where the system does not tell), `allocations`, the strings,
+tuples and closures it created, `steps`, the function calls it made, and
+`max_depth`, the most calls active at once.
+
+`--max-memory` counts everything rinha has allocated, including the
+program itself, and is checked on every function call.
+
+Calls are kept on the heap, so deep recursion never overflows the stack
+of rinha. A call that would nest deeper than `--max-depth` stops the
+program with `maximum recursion depth <n> exceeded` instead of letting
+it grow until memory runs out. Calls in tail position do not nest.
+
+`--cache` names each compiled program after a hash of the input, its
+path and `--extensions`, and keeps the input with it, so a changed or
+moved input is compiled again. A program loaded from the cache is not
+checked or linted again. Programs that use `--extensions modules`, and
+runs with `--lint`, `--show-opt`, `--show-passes` or `--peval`, do not
+use the cache.
+
+Logging replaces `RUST_LOG` when `--log-level` is given, and is off with
+`--quiet`. A bare level only applies to rinha itself; the crates it uses
+only log when a directive names them. At `debug`, hits of the `--cache`
+directory and of memoized calls are logged; at `trace`, every call is a
+span, logged when it returns together with how long it took, and every
+binding made and reference assigned is an event. Other commands log only
+what `RUST_LOG` lets through.
+
+`--checkpoint` and `--resume` always run the VM, which checks for SIGTERM
+every time it enters a function. A stopped program writes what it printed
+so far, saves its stack to the checkpoint and exits with status 143;
+resuming it goes on from that function, with the same output as a run
+that was never stopped.
+Checkpoints only load in the version of rinha that wrote them, and the
+results of memoized calls, `--max-steps` and `--timeout` start over when
+a program resumes. Resumed runs may take a checkpoint again.
+
+`rinha test` runs every `.rinha` and `.json` program in a directory and
+compares what it prints with the `.expected` file of the same name. A
+program that stops with an error is expected to print `error: <message>`
+as its last line. Tests run with `--extensions records,refs,modules`.
+
+`rinha fmt` prints a program as formatted `.rinha` source. With
+`--from-json` the input is read as a JSON AST, which turns the AST files
+of the reference parser back into readable code. With `--check` nothing
+is printed; instead rinha exits with status 1 when the file is not already
+formatted. Comments are not kept.
+
+`rinha emit-ast` parses `.rinha` source and prints its JSON AST in the
+format of the reference parser.
+
+`rinha pack` writes the AST of a program, locations included, in a binary
+form that loads much faster than JSON, next to the input with the
+extension `.rinhab` unless `-o` names another file. Packed files run,
+check and report errors like the program they were made from. `rinha
+unpack` turns one back into a JSON AST, printed unless `-o` names a file.
+
+`rinha check` infers the types of a program without running it and
+prints the type of its result, or the first type error it finds.
+
+`rinha lint` warns about operations that are bound to fail at runtime, such
+as calling a number or comparing tuples with `<`, in programs that `check`
+may reject. Warnings never stop a program from running.
+
+`rinha stats` measures a program without running it: how many terms of
+each kind it has, how deeply they nest, how many different names it binds
+and how many function literals, each making a closure when evaluated, it
+holds.
+
+`rinha highlight` splits a file into tokens for editors that color code
+and prints them as a JSON array of objects holding the `kind` of each
+token and the byte offsets where it `start`s and `end`s. Whitespace and
+comments are tokens too, so together they cover the whole file. With
+`--html` it prints the file as an HTML `pre` element instead, each token
+in a `span` of the class `rinha-<kind>`.
+
+`rinha build` compiles a program ahead of time, writing the result next to
+the input with the extension of the target unless `-o` names another
+file. `--target c` produces a standalone C program, built for example with
+`cc -O2 program.c -o program`. `--target wasm` produces a WebAssembly
+module; the module imports its output functions from the host; run
+it with `node runtime/rinha.mjs <out.wasm>`. This target needs rinha to be
+built with the `wasm` feature.";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    AstJson,
+    Source,
+    Packed,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub input: String,
+    pub format: Format,
+    pub engine: Engine,
+    pub memoize: bool,
+    /// Strict unless `--extensions` is given.
+    pub config: InterpreterConfig,
+    /// Language extensions named after `--extensions`.
+    pub syntax: Syntax,
+    pub max_steps: Option<u64>,
+    /// Most calls that may be active at once.
+    pub max_depth: usize,
+    pub timeout: Option<Duration>,
+    pub max_memory: Option<usize>,
+    /// Where `random` starts, for `--seed`.
+    pub seed: Option<u64>,
+    pub line_buffered: bool,
+    /// Whether to leave out the value of the program and notes to stderr,
+    /// for `--quiet`.
+    pub quiet: bool,
+    pub json_result: bool,
+    pub parallel: bool,
+    pub show_opt: bool,
+    /// Whether to print the program after each optimizer pass, for
+    /// `--show-passes`.
+    pub show_passes: bool,
+    /// Whether to evaluate closed calls before running, for `--peval`.
+    pub peval: bool,
+    pub lint: bool,
+    pub trace: Option<TraceTo>,
+    pub profile: bool,
+    /// File to write the folded stacks of the profile to, for
+    /// `--flamegraph`.
+    pub flamegraph: Option<String>,
+    pub stats: bool,
+    /// Whether to print the resources the program used, for `--report`.
+    pub report: bool,
+    /// Directory of compiled programs given with `--cache`.
+    pub cache: Option<String>,
+    /// File to save the program to when it is stopped by SIGTERM.
+    pub checkpoint: Option<String>,
+    /// Whether `input` is a checkpoint to continue instead of a program.
+    pub resume: bool,
+    /// Whether `input` is the program itself rather than the file holding
+    /// it, for `rinha eval`.
+    pub eval: bool,
+    /// Whether to run the program again when its files change.
+    pub watch: bool,
+    /// When to color errors and the value of the program.
+    pub color: color::Choice,
+    /// Which events to log, for `--log-level`, in place of `RUST_LOG`.
+    pub log_level: Option<String>,
+    /// Whether to log events as JSON, for `--log-json`.
+    pub log_json: bool,
+}
+
+/// Where `--trace` writes its log.
+#[derive(Debug, PartialEq)]
+pub enum TraceTo {
+    Stderr,
+    File(String),
+}
+
+pub enum Command {
+    Run(Options),
+    Repl {
+        memoize: bool,
+    },
+    Test {
+        dir: String,
+        engine: Engine,
+    },
+    Fmt {
+        input: String,
+        format: Format,
+        check: bool,
+    },
+    EmitAst {
+        input: String,
+    },
+    Pack {
+        input: String,
+        format: Format,
+        output: String,
+    },
+    Unpack {
+        input: String,
+        output: Option<String>,
+    },
+    Check {
+        input: String,
+        format: Format,
+    },
+    Lint {
+        input: String,
+        format: Format,
+    },
+    Stats {
+        input: String,
+        format: Format,
+    },
+    Highlight {
+        input: String,
+        html: bool,
+    },
+    Debug {
+        input: String,
+        format: Format,
+        memoize: bool,
+    },
+    Difftest {
+        input: String,
+        format: Format,
+        memoize: bool,
+        config: InterpreterConfig,
+        max_steps: Option<u64>,
+    },
+    Serve {
+        engine: Engine,
+        memoize: bool,
+        config: InterpreterConfig,
+        max_steps: Option<u64>,
+    },
+    Http {
+        listen: String,
+        engine: Engine,
+        memoize: bool,
+        config: InterpreterConfig,
+        max_steps: Option<u64>,
+        timeout: Option<Duration>,
+    },
+    Build(BuildOptions),
+    Lsp,
+    Help,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    C,
+    Wasm,
+}
+
+impl Target {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Target::C => "c",
+            Target::Wasm => "wasm",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BuildOptions {
+    pub input: String,
+    pub format: Format,
+    pub target: Target,
+    pub output: String,
+}
+
+fn parse_repl_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut memoize = true;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--no-memo" => memoize = false,
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    Ok(Command::Repl { memoize })
+}
+
+fn parse_test_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut dir = None;
+    let mut engine = Engine::Tree;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--engine=tree" => engine = Engine::Tree,
+            "--engine=vm" => engine = Engine::Vm,
+            "--engine=jit" => engine = Engine::Jit,
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+            _ if dir.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => dir = Some(arg),
+        }
+    }
+    let dir = dir.ok_or_else(|| "missing test directory".to_string())?;
+    Ok(Command::Test { dir, engine })
+}
+
+fn parse_fmt_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut input = None;
+    let mut format = None;
+    let mut check = false;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--from-json" => format = Some(Format::AstJson),
+            "--check" => check = true,
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    let format = format.unwrap_or_else(|| detect_format(&input));
+    if check && format == Format::AstJson {
+        return Err("`--check` only applies to `.rinha` source".to_string());
+    }
+    Ok(Command::Fmt {
+        input,
+        format,
+        check,
+    })
+}
+
+fn parse_emit_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut input = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    Ok(Command::EmitAst { input })
+}
+
+fn parse_pack_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" => output = Some(args.next().ok_or("missing value for `-o`")?),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    let output = match output {
+        Some(output) => output,
+        None if input == "-" => return Err("`-o` is required when reading stdin".to_string()),
+        None => Path::new(&input)
+            .with_extension("rinhab")
+            .display()
+            .to_string(),
+    };
+    Ok(Command::Pack {
+        format: detect_format(&input),
+        input,
+        output,
+    })
+}
+
+fn parse_unpack_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" => output = Some(args.next().ok_or("missing value for `-o`")?),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    Ok(Command::Unpack { input, output })
+}
+
+fn parse_check_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut input = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    let format = detect_format(&input);
+    Ok(Command::Check { input, format })
+}
+
+fn parse_lint_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut input = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    let format = detect_format(&input);
+    Ok(Command::Lint { input, format })
+}
+
+fn parse_stats_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut input = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    let format = detect_format(&input);
+    Ok(Command::Stats { input, format })
+}
+
+fn parse_highlight_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut input = None;
+    let mut html = false;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--html" => html = true,
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    Ok(Command::Highlight { input, html })
+}
+
+fn parse_debug_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut input = None;
+    let mut memoize = true;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--no-memo" => memoize = false,
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    if input == "-" {
+        return Err("the debugger reads its commands from stdin".to_string());
+    }
+    let format = detect_format(&input);
+    Ok(Command::Debug {
+        input,
+        format,
+        memoize,
+    })
+}
+
+fn parse_difftest_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = None;
+    let mut format = None;
+    let mut memoize = true;
+    let mut config = InterpreterConfig::STRICT;
+    let mut max_steps = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--ast-json" => format = Some(Format::AstJson),
+            "--source" => format = Some(Format::Source),
+            "--packed" => format = Some(Format::Packed),
+            "--no-memo" => memoize = false,
+            "--extensions" => config = InterpreterConfig::EXTENDED,
+            "--max-steps" => {
+                let value = args.next().ok_or("missing value for `--max-steps`")?;
+                max_steps = Some(parse_steps(&value)?);
+            }
+            flag if flag.starts_with("--max-steps=") => {
+                max_steps = Some(parse_steps(&flag["--max-steps=".len()..])?);
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    let format = format.unwrap_or_else(|| detect_format(&input));
+    Ok(Command::Difftest {
+        input,
+        format,
+        memoize,
+        config,
+        max_steps,
+    })
+}
+
+fn parse_serve_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut engine = Engine::Tree;
+    let mut memoize = true;
+    let mut config = InterpreterConfig::STRICT;
+    let mut max_steps = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--engine=tree" => engine = Engine::Tree,
+            "--engine=vm" => engine = Engine::Vm,
+            "--engine=jit" => engine = Engine::Jit,
+            "--no-memo" => memoize = false,
+            "--extensions" => config = InterpreterConfig::EXTENDED,
+            "--max-steps" => {
+                let value = args.next().ok_or("missing value for `--max-steps`")?;
+                max_steps = Some(parse_steps(&value)?);
+            }
+            flag if flag.starts_with("--max-steps=") => {
+                max_steps = Some(parse_steps(&flag["--max-steps=".len()..])?);
+            }
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    Ok(Command::Serve {
+        engine,
+        memoize,
+        config,
+        max_steps,
+    })
+}
+
+fn parse_http_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut listen = "0.0.0.0:8080".to_string();
+    let mut engine = Engine::Tree;
+    let mut memoize = true;
+    let mut config = InterpreterConfig::STRICT;
+    let mut max_steps = None;
+    let mut timeout = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--engine=tree" => engine = Engine::Tree,
+            "--engine=vm" => engine = Engine::Vm,
+            "--engine=jit" => engine = Engine::Jit,
+            "--no-memo" => memoize = false,
+            "--extensions" => config = InterpreterConfig::EXTENDED,
+            "--listen" => {
+                listen = args.next().ok_or("missing value for `--listen`")?;
+            }
+            flag if flag.starts_with("--listen=") => {
+                listen = flag["--listen=".len()..].to_string();
+            }
+            "--max-steps" => {
+                let value = args.next().ok_or("missing value for `--max-steps`")?;
+                max_steps = Some(parse_steps(&value)?);
+            }
+            flag if flag.starts_with("--max-steps=") => {
+                max_steps = Some(parse_steps(&flag["--max-steps=".len()..])?);
+            }
+            "--timeout" => {
+                let value = args.next().ok_or("missing value for `--timeout`")?;
+                timeout = Some(parse_timeout(&value)?);
+            }
+            flag if flag.starts_with("--timeout=") => {
+                timeout = Some(parse_timeout(&flag["--timeout=".len()..])?);
+            }
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    Ok(Command::Http {
+        listen,
+        engine,
+        memoize,
+        config,
+        max_steps,
+        timeout,
+    })
+}
+
+fn parse_lsp_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            // Editors commonly pass this when starting a server.
+            "--stdio" => {}
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    Ok(Command::Lsp)
+}
+
+fn parse_target(value: &str) -> Result<Target, String> {
+    match value {
+        "c" => Ok(Target::C),
+        "wasm" => Ok(Target::Wasm),
+        _ => Err(format!("unknown target `{}`", value)),
+    }
+}
+
+fn parse_build_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.peekable();
+    let mut input = None;
+    let mut target = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--target" => {
+                let value = args.next().ok_or("missing value for `--target`")?;
+                target = Some(parse_target(&value)?);
+            }
+            flag if flag.starts_with("--target=") => {
+                target = Some(parse_target(&flag["--target=".len()..])?);
+            }
+            "-o" => output = Some(args.next().ok_or("missing value for `-o`")?),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    let target = target.ok_or("missing `--target`")?;
+    let output = match output {
+        Some(output) => output,
+        None if input == "-" => return Err("`-o` is required when reading stdin".to_string()),
+        None => Path::new(&input)
+            .with_extension(target.extension())
+            .display()
+            .to_string(),
+    };
+    Ok(Command::Build(BuildOptions {
+        format: detect_format(&input),
+        input,
+        target,
+        output,
+    }))
+}
+
+/// Extensions that can be named after `--extensions`.
+const EXTENSIONS: [&str; 4] = ["records", "refs", "modules", "partial"];
+
+fn enable_extension(
+    name: &str,
+    syntax: &mut Syntax,
+    config: &mut InterpreterConfig,
+) -> Result<(), String> {
+    match name {
+        "records" => syntax.records = true,
+        "refs" => syntax.refs = true,
+        "modules" => syntax.modules = true,
+        "partial" => config.partial_application = true,
+        _ => return Err(format!("unknown extension `{}`", name)),
+    }
+    Ok(())
+}
+
+/// Files ending in `.rinha` are source code and files ending in `.rinhab`
+/// packed ASTs; everything else is JSON.
+fn detect_format(input: &str) -> Format {
+    if input.ends_with(".rinha") {
+        Format::Source
+    } else if input.ends_with(".rinhab") {
+        Format::Packed
+    } else {
+        Format::AstJson
+    }
+}
+
+fn parse_steps(value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number of steps `{}`", value))
+}
+
+fn parse_depth(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number of calls `{}`", value))
+}
+
+fn parse_seed(value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid seed `{}`", value))
+}
+
+fn parse_coercion(value: &str) -> Result<Coercion, String> {
+    Coercion::from_name(value).ok_or_else(|| format!("unknown coercion `{}`", value))
+}
+
+/// Whether `value`, given to `--closure-eq` or `--closure-print`, lets
+/// closures through: it is either `allowed` or `error`.
+fn parse_closure_policy(value: &str, allowed: &str) -> Result<bool, String> {
+    match value {
+        "error" => Ok(false),
+        _ if value == allowed => Ok(true),
+        _ => Err(format!("unknown closure policy `{}`", value)),
+    }
+}
+
+fn parse_color(value: &str) -> Result<color::Choice, String> {
+    match value {
+        "auto" => Ok(color::Choice::Auto),
+        "always" => Ok(color::Choice::Always),
+        "never" => Ok(color::Choice::Never),
+        _ => Err(format!("unknown color choice `{}`", value)),
+    }
+}
+
+fn parse_megabytes(value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .and_then(|megabytes| megabytes.checked_mul(1 << 20))
+        .ok_or_else(|| format!("invalid number of megabytes `{}`", value))
+}
+
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("invalid number of seconds `{}`", value))
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.peekable();
+    match args.peek().map(String::as_str) {
+        Some("repl") => {
+            args.next();
+            return parse_repl_args(args);
+        }
+        Some("test") => {
+            args.next();
+            return parse_test_args(args);
+        }
+        Some("fmt") => {
+            args.next();
+            return parse_fmt_args(args);
+        }
+        Some("emit-ast") => {
+            args.next();
+            return parse_emit_args(args);
+        }
+        Some("pack") => {
+            args.next();
+            return parse_pack_args(args);
+        }
+        Some("unpack") => {
+            args.next();
+            return parse_unpack_args(args);
+        }
+        Some("check") => {
+            args.next();
+            return parse_check_args(args);
+        }
+        Some("lint") => {
+            args.next();
+            return parse_lint_args(args);
+        }
+        Some("stats") => {
+            args.next();
+            return parse_stats_args(args);
+        }
+        Some("highlight") => {
+            args.next();
+            return parse_highlight_args(args);
+        }
+        Some("debug") => {
+            args.next();
+            return parse_debug_args(args);
+        }
+        Some("difftest") => {
+            args.next();
+            return parse_difftest_args(args);
+        }
+        Some("serve") => {
+            args.next();
+            return parse_serve_args(args);
+        }
+        Some("http") => {
+            args.next();
+            return parse_http_args(args);
+        }
+        Some("lsp") => {
+            args.next();
+            return parse_lsp_args(args);
+        }
+        Some("build") => {
+            args.next();
+            return parse_build_args(args);
+        }
+        _ => {}
+    }
+    // Anything else runs a program, given as source by `rinha eval`. `rinha
+    // run` is the same as giving no command.
+    let eval = args.next_if(|arg| arg == "eval").is_some();
+    if !eval {
+        args.next_if(|arg| arg == "run");
+    }
+
+    let mut input = None;
+    let mut format = None;
+    let mut engine = Engine::Tree;
+    let mut memoize = true;
+    let mut config = InterpreterConfig::STRICT;
+    let mut strict = false;
+    let mut coercion = None;
+    let mut compare_closures = None;
+    let mut print_closures = None;
+    let mut syntax = Syntax::default();
+    let mut max_steps = None;
+    let mut max_depth = depth::DEFAULT_LIMIT;
+    let mut timeout = None;
+    let mut max_memory = None;
+    let mut seed = None;
+    let mut line_buffered = false;
+    let mut quiet = false;
+    let mut json_result = false;
+    let mut parallel = false;
+    let mut watch = false;
+    let mut show_opt = false;
+    let mut show_passes = false;
+    let mut peval = false;
+    let mut lint = false;
+    let mut trace = None;
+    let mut profile = false;
+    let mut flamegraph = None;
+    let mut stats = false;
+    let mut report = false;
+    let mut cache = None;
+    let mut checkpoint = None;
+    let mut resume = None;
+    let mut color = color::Choice::Auto;
+    let mut log_level = None;
+    let mut log_json = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--ast-json" => format = Some(Format::AstJson),
+            "--source" => format = Some(Format::Source),
+            "--packed" => format = Some(Format::Packed),
+            "--engine=tree" => engine = Engine::Tree,
+            "--engine=vm" => engine = Engine::Vm,
+            "--engine=jit" => engine = Engine::Jit,
+            "--no-memo" => memoize = false,
+            "--strict" => strict = true,
+            "--extensions" => {
+                config = InterpreterConfig::EXTENDED;
+                // The names may also be given as the next argument, as long
+                // as it is not the input file.
+                let names = args
+                    .peek()
+                    .filter(|names| names.split(',').all(|name| EXTENSIONS.contains(&name)));
+                if names.is_some() {
+                    for name in args.next().unwrap().split(',') {
+                        enable_extension(name, &mut syntax, &mut config)?;
+                    }
+                }
+            }
+            flag if flag.starts_with("--extensions=") => {
+                config = InterpreterConfig::EXTENDED;
+                for name in flag["--extensions=".len()..].split(',') {
+                    enable_extension(name, &mut syntax, &mut config)?;
+                }
+            }
+            "--coerce" => {
+                let value = args.next().ok_or("missing value for `--coerce`")?;
+                coercion = Some(parse_coercion(&value)?);
+            }
+            flag if flag.starts_with("--coerce=") => {
+                coercion = Some(parse_coercion(&flag["--coerce=".len()..])?);
+            }
+            "--closure-eq" => {
+                let value = args.next().ok_or("missing value for `--closure-eq`")?;
+                compare_closures = Some(parse_closure_policy(&value, "identity")?);
+            }
+            flag if flag.starts_with("--closure-eq=") => {
+                let value = &flag["--closure-eq=".len()..];
+                compare_closures = Some(parse_closure_policy(value, "identity")?);
+            }
+            "--closure-print" => {
+                let value = args.next().ok_or("missing value for `--closure-print`")?;
+                print_closures = Some(parse_closure_policy(&value, "show")?);
+            }
+            flag if flag.starts_with("--closure-print=") => {
+                let value = &flag["--closure-print=".len()..];
+                print_closures = Some(parse_closure_policy(value, "show")?);
+            }
+            "--color" => {
+                let value = args.next().ok_or("missing value for `--color`")?;
+                color = parse_color(&value)?;
+            }
+            flag if flag.starts_with("--color=") => {
+                color = parse_color(&flag["--color=".len()..])?;
+            }
+            "--line-buffered" => line_buffered = true,
+            "--quiet" => quiet = true,
+            "--json-result" => json_result = true,
+            "--parallel" => parallel = true,
+            "--watch" => watch = true,
+            "--show-opt" => show_opt = true,
+            "--show-passes" => show_passes = true,
+            "--peval" => peval = true,
+            "--lint" => lint = true,
+            "--trace" => trace = Some(TraceTo::Stderr),
+            "--profile" => profile = true,
+            "--flamegraph" => {
+                flamegraph = Some(args.next().ok_or("missing value for `--flamegraph`")?);
+            }
+            flag if flag.starts_with("--flamegraph=") => {
+                flamegraph = Some(flag["--flamegraph=".len()..].to_string());
+            }
+            "--stats" => stats = true,
+            "--report" => report = true,
+            flag if flag.starts_with("--trace=") => {
+                trace = Some(TraceTo::File(flag["--trace=".len()..].to_string()));
+            }
+            "--max-steps" => {
+                let value = args.next().ok_or("missing value for `--max-steps`")?;
+                max_steps = Some(parse_steps(&value)?);
+            }
+            flag if flag.starts_with("--max-steps=") => {
+                max_steps = Some(parse_steps(&flag["--max-steps=".len()..])?);
+            }
+            "--max-depth" => {
+                let value = args.next().ok_or("missing value for `--max-depth`")?;
+                max_depth = parse_depth(&value)?;
+            }
+            flag if flag.starts_with("--max-depth=") => {
+                max_depth = parse_depth(&flag["--max-depth=".len()..])?;
+            }
+            "--seed" => {
+                let value = args.next().ok_or("missing value for `--seed`")?;
+                seed = Some(parse_seed(&value)?);
+            }
+            flag if flag.starts_with("--seed=") => {
+                seed = Some(parse_seed(&flag["--seed=".len()..])?);
+            }
+            "--timeout" => {
+                let value = args.next().ok_or("missing value for `--timeout`")?;
+                timeout = Some(parse_timeout(&value)?);
+            }
+            flag if flag.starts_with("--timeout=") => {
+                timeout = Some(parse_timeout(&flag["--timeout=".len()..])?);
+            }
+            "--max-memory" => {
+                let value = args.next().ok_or("missing value for `--max-memory`")?;
+                max_memory = Some(parse_megabytes(&value)?);
+            }
+            flag if flag.starts_with("--max-memory=") => {
+                max_memory = Some(parse_megabytes(&flag["--max-memory=".len()..])?);
+            }
+            "--log-level" => {
+                log_level = Some(args.next().ok_or("missing value for `--log-level`")?);
+            }
+            flag if flag.starts_with("--log-level=") => {
+                log_level = Some(flag["--log-level=".len()..].to_string());
+            }
+            "--log-json" => log_json = true,
+            "--cache" => {
+                cache = Some(args.next().ok_or("missing value for `--cache`")?);
+            }
+            flag if flag.starts_with("--cache=") => {
+                cache = Some(flag["--cache=".len()..].to_string());
+            }
+            "--checkpoint" => {
+                checkpoint = Some(args.next().ok_or("missing value for `--checkpoint`")?);
+            }
+            flag if flag.starts_with("--checkpoint=") => {
+                checkpoint = Some(flag["--checkpoint=".len()..].to_string());
+            }
+            "--resume" => {
+                resume = Some(args.next().ok_or("missing value for `--resume`")?);
+            }
+            flag if flag.starts_with("--resume=") => {
+                resume = Some(flag["--resume=".len()..].to_string());
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => input = Some(arg),
+        }
+    }
+
+    let input = match (input, &resume) {
+        (Some(_), Some(_)) => {
+            return Err("`--resume` takes the place of the input file".to_string())
+        }
+        (None, Some(path)) => path.clone(),
+        (None, None) if eval => return Err("missing source to evaluate".to_string()),
+        (input, None) => input.ok_or_else(|| "missing input file".to_string())?,
+    };
+    if eval && (cache.is_some() || resume.is_some()) {
+        return Err("`rinha eval` cannot be combined with `--cache` or `--resume`".to_string());
+    }
+    if watch && (eval || resume.is_some() || input == "-") {
+        return Err("`--watch` needs the program in a file".to_string());
+    }
+    if strict && !config.strict {
+        return Err("`--strict` cannot be combined with `--extensions`".to_string());
+    }
+    if quiet && json_result {
+        return Err("`--quiet` cannot be combined with `--json-result`".to_string());
+    }
+    if quiet && (log_level.is_some() || log_json) {
+        return Err("`--quiet` cannot be combined with `--log-level` or `--log-json`".to_string());
+    }
+    if let Some(coercion) = coercion {
+        if strict && coercion != Coercion::Spec {
+            return Err("`--strict` only allows `--coerce spec`".to_string());
+        }
+        config.coercion = coercion;
+    }
+    config.compare_closures = compare_closures.unwrap_or(config.compare_closures);
+    config.print_closures = print_closures.unwrap_or(config.print_closures);
+    let format = match format {
+        Some(format) => format,
+        None if eval => Format::Source,
+        None => detect_format(&input),
+    };
+    // Only the VM can stop a program and take it up again.
+    if checkpoint.is_some() || resume.is_some() {
+        engine = Engine::Vm;
+    }
+
+    Ok(Command::Run(Options {
+        input,
+        format,
+        engine,
+        memoize,
+        config,
+        syntax,
+        max_steps,
+        max_depth,
+        timeout,
+        max_memory,
+        seed,
+        line_buffered,
+        quiet,
+        json_result,
+        parallel,
+        show_opt,
+        show_passes,
+        peval,
+        lint,
+        trace,
+        profile,
+        flamegraph,
+        stats,
+        report,
+        cache,
+        checkpoint,
+        resume: resume.is_some(),
+        eval,
+        watch,
+        color,
+        log_level,
+        log_json,
+    }))
+}
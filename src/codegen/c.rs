@@ -0,0 +1,667 @@
+//! Compiles a program to a standalone C file.
+//!
+//! Values are pointers to a tagged union allocated on the heap and never
+//! freed. Each Rinha function becomes a C function taking its closure and
+//! one value per parameter, and a closure is a value holding a pointer to
+//! that function and the values it captures. The runtime in `runtime.c` is
+//! copied at the top of every generated file, so the result only needs a
+//! C99 compiler: `cc -O2 program.c -o program`. With optimizations, calls
+//! in tail position are compiled as jumps by GCC and Clang; other calls use
+//! the C stack, so deep recursion may need a larger one (`ulimit -s`).
+
+use std::fmt::Write;
+
+use crate::symbol::Symbol;
+use crate::types::*;
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// How a function reaches a variable.
+#[derive(Clone)]
+enum Access {
+    /// A C variable of the function.
+    Local(String),
+    Capture(usize),
+    /// The closure of the function itself, for `let` recursion.
+    Callee,
+}
+
+struct Scope {
+    name: Option<Symbol>,
+    locals: Vec<(Symbol, String)>,
+    captures: Vec<(Symbol, Access)>,
+    body: String,
+    indent: usize,
+}
+
+struct Compiler {
+    scopes: Vec<Scope>,
+    /// Definitions of the compiled functions by index.
+    functions: Vec<Option<(usize, String)>>,
+    /// Static values for the literals of the program.
+    constants: String,
+    /// Counter for unique C names.
+    names: usize,
+}
+
+/// A C string literal with the bytes of `value`. Octal escapes are used
+/// because, unlike hexadecimal ones, they cannot swallow the next character.
+fn string_literal(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' | b'?' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => write!(literal, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn int_literal(value: i64) -> String {
+    if value == i64::MIN {
+        "INT64_MIN".to_string()
+    } else {
+        format!("INT64_C({})", value)
+    }
+}
+
+fn op_name(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "OP_ADD",
+        BinaryOp::Sub => "OP_SUB",
+        BinaryOp::Mul => "OP_MUL",
+        BinaryOp::Div => "OP_DIV",
+        BinaryOp::Rem => "OP_REM",
+        BinaryOp::Eq => "OP_EQ",
+        BinaryOp::Neq => "OP_NEQ",
+        BinaryOp::Lt => "OP_LT",
+        BinaryOp::Gt => "OP_GT",
+        BinaryOp::Lte => "OP_LTE",
+        BinaryOp::Gte => "OP_GTE",
+        BinaryOp::And | BinaryOp::Or => unreachable!("compiled as control flow"),
+    }
+}
+
+/// The type of a compiled function of `arity` parameters.
+fn code_type(arity: usize) -> String {
+    let mut ty = String::from("Value *(*)(Value *");
+    for _ in 0..arity {
+        ty.push_str(", Value *");
+    }
+    ty.push(')');
+    ty
+}
+
+fn span(location: &Location) -> String {
+    format!("{}, {}", location.start, location.end)
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn line(&mut self, text: &str) {
+        let scope = self.scope();
+        for _ in 0..scope.indent {
+            scope.body.push_str("    ");
+        }
+        scope.body.push_str(text);
+        scope.body.push('\n');
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{}{}", prefix, self.names)
+    }
+
+    /// Declares a C variable holding `value` and returns its name.
+    fn temp(&mut self, prefix: &str, value: &str) -> String {
+        let name = self.fresh(prefix);
+        self.line(&format!("Value *{} = {};", name, value));
+        name
+    }
+
+    fn resolve(&mut self, level: usize, name: Symbol) -> Option<Access> {
+        let scope = &self.scopes[level];
+        if let Some((_, local)) = scope.locals.iter().rev().find(|(n, _)| *n == name) {
+            return Some(Access::Local(local.clone()));
+        }
+        if let Some(index) = scope.captures.iter().position(|(n, _)| *n == name) {
+            return Some(Access::Capture(index));
+        }
+        if scope.name == Some(name) {
+            return Some(Access::Callee);
+        }
+        if level == 0 {
+            return None;
+        }
+        let access = self.resolve(level - 1, name)?;
+        let captures = &mut self.scopes[level].captures;
+        captures.push((name, access));
+        Some(Access::Capture(captures.len() - 1))
+    }
+
+    fn access(access: &Access) -> String {
+        match access {
+            Access::Local(name) => name.clone(),
+            Access::Capture(index) => format!("self->as.closure.captures[{}]", index),
+            Access::Callee => "self".to_string(),
+        }
+    }
+
+    /// Emits `term` in tail position, returning its value.
+    fn tail(&mut self, term: &Term) {
+        match term {
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let condition_value = self.value(condition);
+                self.line(&format!(
+                    "if (rinha_as_bool({}, {})) {{",
+                    condition_value,
+                    span(condition.location())
+                ));
+                self.scope().indent += 1;
+                self.tail(then);
+                self.scope().indent -= 1;
+                self.line("} else {");
+                self.scope().indent += 1;
+                self.tail(otherwise);
+                self.scope().indent -= 1;
+                self.line("}");
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => {
+                let depth = self.scope().locals.len();
+                let next = self.bind(term);
+                self.tail(next);
+                self.scope().locals.truncate(depth);
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                let call = self.call(callee, arguments, location);
+                self.line(&format!("return {};", call));
+            }
+            Term::Match {
+                value,
+                arms,
+                location,
+            } => self.arms(value, arms, location, None),
+            _ => {
+                let value = self.value(term);
+                self.line(&format!("return {};", value));
+            }
+        }
+    }
+
+    /// Binds the names of a `Let` or a `LetTuple` in the current scope and
+    /// returns the term the bindings are visible in. The caller pops the
+    /// bindings.
+    fn bind<'t>(&mut self, term: &'t Term) -> &'t Term {
+        if let Term::LetTuple {
+            first,
+            second,
+            value,
+            next,
+            ..
+        } = term
+        {
+            let tuple = self.value(value);
+            let location = span(value.location());
+            for (name, part) in [(first, "first"), (second, "second")] {
+                let element = format!("rinha_{}({}, {})", part, tuple, location);
+                self.local(name.text, &element);
+            }
+            return next;
+        }
+        let Term::Let {
+            name, value, next, ..
+        } = term
+        else {
+            unreachable!()
+        };
+        let value = match value.as_ref() {
+            Term::Function {
+                parameters,
+                value: body,
+                ..
+            } => self.closure(Some(name.text), parameters, body),
+            _ => self.value(value),
+        };
+        self.local(name.text, &value);
+        next
+    }
+
+    /// Binds `name` to a new temporary holding `value`.
+    fn local(&mut self, name: Symbol, value: &str) {
+        let local = self.temp(&format!("{}_", name), value);
+        if name.as_str().starts_with('_') {
+            // Names like `_` are usually never read.
+            self.line(&format!("(void){};", local));
+        }
+        self.scope().locals.push((name, local));
+    }
+
+    /// Emits a call and returns the C expression performing it.
+    fn call(
+        &mut self,
+        callee: &Term,
+        arguments: &[std::rc::Rc<Term>],
+        location: &Location,
+    ) -> String {
+        let callee_value = self.value(callee);
+        let code = self.fresh("code");
+        self.line(&format!(
+            "Code {} = rinha_callee({}, {}, {}, {});",
+            code,
+            callee_value,
+            arguments.len(),
+            span(callee.location()),
+            span(location)
+        ));
+        let mut call = format!(
+            "(({}){})({}",
+            code_type(arguments.len()),
+            code,
+            callee_value
+        );
+        for argument in arguments {
+            let value = self.value(argument);
+            write!(call, ", {}", value).unwrap();
+        }
+        call.push(')');
+        call
+    }
+
+    fn int_constant(&mut self, value: i64) -> String {
+        let name = self.fresh("int");
+        writeln!(
+            self.constants,
+            "static Value {} = {{TAG_INT, {{.integer = {}}}}};",
+            name,
+            int_literal(value)
+        )
+        .unwrap();
+        format!("&{}", name)
+    }
+
+    fn str_constant(&mut self, value: &str) -> String {
+        let name = self.fresh("str");
+        writeln!(
+            self.constants,
+            "static Value {} = {{TAG_STRING, {{.string = {{{}, {}}}}}}};",
+            name,
+            value.len(),
+            string_literal(value)
+        )
+        .unwrap();
+        format!("&{}", name)
+    }
+
+    /// The C condition under which the value at `path` matches `pattern`,
+    /// collecting the C expressions the names of the pattern bind to.
+    fn pattern(
+        &mut self,
+        pattern: &Pattern,
+        path: &str,
+        bindings: &mut Vec<(Symbol, String)>,
+    ) -> String {
+        match pattern {
+            Pattern::Wildcard { .. } => "true".to_string(),
+            Pattern::Var { text, .. } => {
+                bindings.push((*text, path.to_string()));
+                "true".to_string()
+            }
+            Pattern::Int { value, .. } => {
+                let constant = self.int_constant(*value);
+                format!("rinha_equal({}, {})", path, constant)
+            }
+            Pattern::Str { value, .. } => {
+                let constant = self.str_constant(value);
+                format!("rinha_equal({}, {})", path, constant)
+            }
+            Pattern::Bool { value, .. } => format!("rinha_equal({}, &rinha_{})", path, value),
+            Pattern::Tuple { first, second, .. } => {
+                let first = self.pattern(first, &format!("{}->as.tuple.first", path), bindings);
+                let second = self.pattern(second, &format!("{}->as.tuple.second", path), bindings);
+                format!("{}->tag == TAG_TUPLE && {} && {}", path, first, second)
+            }
+        }
+    }
+
+    /// Emits a `Match` as a chain of `if`s, storing the value of the arm
+    /// taken in `result`, or returning it when `result` is `None`.
+    fn arms(&mut self, value: &Term, arms: &[Arm], location: &Location, result: Option<&str>) {
+        let value = self.value(value);
+        let scrutinee = self.temp("match", &value);
+        for (i, arm) in arms.iter().enumerate() {
+            let mut bindings = Vec::new();
+            let condition = self.pattern(&arm.pattern, &scrutinee, &mut bindings);
+            let keyword = if i == 0 { "if" } else { "} else if" };
+            self.line(&format!("{} ({}) {{", keyword, condition));
+            self.scope().indent += 1;
+            let depth = self.scope().locals.len();
+            for (name, path) in bindings {
+                let local = self.temp(&format!("{}_", name), &path);
+                self.line(&format!("(void){};", local));
+                self.scope().locals.push((name, local));
+            }
+            match result {
+                Some(result) => {
+                    let value = self.value(&arm.value);
+                    self.line(&format!("{} = {};", result, value));
+                }
+                None => self.tail(&arm.value),
+            }
+            self.scope().locals.truncate(depth);
+            self.scope().indent -= 1;
+        }
+        if !arms.is_empty() {
+            self.line("} else {");
+            self.scope().indent += 1;
+        }
+        self.line(&format!(
+            "rinha_fail({}, \"no pattern matches the value\");",
+            span(location)
+        ));
+        if result.is_none() {
+            self.line("return NULL;");
+        }
+        if !arms.is_empty() {
+            self.scope().indent -= 1;
+            self.line("}");
+        }
+    }
+
+    /// Emits the computation of `term` and returns a C expression for its
+    /// value that can be evaluated any number of times.
+    fn value(&mut self, term: &Term) -> String {
+        match term {
+            Term::Int { value, .. } => self.int_constant(*value),
+            Term::Str { value, .. } => self.str_constant(value),
+            Term::Bool { value, .. } => {
+                format!("&rinha_{}", value)
+            }
+            Term::Var { text, location, .. } => {
+                let level = self.scopes.len() - 1;
+                match self.resolve(level, *text) {
+                    Some(access) => Compiler::access(&access),
+                    None => {
+                        self.line(&format!(
+                            "rinha_fail({}, \"cannot find variable %s\", {});",
+                            span(location),
+                            string_literal(text.as_str())
+                        ));
+                        "NULL".to_string()
+                    }
+                }
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let condition_value = self.value(condition);
+                let result = self.fresh("if");
+                self.line(&format!("Value *{};", result));
+                self.line(&format!(
+                    "if (rinha_as_bool({}, {})) {{",
+                    condition_value,
+                    span(condition.location())
+                ));
+                self.scope().indent += 1;
+                let value = self.value(then);
+                self.line(&format!("{} = {};", result, value));
+                self.scope().indent -= 1;
+                self.line("} else {");
+                self.scope().indent += 1;
+                let value = self.value(otherwise);
+                self.line(&format!("{} = {};", result, value));
+                self.scope().indent -= 1;
+                self.line("}");
+                result
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => {
+                let depth = self.scope().locals.len();
+                let next = self.bind(term);
+                let value = self.value(next);
+                self.scope().locals.truncate(depth);
+                value
+            }
+            Term::Binary {
+                lhs,
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                rhs,
+                location,
+            } => {
+                let left = self.value(lhs);
+                let result = self.fresh("logic");
+                self.line(&format!("Value *{};", result));
+                let negate = if let BinaryOp::And = op { "" } else { "!" };
+                self.line(&format!(
+                    "if ({}rinha_as_bool({}, {})) {{",
+                    negate,
+                    left,
+                    span(location)
+                ));
+                self.scope().indent += 1;
+                let right = self.value(rhs);
+                self.line(&format!(
+                    "{} = rinha_check_bool({}, {});",
+                    result,
+                    right,
+                    span(location)
+                ));
+                self.scope().indent -= 1;
+                self.line("} else {");
+                self.line(&format!(
+                    "    {} = {};",
+                    result,
+                    if let BinaryOp::And = op {
+                        "&rinha_false"
+                    } else {
+                        "&rinha_true"
+                    }
+                ));
+                self.line("}");
+                result
+            }
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => {
+                let left = self.value(lhs);
+                let right = self.value(rhs);
+                let value = format!(
+                    "rinha_binary({}, {}, {}, {})",
+                    op_name(op),
+                    left,
+                    right,
+                    span(location)
+                );
+                self.temp("binary", &value)
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                let call = self.call(callee, arguments, location);
+                self.temp("call", &call)
+            }
+            Term::Function {
+                parameters, value, ..
+            } => self.closure(None, parameters, value),
+            Term::Tuple { first, second, .. } => {
+                let first = self.value(first);
+                let second = self.value(second);
+                self.temp("tuple", &format!("rinha_tuple({}, {})", first, second))
+            }
+            Term::First { value, .. } => {
+                let tuple = self.value(value);
+                let first = format!("rinha_first({}, {})", tuple, span(value.location()));
+                self.temp("first", &first)
+            }
+            Term::Second { value, .. } => {
+                let tuple = self.value(value);
+                let second = format!("rinha_second({}, {})", tuple, span(value.location()));
+                self.temp("second", &second)
+            }
+            Term::Print { values, .. } => {
+                let values: Vec<String> = values.iter().map(|value| self.value(value)).collect();
+                let array = self.fresh("printed");
+                self.line(&format!("Value *{}[] = {{{}}};", array, values.join(", ")));
+                self.line(&format!("rinha_print({}, {});", array, values.len()));
+                values.last().unwrap().clone()
+            }
+            Term::Match {
+                value,
+                arms,
+                location,
+            } => {
+                let result = self.fresh("match");
+                self.line(&format!("Value *{} = NULL;", result));
+                self.arms(value, arms, location, Some(&result));
+                result
+            }
+            Term::Record { location, .. } | Term::Field { location, .. } => {
+                self.line(&format!(
+                    "rinha_fail({}, \"records are not supported by this target\");",
+                    span(location)
+                ));
+                "NULL".to_string()
+            }
+            Term::Ref { location, .. }
+            | Term::Deref { location, .. }
+            | Term::Assign { location, .. } => {
+                self.line(&format!(
+                    "rinha_fail({}, \"references are not supported by this target\");",
+                    span(location)
+                ));
+                "NULL".to_string()
+            }
+        }
+    }
+
+    /// Compiles a function and emits the creation of its closure.
+    fn closure(&mut self, name: Option<Symbol>, parameters: &[Parameter], body: &Term) -> String {
+        let (index, captures) = self.function(name, parameters, body);
+        let closure = self.temp(
+            "closure",
+            &format!(
+                "rinha_closure((Code)fn{}, {}, {})",
+                index,
+                parameters.len(),
+                captures.len()
+            ),
+        );
+        for (i, access) in captures.iter().enumerate() {
+            self.line(&format!(
+                "{}->as.closure.captures[{}] = {};",
+                closure,
+                i,
+                Compiler::access(access)
+            ));
+        }
+        closure
+    }
+
+    /// Compiles a function and returns its index along with how to reach,
+    /// from the enclosing function, each value it captures.
+    fn function(
+        &mut self,
+        name: Option<Symbol>,
+        parameters: &[Parameter],
+        body: &Term,
+    ) -> (usize, Vec<Access>) {
+        let index = self.functions.len();
+        self.functions.push(None);
+        let locals: Vec<(Symbol, String)> = parameters
+            .iter()
+            .map(|p| (p.text, format!("{}_p{}", p.text, index)))
+            .collect();
+        let mut header = format!("static Value *fn{}(Value *self", index);
+        for (_, local) in &locals {
+            write!(header, ", Value *{}", local).unwrap();
+        }
+        header.push(')');
+        self.scopes.push(Scope {
+            name,
+            locals,
+            captures: Vec::new(),
+            body: String::new(),
+            indent: 1,
+        });
+        self.line("(void)self;");
+        self.tail(body);
+        let scope = self.scopes.pop().unwrap();
+        let definition = format!("{} {{\n{}}}\n", header, scope.body);
+        self.functions[index] = Some((parameters.len(), definition));
+        let captures = scope
+            .captures
+            .into_iter()
+            .map(|(_, access)| access)
+            .collect();
+        (index, captures)
+    }
+}
+
+/// Compiles a file into the source of a C program.
+pub fn compile(file: &File) -> String {
+    let mut compiler = Compiler {
+        scopes: Vec::new(),
+        functions: Vec::new(),
+        constants: String::new(),
+        names: 0,
+    };
+    let (main, _) = compiler.function(None, &[], &file.expression);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* Compiled by `rinha build --target c` from {}. */\n",
+        file.name
+    )
+    .unwrap();
+    out.push_str(RUNTIME);
+    out.push('\n');
+    out.push_str(&compiler.constants);
+    out.push('\n');
+    let functions: Vec<(usize, String)> =
+        compiler.functions.into_iter().map(Option::unwrap).collect();
+    for (index, (arity, _)) in functions.iter().enumerate() {
+        let mut declaration = format!("static Value *fn{}(Value *self", index);
+        for _ in 0..*arity {
+            declaration.push_str(", Value *");
+        }
+        writeln!(out, "{});", declaration).unwrap();
+    }
+    for (_, definition) in &functions {
+        out.push('\n');
+        out.push_str(definition);
+    }
+    writeln!(
+        out,
+        "\nint main(void) {{\n    rinha_filename = {};\n    fn{}(NULL);\n    return 0;\n}}",
+        string_literal(&file.location.filename),
+        main
+    )
+    .unwrap();
+    out
+}
@@ -0,0 +1,5 @@
+//! Ahead-of-time backends that translate a program into another language.
+
+pub mod c;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,336 @@
+/* Runtime of programs compiled by `rinha build --target c`. Its functions
+   are `static inline` so that compilers do not warn about the ones a
+   program does not use. */
+
+#include <inttypes.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct Value Value;
+
+/* Compiled functions take their closure followed by one `Value *` per
+   parameter; `Code` is cast to the right type at each call. */
+typedef void (*Code)(void);
+
+enum Tag { TAG_INT, TAG_BOOL, TAG_STRING, TAG_TUPLE, TAG_CLOSURE };
+
+/* Values live on the heap and are never freed. */
+struct Value {
+    enum Tag tag;
+    union {
+        int64_t integer;
+        bool boolean;
+        struct {
+            size_t len;
+            const char *bytes;
+        } string;
+        struct {
+            Value *first;
+            Value *second;
+        } tuple;
+        struct {
+            Code code;
+            size_t arity;
+            Value **captures;
+        } closure;
+    } as;
+};
+
+/* Same order as the operators of the AST. */
+enum Op { OP_ADD, OP_SUB, OP_MUL, OP_DIV, OP_REM, OP_EQ, OP_NEQ, OP_LT, OP_GT, OP_LTE, OP_GTE };
+
+static const char *rinha_filename;
+
+static Value rinha_true = {TAG_BOOL, {.boolean = true}};
+static Value rinha_false = {TAG_BOOL, {.boolean = false}};
+
+static inline void rinha_fail(size_t start, size_t end, const char *format, ...) {
+    va_list args;
+    fflush(stdout);
+    fputs("error: ", stderr);
+    va_start(args, format);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    fprintf(stderr, "\n  --> %s:%zu..%zu\n", rinha_filename, start, end);
+    exit(1);
+}
+
+static inline void *rinha_alloc(size_t size) {
+    void *ptr = malloc(size);
+    if (ptr == NULL) {
+        fputs("error: out of memory\n", stderr);
+        exit(1);
+    }
+    return ptr;
+}
+
+static inline const char *rinha_type_name(Value *value) {
+    switch (value->tag) {
+    case TAG_INT: return "int";
+    case TAG_BOOL: return "bool";
+    case TAG_STRING: return "string";
+    case TAG_TUPLE: return "tuple";
+    default: return "closure";
+    }
+}
+
+static inline void rinha_type_mismatch(const char *expected, Value *found, size_t start,
+                                       size_t end) {
+    rinha_fail(start, end, "expected %s but found %s", expected, rinha_type_name(found));
+}
+
+static inline Value *rinha_bool(bool boolean) {
+    return boolean ? &rinha_true : &rinha_false;
+}
+
+static inline Value *rinha_int(int64_t integer) {
+    Value *value = rinha_alloc(sizeof(Value));
+    value->tag = TAG_INT;
+    value->as.integer = integer;
+    return value;
+}
+
+static inline Value *rinha_string(const char *bytes, size_t len) {
+    Value *value = rinha_alloc(sizeof(Value));
+    value->tag = TAG_STRING;
+    value->as.string.bytes = bytes;
+    value->as.string.len = len;
+    return value;
+}
+
+static inline Value *rinha_tuple(Value *first, Value *second) {
+    Value *value = rinha_alloc(sizeof(Value));
+    value->tag = TAG_TUPLE;
+    value->as.tuple.first = first;
+    value->as.tuple.second = second;
+    return value;
+}
+
+static inline Value *rinha_closure(Code code, size_t arity, size_t captures) {
+    Value *value = rinha_alloc(sizeof(Value));
+    value->tag = TAG_CLOSURE;
+    value->as.closure.code = code;
+    value->as.closure.arity = arity;
+    value->as.closure.captures = rinha_alloc(captures * sizeof(Value *) + 1);
+    return value;
+}
+
+static inline bool rinha_as_bool(Value *value, size_t start, size_t end) {
+    if (value->tag != TAG_BOOL) {
+        rinha_type_mismatch("bool", value, start, end);
+    }
+    return value->as.boolean;
+}
+
+static inline int64_t rinha_as_int(Value *value, size_t start, size_t end) {
+    if (value->tag != TAG_INT) {
+        rinha_type_mismatch("int", value, start, end);
+    }
+    return value->as.integer;
+}
+
+static inline Value *rinha_check_bool(Value *value, size_t start, size_t end) {
+    rinha_as_bool(value, start, end);
+    return value;
+}
+
+static inline Value *rinha_first(Value *value, size_t start, size_t end) {
+    if (value->tag != TAG_TUPLE) {
+        rinha_type_mismatch("tuple", value, start, end);
+    }
+    return value->as.tuple.first;
+}
+
+static inline Value *rinha_second(Value *value, size_t start, size_t end) {
+    if (value->tag != TAG_TUPLE) {
+        rinha_type_mismatch("tuple", value, start, end);
+    }
+    return value->as.tuple.second;
+}
+
+/* Checks that `callee` is a closure taking `argc` arguments. A value that
+   is not a closure is reported at the callee, a wrong arity at the call. */
+static inline Code rinha_callee(Value *callee, size_t argc, size_t callee_start,
+                                size_t callee_end, size_t start, size_t end) {
+    if (callee->tag != TAG_CLOSURE) {
+        rinha_type_mismatch("closure", callee, callee_start, callee_end);
+    }
+    if (callee->as.closure.arity != argc) {
+        rinha_fail(start, end, "expected %zu arguments but instead got %zu",
+                   callee->as.closure.arity, argc);
+    }
+    return callee->as.closure.code;
+}
+
+typedef struct {
+    char *bytes;
+    size_t len;
+    size_t capacity;
+} Buffer;
+
+static inline void rinha_push(Buffer *buffer, const char *bytes, size_t len) {
+    if (buffer->len + len > buffer->capacity) {
+        buffer->capacity = (buffer->len + len) * 2;
+        buffer->bytes = realloc(buffer->bytes, buffer->capacity);
+        if (buffer->bytes == NULL) {
+            fputs("error: out of memory\n", stderr);
+            exit(1);
+        }
+    }
+    memcpy(buffer->bytes + buffer->len, bytes, len);
+    buffer->len += len;
+}
+
+static inline void rinha_push_int(Buffer *buffer, int64_t integer) {
+    char digits[24];
+    int len = snprintf(digits, sizeof digits, "%" PRId64, integer);
+    rinha_push(buffer, digits, (size_t)len);
+}
+
+static inline void rinha_show(Buffer *buffer, Value *value) {
+    switch (value->tag) {
+    case TAG_INT:
+        rinha_push_int(buffer, value->as.integer);
+        break;
+    case TAG_BOOL:
+        if (value->as.boolean) {
+            rinha_push(buffer, "true", 4);
+        } else {
+            rinha_push(buffer, "false", 5);
+        }
+        break;
+    case TAG_STRING:
+        rinha_push(buffer, value->as.string.bytes, value->as.string.len);
+        break;
+    case TAG_TUPLE:
+        rinha_push(buffer, "(", 1);
+        rinha_show(buffer, value->as.tuple.first);
+        rinha_push(buffer, ", ", 2);
+        rinha_show(buffer, value->as.tuple.second);
+        rinha_push(buffer, ")", 1);
+        break;
+    case TAG_CLOSURE:
+        rinha_push(buffer, "<#closure>", 10);
+        break;
+    }
+}
+
+/* Prints `count` values on one line, separated by spaces. */
+static inline void rinha_print(Value **values, size_t count) {
+    Buffer buffer = {NULL, 0, 0};
+    for (size_t i = 0; i < count; i++) {
+        if (i > 0) {
+            rinha_push(&buffer, " ", 1);
+        }
+        rinha_show(&buffer, values[i]);
+    }
+    rinha_push(&buffer, "\n", 1);
+    fwrite(buffer.bytes, 1, buffer.len, stdout);
+    free(buffer.bytes);
+}
+
+/* Structural equality; closures only equal themselves. */
+static inline bool rinha_equal(Value *left, Value *right) {
+    if (left->tag != right->tag) {
+        return false;
+    }
+    switch (left->tag) {
+    case TAG_INT:
+        return left->as.integer == right->as.integer;
+    case TAG_BOOL:
+        return left->as.boolean == right->as.boolean;
+    case TAG_STRING:
+        return left->as.string.len == right->as.string.len &&
+               memcmp(left->as.string.bytes, right->as.string.bytes, left->as.string.len) == 0;
+    case TAG_TUPLE:
+        return rinha_equal(left->as.tuple.first, right->as.tuple.first) &&
+               rinha_equal(left->as.tuple.second, right->as.tuple.second);
+    default:
+        return left == right;
+    }
+}
+
+static inline void rinha_push_operand(Buffer *buffer, Value *value, size_t start,
+                                      size_t end) {
+    if (value->tag == TAG_INT) {
+        rinha_push_int(buffer, value->as.integer);
+    } else if (value->tag == TAG_STRING) {
+        rinha_push(buffer, value->as.string.bytes, value->as.string.len);
+    } else {
+        rinha_type_mismatch("string or int", value, start, end);
+    }
+}
+
+static inline Value *rinha_arithmetic(enum Op op, Value *left, Value *right, size_t start,
+                                      size_t end) {
+    int64_t x = rinha_as_int(left, start, end);
+    int64_t y = rinha_as_int(right, start, end);
+    bool overflow = false;
+    int64_t result = 0;
+    if ((op == OP_DIV || op == OP_REM) && y == 0) {
+        rinha_fail(start, end, "division by zero");
+    }
+    switch (op) {
+    case OP_ADD:
+        overflow = (y > 0 && x > INT64_MAX - y) || (y < 0 && x < INT64_MIN - y);
+        if (!overflow) result = x + y;
+        break;
+    case OP_SUB:
+        overflow = (y < 0 && x > INT64_MAX + y) || (y > 0 && x < INT64_MIN + y);
+        if (!overflow) result = x - y;
+        break;
+    case OP_MUL:
+        if (x > 0) {
+            overflow = y > 0 ? x > INT64_MAX / y : y < INT64_MIN / x;
+        } else if (x < 0) {
+            overflow = y > 0 ? x < INT64_MIN / y : y != 0 && x < INT64_MAX / y;
+        }
+        if (!overflow) result = x * y;
+        break;
+    default:
+        overflow = x == INT64_MIN && y == -1;
+        if (!overflow) result = op == OP_DIV ? x / y : x % y;
+        break;
+    }
+    if (overflow) {
+        rinha_fail(start, end, "integer overflow");
+    }
+    return rinha_int(result);
+}
+
+static inline Value *rinha_binary(enum Op op, Value *left, Value *right, size_t start,
+                                  size_t end) {
+    switch (op) {
+    case OP_ADD:
+        if (left->tag != TAG_INT || right->tag != TAG_INT) {
+            Buffer buffer = {NULL, 0, 0};
+            rinha_push_operand(&buffer, left, start, end);
+            rinha_push_operand(&buffer, right, start, end);
+            return rinha_string(buffer.bytes, buffer.len);
+        }
+        return rinha_arithmetic(op, left, right, start, end);
+    case OP_EQ:
+        return rinha_bool(rinha_equal(left, right));
+    case OP_NEQ:
+        return rinha_bool(!rinha_equal(left, right));
+    case OP_LT:
+    case OP_GT:
+    case OP_LTE:
+    case OP_GTE: {
+        int64_t x = rinha_as_int(left, start, end);
+        int64_t y = rinha_as_int(right, start, end);
+        switch (op) {
+        case OP_LT: return rinha_bool(x < y);
+        case OP_GT: return rinha_bool(x > y);
+        case OP_LTE: return rinha_bool(x <= y);
+        default: return rinha_bool(x >= y);
+        }
+    }
+    default:
+        return rinha_arithmetic(op, left, right, start, end);
+    }
+}
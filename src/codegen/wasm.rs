@@ -0,0 +1,1679 @@
+//! Compiles a program to a WebAssembly module.
+//!
+//! Every value is a pointer to an object in linear memory whose first word
+//! is a tag. Objects are bump-allocated and never freed. Closures hold the
+//! table index of their function and the values they capture; a function
+//! receives its own closure as its first parameter.
+//!
+//! The module imports two functions from the `rinha` namespace, provided by
+//! a small host shim such as `runtime/rinha.mjs`:
+//!
+//! - `print(ptr: i32, len: i32)` writes one line of UTF-8 text.
+//! - `error(kind: i32, start: i32, end: i32)` reports a runtime error at a
+//!   source span; the module traps right after it returns. `kind` is one of
+//!   the `ERROR_*` constants below.
+//!
+//! It exports its `memory` and a `main` function that runs the program and
+//! returns a pointer to the resulting value.
+
+use wasm_encoder::{
+    BlockType, CodeSection, ConstExpr, DataSection, ElementSection, Elements, EntityType,
+    ExportKind, ExportSection, Function, FunctionSection, GlobalSection, GlobalType, ImportSection,
+    Instruction, MemArg, MemorySection, MemoryType, Module, RefType, TableSection, TableType,
+    TypeSection, ValType,
+};
+
+use crate::symbol::Symbol;
+use crate::types::*;
+
+pub const ERROR_TYPE_MISMATCH: i32 = 0;
+pub const ERROR_UNBOUND_VARIABLE: i32 = 1;
+pub const ERROR_ARITY_MISMATCH: i32 = 2;
+pub const ERROR_INTEGER_OVERFLOW: i32 = 3;
+pub const ERROR_DIVISION_BY_ZERO: i32 = 4;
+pub const ERROR_NO_MATCH: i32 = 5;
+pub const ERROR_UNSUPPORTED_RECORD: i32 = 6;
+pub const ERROR_UNSUPPORTED_REF: i32 = 7;
+
+const TAG_INT: i32 = 0;
+const TAG_BOOL: i32 = 1;
+const TAG_STRING: i32 = 2;
+const TAG_TUPLE: i32 = 3;
+const TAG_CLOSURE: i32 = 4;
+
+// Object layouts, as byte offsets from the object pointer. All objects
+// start with an i32 tag and are aligned to 8 bytes.
+const INT_VALUE: u64 = 8;
+const BOOL_VALUE: u64 = 4;
+const STRING_LEN: u64 = 4;
+const STRING_BYTES: u64 = 8;
+const TUPLE_FIRST: u64 = 4;
+const TUPLE_SECOND: u64 = 8;
+const CLOSURE_FUNCTION: u64 = 4;
+const CLOSURE_ARITY: u64 = 8;
+const CLOSURE_CAPTURES: u64 = 12;
+
+/// Static data starts past address 0, so no object lives at the null
+/// pointer.
+const DATA_START: u32 = 8;
+
+// Function indices: the imports come first, then the runtime functions in
+// the order `Runtime::functions` defines them, then the program.
+const PRINT_IMPORT: u32 = 0;
+const ERROR_IMPORT: u32 = 1;
+const ALLOC: u32 = 2;
+const MAKE_INT: u32 = 3;
+const MAKE_TUPLE: u32 = 4;
+const INT: u32 = 5;
+const BOOL: u32 = 6;
+const CHECK_BOOL: u32 = 7;
+const FIRST: u32 = 8;
+const SECOND: u32 = 9;
+const CALLEE: u32 = 10;
+const INT_TO_STRING: u32 = 11;
+const TO_STRING: u32 = 12;
+const CONCAT: u32 = 13;
+const SHOW: u32 = 14;
+const PRINT: u32 = 15;
+const EQUAL: u32 = 16;
+const STRING_EQUAL: u32 = 17;
+const ADD: u32 = 18;
+const ARITHMETIC: u32 = 19;
+const COMPARE: u32 = 20;
+const BINARY: u32 = 21;
+const RUNTIME_FUNCTIONS: u32 = 22;
+
+const HEAP_GLOBAL: u32 = 0;
+
+fn mem(offset: u64, align: u32) -> MemArg {
+    MemArg {
+        offset,
+        align,
+        memory_index: 0,
+    }
+}
+
+fn load32(offset: u64) -> Instruction<'static> {
+    Instruction::I32Load(mem(offset, 2))
+}
+
+fn store32(offset: u64) -> Instruction<'static> {
+    Instruction::I32Store(mem(offset, 2))
+}
+
+/// Static objects: string and integer literals and the constants the
+/// runtime needs, laid out in the data segment.
+struct Data {
+    bytes: Vec<u8>,
+}
+
+impl Data {
+    fn object(&mut self, object: &[u8]) -> i32 {
+        while !self.bytes.len().is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        let ptr = DATA_START as usize + self.bytes.len();
+        self.bytes.extend_from_slice(object);
+        ptr as i32
+    }
+
+    fn string(&mut self, value: &str) -> i32 {
+        let mut object = Vec::new();
+        object.extend_from_slice(&TAG_STRING.to_le_bytes());
+        object.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        object.extend_from_slice(value.as_bytes());
+        self.object(&object)
+    }
+
+    fn int(&mut self, value: i64) -> i32 {
+        let mut object = Vec::new();
+        object.extend_from_slice(&TAG_INT.to_le_bytes());
+        object.extend_from_slice(&0i32.to_le_bytes());
+        object.extend_from_slice(&value.to_le_bytes());
+        self.object(&object)
+    }
+
+    fn bool(&mut self, value: bool) -> i32 {
+        let mut object = Vec::new();
+        object.extend_from_slice(&TAG_BOOL.to_le_bytes());
+        object.extend_from_slice(&(value as i32).to_le_bytes());
+        self.object(&object)
+    }
+
+    fn end(&self) -> u32 {
+        DATA_START + self.bytes.len() as u32
+    }
+}
+
+/// Pointers to the static objects the runtime refers to.
+struct Constants {
+    true_value: i32,
+    false_value: i32,
+    true_string: i32,
+    false_string: i32,
+    closure_string: i32,
+    open_paren: i32,
+    comma: i32,
+    close_paren: i32,
+    space: i32,
+}
+
+/// Deduplicated function types.
+#[derive(Default)]
+struct Types {
+    types: Vec<(Vec<ValType>, Vec<ValType>)>,
+}
+
+impl Types {
+    fn get(&mut self, params: &[ValType], results: &[ValType]) -> u32 {
+        let ty = (params.to_vec(), results.to_vec());
+        match self.types.iter().position(|t| *t == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Type of a compiled function of `arity` parameters, which also
+    /// receives its closure.
+    fn closure(&mut self, arity: usize) -> u32 {
+        self.get(&vec![ValType::I32; arity + 1], &[ValType::I32])
+    }
+}
+
+/// A function under construction. Locals are declared up front in wasm,
+/// so the body is buffered until the function is complete.
+struct Body {
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+    locals: Vec<ValType>,
+    code: Vec<Instruction<'static>>,
+}
+
+impl Body {
+    fn new(params: &[ValType], results: &[ValType]) -> Body {
+        Body {
+            params: params.to_vec(),
+            results: results.to_vec(),
+            locals: Vec::new(),
+            code: Vec::new(),
+        }
+    }
+
+    fn local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        (self.params.len() + self.locals.len() - 1) as u32
+    }
+
+    fn emit(&mut self, instruction: Instruction<'static>) {
+        self.code.push(instruction);
+    }
+
+    fn emit_all(&mut self, instructions: impl IntoIterator<Item = Instruction<'static>>) {
+        self.code.extend(instructions);
+    }
+
+    /// Reports an error of `kind` at the span passed in locals `start` and
+    /// `end`, then traps.
+    fn fail(&mut self, kind: i32, start: u32, end: u32) {
+        self.emit_all([
+            Instruction::I32Const(kind),
+            Instruction::LocalGet(start),
+            Instruction::LocalGet(end),
+            Instruction::Call(ERROR_IMPORT),
+            Instruction::Unreachable,
+        ]);
+    }
+
+    /// Fails with a type mismatch unless the object in local `value` has
+    /// `tag`.
+    fn expect_tag(&mut self, value: u32, tag: i32, start: u32, end: u32) {
+        self.emit_all([
+            Instruction::LocalGet(value),
+            load32(0),
+            Instruction::I32Const(tag),
+            Instruction::I32Ne,
+            Instruction::If(BlockType::Empty),
+        ]);
+        self.fail(ERROR_TYPE_MISMATCH, start, end);
+        self.emit(Instruction::End);
+    }
+
+    fn finish(self) -> Function {
+        let mut function = Function::new_with_locals_types(self.locals);
+        for instruction in &self.code {
+            function.instruction(instruction);
+        }
+        function.instruction(&Instruction::End);
+        function
+    }
+}
+
+const I32: ValType = ValType::I32;
+const I64: ValType = ValType::I64;
+
+/// Runtime support functions, written directly in wasm.
+struct Runtime<'a> {
+    constants: &'a Constants,
+}
+
+impl Runtime<'_> {
+    fn functions(&self) -> Vec<Body> {
+        let functions = vec![
+            self.alloc(),
+            self.make_int(),
+            self.make_tuple(),
+            self.int(),
+            self.bool(),
+            self.check_bool(),
+            self.project(TUPLE_FIRST),
+            self.project(TUPLE_SECOND),
+            self.callee(),
+            self.int_to_string(),
+            self.to_string(),
+            self.concat(),
+            self.show(),
+            self.print(),
+            self.equal(),
+            self.string_equal(),
+            self.add(),
+            self.arithmetic(),
+            self.compare(),
+            self.binary(),
+        ];
+        assert_eq!(functions.len() as u32, RUNTIME_FUNCTIONS - ALLOC);
+        functions
+    }
+
+    /// `alloc(size) -> ptr`, growing the memory when the heap runs out.
+    fn alloc(&self) -> Body {
+        let mut f = Body::new(&[I32], &[I32]);
+        let ptr = f.local(I32);
+        f.emit_all([
+            Instruction::GlobalGet(HEAP_GLOBAL),
+            Instruction::LocalSet(ptr),
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(0),
+            Instruction::I32Add,
+            Instruction::I32Const(7),
+            Instruction::I32Add,
+            Instruction::I32Const(!7),
+            Instruction::I32And,
+            Instruction::GlobalSet(HEAP_GLOBAL),
+            Instruction::GlobalGet(HEAP_GLOBAL),
+            Instruction::MemorySize(0),
+            Instruction::I32Const(16),
+            Instruction::I32Shl,
+            Instruction::I32GtU,
+            Instruction::If(BlockType::Empty),
+            Instruction::GlobalGet(HEAP_GLOBAL),
+            Instruction::MemorySize(0),
+            Instruction::I32Const(16),
+            Instruction::I32Shl,
+            Instruction::I32Sub,
+            Instruction::I32Const(16),
+            Instruction::I32ShrU,
+            Instruction::I32Const(1),
+            Instruction::I32Add,
+            Instruction::MemoryGrow(0),
+            Instruction::Drop,
+            Instruction::End,
+            Instruction::LocalGet(ptr),
+        ]);
+        f
+    }
+
+    /// `make_int(value: i64) -> ptr`
+    fn make_int(&self) -> Body {
+        let mut f = Body::new(&[I64], &[I32]);
+        let ptr = f.local(I32);
+        f.emit_all([
+            Instruction::I32Const(16),
+            Instruction::Call(ALLOC),
+            Instruction::LocalTee(ptr),
+            Instruction::I32Const(TAG_INT),
+            store32(0),
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(0),
+            Instruction::I64Store(mem(INT_VALUE, 3)),
+            Instruction::LocalGet(ptr),
+        ]);
+        f
+    }
+
+    /// `make_tuple(first, second) -> ptr`
+    fn make_tuple(&self) -> Body {
+        let mut f = Body::new(&[I32, I32], &[I32]);
+        let ptr = f.local(I32);
+        f.emit_all([
+            Instruction::I32Const(12),
+            Instruction::Call(ALLOC),
+            Instruction::LocalTee(ptr),
+            Instruction::I32Const(TAG_TUPLE),
+            store32(0),
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(0),
+            store32(TUPLE_FIRST),
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(1),
+            store32(TUPLE_SECOND),
+            Instruction::LocalGet(ptr),
+        ]);
+        f
+    }
+
+    /// `int(value, start, end) -> i64`
+    fn int(&self) -> Body {
+        let mut f = Body::new(&[I32, I32, I32], &[I64]);
+        f.expect_tag(0, TAG_INT, 1, 2);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            Instruction::I64Load(mem(INT_VALUE, 3)),
+        ]);
+        f
+    }
+
+    /// `bool(value, start, end) -> i32`
+    fn bool(&self) -> Body {
+        let mut f = Body::new(&[I32, I32, I32], &[I32]);
+        f.expect_tag(0, TAG_BOOL, 1, 2);
+        f.emit_all([Instruction::LocalGet(0), load32(BOOL_VALUE)]);
+        f
+    }
+
+    /// `check_bool(value, start, end) -> value`
+    fn check_bool(&self) -> Body {
+        let mut f = Body::new(&[I32, I32, I32], &[I32]);
+        f.expect_tag(0, TAG_BOOL, 1, 2);
+        f.emit(Instruction::LocalGet(0));
+        f
+    }
+
+    /// `first` or `second`: `(tuple, start, end) -> element`
+    fn project(&self, offset: u64) -> Body {
+        let mut f = Body::new(&[I32, I32, I32], &[I32]);
+        f.expect_tag(0, TAG_TUPLE, 1, 2);
+        f.emit_all([Instruction::LocalGet(0), load32(offset)]);
+        f
+    }
+
+    /// `callee(closure, argc, start, end) -> table index`, checking that
+    /// the value is a closure taking `argc` arguments.
+    fn callee(&self) -> Body {
+        let mut f = Body::new(&[I32, I32, I32, I32], &[I32]);
+        f.expect_tag(0, TAG_CLOSURE, 2, 3);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            load32(CLOSURE_ARITY),
+            Instruction::LocalGet(1),
+            Instruction::I32Ne,
+            Instruction::If(BlockType::Empty),
+        ]);
+        f.fail(ERROR_ARITY_MISMATCH, 2, 3);
+        f.emit_all([
+            Instruction::End,
+            Instruction::LocalGet(0),
+            load32(CLOSURE_FUNCTION),
+        ]);
+        f
+    }
+
+    /// `int_to_string(value: i64) -> string`. Digits are written backwards
+    /// at the end of a buffer and then moved to the front.
+    fn int_to_string(&self) -> Body {
+        const END: i32 = 8 + 20;
+        let mut f = Body::new(&[I64], &[I32]);
+        let ptr = f.local(I32);
+        let pos = f.local(I32);
+        let magnitude = f.local(I64);
+        f.emit_all([
+            Instruction::I32Const(END),
+            Instruction::Call(ALLOC),
+            Instruction::LocalSet(ptr),
+            Instruction::I32Const(END),
+            Instruction::LocalSet(pos),
+            // The magnitude of i64::MIN only fits as unsigned.
+            Instruction::I64Const(0),
+            Instruction::LocalGet(0),
+            Instruction::I64Sub,
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(0),
+            Instruction::I64Const(0),
+            Instruction::I64LtS,
+            Instruction::Select,
+            Instruction::LocalSet(magnitude),
+            Instruction::Loop(BlockType::Empty),
+            Instruction::LocalGet(pos),
+            Instruction::I32Const(1),
+            Instruction::I32Sub,
+            Instruction::LocalSet(pos),
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(pos),
+            Instruction::I32Add,
+            Instruction::LocalGet(magnitude),
+            Instruction::I64Const(10),
+            Instruction::I64RemU,
+            Instruction::I32WrapI64,
+            Instruction::I32Const(b'0' as i32),
+            Instruction::I32Add,
+            Instruction::I32Store8(mem(0, 0)),
+            Instruction::LocalGet(magnitude),
+            Instruction::I64Const(10),
+            Instruction::I64DivU,
+            Instruction::LocalTee(magnitude),
+            Instruction::I64Const(0),
+            Instruction::I64Ne,
+            Instruction::BrIf(0),
+            Instruction::End,
+            Instruction::LocalGet(0),
+            Instruction::I64Const(0),
+            Instruction::I64LtS,
+            Instruction::If(BlockType::Empty),
+            Instruction::LocalGet(pos),
+            Instruction::I32Const(1),
+            Instruction::I32Sub,
+            Instruction::LocalSet(pos),
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(pos),
+            Instruction::I32Add,
+            Instruction::I32Const(b'-' as i32),
+            Instruction::I32Store8(mem(0, 0)),
+            Instruction::End,
+            // Move the digits right after the header.
+            Instruction::LocalGet(ptr),
+            Instruction::I32Const(STRING_BYTES as i32),
+            Instruction::I32Add,
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(pos),
+            Instruction::I32Add,
+            Instruction::I32Const(END),
+            Instruction::LocalGet(pos),
+            Instruction::I32Sub,
+            Instruction::MemoryCopy {
+                src_mem: 0,
+                dst_mem: 0,
+            },
+            Instruction::LocalGet(ptr),
+            Instruction::I32Const(TAG_STRING),
+            store32(0),
+            Instruction::LocalGet(ptr),
+            Instruction::I32Const(END),
+            Instruction::LocalGet(pos),
+            Instruction::I32Sub,
+            store32(STRING_LEN),
+            Instruction::LocalGet(ptr),
+        ]);
+        f
+    }
+
+    /// `to_string(value, start, end) -> string` for the operands of a
+    /// string concatenation.
+    fn to_string(&self) -> Body {
+        let mut f = Body::new(&[I32, I32, I32], &[I32]);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            load32(0),
+            Instruction::I32Const(TAG_STRING),
+            Instruction::I32Eq,
+            Instruction::If(BlockType::Empty),
+            Instruction::LocalGet(0),
+            Instruction::Return,
+            Instruction::End,
+        ]);
+        f.expect_tag(0, TAG_INT, 1, 2);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            Instruction::I64Load(mem(INT_VALUE, 3)),
+            Instruction::Call(INT_TO_STRING),
+        ]);
+        f
+    }
+
+    /// `concat(left, right) -> string`
+    fn concat(&self) -> Body {
+        let mut f = Body::new(&[I32, I32], &[I32]);
+        let ptr = f.local(I32);
+        let left_len = f.local(I32);
+        let right_len = f.local(I32);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            load32(STRING_LEN),
+            Instruction::LocalSet(left_len),
+            Instruction::LocalGet(1),
+            load32(STRING_LEN),
+            Instruction::LocalSet(right_len),
+            Instruction::LocalGet(left_len),
+            Instruction::LocalGet(right_len),
+            Instruction::I32Add,
+            Instruction::I32Const(STRING_BYTES as i32),
+            Instruction::I32Add,
+            Instruction::Call(ALLOC),
+            Instruction::LocalTee(ptr),
+            Instruction::I32Const(TAG_STRING),
+            store32(0),
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(left_len),
+            Instruction::LocalGet(right_len),
+            Instruction::I32Add,
+            store32(STRING_LEN),
+            Instruction::LocalGet(ptr),
+            Instruction::I32Const(STRING_BYTES as i32),
+            Instruction::I32Add,
+            Instruction::LocalGet(0),
+            Instruction::I32Const(STRING_BYTES as i32),
+            Instruction::I32Add,
+            Instruction::LocalGet(left_len),
+            Instruction::MemoryCopy {
+                src_mem: 0,
+                dst_mem: 0,
+            },
+            Instruction::LocalGet(ptr),
+            Instruction::I32Const(STRING_BYTES as i32),
+            Instruction::I32Add,
+            Instruction::LocalGet(left_len),
+            Instruction::I32Add,
+            Instruction::LocalGet(1),
+            Instruction::I32Const(STRING_BYTES as i32),
+            Instruction::I32Add,
+            Instruction::LocalGet(right_len),
+            Instruction::MemoryCopy {
+                src_mem: 0,
+                dst_mem: 0,
+            },
+            Instruction::LocalGet(ptr),
+        ]);
+        f
+    }
+
+    /// `show(value) -> string`, formatting values like `print` does.
+    fn show(&self) -> Body {
+        let c = self.constants;
+        let mut f = Body::new(&[I32], &[I32]);
+        let tag = f.local(I32);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            load32(0),
+            Instruction::LocalSet(tag),
+        ]);
+        let cases: [(i32, Vec<Instruction<'static>>); 4] = [
+            (
+                TAG_INT,
+                vec![
+                    Instruction::LocalGet(0),
+                    Instruction::I64Load(mem(INT_VALUE, 3)),
+                    Instruction::Call(INT_TO_STRING),
+                ],
+            ),
+            (
+                TAG_BOOL,
+                vec![
+                    Instruction::I32Const(c.true_string),
+                    Instruction::I32Const(c.false_string),
+                    Instruction::LocalGet(0),
+                    load32(BOOL_VALUE),
+                    Instruction::Select,
+                ],
+            ),
+            (TAG_STRING, vec![Instruction::LocalGet(0)]),
+            (TAG_CLOSURE, vec![Instruction::I32Const(c.closure_string)]),
+        ];
+        for (case_tag, code) in cases {
+            f.emit_all([
+                Instruction::LocalGet(tag),
+                Instruction::I32Const(case_tag),
+                Instruction::I32Eq,
+                Instruction::If(BlockType::Empty),
+            ]);
+            f.emit_all(code);
+            f.emit_all([Instruction::Return, Instruction::End]);
+        }
+        // A tuple: "(" + show(first) + ", " + show(second) + ")"
+        f.emit_all([
+            Instruction::I32Const(c.open_paren),
+            Instruction::LocalGet(0),
+            load32(TUPLE_FIRST),
+            Instruction::Call(SHOW),
+            Instruction::Call(CONCAT),
+            Instruction::I32Const(c.comma),
+            Instruction::Call(CONCAT),
+            Instruction::LocalGet(0),
+            load32(TUPLE_SECOND),
+            Instruction::Call(SHOW),
+            Instruction::Call(CONCAT),
+            Instruction::I32Const(c.close_paren),
+            Instruction::Call(CONCAT),
+        ]);
+        f
+    }
+
+    /// `print(value) -> value`
+    fn print(&self) -> Body {
+        let mut f = Body::new(&[I32], &[I32]);
+        let text = f.local(I32);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            Instruction::Call(SHOW),
+            Instruction::LocalTee(text),
+            Instruction::I32Const(STRING_BYTES as i32),
+            Instruction::I32Add,
+            Instruction::LocalGet(text),
+            load32(STRING_LEN),
+            Instruction::Call(PRINT_IMPORT),
+            Instruction::LocalGet(0),
+        ]);
+        f
+    }
+
+    /// `equal(left, right) -> i32`: structural equality, false across
+    /// types and identity for closures.
+    fn equal(&self) -> Body {
+        let mut f = Body::new(&[I32, I32], &[I32]);
+        let tag = f.local(I32);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            load32(0),
+            Instruction::LocalTee(tag),
+            Instruction::LocalGet(1),
+            load32(0),
+            Instruction::I32Ne,
+            Instruction::If(BlockType::Empty),
+            Instruction::I32Const(0),
+            Instruction::Return,
+            Instruction::End,
+        ]);
+        let cases: [(i32, Vec<Instruction<'static>>); 4] = [
+            (
+                TAG_INT,
+                vec![
+                    Instruction::LocalGet(0),
+                    Instruction::I64Load(mem(INT_VALUE, 3)),
+                    Instruction::LocalGet(1),
+                    Instruction::I64Load(mem(INT_VALUE, 3)),
+                    Instruction::I64Eq,
+                ],
+            ),
+            (
+                TAG_BOOL,
+                vec![
+                    Instruction::LocalGet(0),
+                    load32(BOOL_VALUE),
+                    Instruction::LocalGet(1),
+                    load32(BOOL_VALUE),
+                    Instruction::I32Eq,
+                ],
+            ),
+            (
+                TAG_STRING,
+                vec![
+                    Instruction::LocalGet(0),
+                    Instruction::LocalGet(1),
+                    Instruction::Call(STRING_EQUAL),
+                ],
+            ),
+            (
+                TAG_TUPLE,
+                vec![
+                    Instruction::LocalGet(0),
+                    load32(TUPLE_FIRST),
+                    Instruction::LocalGet(1),
+                    load32(TUPLE_FIRST),
+                    Instruction::Call(EQUAL),
+                    Instruction::If(BlockType::Result(I32)),
+                    Instruction::LocalGet(0),
+                    load32(TUPLE_SECOND),
+                    Instruction::LocalGet(1),
+                    load32(TUPLE_SECOND),
+                    Instruction::Call(EQUAL),
+                    Instruction::Else,
+                    Instruction::I32Const(0),
+                    Instruction::End,
+                ],
+            ),
+        ];
+        for (case_tag, code) in cases {
+            f.emit_all([
+                Instruction::LocalGet(tag),
+                Instruction::I32Const(case_tag),
+                Instruction::I32Eq,
+                Instruction::If(BlockType::Empty),
+            ]);
+            f.emit_all(code);
+            f.emit_all([Instruction::Return, Instruction::End]);
+        }
+        f.emit_all([
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::I32Eq,
+        ]);
+        f
+    }
+
+    /// `string_equal(left, right) -> i32`
+    fn string_equal(&self) -> Body {
+        let mut f = Body::new(&[I32, I32], &[I32]);
+        let index = f.local(I32);
+        let len = f.local(I32);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            load32(STRING_LEN),
+            Instruction::LocalTee(len),
+            Instruction::LocalGet(1),
+            load32(STRING_LEN),
+            Instruction::I32Ne,
+            Instruction::If(BlockType::Empty),
+            Instruction::I32Const(0),
+            Instruction::Return,
+            Instruction::End,
+            Instruction::Block(BlockType::Empty),
+            Instruction::Loop(BlockType::Empty),
+            Instruction::LocalGet(index),
+            Instruction::LocalGet(len),
+            Instruction::I32GeU,
+            Instruction::BrIf(1),
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(index),
+            Instruction::I32Add,
+            Instruction::I32Load8U(mem(STRING_BYTES, 0)),
+            Instruction::LocalGet(1),
+            Instruction::LocalGet(index),
+            Instruction::I32Add,
+            Instruction::I32Load8U(mem(STRING_BYTES, 0)),
+            Instruction::I32Ne,
+            Instruction::If(BlockType::Empty),
+            Instruction::I32Const(0),
+            Instruction::Return,
+            Instruction::End,
+            Instruction::LocalGet(index),
+            Instruction::I32Const(1),
+            Instruction::I32Add,
+            Instruction::LocalSet(index),
+            Instruction::Br(0),
+            Instruction::End,
+            Instruction::End,
+            Instruction::I32Const(1),
+        ]);
+        f
+    }
+
+    /// `add(left, right, start, end) -> value`: integer addition or string
+    /// concatenation.
+    fn add(&self) -> Body {
+        let mut f = Body::new(&[I32, I32, I32, I32], &[I32]);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            load32(0),
+            Instruction::LocalGet(1),
+            load32(0),
+            Instruction::I32Or,
+            Instruction::I32Eqz,
+            Instruction::If(BlockType::Empty),
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::I32Const(BinaryOp::Add as i32),
+            Instruction::LocalGet(2),
+            Instruction::LocalGet(3),
+            Instruction::Call(ARITHMETIC),
+            Instruction::Return,
+            Instruction::End,
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(2),
+            Instruction::LocalGet(3),
+            Instruction::Call(TO_STRING),
+            Instruction::LocalGet(1),
+            Instruction::LocalGet(2),
+            Instruction::LocalGet(3),
+            Instruction::Call(TO_STRING),
+            Instruction::Call(CONCAT),
+        ]);
+        f
+    }
+
+    /// `arithmetic(left, right, op, start, end) -> int` with overflow and
+    /// division by zero checks. `op` is a `BinaryOp` discriminant.
+    fn arithmetic(&self) -> Body {
+        let mut f = Body::new(&[I32, I32, I32, I32, I32], &[I32]);
+        let (op, start, end) = (2, 3, 4);
+        let x = f.local(I64);
+        let y = f.local(I64);
+        let r = f.local(I64);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(start),
+            Instruction::LocalGet(end),
+            Instruction::Call(INT),
+            Instruction::LocalSet(x),
+            Instruction::LocalGet(1),
+            Instruction::LocalGet(start),
+            Instruction::LocalGet(end),
+            Instruction::Call(INT),
+            Instruction::LocalSet(y),
+        ]);
+
+        let overflow_if = |f: &mut Body, condition: Vec<Instruction<'static>>| {
+            f.emit_all(condition);
+            f.emit(Instruction::If(BlockType::Empty));
+            f.fail(ERROR_INTEGER_OVERFLOW, start, end);
+            f.emit(Instruction::End);
+        };
+        let case = |f: &mut Body, case_op: BinaryOp| {
+            f.emit_all([
+                Instruction::LocalGet(op),
+                Instruction::I32Const(case_op as i32),
+                Instruction::I32Eq,
+                Instruction::If(BlockType::Empty),
+            ]);
+        };
+        let finish = |f: &mut Body| {
+            f.emit_all([
+                Instruction::LocalGet(r),
+                Instruction::Call(MAKE_INT),
+                Instruction::Return,
+                Instruction::End,
+            ]);
+        };
+
+        case(&mut f, BinaryOp::Add);
+        f.emit_all([
+            Instruction::LocalGet(x),
+            Instruction::LocalGet(y),
+            Instruction::I64Add,
+            Instruction::LocalSet(r),
+        ]);
+        // Overflow when both operands have a sign different from the result.
+        overflow_if(
+            &mut f,
+            vec![
+                Instruction::LocalGet(x),
+                Instruction::LocalGet(r),
+                Instruction::I64Xor,
+                Instruction::LocalGet(y),
+                Instruction::LocalGet(r),
+                Instruction::I64Xor,
+                Instruction::I64And,
+                Instruction::I64Const(0),
+                Instruction::I64LtS,
+            ],
+        );
+        finish(&mut f);
+
+        case(&mut f, BinaryOp::Sub);
+        f.emit_all([
+            Instruction::LocalGet(x),
+            Instruction::LocalGet(y),
+            Instruction::I64Sub,
+            Instruction::LocalSet(r),
+        ]);
+        overflow_if(
+            &mut f,
+            vec![
+                Instruction::LocalGet(x),
+                Instruction::LocalGet(y),
+                Instruction::I64Xor,
+                Instruction::LocalGet(x),
+                Instruction::LocalGet(r),
+                Instruction::I64Xor,
+                Instruction::I64And,
+                Instruction::I64Const(0),
+                Instruction::I64LtS,
+            ],
+        );
+        finish(&mut f);
+
+        case(&mut f, BinaryOp::Mul);
+        f.emit_all([
+            Instruction::LocalGet(x),
+            Instruction::LocalGet(y),
+            Instruction::I64Mul,
+            Instruction::LocalSet(r),
+        ]);
+        // Overflow unless dividing the result by `x` gives `y` back; `x` of
+        // -1 is checked separately because `i64::MIN / -1` traps.
+        overflow_if(
+            &mut f,
+            vec![
+                Instruction::LocalGet(x),
+                Instruction::I64Const(-1),
+                Instruction::I64Eq,
+                Instruction::If(BlockType::Result(I32)),
+                Instruction::LocalGet(y),
+                Instruction::I64Const(i64::MIN),
+                Instruction::I64Eq,
+                Instruction::Else,
+                Instruction::LocalGet(x),
+                Instruction::I64Eqz,
+                Instruction::If(BlockType::Result(I32)),
+                Instruction::I32Const(0),
+                Instruction::Else,
+                Instruction::LocalGet(r),
+                Instruction::LocalGet(x),
+                Instruction::I64DivS,
+                Instruction::LocalGet(y),
+                Instruction::I64Ne,
+                Instruction::End,
+                Instruction::End,
+            ],
+        );
+        finish(&mut f);
+
+        // Division and remainder share their checks.
+        f.emit_all([Instruction::LocalGet(y), Instruction::I64Eqz]);
+        f.emit(Instruction::If(BlockType::Empty));
+        f.fail(ERROR_DIVISION_BY_ZERO, start, end);
+        f.emit(Instruction::End);
+        overflow_if(
+            &mut f,
+            vec![
+                Instruction::LocalGet(x),
+                Instruction::I64Const(i64::MIN),
+                Instruction::I64Eq,
+                Instruction::LocalGet(y),
+                Instruction::I64Const(-1),
+                Instruction::I64Eq,
+                Instruction::I32And,
+            ],
+        );
+        case(&mut f, BinaryOp::Div);
+        f.emit_all([
+            Instruction::LocalGet(x),
+            Instruction::LocalGet(y),
+            Instruction::I64DivS,
+            Instruction::LocalSet(r),
+        ]);
+        finish(&mut f);
+        f.emit_all([
+            Instruction::LocalGet(x),
+            Instruction::LocalGet(y),
+            Instruction::I64RemS,
+            Instruction::Call(MAKE_INT),
+        ]);
+        f
+    }
+
+    /// `compare(left, right, op, start, end) -> bool` for `<`, `>`, `<=`
+    /// and `>=`.
+    fn compare(&self) -> Body {
+        let c = self.constants;
+        let mut f = Body::new(&[I32, I32, I32, I32, I32], &[I32]);
+        let (op, start, end) = (2, 3, 4);
+        let x = f.local(I64);
+        let y = f.local(I64);
+        f.emit_all([
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(start),
+            Instruction::LocalGet(end),
+            Instruction::Call(INT),
+            Instruction::LocalSet(x),
+            Instruction::LocalGet(1),
+            Instruction::LocalGet(start),
+            Instruction::LocalGet(end),
+            Instruction::Call(INT),
+            Instruction::LocalSet(y),
+        ]);
+        let ops = [
+            (BinaryOp::Lt, Instruction::I64LtS),
+            (BinaryOp::Gt, Instruction::I64GtS),
+            (BinaryOp::Lte, Instruction::I64LeS),
+        ];
+        for (case_op, instruction) in ops {
+            f.emit_all([
+                Instruction::LocalGet(op),
+                Instruction::I32Const(case_op as i32),
+                Instruction::I32Eq,
+                Instruction::If(BlockType::Empty),
+                Instruction::I32Const(c.true_value),
+                Instruction::I32Const(c.false_value),
+                Instruction::LocalGet(x),
+                Instruction::LocalGet(y),
+                instruction,
+                Instruction::Select,
+                Instruction::Return,
+                Instruction::End,
+            ]);
+        }
+        f.emit_all([
+            Instruction::I32Const(c.true_value),
+            Instruction::I32Const(c.false_value),
+            Instruction::LocalGet(x),
+            Instruction::LocalGet(y),
+            Instruction::I64GeS,
+            Instruction::Select,
+        ]);
+        f
+    }
+
+    /// `binary(left, right, op, start, end) -> value` for every operator
+    /// except `&&` and `||`, which are compiled inline.
+    fn binary(&self) -> Body {
+        let c = self.constants;
+        let mut f = Body::new(&[I32, I32, I32, I32, I32], &[I32]);
+        let op = 2;
+        let args = |callee: u32| {
+            vec![
+                Instruction::LocalGet(0),
+                Instruction::LocalGet(1),
+                Instruction::LocalGet(2),
+                Instruction::LocalGet(3),
+                Instruction::LocalGet(4),
+                Instruction::Call(callee),
+            ]
+        };
+        let equal = |negate: bool| {
+            let mut code = vec![
+                Instruction::I32Const(c.true_value),
+                Instruction::I32Const(c.false_value),
+                Instruction::LocalGet(0),
+                Instruction::LocalGet(1),
+                Instruction::Call(EQUAL),
+            ];
+            if negate {
+                code.push(Instruction::I32Eqz);
+            }
+            code.push(Instruction::Select);
+            code
+        };
+        let cases = [
+            (
+                BinaryOp::Add,
+                vec![
+                    Instruction::LocalGet(0),
+                    Instruction::LocalGet(1),
+                    Instruction::LocalGet(3),
+                    Instruction::LocalGet(4),
+                    Instruction::Call(ADD),
+                ],
+            ),
+            (BinaryOp::Eq, equal(false)),
+            (BinaryOp::Neq, equal(true)),
+            (BinaryOp::Lt, args(COMPARE)),
+            (BinaryOp::Gt, args(COMPARE)),
+            (BinaryOp::Lte, args(COMPARE)),
+            (BinaryOp::Gte, args(COMPARE)),
+        ];
+        for (case_op, code) in cases {
+            f.emit_all([
+                Instruction::LocalGet(op),
+                Instruction::I32Const(case_op as i32),
+                Instruction::I32Eq,
+                Instruction::If(BlockType::Empty),
+            ]);
+            f.emit_all(code);
+            f.emit_all([Instruction::Return, Instruction::End]);
+        }
+        f.emit_all(args(ARITHMETIC));
+        f
+    }
+}
+
+/// How a function reaches a variable.
+#[derive(Clone, Copy)]
+enum Access {
+    Local(u32),
+    Capture(u32),
+    /// The closure of the function itself, for `let` recursion.
+    Callee,
+}
+
+struct Scope {
+    name: Option<Symbol>,
+    locals: Vec<(Symbol, u32)>,
+    captures: Vec<(Symbol, Access)>,
+    body: Body,
+}
+
+struct Compiler {
+    scopes: Vec<Scope>,
+    /// Compiled program functions by table index, with their arity.
+    functions: Vec<Option<(usize, Body)>>,
+    types: Types,
+    data: Data,
+    constants: Constants,
+}
+
+impl Compiler {
+    fn body(&mut self) -> &mut Body {
+        &mut self.scopes.last_mut().unwrap().body
+    }
+
+    fn emit(&mut self, instruction: Instruction<'static>) {
+        self.body().emit(instruction);
+    }
+
+    fn location(&mut self, location: &Location) {
+        self.emit(Instruction::I32Const(location.start as i32));
+        self.emit(Instruction::I32Const(location.end as i32));
+    }
+
+    fn resolve(&mut self, level: usize, name: Symbol) -> Option<Access> {
+        let scope = &self.scopes[level];
+        if let Some((_, local)) = scope.locals.iter().rev().find(|(n, _)| *n == name) {
+            return Some(Access::Local(*local));
+        }
+        if let Some(index) = scope.captures.iter().position(|(n, _)| *n == name) {
+            return Some(Access::Capture(index as u32));
+        }
+        if scope.name == Some(name) {
+            return Some(Access::Callee);
+        }
+        if level == 0 {
+            return None;
+        }
+        let access = self.resolve(level - 1, name)?;
+        let captures = &mut self.scopes[level].captures;
+        captures.push((name, access));
+        Some(Access::Capture(captures.len() as u32 - 1))
+    }
+
+    fn access(&mut self, access: Access) {
+        match access {
+            Access::Local(local) => self.emit(Instruction::LocalGet(local)),
+            Access::Capture(index) => {
+                self.emit(Instruction::LocalGet(0));
+                self.emit(load32(CLOSURE_CAPTURES + 4 * index as u64));
+            }
+            Access::Callee => self.emit(Instruction::LocalGet(0)),
+        }
+    }
+
+    fn compile(&mut self, term: &Term, tail: bool) {
+        match term {
+            Term::Int { value, .. } => {
+                let ptr = self.data.int(*value);
+                self.emit(Instruction::I32Const(ptr));
+            }
+            Term::Str { value, .. } => {
+                let ptr = self.data.string(value);
+                self.emit(Instruction::I32Const(ptr));
+            }
+            Term::Bool { value, .. } => {
+                let ptr = if *value {
+                    self.constants.true_value
+                } else {
+                    self.constants.false_value
+                };
+                self.emit(Instruction::I32Const(ptr));
+            }
+            Term::Var { text, location, .. } => {
+                let level = self.scopes.len() - 1;
+                match self.resolve(level, *text) {
+                    Some(access) => self.access(access),
+                    None => {
+                        self.emit(Instruction::I32Const(ERROR_UNBOUND_VARIABLE));
+                        self.location(location);
+                        self.emit(Instruction::Call(ERROR_IMPORT));
+                        self.emit(Instruction::Unreachable);
+                    }
+                }
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.compile(condition, false);
+                self.location(condition.location());
+                self.emit(Instruction::Call(BOOL));
+                self.emit(Instruction::If(BlockType::Result(I32)));
+                self.compile(then, tail);
+                self.emit(Instruction::Else);
+                self.compile(otherwise, tail);
+                self.emit(Instruction::End);
+            }
+            Term::Let {
+                name, value, next, ..
+            } => {
+                match value.as_ref() {
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => self.closure(Some(name.text), parameters, body),
+                    _ => self.compile(value, false),
+                }
+                let local = self.body().local(I32);
+                self.emit(Instruction::LocalSet(local));
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .locals
+                    .push((name.text, local));
+                self.compile(next, tail);
+                self.scopes.last_mut().unwrap().locals.pop();
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                next,
+                ..
+            } => {
+                self.compile(value, false);
+                let tuple = self.body().local(I32);
+                self.emit(Instruction::LocalSet(tuple));
+                let depth = self.scopes.last().unwrap().locals.len();
+                for (name, function) in [(first, FIRST), (second, SECOND)] {
+                    self.emit(Instruction::LocalGet(tuple));
+                    self.location(value.location());
+                    self.emit(Instruction::Call(function));
+                    let local = self.body().local(I32);
+                    self.emit(Instruction::LocalSet(local));
+                    self.scopes
+                        .last_mut()
+                        .unwrap()
+                        .locals
+                        .push((name.text, local));
+                }
+                self.compile(next, tail);
+                self.scopes.last_mut().unwrap().locals.truncate(depth);
+            }
+            Term::Binary {
+                lhs,
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                rhs,
+                location,
+            } => {
+                let (true_value, false_value) =
+                    (self.constants.true_value, self.constants.false_value);
+                self.compile(lhs, false);
+                self.location(location);
+                self.emit(Instruction::Call(BOOL));
+                self.emit(Instruction::If(BlockType::Result(I32)));
+                if let BinaryOp::Or = op {
+                    self.emit(Instruction::I32Const(true_value));
+                    self.emit(Instruction::Else);
+                }
+                self.compile(rhs, false);
+                self.location(location);
+                self.emit(Instruction::Call(CHECK_BOOL));
+                if let BinaryOp::And = op {
+                    self.emit(Instruction::Else);
+                    self.emit(Instruction::I32Const(false_value));
+                }
+                self.emit(Instruction::End);
+            }
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => {
+                self.compile(lhs, false);
+                self.compile(rhs, false);
+                self.emit(Instruction::I32Const(op.clone() as i32));
+                self.location(location);
+                self.emit(Instruction::Call(BINARY));
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                let closure = self.body().local(I32);
+                let function = self.body().local(I32);
+                self.compile(callee, false);
+                self.emit(Instruction::LocalTee(closure));
+                self.emit(Instruction::I32Const(arguments.len() as i32));
+                self.location(location);
+                self.emit(Instruction::Call(CALLEE));
+                self.emit(Instruction::LocalSet(function));
+                self.emit(Instruction::LocalGet(closure));
+                for argument in arguments {
+                    self.compile(argument, false);
+                }
+                self.emit(Instruction::LocalGet(function));
+                let type_index = self.types.closure(arguments.len());
+                self.emit(if tail {
+                    Instruction::ReturnCallIndirect {
+                        type_index,
+                        table_index: 0,
+                    }
+                } else {
+                    Instruction::CallIndirect {
+                        type_index,
+                        table_index: 0,
+                    }
+                });
+            }
+            Term::Function {
+                parameters, value, ..
+            } => self.closure(None, parameters, value),
+            Term::Tuple { first, second, .. } => {
+                self.compile(first, false);
+                self.compile(second, false);
+                self.emit(Instruction::Call(MAKE_TUPLE));
+            }
+            Term::First { value, .. } => {
+                self.compile(value, false);
+                self.location(value.location());
+                self.emit(Instruction::Call(FIRST));
+            }
+            Term::Second { value, .. } => {
+                self.compile(value, false);
+                self.location(value.location());
+                self.emit(Instruction::Call(SECOND));
+            }
+            Term::Print { values, .. } => match values.as_slice() {
+                [value] => {
+                    self.compile(value, false);
+                    self.emit(Instruction::Call(PRINT));
+                }
+                // Several values are shown and joined with spaces into one
+                // line, leaving the last value as the result.
+                values => {
+                    let text = self.body().local(I32);
+                    let last = self.body().local(I32);
+                    for (i, value) in values.iter().enumerate() {
+                        if i > 0 {
+                            self.emit(Instruction::LocalGet(text));
+                            self.emit(Instruction::I32Const(self.constants.space));
+                            self.emit(Instruction::Call(CONCAT));
+                        }
+                        self.compile(value, false);
+                        self.emit(Instruction::LocalTee(last));
+                        self.emit(Instruction::Call(SHOW));
+                        if i > 0 {
+                            self.emit(Instruction::Call(CONCAT));
+                        }
+                        self.emit(Instruction::LocalSet(text));
+                    }
+                    self.body().emit_all([
+                        Instruction::LocalGet(text),
+                        Instruction::I32Const(STRING_BYTES as i32),
+                        Instruction::I32Add,
+                        Instruction::LocalGet(text),
+                        load32(STRING_LEN),
+                        Instruction::Call(PRINT_IMPORT),
+                        Instruction::LocalGet(last),
+                    ]);
+                }
+            },
+            Term::Match {
+                value,
+                arms,
+                location,
+            } => {
+                self.compile(value, false);
+                let scrutinee = self.body().local(I32);
+                self.emit(Instruction::LocalSet(scrutinee));
+                for arm in arms {
+                    let mut bindings = Vec::new();
+                    self.pattern(&arm.pattern, scrutinee, &mut Vec::new(), &mut bindings);
+                    self.emit(Instruction::If(BlockType::Result(I32)));
+                    let depth = self.scopes.last().unwrap().locals.len();
+                    for (name, path) in bindings {
+                        self.load(scrutinee, &path);
+                        let local = self.body().local(I32);
+                        self.emit(Instruction::LocalSet(local));
+                        self.scopes.last_mut().unwrap().locals.push((name, local));
+                    }
+                    self.compile(&arm.value, tail);
+                    self.scopes.last_mut().unwrap().locals.truncate(depth);
+                    self.emit(Instruction::Else);
+                }
+                self.emit(Instruction::I32Const(ERROR_NO_MATCH));
+                self.location(location);
+                self.emit(Instruction::Call(ERROR_IMPORT));
+                self.emit(Instruction::Unreachable);
+                for _ in arms {
+                    self.emit(Instruction::End);
+                }
+            }
+            Term::Record { location, .. } | Term::Field { location, .. } => {
+                self.emit(Instruction::I32Const(ERROR_UNSUPPORTED_RECORD));
+                self.location(location);
+                self.emit(Instruction::Call(ERROR_IMPORT));
+                self.emit(Instruction::Unreachable);
+            }
+            Term::Ref { location, .. }
+            | Term::Deref { location, .. }
+            | Term::Assign { location, .. } => {
+                self.emit(Instruction::I32Const(ERROR_UNSUPPORTED_REF));
+                self.location(location);
+                self.emit(Instruction::Call(ERROR_IMPORT));
+                self.emit(Instruction::Unreachable);
+            }
+        }
+    }
+
+    /// Pushes the part of the value in `local` reached by following the
+    /// tuple fields at the offsets in `path`.
+    fn load(&mut self, local: u32, path: &[u64]) {
+        self.emit(Instruction::LocalGet(local));
+        for offset in path {
+            self.emit(load32(*offset));
+        }
+    }
+
+    /// Pushes whether the part of the value in `local` at `path` matches
+    /// `pattern`, collecting the paths the names of the pattern bind to.
+    fn pattern(
+        &mut self,
+        pattern: &Pattern,
+        local: u32,
+        path: &mut Vec<u64>,
+        bindings: &mut Vec<(Symbol, Vec<u64>)>,
+    ) {
+        let literal = match pattern {
+            Pattern::Wildcard { .. } => None,
+            Pattern::Var { text, .. } => {
+                bindings.push((*text, path.clone()));
+                None
+            }
+            Pattern::Int { value, .. } => Some(self.data.int(*value)),
+            Pattern::Str { value, .. } => Some(self.data.string(value)),
+            Pattern::Bool { value: true, .. } => Some(self.constants.true_value),
+            Pattern::Bool { value: false, .. } => Some(self.constants.false_value),
+            Pattern::Tuple { first, second, .. } => {
+                self.load(local, path);
+                self.emit(load32(0));
+                self.emit(Instruction::I32Const(TAG_TUPLE));
+                self.emit(Instruction::I32Eq);
+                self.emit(Instruction::If(BlockType::Result(I32)));
+                path.push(TUPLE_FIRST);
+                self.pattern(first, local, path, bindings);
+                path.pop();
+                path.push(TUPLE_SECOND);
+                self.pattern(second, local, path, bindings);
+                path.pop();
+                self.emit(Instruction::I32And);
+                self.emit(Instruction::Else);
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::End);
+                return;
+            }
+        };
+        match literal {
+            Some(ptr) => {
+                self.load(local, path);
+                self.emit(Instruction::I32Const(ptr));
+                self.emit(Instruction::Call(EQUAL));
+            }
+            None => self.emit(Instruction::I32Const(1)),
+        }
+    }
+
+    /// Compiles a function and emits the creation of its closure.
+    fn closure(&mut self, name: Option<Symbol>, parameters: &[Parameter], body: &Term) {
+        let (index, captures) = self.function(name, parameters, body);
+        let ptr = self.body().local(I32);
+        let size = CLOSURE_CAPTURES as i32 + 4 * captures.len() as i32;
+        self.body().emit_all([
+            Instruction::I32Const(size),
+            Instruction::Call(ALLOC),
+            Instruction::LocalTee(ptr),
+            Instruction::I32Const(TAG_CLOSURE),
+            store32(0),
+            Instruction::LocalGet(ptr),
+            Instruction::I32Const(index as i32),
+            store32(CLOSURE_FUNCTION),
+            Instruction::LocalGet(ptr),
+            Instruction::I32Const(parameters.len() as i32),
+            store32(CLOSURE_ARITY),
+        ]);
+        for (i, access) in captures.into_iter().enumerate() {
+            self.emit(Instruction::LocalGet(ptr));
+            self.access(access);
+            self.emit(store32(CLOSURE_CAPTURES + 4 * i as u64));
+        }
+        self.emit(Instruction::LocalGet(ptr));
+    }
+
+    /// Compiles a function body and returns its table index along with how
+    /// to reach, from the enclosing function, each value it captures.
+    fn function(
+        &mut self,
+        name: Option<Symbol>,
+        parameters: &[Parameter],
+        body: &Term,
+    ) -> (usize, Vec<Access>) {
+        let index = self.functions.len();
+        self.functions.push(None);
+        self.scopes.push(Scope {
+            name,
+            locals: parameters
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (p.text, i as u32 + 1))
+                .collect(),
+            captures: Vec::new(),
+            body: Body::new(&vec![I32; parameters.len() + 1], &[I32]),
+        });
+        self.compile(body, true);
+        let scope = self.scopes.pop().unwrap();
+        self.functions[index] = Some((parameters.len(), scope.body));
+        let captures = scope
+            .captures
+            .into_iter()
+            .map(|(_, access)| access)
+            .collect();
+        (index, captures)
+    }
+}
+
+/// Compiles a file into the bytes of a WebAssembly module.
+pub fn compile(file: &File) -> Vec<u8> {
+    let mut data = Data { bytes: Vec::new() };
+    let constants = Constants {
+        true_value: data.bool(true),
+        false_value: data.bool(false),
+        true_string: data.string("true"),
+        false_string: data.string("false"),
+        closure_string: data.string("<#closure>"),
+        open_paren: data.string("("),
+        comma: data.string(", "),
+        close_paren: data.string(")"),
+        space: data.string(" "),
+    };
+    let mut compiler = Compiler {
+        scopes: Vec::new(),
+        functions: Vec::new(),
+        types: Types::default(),
+        data,
+        constants,
+    };
+    let (main, _) = compiler.function(None, &[], &file.expression);
+
+    let mut types = compiler.types;
+    let print_type = types.get(&[I32, I32], &[]);
+    let error_type = types.get(&[I32, I32, I32], &[]);
+    let mut bodies = Runtime {
+        constants: &compiler.constants,
+    }
+    .functions();
+    let program_start = RUNTIME_FUNCTIONS;
+    let table_size = compiler.functions.len() as u32;
+    for function in compiler.functions {
+        let (_, body) = function.unwrap();
+        bodies.push(body);
+    }
+    let mut entry = Body::new(&[], &[I32]);
+    entry.emit_all([
+        Instruction::I32Const(0),
+        Instruction::Call(program_start + main as u32),
+    ]);
+    bodies.push(entry);
+
+    let mut imports = ImportSection::new();
+    imports.import("rinha", "print", EntityType::Function(print_type));
+    imports.import("rinha", "error", EntityType::Function(error_type));
+
+    let mut functions = FunctionSection::new();
+    let mut code = CodeSection::new();
+    for body in bodies {
+        functions.function(types.get(&body.params, &body.results));
+        code.function(&body.finish());
+    }
+    let entry_index = ALLOC + functions.len() - 1;
+
+    let mut type_section = TypeSection::new();
+    for (params, results) in &types.types {
+        type_section
+            .ty()
+            .function(params.iter().copied(), results.iter().copied());
+    }
+
+    let mut tables = TableSection::new();
+    tables.table(TableType {
+        element_type: RefType::FUNCREF,
+        table64: false,
+        minimum: table_size as u64,
+        maximum: Some(table_size as u64),
+        shared: false,
+    });
+
+    let mut memories = MemorySection::new();
+    let heap_start = (compiler.data.end() + 7) & !7;
+    memories.memory(MemoryType {
+        minimum: (heap_start as u64 >> 16) + 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+
+    let mut globals = GlobalSection::new();
+    globals.global(
+        GlobalType {
+            val_type: I32,
+            mutable: true,
+            shared: false,
+        },
+        &ConstExpr::i32_const(heap_start as i32),
+    );
+
+    let mut exports = ExportSection::new();
+    exports.export("memory", ExportKind::Memory, 0);
+    exports.export("main", ExportKind::Func, entry_index);
+
+    let mut elements = ElementSection::new();
+    let table: Vec<u32> = (0..table_size).map(|i| program_start + i).collect();
+    elements.active(
+        None,
+        &ConstExpr::i32_const(0),
+        Elements::Functions(table.into()),
+    );
+
+    let mut data_section = DataSection::new();
+    data_section.active(
+        0,
+        &ConstExpr::i32_const(DATA_START as i32),
+        compiler.data.bytes,
+    );
+
+    let mut module = Module::new();
+    module
+        .section(&type_section)
+        .section(&imports)
+        .section(&functions)
+        .section(&tables)
+        .section(&memories)
+        .section(&globals)
+        .section(&exports)
+        .section(&elements)
+        .section(&code)
+        .section(&data_section);
+    module.finish()
+}
@@ -0,0 +1,82 @@
+//! Colors for what rinha shows to people rather than to programs, for
+//! `--color`.
+//!
+//! Diagnostics color their label by kind and mark the span they point at,
+//! and the value a program ends with stands apart from what it printed.
+//! Colors are written as ANSI escape codes, and by default only to streams
+//! that are terminals, unless the `NO_COLOR` environment variable is set.
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// When to color output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Choice {
+    /// Color streams that are terminals.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+static CHOICE: AtomicU8 = AtomicU8::new(Choice::Auto as u8);
+
+/// Sets when output is colored from now on.
+pub fn set(choice: Choice) {
+    CHOICE.store(choice as u8, Ordering::Relaxed);
+}
+
+fn enabled(terminal: bool) -> bool {
+    match CHOICE.load(Ordering::Relaxed) {
+        choice if choice == Choice::Always as u8 => true,
+        choice if choice == Choice::Never as u8 => false,
+        _ => terminal && env::var_os("NO_COLOR").is_none(),
+    }
+}
+
+/// Whether what is written to stdout should be colored.
+pub fn stdout() -> bool {
+    enabled(io::stdout().is_terminal())
+}
+
+/// Whether what is written to stderr should be colored.
+pub fn stderr() -> bool {
+    enabled(io::stderr().is_terminal())
+}
+
+/// How a piece of text is shown.
+#[derive(Clone, Copy, Debug)]
+pub enum Style {
+    /// Labels and spans of errors.
+    Error,
+    /// Labels and spans of warnings.
+    Warning,
+    /// File positions and the gutter of quoted code.
+    Note,
+    /// The code a diagnostic points at.
+    Underline,
+    /// The value a program ends with.
+    Value,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Error => "1;31",
+            Style::Warning => "1;33",
+            Style::Note => "1;34",
+            Style::Underline => "4",
+            Style::Value => "1;32",
+        }
+    }
+}
+
+/// `text` shown with `style` when `enabled`, and as it is otherwise.
+pub fn paint(text: &str, style: Style, enabled: bool) -> String {
+    if enabled && !text.is_empty() {
+        format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+    } else {
+        text.to_string()
+    }
+}
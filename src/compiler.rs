@@ -0,0 +1,565 @@
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::builtins;
+use crate::config::InterpreterConfig;
+use crate::escape::is_projected;
+use crate::memo::is_pure;
+use crate::symbol::Symbol;
+use crate::types::*;
+use crate::value::Value;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Instr {
+    Constant(usize),
+    LoadLocal(usize),
+    LoadCapture(usize),
+    /// Pushes the closure of the running frame.
+    LoadCallee,
+    Unbound(Symbol),
+    MakeClosure(usize),
+    MakeTuple,
+    /// Pops one value for each name, the first name's deepest, and pushes
+    /// the record of them.
+    MakeRecord(Rc<[Symbol]>),
+    Field(Symbol),
+    MakeRef,
+    Deref,
+    /// Pops a value and a reference below it, stores the value in the
+    /// reference and pushes it back.
+    Assign,
+    First,
+    Second,
+    /// Pops a tuple and pushes its first element, then its second.
+    Unpack,
+    /// Prints the top `n` values on one line and leaves the last of them.
+    Print(usize),
+    Binary(BinaryOp),
+    Jump(usize),
+    JumpIfFalse(usize),
+    /// Pops a value and pushes what the names of the pattern bind to when
+    /// it matches, or jumps when it does not.
+    Match(Rc<Pattern>, usize),
+    /// Stops the program because no arm of a `Match` matched.
+    NoMatch,
+    Call(usize),
+    TailCall(usize),
+    /// Drops `n` values sitting right below the top of the stack.
+    Slide(usize),
+    Return,
+}
+
+/// Where a closure finds a captured value when it is created: a slot of the
+/// enclosing frame, one of the enclosing closure's own captures or the
+/// enclosing closure itself.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum Capture {
+    Local(usize),
+    Capture(usize),
+    Callee,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Function {
+    pub arity: usize,
+    /// Whether the body has no `print` or use of a reference of its own,
+    /// making calls cacheable.
+    pub pure: bool,
+    pub captures: Vec<Capture>,
+    pub code: Vec<Instr>,
+    /// Source location of each instruction, used for runtime errors.
+    pub locations: Vec<Location>,
+    /// Location of the callee of each call, by the index of its
+    /// instruction, where a callee that is not a function is reported.
+    #[serde(default)]
+    pub callees: Vec<(usize, Location)>,
+}
+
+impl Function {
+    /// Where the callee of the call instruction at `ip` is written.
+    pub fn callee_location(&self, ip: usize) -> &Location {
+        match self.callees.binary_search_by_key(&ip, |(at, _)| *at) {
+            Ok(index) => &self.callees[index].1,
+            Err(_) => &self.locations[ip],
+        }
+    }
+}
+
+/// A compiled program, which [`crate::cache`] can store as JSON.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    #[serde(with = "crate::cache::constants")]
+    pub constants: Vec<Value>,
+    pub main: usize,
+}
+
+struct Scope {
+    /// Name of the function when it is bound by `let`, which refers to the
+    /// function itself inside its body.
+    name: Option<Symbol>,
+    /// Names bound in the current frame together with their stack slot.
+    locals: Vec<(Symbol, usize)>,
+    captures: Vec<(Symbol, Capture)>,
+    /// Names bound to tuples that are only taken apart, which are never
+    /// built, with the slot of their first element; the second element is
+    /// in the next slot.
+    pairs: Vec<(Symbol, usize)>,
+    code: Vec<Instr>,
+    locations: Vec<Location>,
+    callees: Vec<(usize, Location)>,
+    /// Number of values the frame has on the stack at this point.
+    depth: usize,
+}
+
+struct Compiler {
+    scopes: Vec<Scope>,
+    functions: Vec<Option<Function>>,
+    constants: Vec<Value>,
+    /// Values for names the program does not bind.
+    globals: Vec<(Symbol, Value)>,
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, instr: Instr, location: &Location) -> usize {
+        let scope = self.scope();
+        scope.depth = match instr {
+            Instr::Constant(_)
+            | Instr::LoadLocal(_)
+            | Instr::LoadCapture(_)
+            | Instr::LoadCallee
+            | Instr::Unbound(_)
+            | Instr::MakeClosure(_)
+            | Instr::Unpack
+            | Instr::NoMatch => scope.depth + 1,
+            Instr::MakeTuple | Instr::Assign | Instr::Binary(_) | Instr::JumpIfFalse(_) => {
+                scope.depth - 1
+            }
+            Instr::Match(ref pattern, _) => scope.depth - 1 + pattern.names().len(),
+            Instr::Call(argc) | Instr::TailCall(argc) | Instr::Slide(argc) => scope.depth - argc,
+            Instr::MakeRecord(ref names) => scope.depth + 1 - names.len(),
+            Instr::Print(n) => scope.depth + 1 - n,
+            Instr::First
+            | Instr::Second
+            | Instr::Field(_)
+            | Instr::MakeRef
+            | Instr::Deref
+            | Instr::Jump(_)
+            | Instr::Return => scope.depth,
+        };
+        scope.code.push(instr);
+        scope.locations.push(location.clone());
+        scope.code.len() - 1
+    }
+
+    fn patch(&mut self, at: usize) {
+        let target = self.scope().code.len();
+        match &mut self.scope().code[at] {
+            Instr::Jump(to) | Instr::JumpIfFalse(to) | Instr::Match(_, to) => *to = target,
+            _ => unreachable!("only jumps can be patched"),
+        }
+    }
+
+    fn constant(&mut self, value: Value, location: &Location) {
+        self.constants.push(value);
+        self.emit(Instr::Constant(self.constants.len() - 1), location);
+    }
+
+    /// Finds `name` in the scope at `level`, capturing it from the enclosing
+    /// scopes when needed.
+    fn resolve(&mut self, level: usize, name: Symbol) -> Option<Instr> {
+        let scope = &self.scopes[level];
+        if let Some((_, slot)) = scope.locals.iter().rev().find(|(n, _)| *n == name) {
+            return Some(Instr::LoadLocal(*slot));
+        }
+        if let Some(index) = scope.captures.iter().position(|(n, _)| *n == name) {
+            return Some(Instr::LoadCapture(index));
+        }
+        if scope.name == Some(name) {
+            return Some(Instr::LoadCallee);
+        }
+        if level == 0 {
+            return None;
+        }
+
+        let capture = match self.resolve(level - 1, name)? {
+            Instr::LoadLocal(slot) => Capture::Local(slot),
+            Instr::LoadCapture(index) => Capture::Capture(index),
+            Instr::LoadCallee => Capture::Callee,
+            _ => unreachable!(),
+        };
+        let captures = &mut self.scopes[level].captures;
+        captures.push((name, capture));
+        Some(Instr::LoadCapture(captures.len() - 1))
+    }
+
+    /// The slot of the first element of the tuple `name` is bound to in
+    /// the current frame, when that tuple is never built.
+    fn pair(&mut self, name: Symbol) -> Option<usize> {
+        let scope = self.scope();
+        let (_, slot) = scope.pairs.iter().rev().find(|(n, _)| *n == name)?;
+        let shadowed = scope.locals.iter().any(|(n, s)| *n == name && s > slot);
+        (!shadowed).then_some(*slot)
+    }
+
+    /// Compiles `first` (`index` 0) or `second` (`index` 1) of `value`,
+    /// without building `value` when it is a tuple that is only taken apart.
+    fn projection(&mut self, value: &Term, index: usize, instr: Instr, location: &Location) {
+        match value {
+            Term::Var { text, .. } => {
+                if let Some(slot) = self.pair(*text) {
+                    self.emit(Instr::LoadLocal(slot + index), location);
+                    return;
+                }
+            }
+            Term::Tuple { first, second, .. } => {
+                self.compile(first, false);
+                self.compile(second, false);
+                let slot = self.scope().depth - 2 + index;
+                self.emit(Instr::LoadLocal(slot), location);
+                self.emit(Instr::Slide(2), location);
+                return;
+            }
+            _ => {}
+        }
+        self.compile(value, false);
+        // Like the tree-walker, a value that is not a tuple is reported
+        // where it is written.
+        self.emit(instr, value.location());
+    }
+
+    /// Compiles the value of the `let` or `let (first, second)` `term`
+    /// and binds its names, giving back how many slots they take and
+    /// whether they hold a tuple that is never built.
+    fn bind(&mut self, term: &Term) -> (usize, bool) {
+        match term {
+            Term::Let {
+                name, value, next, ..
+            } => {
+                match value.as_ref() {
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        location,
+                        ..
+                    } => {
+                        let index = self.function(Some(name.text), parameters, body);
+                        self.emit(Instr::MakeClosure(index), location);
+                    }
+                    // Its elements stay in two slots instead.
+                    Term::Tuple { first, second, .. } if is_projected(name.text, next) => {
+                        self.compile(first, false);
+                        self.compile(second, false);
+                        let scope = self.scope();
+                        scope.pairs.push((name.text, scope.depth - 2));
+                        return (2, true);
+                    }
+                    _ => self.compile(value, false),
+                }
+                let scope = self.scope();
+                let slot = scope.depth - 1;
+                scope.locals.push((name.text, slot));
+                (1, false)
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                ..
+            } => {
+                match value.as_ref() {
+                    // A tuple written out is never built.
+                    Term::Tuple {
+                        first: first_value,
+                        second: second_value,
+                        ..
+                    } => {
+                        self.compile(first_value, false);
+                        self.compile(second_value, false);
+                    }
+                    _ => {
+                        self.compile(value, false);
+                        self.emit(Instr::Unpack, value.location());
+                    }
+                }
+                let scope = self.scope();
+                let slot = scope.depth - 2;
+                scope.locals.push((first.text, slot));
+                scope.locals.push((second.text, slot + 1));
+                (2, false)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn bindings(&mut self, term: &Term, tail: bool) {
+        let mut chain = term.let_chain();
+        let bound: Vec<_> = chain
+            .by_ref()
+            .map(|binding| (self.bind(binding), binding.location()))
+            .collect();
+        self.compile(chain.rest(), tail);
+        for ((slots, pair), location) in bound.into_iter().rev() {
+            let scope = self.scope();
+            if pair {
+                scope.pairs.pop();
+            } else {
+                scope.locals.truncate(scope.locals.len() - slots);
+            }
+            self.emit(Instr::Slide(slots), location);
+        }
+    }
+
+    fn compile(&mut self, term: &Term, tail: bool) {
+        match term {
+            Term::Int { value, location } => self.constant(Value::Number(*value), location),
+            Term::Str { value, location } => self.constant(Value::string(value.clone()), location),
+            Term::Bool { value, location } => self.constant(Value::Boolean(*value), location),
+            Term::Var { text, location, .. } => {
+                let level = self.scopes.len() - 1;
+                match self.resolve(level, *text) {
+                    Some(instr) => {
+                        self.emit(instr, location);
+                    }
+                    None => match self.globals.iter().find(|(name, _)| name == text) {
+                        Some((_, value)) => self.constant(value.clone(), location),
+                        None => {
+                            self.emit(Instr::Unbound(*text), location);
+                        }
+                    },
+                }
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                location,
+            } => {
+                self.compile(condition, false);
+                let jump_to_else = self.emit(Instr::JumpIfFalse(0), condition.location());
+                self.compile(then, tail);
+                let jump_to_end = self.emit(Instr::Jump(0), location);
+                self.scope().depth -= 1;
+                self.patch(jump_to_else);
+                self.compile(otherwise, tail);
+                self.patch(jump_to_end);
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => self.bindings(term, tail),
+            Term::Binary {
+                lhs,
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                rhs,
+                location,
+            } => self.logical(lhs, op, rhs, location),
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => {
+                self.compile(lhs, false);
+                self.compile(rhs, false);
+                self.emit(Instr::Binary(op.clone()), location);
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                self.compile(callee, false);
+                for argument in arguments {
+                    self.compile(argument, false);
+                }
+                let call = if tail {
+                    Instr::TailCall(arguments.len())
+                } else {
+                    Instr::Call(arguments.len())
+                };
+                let ip = self.emit(call, location);
+                self.scope().callees.push((ip, callee.location().clone()));
+            }
+            Term::Function {
+                parameters,
+                value,
+                location,
+                ..
+            } => {
+                let index = self.function(None, parameters, value);
+                self.emit(Instr::MakeClosure(index), location);
+            }
+            Term::Tuple {
+                first,
+                second,
+                location,
+            } => {
+                self.compile(first, false);
+                self.compile(second, false);
+                self.emit(Instr::MakeTuple, location);
+            }
+            Term::Record { fields, location } => {
+                for field in fields {
+                    self.compile(&field.value, false);
+                }
+                let names = fields.iter().map(|field| field.name.text).collect();
+                self.emit(Instr::MakeRecord(names), location);
+            }
+            Term::Field {
+                value,
+                name,
+                location,
+            } => {
+                self.compile(value, false);
+                self.emit(Instr::Field(name.text), location);
+            }
+            Term::Ref { value, location } => {
+                self.compile(value, false);
+                self.emit(Instr::MakeRef, location);
+            }
+            Term::Deref { value, .. } => {
+                self.compile(value, false);
+                self.emit(Instr::Deref, value.location());
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                self.compile(reference, false);
+                self.compile(value, false);
+                self.emit(Instr::Assign, reference.location());
+            }
+            Term::First { value, location } => self.projection(value, 0, Instr::First, location),
+            Term::Second { value, location } => self.projection(value, 1, Instr::Second, location),
+            Term::Print { values, location } => {
+                for value in values {
+                    self.compile(value, false);
+                }
+                self.emit(Instr::Print(values.len()), location);
+            }
+            Term::Match {
+                value,
+                arms,
+                location,
+            } => {
+                self.compile(value, false);
+                let slot = self.scope().depth - 1;
+                let mut jumps_to_end = Vec::new();
+                for arm in arms {
+                    let pattern = arm.pattern.location();
+                    self.emit(Instr::LoadLocal(slot), pattern);
+                    let instr = Instr::Match(Rc::new(arm.pattern.clone()), 0);
+                    let jump_to_next = self.emit(instr, pattern);
+                    let names = arm.pattern.names();
+                    let scope = self.scope();
+                    let first = scope.depth - names.len();
+                    let locals = scope.locals.len();
+                    scope
+                        .locals
+                        .extend(names.iter().enumerate().map(|(i, name)| (*name, first + i)));
+                    self.compile(&arm.value, tail);
+                    self.scope().locals.truncate(locals);
+                    self.emit(Instr::Slide(names.len()), location);
+                    jumps_to_end.push(self.emit(Instr::Jump(0), location));
+                    self.scope().depth -= 1;
+                    self.patch(jump_to_next);
+                }
+                self.emit(Instr::NoMatch, location);
+                for jump in jumps_to_end {
+                    self.patch(jump);
+                }
+                self.emit(Instr::Slide(1), location);
+            }
+        }
+    }
+
+    /// Compiles `&&` and `||` so that `rhs` only runs when `lhs` does not
+    /// decide the result. Both operands are checked to be booleans.
+    fn logical(&mut self, lhs: &Term, op: &BinaryOp, rhs: &Term, location: &Location) {
+        self.compile(lhs, false);
+        let lhs_false = self.emit(Instr::JumpIfFalse(0), location);
+        let lhs_true_to_end = if let BinaryOp::Or = op {
+            self.constant(Value::Boolean(true), location);
+            let jump = self.emit(Instr::Jump(0), location);
+            self.scope().depth -= 1;
+            self.patch(lhs_false);
+            Some(jump)
+        } else {
+            None
+        };
+
+        self.compile(rhs, false);
+        let rhs_false = self.emit(Instr::JumpIfFalse(0), location);
+        self.constant(Value::Boolean(true), location);
+        let rhs_true_to_end = self.emit(Instr::Jump(0), location);
+        self.scope().depth -= 1;
+
+        if let BinaryOp::And = op {
+            self.patch(lhs_false);
+        }
+        self.patch(rhs_false);
+        self.constant(Value::Boolean(false), location);
+        self.patch(rhs_true_to_end);
+        if let Some(jump) = lhs_true_to_end {
+            self.patch(jump);
+        }
+    }
+
+    fn function(&mut self, name: Option<Symbol>, parameters: &[Parameter], body: &Term) -> usize {
+        let index = self.functions.len();
+        self.functions.push(None);
+        self.scopes.push(Scope {
+            name,
+            locals: parameters
+                .iter()
+                .enumerate()
+                .map(|(slot, p)| (p.text, slot))
+                .collect(),
+            captures: Vec::new(),
+            pairs: Vec::new(),
+            code: Vec::new(),
+            locations: Vec::new(),
+            callees: Vec::new(),
+            depth: parameters.len(),
+        });
+        self.compile(body, true);
+        self.emit(Instr::Return, body.location());
+
+        let scope = self.scopes.pop().unwrap();
+        self.functions[index] = Some(Function {
+            arity: parameters.len(),
+            pure: is_pure(body),
+            captures: scope.captures.into_iter().map(|(_, c)| c).collect(),
+            code: scope.code,
+            locations: scope.locations,
+            callees: scope.callees,
+        });
+        index
+    }
+}
+
+/// Lowers a file into bytecode. The top-level expression becomes a function
+/// without parameters that the VM calls to start the program. When `config`
+/// binds them, free names of the builtins in [`crate::builtins`] load them
+/// as constants.
+pub fn compile(file: &File, config: InterpreterConfig) -> Program {
+    let mut compiler = Compiler {
+        scopes: Vec::new(),
+        functions: Vec::new(),
+        constants: Vec::new(),
+        globals: if config.builtins() {
+            builtins::globals(config.clock)
+        } else {
+            Vec::new()
+        },
+    };
+    let main = compiler.function(None, &[], &file.expression);
+    Program {
+        functions: compiler.functions.into_iter().map(Option::unwrap).collect(),
+        constants: compiler.constants,
+        main,
+    }
+}
@@ -0,0 +1,120 @@
+//! Measures the shape of a program without running it, for `rinha stats`.
+//!
+//! The numbers are meant to spot submissions that will be slow to check or
+//! run, such as ones with a huge or deeply nested AST, and to give users
+//! something to compare when they rewrite a program.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+use crate::types::*;
+
+/// What [`analyze`] found in a program.
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    /// Number of terms of each kind, by the name of the kind in the JSON
+    /// AST.
+    pub kinds: BTreeMap<&'static str, usize>,
+    /// Terms nested in the deepest one, counting itself and the root.
+    pub max_depth: usize,
+    /// Different names bound by `let`, by function parameters and by
+    /// patterns.
+    pub variables: usize,
+    /// Function literals. Each makes a closure whenever it is evaluated,
+    /// so this is the number of closures of a program that evaluates each
+    /// of them once.
+    pub closures: usize,
+}
+
+impl Report {
+    /// Number of terms in the program.
+    pub fn nodes(&self) -> usize {
+        self.kinds.values().sum()
+    }
+}
+
+/// Shows the report one number per line, the count of each kind indented
+/// below the total.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "nodes: {}", self.nodes())?;
+        for (kind, count) in &self.kinds {
+            writeln!(f, "  {}: {}", kind, count)?;
+        }
+        writeln!(f, "max depth: {}", self.max_depth)?;
+        writeln!(f, "variables: {}", self.variables)?;
+        write!(f, "closures: {}", self.closures)
+    }
+}
+
+/// Counts the terms of `file`.
+pub fn analyze(file: &File) -> Report {
+    let mut report = Report::default();
+    let mut names: HashSet<Symbol> = HashSet::new();
+    let mut pending: Vec<(&Rc<Term>, usize)> = vec![(&file.expression, 1)];
+    while let Some((term, depth)) = pending.pop() {
+        *report.kinds.entry(term.kind()).or_default() += 1;
+        report.max_depth = report.max_depth.max(depth);
+        let children: Vec<&Rc<Term>> = match term.as_ref() {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => vec![],
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => vec![condition, then, otherwise],
+            Term::Let {
+                name, value, next, ..
+            } => {
+                names.insert(name.text);
+                vec![value, next]
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                next,
+                ..
+            } => {
+                names.insert(first.text);
+                names.insert(second.text);
+                vec![value, next]
+            }
+            Term::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Term::Call {
+                callee, arguments, ..
+            } => std::iter::once(callee).chain(arguments).collect(),
+            Term::Function {
+                parameters, value, ..
+            } => {
+                report.closures += 1;
+                names.extend(parameters.iter().map(|p| p.text));
+                vec![value]
+            }
+            Term::Tuple { first, second, .. } => vec![first, second],
+            Term::Print { values, .. } => values.iter().collect(),
+            Term::First { value, .. }
+            | Term::Second { value, .. }
+            | Term::Field { value, .. }
+            | Term::Ref { value, .. }
+            | Term::Deref { value, .. } => vec![value],
+            Term::Match { value, arms, .. } => {
+                for arm in arms {
+                    names.extend(arm.pattern.names());
+                }
+                std::iter::once(value)
+                    .chain(arms.iter().map(|arm| &arm.value))
+                    .collect()
+            }
+            Term::Record { fields, .. } => fields.iter().map(|field| &field.value).collect(),
+            Term::Assign {
+                reference, value, ..
+            } => vec![reference, value],
+        };
+        pending.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+    }
+    report.variables = names.len();
+    report
+}
@@ -0,0 +1,93 @@
+//! Whether programs run as the specification describes or with everything
+//! rinha adds to the language.
+//!
+//! The competition judges programs against the specification alone, where
+//! lists, records and builtins do not exist and `+` only joins strings and
+//! integers. Experimenting is easier with them, so the same binary can run
+//! either way: [`InterpreterConfig::STRICT`] is the default, and giving any
+//! `--extensions` switches to [`InterpreterConfig::EXTENDED`].
+//!
+//! The config travels with each run: [`crate::Engine::run`] and the
+//! sessions of the interpreter take it, and so do [`crate::validate`] and
+//! [`crate::lint`], so programs run side by side with different configs.
+
+/// Which values `+` turns into strings to join them to a string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Coercion {
+    /// Only integers, as the specification says.
+    Spec,
+    /// Integers, and booleans as `true` and `false`.
+    Booleans,
+    /// Every value, shown as `print` shows it.
+    All,
+}
+
+impl Coercion {
+    /// The coercion named `name` by `--coerce`.
+    pub fn from_name(name: &str) -> Option<Coercion> {
+        match name {
+            "spec" => Some(Coercion::Spec),
+            "booleans" => Some(Coercion::Booleans),
+            "all" => Some(Coercion::All),
+            _ => None,
+        }
+    }
+}
+
+/// How closely programs follow the specification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterpreterConfig {
+    /// Leave out the builtins and the syntax of the extensions.
+    pub strict: bool,
+    /// What `+` joins to strings. Values other than integers are only
+    /// joined when the other side is a string, so `1 + true` always fails.
+    pub coercion: Coercion,
+    /// Whether `==` and `!=` compare closures by identity, rather than
+    /// failing. The specification does not say how closures compare.
+    pub compare_closures: bool,
+    /// Whether `print` shows closures as `<#closure>`, as the
+    /// specification says, rather than failing.
+    pub print_closures: bool,
+    /// Whether calling a function with fewer arguments than it takes gives
+    /// a [`crate::value::Partial`] waiting for the rest, rather than
+    /// failing. Off even in [`InterpreterConfig::EXTENDED`], as it turns
+    /// programs the specification rejects into working ones; `--extensions
+    /// partial` turns it on.
+    pub partial_application: bool,
+    /// Milliseconds the builtin `now()` gives on every call, instead of the
+    /// time since the builtins were bound, so that tests of programs that
+    /// time themselves print the same on every run.
+    pub clock: Option<u64>,
+}
+
+impl InterpreterConfig {
+    /// The language of the specification, for the competition.
+    pub const STRICT: InterpreterConfig = InterpreterConfig {
+        strict: true,
+        coercion: Coercion::Spec,
+        compare_closures: true,
+        print_closures: true,
+        partial_application: false,
+        clock: None,
+    };
+    /// The language with every extension, for experimenting.
+    pub const EXTENDED: InterpreterConfig = InterpreterConfig {
+        strict: false,
+        coercion: Coercion::Booleans,
+        compare_closures: true,
+        print_closures: true,
+        partial_application: false,
+        clock: None,
+    };
+
+    /// Whether the functions of [`crate::builtins`] are bound.
+    pub fn builtins(self) -> bool {
+        !self.strict
+    }
+}
+
+impl Default for InterpreterConfig {
+    fn default() -> InterpreterConfig {
+        InterpreterConfig::STRICT
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::SourceMap;
+use crate::parser::Syntax;
+use crate::{Engine, File, Fuel, InterpreterConfig};
+
+/// Result of running one program of a conformance directory.
+pub enum Outcome {
+    Pass,
+    /// The program printed something other than its `.expected` file.
+    Fail {
+        expected: String,
+        actual: String,
+    },
+    /// The program or its `.expected` file could not be read.
+    Broken(String),
+}
+
+pub struct Case {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+}
+
+impl Case {
+    pub fn passed(&self) -> bool {
+        matches!(self.outcome, Outcome::Pass)
+    }
+}
+
+/// Reads a program the way `rinha` does before running it, including
+/// [`crate::validate`], whose violations come back one per line.
+fn load(path: &Path, source: &str) -> Result<File, String> {
+    let file = if path.extension().is_some_and(|ext| ext == "rinha") {
+        crate::parse_with(source, &path.display().to_string(), Syntax::all())
+            .map_err(|err| err.message)?
+    } else {
+        crate::parse_json(source).map_err(|err| err.to_string())?
+    };
+    let file = crate::modules::link(
+        file,
+        &path.display().to_string(),
+        Syntax::all(),
+        &mut SourceMap::new(),
+    )
+    .map_err(|err| err.message)?;
+    let violations = crate::validate::validate(&file, InterpreterConfig::EXTENDED);
+    if violations.is_empty() {
+        return Ok(file);
+    }
+    let messages: Vec<_> = violations.into_iter().map(|v| v.message).collect();
+    Err(messages.join("\nerror: "))
+}
+
+/// Everything the program prints, one line per `print`, followed by an
+/// `error: ...` line if it stops with an error.
+fn output(path: &Path, engine: Engine) -> Result<String, String> {
+    let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut lines = Vec::new();
+    match load(path, &source) {
+        Ok(file) => {
+            let config = InterpreterConfig::EXTENDED;
+            if let Err(err) = engine.run(&file, true, config, Fuel::unlimited(), &mut lines) {
+                lines.push(format!("error: {}", err));
+            }
+        }
+        Err(message) => lines.push(format!("error: {}", message)),
+    }
+    Ok(lines.join("\n"))
+}
+
+fn run_case(path: PathBuf, engine: Engine) -> Case {
+    let expected = fs::read_to_string(path.with_extension("expected"));
+    let outcome = match (expected, output(&path, engine)) {
+        (Err(err), _) => Outcome::Broken(format!("cannot read .expected file: {}", err)),
+        (_, Err(err)) => Outcome::Broken(err),
+        (Ok(expected), Ok(actual)) if expected.trim_end() == actual.trim_end() => Outcome::Pass,
+        (Ok(expected), Ok(actual)) => Outcome::Fail { expected, actual },
+    };
+    Case { path, outcome }
+}
+
+/// Runs every `.rinha` and `.json` program in `dir`, in name order, and
+/// compares what it prints with the `.expected` file next to it. Programs
+/// run with [`InterpreterConfig::EXTENDED`].
+pub fn run_dir(dir: &Path, engine: Engine) -> io::Result<Vec<Case>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext == "rinha" || ext == "json")
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths
+        .into_iter()
+        .map(|path| run_case(path, engine))
+        .collect())
+}
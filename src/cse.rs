@@ -0,0 +1,646 @@
+//! Common subexpression elimination, part of [`crate::optimizer`].
+//!
+//! A pure term written twice where both copies are always evaluated, with
+//! the same names in scope, gives the same value both times. It is bound
+//! once by a `let` and both copies use the name instead:
+//!
+//! ```text
+//! fib(n - 1) + fib(n - 1)
+//! let __cse_1 = n - 1; let __cse_2 = fib(__cse_1); __cse_2 + __cse_2
+//! ```
+//!
+//! A term is pure when it cannot print, make closures or use references,
+//! and only calls functions bound by `let` that are pure themselves.
+//! Copies are only looked for where they are surely evaluated: not in the
+//! branches of an `if` or `match`, the right side of `&&` and `||`, function
+//! bodies or after a `let`. The first copy must also be the first thing
+//! to run that could fail, so binding it first changes neither the value
+//! nor the error of the program.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+
+use crate::symbol::{Fresh, Symbol};
+use crate::types::*;
+
+struct Eliminator {
+    /// Names bound around the term being rewritten, innermost last, and
+    /// whether each is a pure function.
+    scope: Vec<(Symbol, bool)>,
+    /// Names for the terms bound.
+    fresh: Fresh,
+    /// Terms of the program that hold copies of some candidate, see
+    /// [`heat`]. Copies are not looked for anywhere else.
+    hot: HashSet<*const Term>,
+}
+
+/// A term evaluated whenever the term it was found in is.
+struct Entry<'t> {
+    term: &'t Rc<Term>,
+    /// How many entries were found before the first one inside `term`,
+    /// which all finish before `term` starts.
+    before: usize,
+}
+
+/// Whether `term` is worth binding: it does more than read a name or a
+/// literal.
+fn is_candidate(term: &Term) -> bool {
+    matches!(
+        term,
+        Term::Call { .. }
+            | Term::Binary { .. }
+            | Term::First { .. }
+            | Term::Second { .. }
+            | Term::Field { .. }
+    )
+}
+
+/// Lists the terms inside `term` that evaluating it always evaluates, in
+/// the order they finish, `term` last.
+fn evaluated<'t>(term: &'t Rc<Term>, entries: &mut Vec<Entry<'t>>) {
+    let before = entries.len();
+    match term.as_ref() {
+        Term::Int { .. }
+        | Term::Str { .. }
+        | Term::Bool { .. }
+        | Term::Var { .. }
+        | Term::Function { .. } => {}
+        Term::Let { value, .. }
+        | Term::LetTuple { value, .. }
+        | Term::First { value, .. }
+        | Term::Second { value, .. }
+        | Term::Field { value, .. }
+        | Term::Ref { value, .. }
+        | Term::Deref { value, .. }
+        | Term::Match { value, .. } => evaluated(value, entries),
+        Term::Print { values, .. } => {
+            for value in values {
+                evaluated(value, entries);
+            }
+        }
+        Term::If { condition, .. } => evaluated(condition, entries),
+        Term::Binary { lhs, op, rhs, .. } => {
+            evaluated(lhs, entries);
+            if !matches!(op, BinaryOp::And | BinaryOp::Or) {
+                evaluated(rhs, entries);
+            }
+        }
+        Term::Call {
+            callee, arguments, ..
+        } => {
+            evaluated(callee, entries);
+            for argument in arguments {
+                evaluated(argument, entries);
+            }
+        }
+        Term::Tuple { first, second, .. } => {
+            evaluated(first, entries);
+            evaluated(second, entries);
+        }
+        Term::Record { fields, .. } => {
+            for field in fields {
+                evaluated(&field.value, entries);
+            }
+        }
+        Term::Assign {
+            reference, value, ..
+        } => {
+            evaluated(reference, entries);
+            evaluated(value, entries);
+        }
+    }
+    entries.push(Entry { term, before });
+}
+
+/// A hash of the structure of each term inside a term, by address. Copies
+/// of a term, whatever their locations, have the same digest, so they are
+/// found by looking digests up rather than by comparing terms pairwise.
+type Digests = HashMap<*const Term, u64>;
+
+/// The terms directly inside `term`, hashing what else tells it apart
+/// from other terms of its kind into `hasher`.
+fn subterms<'t>(term: &'t Term, hasher: &mut DefaultHasher) -> Vec<&'t Rc<Term>> {
+    mem::discriminant(term).hash(hasher);
+    match term {
+        Term::Int { value, .. } => value.hash(hasher),
+        Term::Str { value, .. } => value.hash(hasher),
+        Term::Bool { value, .. } => value.hash(hasher),
+        Term::Var { text, .. } => text.hash(hasher),
+        Term::Function {
+            parameters, value, ..
+        } => {
+            parameters.iter().for_each(|p| p.text.hash(hasher));
+            return vec![value];
+        }
+        Term::Let {
+            name, value, next, ..
+        } => {
+            name.text.hash(hasher);
+            return vec![value, next];
+        }
+        Term::LetTuple {
+            first,
+            second,
+            value,
+            next,
+            ..
+        } => {
+            first.text.hash(hasher);
+            second.text.hash(hasher);
+            return vec![value, next];
+        }
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => return vec![condition, then, otherwise],
+        Term::Binary { lhs, op, rhs, .. } => {
+            mem::discriminant(op).hash(hasher);
+            return vec![lhs, rhs];
+        }
+        Term::Call {
+            callee, arguments, ..
+        } => return [callee].into_iter().chain(arguments).collect(),
+        Term::Print { values, .. } => return values.iter().collect(),
+        Term::Tuple { first, second, .. } => return vec![first, second],
+        Term::Assign {
+            reference, value, ..
+        } => return vec![reference, value],
+        Term::Field { value, name, .. } => {
+            name.text.hash(hasher);
+            return vec![value];
+        }
+        Term::First { value, .. }
+        | Term::Second { value, .. }
+        | Term::Ref { value, .. }
+        | Term::Deref { value, .. } => return vec![value],
+        // Patterns are left out: copies are still compared in full before
+        // one is bound.
+        Term::Match { value, arms, .. } => {
+            return [value]
+                .into_iter()
+                .chain(arms.iter().map(|arm| &arm.value))
+                .collect()
+        }
+        Term::Record { fields, .. } => {
+            fields.iter().for_each(|field| field.name.text.hash(hasher));
+            return fields.iter().map(|field| &field.value).collect();
+        }
+    }
+    Vec::new()
+}
+
+/// Digests `term` and every term inside it into `digests`, bottom-up, so
+/// that each term is only hashed once. Candidates are counted in `copies`
+/// by digest.
+fn digest(term: &Term, digests: &mut Digests, copies: &mut HashMap<u64, usize>) -> u64 {
+    let mut chain = term.let_chain();
+    let bindings: Vec<_> = chain.by_ref().collect();
+    let mut digest = digest_one(chain.rest(), None, digests, copies);
+    for binding in bindings.into_iter().rev() {
+        digest = digest_one(binding, Some(digest), digests, copies);
+    }
+    digest
+}
+
+/// Digests `term`, a `let` whose rest of the chain has the digest `next`
+/// or any other term.
+fn digest_one(
+    term: &Term,
+    next: Option<u64>,
+    digests: &mut Digests,
+    copies: &mut HashMap<u64, usize>,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let mut subterms = subterms(term, &mut hasher);
+    subterms.len().hash(&mut hasher);
+    if next.is_some() {
+        subterms.pop();
+    }
+    for subterm in subterms {
+        digest(subterm, digests, copies).hash(&mut hasher);
+    }
+    next.hash(&mut hasher);
+    let digest = hasher.finish();
+    digests.insert(term, digest);
+    if is_candidate(term) {
+        *copies.entry(digest).or_default() += 1;
+    }
+    digest
+}
+
+/// Adds to `hot` the terms inside `term` that hold a candidate with more
+/// than one copy in `copies`, which are the only ones worth looking for
+/// copies in. Gives back whether `term` is one of them.
+fn heat(
+    term: &Term,
+    digests: &Digests,
+    copies: &HashMap<u64, usize>,
+    hot: &mut HashSet<*const Term>,
+) -> bool {
+    let mut chain = term.let_chain();
+    let bindings: Vec<_> = chain.by_ref().collect();
+    let mut is_hot = heat_one(chain.rest(), false, digests, copies, hot);
+    for binding in bindings.into_iter().rev() {
+        is_hot = heat_one(binding, is_hot, digests, copies, hot);
+    }
+    is_hot
+}
+
+/// Heats `term`, a `let` whose rest of the chain is hot when `next` is,
+/// or any other term.
+fn heat_one(
+    term: &Term,
+    next: bool,
+    digests: &Digests,
+    copies: &HashMap<u64, usize>,
+    hot: &mut HashSet<*const Term>,
+) -> bool {
+    let mut subterms = subterms(term, &mut DefaultHasher::new());
+    if term.next().is_some() {
+        subterms.pop();
+    }
+    let mut is_hot = next || is_candidate(term) && copies[&digests[&(term as *const Term)]] > 1;
+    for subterm in subterms {
+        is_hot |= heat(subterm, digests, copies, hot);
+    }
+    if is_hot {
+        hot.insert(term);
+    }
+    is_hot
+}
+
+/// A term to replace with a name: its digest, and its source to tell it
+/// apart from other terms with the same digest.
+struct Key {
+    digest: u64,
+    source: String,
+}
+
+impl Key {
+    /// Whether `term`, digested in `digests`, is a copy of the term.
+    fn matches(&self, term: &Rc<Term>, digests: &Digests) -> bool {
+        is_candidate(term)
+            && digests.get(&Rc::as_ptr(term)) == Some(&self.digest)
+            && term.to_source() == self.source
+    }
+}
+
+/// Replaces the copies of `key` where [`evaluated`] looks with `name`.
+/// Terms are looked up in `digests` before they are made unique, while
+/// they still have the address they were digested at.
+fn replace(term: &mut Rc<Term>, key: &Key, digests: &Digests, name: Symbol) {
+    if key.matches(term, digests) {
+        *term = Rc::new(Term::Var {
+            text: name,
+            index: None,
+            location: term.location().clone(),
+        });
+        return;
+    }
+    let replace = |term: &mut Rc<Term>| replace(term, key, digests, name);
+    match Rc::make_mut(term) {
+        Term::Int { .. }
+        | Term::Str { .. }
+        | Term::Bool { .. }
+        | Term::Var { .. }
+        | Term::Function { .. } => {}
+        Term::Let { value, .. }
+        | Term::LetTuple { value, .. }
+        | Term::First { value, .. }
+        | Term::Second { value, .. }
+        | Term::Field { value, .. }
+        | Term::Ref { value, .. }
+        | Term::Deref { value, .. }
+        | Term::Match { value, .. } => replace(value),
+        Term::Print { values, .. } => values.iter_mut().for_each(replace),
+        Term::If { condition, .. } => replace(condition),
+        Term::Binary { lhs, op, rhs, .. } => {
+            replace(lhs);
+            if !matches!(op, BinaryOp::And | BinaryOp::Or) {
+                replace(rhs);
+            }
+        }
+        Term::Call {
+            callee, arguments, ..
+        } => {
+            replace(callee);
+            arguments.iter_mut().for_each(replace);
+        }
+        Term::Tuple { first, second, .. } => {
+            replace(first);
+            replace(second);
+        }
+        Term::Record { fields, .. } => {
+            for field in fields {
+                replace(&mut field.value);
+            }
+        }
+        Term::Assign {
+            reference, value, ..
+        } => {
+            replace(reference);
+            replace(value);
+        }
+    }
+}
+
+impl Eliminator {
+    fn bound<T>(
+        &mut self,
+        names: impl IntoIterator<Item = Symbol>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let depth = self.scope.len();
+        self.scope
+            .extend(names.into_iter().map(|name| (name, false)));
+        let result = f(self);
+        self.scope.truncate(depth);
+        result
+    }
+
+    /// Whether evaluating `term` cannot fail: it is a literal or a name
+    /// bound around it.
+    fn is_leaf(&self, term: &Term) -> bool {
+        match term {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } => true,
+            Term::Var { text, .. } => self.scope.iter().rev().any(|(name, _)| name == text),
+            _ => false,
+        }
+    }
+
+    /// Whether calling the function bound to `name` with `parameters` and
+    /// `body` is pure.
+    fn is_pure_function(&mut self, name: Symbol, parameters: &[Parameter], body: &Term) -> bool {
+        // Calls to itself are pure if the rest of it is.
+        self.scope.push((name, true));
+        let names = parameters.iter().map(|p| p.text);
+        let result = self.bound(names, |this| this.is_pure(body));
+        self.scope.pop();
+        result
+    }
+
+    /// Whether evaluating `term` gives the same value every time, without
+    /// any other effect than failing or running forever.
+    fn is_pure(&mut self, term: &Term) -> bool {
+        match term {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => true,
+            // A new closure is a different value every time.
+            Term::Function { .. }
+            | Term::Print { .. }
+            | Term::Ref { .. }
+            | Term::Deref { .. }
+            | Term::Assign { .. } => false,
+            Term::Let {
+                name, value, next, ..
+            } => self.is_pure(value) && self.bound([name.text], |this| this.is_pure(next)),
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                next,
+                ..
+            } => {
+                self.is_pure(value)
+                    && self.bound([first.text, second.text], |this| this.is_pure(next))
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => self.is_pure(condition) && self.is_pure(then) && self.is_pure(otherwise),
+            Term::Binary { lhs, rhs, .. } => self.is_pure(lhs) && self.is_pure(rhs),
+            Term::Call {
+                callee, arguments, ..
+            } => {
+                let Term::Var { text, .. } = callee.as_ref() else {
+                    return false;
+                };
+                // Names bound nowhere are builtins, which may have effects.
+                let known = self.scope.iter().rev().find(|(name, _)| name == text);
+                matches!(known, Some((_, true)))
+                    && arguments.iter().all(|argument| self.is_pure(argument))
+            }
+            Term::Tuple { first, second, .. } => self.is_pure(first) && self.is_pure(second),
+            Term::First { value, .. } | Term::Second { value, .. } | Term::Field { value, .. } => {
+                self.is_pure(value)
+            }
+            Term::Match { value, arms, .. } => {
+                self.is_pure(value)
+                    && arms
+                        .iter()
+                        .all(|arm| self.bound(arm.pattern.names(), |this| this.is_pure(&arm.value)))
+            }
+            Term::Record { fields, .. } => fields.iter().all(|field| self.is_pure(&field.value)),
+        }
+    }
+
+    /// The first pure term evaluated twice by `term` that can be bound
+    /// before it, if any, with the digests of the terms in `term`.
+    fn common(&mut self, term: &Rc<Term>) -> Option<(Key, Rc<Term>, Digests)> {
+        let mut entries = Vec::new();
+        evaluated(term, &mut entries);
+        // `term` itself, last, has no copy inside it. Outer entries come
+        // after the ones inside them, whose digests they leave behind.
+        let candidates = entries[..entries.len() - 1]
+            .iter()
+            .filter(|e| is_candidate(e.term));
+        let mut digests = Digests::new();
+        let mut copies = HashMap::new();
+        for entry in candidates.rev() {
+            if !digests.contains_key(&Rc::as_ptr(entry.term)) {
+                digest(entry.term, &mut digests, &mut HashMap::new());
+            }
+            *copies.entry(digests[&Rc::as_ptr(entry.term)]).or_insert(0) += 1;
+        }
+        // Anything that runs before a term bound first must not be able
+        // to fail.
+        let barrier = entries
+            .iter()
+            .position(|e| !self.is_leaf(e.term))
+            .unwrap_or(entries.len());
+        for (index, entry) in entries.iter().enumerate() {
+            let Some(&digest) = digests.get(&Rc::as_ptr(entry.term)) else {
+                continue;
+            };
+            let repeated = is_candidate(entry.term) && copies.get(&digest) > Some(&1);
+            if entry.before > barrier || !repeated || !self.is_pure(entry.term) {
+                continue;
+            }
+            let key = Key {
+                digest,
+                source: entry.term.to_source(),
+            };
+            if entries[index + 1..]
+                .iter()
+                .any(|e| key.matches(e.term, &digests))
+            {
+                return Some((key, entry.term.clone(), digests));
+            }
+        }
+        None
+    }
+
+    /// Binds the terms `term` evaluates more than once before it.
+    fn lift(&mut self, term: &mut Rc<Term>) {
+        let Some((key, value, digests)) = self.common(term) else {
+            return;
+        };
+        let name = self.fresh.name("cse");
+        let mut next = term.clone();
+        replace(&mut next, &key, &digests, name);
+        self.bound([name], |this| this.lift(&mut next));
+        let location = term.location().clone();
+        *term = Rc::new(Term::Let {
+            name: Parameter {
+                text: name,
+                location: value.location().clone(),
+            },
+            value,
+            next,
+            location,
+        });
+    }
+
+    /// Nothing is bound before a `let` itself: it only evaluates its value
+    /// before binding it, which already had its terms bound.
+    fn bindings(&mut self, term: &mut Rc<Term>) {
+        let depth = self.scope.len();
+        let rest = Term::let_chain_mut(term, |binding| match binding {
+            Term::Let { name, value, .. } => {
+                let function = match Rc::make_mut(value) {
+                    // A function sees the name it is bound to.
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => {
+                        let names: Vec<_> = parameters.iter().map(|p| p.text).collect();
+                        let pure = self.is_pure_function(name.text, parameters, body);
+                        self.scope.push((name.text, pure));
+                        self.bound(names, |this| this.eliminate(body));
+                        self.scope.pop();
+                        pure
+                    }
+                    _ => {
+                        self.eliminate(value);
+                        false
+                    }
+                };
+                self.scope.push((name.text, function));
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                ..
+            } => {
+                self.eliminate(value);
+                self.scope
+                    .extend([(first.text, false), (second.text, false)]);
+            }
+            _ => unreachable!(),
+        });
+        self.eliminate(rest);
+        self.scope.truncate(depth);
+    }
+
+    fn eliminate(&mut self, term: &mut Rc<Term>) {
+        if term.next().is_some() {
+            return self.bindings(term);
+        }
+        let hot = self.hot.contains(&Rc::as_ptr(term));
+        match Rc::make_mut(term) {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => {}
+            Term::Let { .. } | Term::LetTuple { .. } => unreachable!("eliminated above"),
+            Term::Function {
+                parameters, value, ..
+            } => {
+                let names: Vec<_> = parameters.iter().map(|p| p.text).collect();
+                self.bound(names, |this| this.eliminate(value));
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.eliminate(condition);
+                self.eliminate(then);
+                self.eliminate(otherwise);
+            }
+            Term::Binary { lhs, rhs, .. } => {
+                self.eliminate(lhs);
+                self.eliminate(rhs);
+            }
+            Term::Call {
+                callee, arguments, ..
+            } => {
+                self.eliminate(callee);
+                for argument in arguments {
+                    self.eliminate(argument);
+                }
+            }
+            Term::Tuple { first, second, .. } => {
+                self.eliminate(first);
+                self.eliminate(second);
+            }
+            Term::Print { values, .. } => {
+                for value in values {
+                    self.eliminate(value);
+                }
+            }
+            Term::First { value, .. }
+            | Term::Second { value, .. }
+            | Term::Field { value, .. }
+            | Term::Ref { value, .. }
+            | Term::Deref { value, .. } => self.eliminate(value),
+            Term::Match { value, arms, .. } => {
+                self.eliminate(value);
+                for arm in arms {
+                    self.bound(arm.pattern.names(), |this| this.eliminate(&mut arm.value));
+                }
+            }
+            Term::Record { fields, .. } => {
+                for field in fields {
+                    self.eliminate(&mut field.value);
+                }
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                self.eliminate(reference);
+                self.eliminate(value);
+            }
+        }
+        if hot {
+            self.lift(term);
+        }
+    }
+}
+
+/// Binds the pure terms `file` evaluates more than once to a name, as
+/// described above.
+pub fn eliminate(file: &File) -> File {
+    let mut digests = Digests::new();
+    let mut copies = HashMap::new();
+    digest(&file.expression, &mut digests, &mut copies);
+    let mut hot = HashSet::new();
+    heat(&file.expression, &digests, &copies, &mut hot);
+    let mut eliminator = Eliminator {
+        scope: Vec::new(),
+        fresh: Fresh::new(file.expression.names()),
+        hot,
+    };
+    let mut expression = file.expression.clone();
+    eliminator.eliminate(&mut expression);
+    File {
+        expression,
+        ..file.clone()
+    }
+}
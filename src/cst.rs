@@ -0,0 +1,397 @@
+//! Lossless syntax trees, which the language server keeps up to date as a
+//! document is edited.
+//!
+//! A [`Cst`] holds every byte of the text it was read from in tokens with
+//! their byte ranges, whitespace and comments included. The tokens are
+//! grouped into the top-level items of the program, each `let` binding up
+//! to its `;` and the term they are bound in, and brackets nest into
+//! groups inside them. [`Cst::edit`] lexes and parses again only the items
+//! an edit touches and keeps the others, so a keystroke in a large file
+//! costs about as much as the binding it lands in.
+
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::lexer::{self, Lexeme, TokenKind};
+use crate::parser::{self, Binding, ParseError, Parser, Syntax};
+use crate::types::{File, Location};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub range: Range<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    /// A top-level `let` binding, from the trivia before it to its `;`.
+    Binding,
+    /// The term the bindings are bound in, up to the end of the text.
+    Term,
+    /// Brackets and what is between them. A group left open ends with the
+    /// item holding it.
+    Group,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub range: Range<usize>,
+    pub children: Vec<Element>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Element {
+    Node(Node),
+    Token(Token),
+}
+
+/// A top-level item of a [`Cst`]. Ranges inside its node count from the
+/// start of the item, so an edit before it only moves its start.
+#[derive(Debug)]
+pub struct Item {
+    start: usize,
+    node: Node,
+    /// The tokens the parser reads, ending with the end of the item.
+    tokens: Vec<(parser::Token, usize, usize)>,
+    /// The first error lexing or parsing the item.
+    error: Option<(String, Range<usize>)>,
+}
+
+impl Item {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.node.range.end
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.node.kind
+    }
+
+    /// The tree of the item, with ranges counted from its start.
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    fn parser<'a>(&self, filename: &'a str, syntax: Syntax) -> Parser<'a> {
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|(token, start, end)| (token.clone(), self.start + start, self.start + end))
+            .collect();
+        Parser::from_tokens(tokens, filename, syntax)
+    }
+}
+
+/// The lossless tree of a program.
+#[derive(Debug)]
+pub struct Cst {
+    text: String,
+    filename: String,
+    syntax: Syntax,
+    items: Vec<Item>,
+}
+
+impl Cst {
+    /// Reads `text` accepting the extensions enabled in `syntax`. Errors
+    /// do not stop it; they are kept in the items they are found in.
+    pub fn parse(text: &str, filename: &str, syntax: Syntax) -> Cst {
+        let items = Reader::new(text, 0, filename, syntax).collect();
+        Cst {
+            text: text.to_string(),
+            filename: filename.to_string(),
+            syntax,
+            items,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /// Every token in order, with ranges into the whole text. Their text
+    /// put together is the text of the tree.
+    pub fn tokens(&self) -> impl Iterator<Item = Token> + '_ {
+        self.items.iter().flat_map(|item| {
+            let mut tokens = Vec::new();
+            flatten(&item.node, item.start, &mut tokens);
+            tokens
+        })
+    }
+
+    /// The first error of each item that has one.
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.items
+            .iter()
+            .filter_map(|item| {
+                let (message, range) = item.error.as_ref()?;
+                Some(self.error(
+                    message.clone(),
+                    item.start + range.start..item.start + range.end,
+                ))
+            })
+            .collect()
+    }
+
+    fn error(&self, message: String, range: Range<usize>) -> ParseError {
+        ParseError {
+            message,
+            location: Location {
+                start: range.start,
+                end: range.end,
+                filename: self.filename.clone(),
+            },
+        }
+    }
+
+    /// The program, as [`parser::parse_with`] reads the same text, built
+    /// from the tokens already lexed.
+    pub fn file(&self) -> Result<File, ParseError> {
+        if let Some(error) = self.errors().into_iter().next() {
+            return Err(error);
+        }
+        let (last, bindings) = self.items.split_last().expect("a tree has a last item");
+        let bindings = bindings
+            .iter()
+            .map(|item| {
+                let mut parser = item.parser(&self.filename, self.syntax);
+                let binding = parser.binding()?;
+                parser.finish(binding)
+            })
+            .collect::<Result<Vec<Binding>, ParseError>>()?;
+        let mut parser = last.parser(&self.filename, self.syntax);
+        let term = parser.term()?;
+        let term = parser.finish(term)?;
+        let end = last
+            .tokens
+            .iter()
+            .rev()
+            .nth(1)
+            .map_or(last.start, |(_, _, end)| last.start + end);
+        Ok(File {
+            name: self.filename.clone(),
+            expression: Rc::new(parser::bind(bindings, term, end, &self.filename)),
+            location: Location {
+                start: 0,
+                end: self.text.len(),
+                filename: self.filename.clone(),
+            },
+        })
+    }
+
+    /// Replaces the bytes in `range` with `replacement` and reads again the
+    /// items the edit touches, up to the first item after it that ends
+    /// where an old one did. Returns the indices of the items read again.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Range<usize> {
+        self.text.replace_range(range.clone(), replacement);
+        let delta = replacement.len() as isize - range.len() as isize;
+        let edited_end = range.start + replacement.len();
+        // Items ending right where the edit starts are read again, as the
+        // edit may extend their last token.
+        let first = self
+            .items
+            .partition_point(|item| item.range().end < range.start);
+        let start = self.items[first].start;
+
+        let mut read = Vec::new();
+        let mut kept = self.items.len();
+        for item in Reader::new(&self.text, start, &self.filename, self.syntax) {
+            let end = item.range().end;
+            let binding = item.node.kind == NodeKind::Binding;
+            read.push(item);
+            if !binding || end < edited_end {
+                continue;
+            }
+            // Past the edit, an item ending after a `;` where an old one
+            // did is followed by the same text, which reads the same.
+            let old_end = (end as isize - delta) as usize;
+            let old = &self.items[first..];
+            let index = old.partition_point(|item| item.range().end < old_end);
+            if old.get(index).is_some_and(|item| {
+                item.range().end == old_end && item.node.kind == NodeKind::Binding
+            }) {
+                kept = first + index + 1;
+                break;
+            }
+        }
+
+        let count = read.len();
+        let mut rest = self.items.split_off(kept);
+        for item in &mut rest {
+            item.start = (item.start as isize + delta) as usize;
+        }
+        self.items.truncate(first);
+        self.items.extend(read);
+        self.items.extend(rest);
+        first..first + count
+    }
+}
+
+fn flatten(node: &Node, offset: usize, tokens: &mut Vec<Token>) {
+    for child in &node.children {
+        match child {
+            Element::Node(node) => flatten(node, offset, tokens),
+            Element::Token(token) => tokens.push(Token {
+                kind: token.kind,
+                range: offset + token.range.start..offset + token.range.end,
+            }),
+        }
+    }
+}
+
+/// Reads the items of a text one at a time from a byte offset where one
+/// starts.
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+    filename: &'a str,
+    syntax: Syntax,
+    done: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(text: &'a str, pos: usize, filename: &'a str, syntax: Syntax) -> Reader<'a> {
+        Reader {
+            text,
+            pos,
+            filename,
+            syntax,
+            done: false,
+        }
+    }
+
+    fn parse(&self, item: &mut Item) {
+        if item.error.is_some() {
+            return;
+        }
+        let mut parser = item.parser(self.filename, self.syntax);
+        let result = match item.node.kind {
+            NodeKind::Binding => parser
+                .binding()
+                .and_then(|binding| parser.finish(binding).map(drop)),
+            _ => parser.term().and_then(|term| parser.finish(term).map(drop)),
+        };
+        if let Err(err) = result {
+            let start = err.location.start - item.start;
+            let end = err.location.end - item.start;
+            item.error = Some((err.message, start..end));
+        }
+    }
+}
+
+impl Iterator for Reader<'_> {
+    type Item = Item;
+
+    fn next(&mut self) -> Option<Item> {
+        if self.done {
+            return None;
+        }
+        let start = self.pos;
+        let mut item = Item {
+            start,
+            node: Node {
+                kind: NodeKind::Term,
+                range: 0..0,
+                children: Vec::new(),
+            },
+            tokens: Vec::new(),
+            error: None,
+        };
+        // Open groups, each with the token that closes it.
+        let mut groups: Vec<(Node, parser::Token)> = Vec::new();
+        // Bindings at the top level still waiting for their `;`, or `None`
+        // when the item does not start with `let` and so runs to the end.
+        let mut lets = None;
+        loop {
+            let Some(Lexeme {
+                kind,
+                range,
+                token: lexed,
+                error,
+            }) = lexer::lex(self.text, self.pos, self.filename)
+            else {
+                self.done = true;
+                break;
+            };
+            self.pos = range.end;
+            let token = Token {
+                kind,
+                range: range.start - start..range.end - start,
+            };
+            if let Some((message, at)) = error {
+                if item.error.is_none() {
+                    item.error = Some((message, at - start..token.range.end));
+                }
+            }
+            let Some(lexed) = lexed else {
+                push(&mut groups, &mut item.node, Element::Token(token));
+                continue;
+            };
+            item.tokens
+                .push((lexed.clone(), token.range.start, token.range.end));
+            let range = token.range.clone();
+            let top_level = groups.is_empty();
+            let closes = groups.last().is_some_and(|(_, closer)| *closer == lexed);
+            match lexed {
+                parser::Token::LParen | parser::Token::LBrace => {
+                    let closer = if lexed == parser::Token::LParen {
+                        parser::Token::RParen
+                    } else {
+                        parser::Token::RBrace
+                    };
+                    let group = Node {
+                        kind: NodeKind::Group,
+                        range: range.clone(),
+                        children: vec![Element::Token(token)],
+                    };
+                    groups.push((group, closer));
+                }
+                _ if closes => {
+                    let (mut group, _) = groups.pop().expect("a group is open");
+                    group.children.push(Element::Token(token));
+                    group.range.end = range.end;
+                    push(&mut groups, &mut item.node, Element::Node(group));
+                }
+                _ => push(&mut groups, &mut item.node, Element::Token(token)),
+            }
+            if !top_level {
+                continue;
+            }
+            match (&lexed, lets) {
+                (parser::Token::Let, None) if item.tokens.len() == 1 => lets = Some(1),
+                (parser::Token::Let, Some(n)) => lets = Some(n + 1),
+                (parser::Token::Semicolon, Some(1)) => {
+                    item.node.kind = NodeKind::Binding;
+                    break;
+                }
+                (parser::Token::Semicolon, Some(n)) => lets = Some(n - 1),
+                _ => {}
+            }
+        }
+        while let Some((group, _)) = groups.pop() {
+            push(&mut groups, &mut item.node, Element::Node(group));
+        }
+        let end = self.pos - start;
+        item.node.range = 0..end;
+        item.tokens.push((parser::Token::Eof, end, end));
+        self.parse(&mut item);
+        Some(item)
+    }
+}
+
+/// Adds `element` to the innermost open group, or to the item itself.
+fn push(groups: &mut [(Node, parser::Token)], item: &mut Node, element: Element) {
+    match groups.last_mut() {
+        Some((group, _)) => {
+            group.range.end = match &element {
+                Element::Node(node) => node.range.end,
+                Element::Token(token) => token.range.end,
+            };
+            group.children.push(element);
+        }
+        None => item.children.push(element),
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use rinha::error::{print_diagnostic, SourceMap};
+use rinha::interpreter::{self, Debugger, Frame, Scope};
+use rinha::types::{File, Location, Term};
+use rinha::{Fuel, InterpreterConfig};
+
+const HELP: &str = "\
+commands:
+  break <offset>   stop at the first term starting at or after <offset>
+  delete <offset>  remove the breakpoint at <offset>
+  step             run until the next term
+  next             run until the next term outside of calls made here
+  continue         run until the next breakpoint
+  print <name>     show the value bound to <name>
+  backtrace        show the active calls
+  quit             stop the program";
+
+/// How far to run before stopping again.
+enum Mode {
+    Step,
+    /// Stops at a term of a call at most this deep.
+    Next(usize),
+    Continue,
+}
+
+struct Stepper {
+    /// Start offsets of every term in the program, where breakpoints can
+    /// be set.
+    starts: BTreeSet<usize>,
+    breakpoints: BTreeSet<usize>,
+    /// Where the program last stopped, until it reaches a term starting
+    /// elsewhere, so a breakpoint stops once rather than again for each
+    /// term inside that starts at the same offset.
+    stopped_at: Option<usize>,
+    mode: Mode,
+}
+
+fn collect_starts(term: &Term, starts: &mut BTreeSet<usize>) {
+    starts.insert(term.location().start);
+    match term {
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => {}
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => {
+            collect_starts(condition, starts);
+            collect_starts(then, starts);
+            collect_starts(otherwise, starts);
+        }
+        Term::Let { value, next, .. } | Term::LetTuple { value, next, .. } => {
+            collect_starts(value, starts);
+            collect_starts(next, starts);
+        }
+        Term::Binary { lhs, rhs, .. } => {
+            collect_starts(lhs, starts);
+            collect_starts(rhs, starts);
+        }
+        Term::Call {
+            callee, arguments, ..
+        } => {
+            collect_starts(callee, starts);
+            for argument in arguments {
+                collect_starts(argument, starts);
+            }
+        }
+        Term::Tuple { first, second, .. } => {
+            collect_starts(first, starts);
+            collect_starts(second, starts);
+        }
+        Term::Function { value, .. } | Term::First { value, .. } | Term::Second { value, .. } => {
+            collect_starts(value, starts)
+        }
+        Term::Print { values, .. } => {
+            for value in values {
+                collect_starts(value, starts);
+            }
+        }
+        Term::Match { value, arms, .. } => {
+            collect_starts(value, starts);
+            for arm in arms {
+                collect_starts(&arm.value, starts);
+            }
+        }
+        Term::Record { fields, .. } => {
+            for field in fields {
+                collect_starts(&field.value, starts);
+            }
+        }
+        Term::Field { value, .. } | Term::Ref { value, .. } | Term::Deref { value, .. } => {
+            collect_starts(value, starts)
+        }
+        Term::Assign {
+            reference, value, ..
+        } => {
+            collect_starts(reference, starts);
+            collect_starts(value, starts);
+        }
+    }
+}
+
+fn show_location(location: &Location) -> String {
+    format!("{}:{}..{}", location.filename, location.start, location.end)
+}
+
+fn parse_offset(argument: &str) -> Option<usize> {
+    let offset = argument.trim().parse();
+    if offset.is_err() {
+        eprintln!("error: expected an offset but found `{}`", argument.trim());
+    }
+    offset.ok()
+}
+
+impl Stepper {
+    fn should_stop(&self, term: &Term, frames: &[Frame]) -> bool {
+        match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) if frames.len() <= depth => true,
+            _ => {
+                let start = term.location().start;
+                self.stopped_at != Some(start) && self.breakpoints.contains(&start)
+            }
+        }
+    }
+
+    /// Reads commands until one of them resumes the program.
+    fn prompt(&mut self, scope: &Scope, frames: &[Frame]) {
+        let mut lines = io::stdin().lock().lines();
+        loop {
+            print!("(debug) ");
+            io::stdout()
+                .flush()
+                .expect("Falha ao escrever na saída padrão");
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                // Without more commands, run the rest of the program.
+                _ => {
+                    println!();
+                    self.breakpoints.clear();
+                    self.mode = Mode::Continue;
+                    return;
+                }
+            };
+            let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            match command {
+                "" => {}
+                "s" | "step" => {
+                    self.mode = Mode::Step;
+                    return;
+                }
+                "n" | "next" => {
+                    self.mode = Mode::Next(frames.len());
+                    return;
+                }
+                "c" | "continue" => {
+                    self.mode = Mode::Continue;
+                    return;
+                }
+                "b" | "break" => {
+                    let Some(offset) = parse_offset(argument) else {
+                        continue;
+                    };
+                    match self.starts.range(offset..).next() {
+                        Some(&start) => {
+                            self.breakpoints.insert(start);
+                            println!("breakpoint at offset {}", start);
+                        }
+                        None => eprintln!("error: no term starts at or after offset {}", offset),
+                    }
+                }
+                "d" | "delete" => {
+                    let Some(offset) = parse_offset(argument) else {
+                        continue;
+                    };
+                    if !self.breakpoints.remove(&offset) {
+                        eprintln!("error: no breakpoint at offset {}", offset);
+                    }
+                }
+                "p" | "print" => match scope.get(argument.trim()) {
+                    Some(value) => println!("{} = {}", argument.trim(), value),
+                    None => eprintln!("error: cannot find variable {}", argument.trim()),
+                },
+                "bt" | "backtrace" => {
+                    for (depth, frame) in frames.iter().enumerate().rev() {
+                        let name = frame.name().map_or("<anonymous>", |name| name.as_str());
+                        println!(
+                            "#{} {} called at {}",
+                            depth,
+                            name,
+                            show_location(frame.location())
+                        );
+                    }
+                }
+                "q" | "quit" => process::exit(0),
+                "h" | "help" => println!("{}", HELP),
+                _ => eprintln!("error: unknown command `{}`; try `help`", command),
+            }
+        }
+    }
+}
+
+impl Debugger for Stepper {
+    fn before(&mut self, term: &Term, scope: &Scope, frames: &[Frame]) {
+        let start = term.location().start;
+        if self.should_stop(term, frames) {
+            self.stopped_at = Some(start);
+            println!("{} {}", term.kind(), show_location(term.location()));
+            self.prompt(scope, frames);
+        } else if self.stopped_at != Some(start) {
+            self.stopped_at = None;
+        }
+    }
+}
+
+/// Runs `file` with the tree-walker, stopping before its first term to
+/// read commands from stdin.
+pub fn run(file: &File, memoize: bool, sources: &SourceMap) {
+    let mut starts = BTreeSet::new();
+    collect_starts(&file.expression, &mut starts);
+    let mut stepper = Stepper {
+        starts,
+        breakpoints: BTreeSet::new(),
+        stopped_at: None,
+        mode: Mode::Step,
+    };
+    let mut stdout = io::stdout();
+    let result = interpreter::interpret_file_debug(
+        file,
+        memoize,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        &mut stdout,
+        &mut stepper,
+    );
+    match result {
+        Ok(value) => crate::print_value(&value),
+        Err(err) => {
+            print_diagnostic(sources, &err, err.location());
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! The deepest recursion programs may reach, for `--max-depth`.
+//!
+//! Both engines keep calls on the heap rather than on the Rust stack, so
+//! recursion that never ends would otherwise grow until the process runs
+//! out of memory and aborts. Instead, a call that would nest deeper than
+//! the limit stops the program with [`RuntimeError::RecursionLimit`].
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::RuntimeError;
+use crate::stats;
+use crate::types::Location;
+
+/// Deep enough for any reasonable recursion; a million calls take a few
+/// hundred megabytes.
+pub const DEFAULT_LIMIT: usize = 1_000_000;
+
+static LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_LIMIT);
+
+/// Sets the most calls that may be active at once.
+pub fn set_limit(calls: usize) {
+    LIMIT.store(calls, Ordering::Relaxed);
+}
+
+/// Fails when `calls`, the number of active calls counting the one being
+/// made at `location`, is more than the limit allows.
+pub fn check(calls: usize, location: &Location) -> Result<(), RuntimeError> {
+    stats::depth(calls);
+    let limit = LIMIT.load(Ordering::Relaxed);
+    if calls > limit {
+        return Err(RuntimeError::RecursionLimit {
+            limit,
+            location: location.clone(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Runs a program with every engine to find where the faster ones stop
+//! agreeing with the tree-walker.
+
+use crate::{Engine, File, Fuel, InterpreterConfig};
+
+/// What one engine made of a program: a line per `print`, then a line
+/// with the final value or the error it stopped with.
+pub struct Run {
+    pub engine: Engine,
+    pub lines: Vec<String>,
+}
+
+/// The first line on which the runs differ, with what each of them has
+/// there, or `None` for runs that ended before it.
+pub struct Divergence {
+    pub line: usize,
+    pub lines: Vec<(Engine, Option<String>)>,
+}
+
+/// Runs `file` with each engine in [`Engine::ALL`].
+pub fn run_all(file: &File, memoize: bool, config: InterpreterConfig, fuel: Fuel) -> Vec<Run> {
+    Engine::ALL
+        .into_iter()
+        .map(|engine| {
+            let mut lines = Vec::new();
+            match engine.run(file, memoize, config, fuel, &mut lines) {
+                Ok(value) => lines.push(format!("value: {}", value)),
+                Err(err) => {
+                    let location = err.location();
+                    lines.push(format!(
+                        "error: {} at {}:{}..{}",
+                        err, location.filename, location.start, location.end
+                    ));
+                }
+            }
+            Run { engine, lines }
+        })
+        .collect()
+}
+
+pub fn divergence(runs: &[Run]) -> Option<Divergence> {
+    let longest = runs.iter().map(|run| run.lines.len()).max()?;
+    (0..longest).find_map(|line| {
+        let lines: Vec<_> = runs
+            .iter()
+            .map(|run| (run.engine, run.lines.get(line).cloned()))
+            .collect();
+        let differs = lines.iter().any(|(_, text)| *text != lines[0].1);
+        differs.then_some(Divergence { line, lines })
+    })
+}
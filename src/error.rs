@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::color::{self, Style};
+use crate::types::Location;
+
+/// The text of the files a program was loaded from, so diagnostics can
+/// quote the code they point at.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: HashMap<String, String>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    pub fn add(&mut self, filename: &str, source: &str) {
+        self.files.insert(filename.to_string(), source.to_string());
+    }
+
+    pub fn get(&self, filename: &str) -> Option<&str> {
+        self.files.get(filename).map(String::as_str)
+    }
+
+    /// Names of the files added so far, in no particular order.
+    pub fn filenames(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// Where `location` starts as `file:line:column`, or its span of bytes
+    /// when the source of the file is not known.
+    pub fn position(&self, location: &Location) -> String {
+        match self.excerpt(location) {
+            Some((line, column, ..)) => format!("{}:{}:{}", location.filename, line, column),
+            None => format!("{}:{}..{}", location.filename, location.start, location.end),
+        }
+    }
+
+    /// The line and column, both counted from 1, where `location` starts,
+    /// the text of that line and how many of its characters the span
+    /// covers. Spans over several lines only cover the rest of the first.
+    fn excerpt(&self, location: &Location) -> Option<(usize, usize, &str, usize)> {
+        let source = self.get(&location.filename)?;
+        let start = location.start;
+        if start > source.len() || !source.is_char_boundary(start) {
+            return None;
+        }
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let span = source
+            .get(start..location.end.clamp(start, line_end))
+            .map_or(0, |span| span.chars().count());
+        Some((line, column, &source[line_start..line_end], span))
+    }
+}
+
+/// Prints `message` to stderr together with the span it refers to, quoting
+/// the code of the span when `sources` has it.
+pub fn print_diagnostic(sources: &SourceMap, message: &dyn fmt::Display, location: &Location) {
+    eprint!(
+        "{}",
+        diagnostic(sources, message, location, color::stderr())
+    );
+}
+
+/// Like [`print_diagnostic`], for problems that do not stop the program.
+pub fn print_warning(sources: &SourceMap, message: &dyn fmt::Display, location: &Location) {
+    eprint!(
+        "{}",
+        labeled(sources, Style::Warning, message, location, color::stderr())
+    );
+}
+
+/// The text [`print_diagnostic`] writes, colored when `color` is set.
+pub fn diagnostic(
+    sources: &SourceMap,
+    message: &dyn fmt::Display,
+    location: &Location,
+    color: bool,
+) -> String {
+    labeled(sources, Style::Error, message, location, color)
+}
+
+fn labeled(
+    sources: &SourceMap,
+    style: Style,
+    message: &dyn fmt::Display,
+    location: &Location,
+    color: bool,
+) -> String {
+    let label = match style {
+        Style::Warning => "warning",
+        _ => "error",
+    };
+    let mut out = format!("{}: {}\n", color::paint(label, style, color), message);
+    let Some((line, column, text, span)) = sources.excerpt(location) else {
+        let position = format!("{}:{}..{}", location.filename, location.start, location.end);
+        out.push_str(&format!(
+            "  {} {}\n",
+            color::paint("-->", Style::Note, color),
+            position
+        ));
+        return out;
+    };
+    let number = line.to_string();
+    let gutter = " ".repeat(number.len());
+    let bar = color::paint("|", Style::Note, color);
+    out.push_str(&format!(
+        "{}{} {}:{}:{}\n",
+        gutter,
+        color::paint("-->", Style::Note, color),
+        location.filename,
+        line,
+        column
+    ));
+    out.push_str(&format!("{} {}\n", gutter, bar));
+    // Columns count characters, so the span is split on them too.
+    let start = text
+        .char_indices()
+        .nth(column - 1)
+        .map_or(text.len(), |(i, _)| i);
+    let end = text[start..]
+        .char_indices()
+        .nth(span)
+        .map_or(text.len(), |(i, _)| start + i);
+    out.push_str(&format!(
+        "{} {} {}{}{}\n",
+        color::paint(&number, Style::Note, color),
+        bar,
+        &text[..start],
+        color::paint(&text[start..end], Style::Underline, color),
+        &text[end..]
+    ));
+    out.push_str(&format!(
+        "{} {} {}{}\n",
+        gutter,
+        bar,
+        " ".repeat(column - 1),
+        color::paint(&"^".repeat(span.max(1)), style, color)
+    ));
+    out
+}
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+        location: Location,
+    },
+    UnboundVariable {
+        name: String,
+        location: Location,
+    },
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+        location: Location,
+    },
+    IntegerOverflow {
+        location: Location,
+    },
+    DivisionByZero {
+        location: Location,
+    },
+    BudgetExceeded {
+        location: Location,
+    },
+    OutOfMemory {
+        location: Location,
+    },
+    /// The program was stopped with ctrl-c, see [`crate::interrupt`],
+    /// after making `calls` calls.
+    Interrupted {
+        calls: u64,
+        location: Location,
+    },
+    /// A call would nest deeper than [`crate::depth`] allows.
+    RecursionLimit {
+        limit: usize,
+        location: Location,
+    },
+    EmptyList {
+        operation: &'static str,
+        location: Location,
+    },
+    IndexOutOfRange {
+        index: i64,
+        length: usize,
+        location: Location,
+    },
+    InvalidInt {
+        text: String,
+        location: Location,
+    },
+    /// A record does not have the field a `Field` reads.
+    NoField {
+        name: String,
+        location: Location,
+    },
+    /// No arm of a `Match` has a pattern the value matches.
+    NoMatch {
+        location: Location,
+    },
+    /// A closure was compared or printed where [`crate::InterpreterConfig`]
+    /// does not allow it.
+    Closure {
+        operation: &'static str,
+        location: Location,
+    },
+    /// An error reported by `read_line` or by a function registered with
+    /// [`crate::interpreter::Session::register_builtin`].
+    Builtin {
+        name: String,
+        message: String,
+        location: Location,
+    },
+}
+
+impl RuntimeError {
+    pub fn location(&self) -> &Location {
+        match self {
+            RuntimeError::TypeMismatch { location, .. }
+            | RuntimeError::UnboundVariable { location, .. }
+            | RuntimeError::ArityMismatch { location, .. }
+            | RuntimeError::IntegerOverflow { location }
+            | RuntimeError::DivisionByZero { location }
+            | RuntimeError::BudgetExceeded { location }
+            | RuntimeError::OutOfMemory { location }
+            | RuntimeError::Interrupted { location, .. }
+            | RuntimeError::RecursionLimit { location, .. }
+            | RuntimeError::EmptyList { location, .. }
+            | RuntimeError::IndexOutOfRange { location, .. }
+            | RuntimeError::InvalidInt { location, .. }
+            | RuntimeError::NoField { location, .. }
+            | RuntimeError::NoMatch { location }
+            | RuntimeError::Closure { location, .. }
+            | RuntimeError::Builtin { location, .. } => location,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::TypeMismatch {
+                expected, found, ..
+            } => write!(f, "expected {} but found {}", expected, found),
+            RuntimeError::UnboundVariable { name, .. } => {
+                write!(f, "cannot find variable {}", name)
+            }
+            RuntimeError::ArityMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "expected {} arguments but instead got {}",
+                expected, found
+            ),
+            RuntimeError::IntegerOverflow { .. } => write!(f, "integer overflow"),
+            RuntimeError::DivisionByZero { .. } => write!(f, "division by zero"),
+            RuntimeError::BudgetExceeded { .. } => write!(f, "execution budget exceeded"),
+            RuntimeError::OutOfMemory { .. } => write!(f, "memory limit exceeded"),
+            RuntimeError::Interrupted { calls, .. } => {
+                write!(f, "interrupted after {} calls", calls)
+            }
+            RuntimeError::RecursionLimit { limit, .. } => {
+                write!(f, "maximum recursion depth {} exceeded", limit)
+            }
+            RuntimeError::EmptyList { operation, .. } => {
+                write!(f, "cannot take the {} of an empty list", operation)
+            }
+            RuntimeError::IndexOutOfRange { index, length, .. } => write!(
+                f,
+                "index {} is out of range for a string of length {}",
+                index, length
+            ),
+            RuntimeError::InvalidInt { text, .. } => {
+                write!(f, "cannot convert {:?} to int", text)
+            }
+            RuntimeError::NoField { name, .. } => write!(f, "record has no field {}", name),
+            RuntimeError::NoMatch { .. } => write!(f, "no pattern matches the value"),
+            RuntimeError::Closure { operation, .. } => write!(f, "cannot {} closures", operation),
+            RuntimeError::Builtin { name, message, .. } => write!(f, "{}: {}", name, message),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Escape analysis of tuples bound by `let`, for [`crate::compiler`].
+//!
+//! A tuple that a program only takes apart, as in
+//!
+//! ```text
+//! let p = (a, b); first(p) + second(p)
+//! ```
+//!
+//! never needs to exist: its elements can stay where they were computed.
+//! [`is_projected`] tells when every use of a name is such a projection.
+
+use crate::symbol::Symbol;
+use crate::types::*;
+
+/// Whether `term` only uses `name`, as bound around it, as the operand of
+/// `first` or `second`. A function that uses it at all lets it escape, as
+/// the function may outlive the binding.
+pub fn is_projected(name: Symbol, term: &Term) -> bool {
+    let projected = |term: &Term| is_projected(name, term);
+    match term {
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } => true,
+        Term::Var { text, .. } => *text != name,
+        Term::First { value, .. } | Term::Second { value, .. } => {
+            matches!(value.as_ref(), Term::Var { text, .. } if *text == name) || projected(value)
+        }
+        Term::Let {
+            name: bound,
+            value,
+            next,
+            ..
+        } => {
+            // A function sees the name it is bound to.
+            let recursive = bound.text == name && matches!(value.as_ref(), Term::Function { .. });
+            (recursive || projected(value)) && (bound.text == name || projected(next))
+        }
+        Term::LetTuple {
+            first,
+            second,
+            value,
+            next,
+            ..
+        } => projected(value) && (first.text == name || second.text == name || projected(next)),
+        Term::Function {
+            parameters, value, ..
+        } => parameters.iter().any(|p| p.text == name) || !value.free_names().contains(&name),
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => projected(condition) && projected(then) && projected(otherwise),
+        Term::Binary { lhs, rhs, .. } => projected(lhs) && projected(rhs),
+        Term::Call {
+            callee, arguments, ..
+        } => projected(callee) && arguments.iter().all(|argument| projected(argument)),
+        Term::Tuple { first, second, .. } => projected(first) && projected(second),
+        Term::Print { values, .. } => values.iter().all(|value| projected(value)),
+        Term::Field { value, .. } | Term::Ref { value, .. } | Term::Deref { value, .. } => {
+            projected(value)
+        }
+        Term::Match { value, arms, .. } => {
+            projected(value)
+                && arms
+                    .iter()
+                    .all(|arm| arm.pattern.names().contains(&name) || projected(&arm.value))
+        }
+        Term::Record { fields, .. } => fields.iter().all(|field| projected(&field.value)),
+        Term::Assign {
+            reference, value, ..
+        } => projected(reference) && projected(value),
+    }
+}
@@ -0,0 +1,38 @@
+use crate::error::RuntimeError;
+use crate::stats;
+use crate::types::Location;
+
+/// Budget of function calls a program may make before it is stopped. Every
+/// recursion goes through a call, so a finite budget ends any program that
+/// would otherwise loop forever. Both engines count calls the same way.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fuel(Option<u64>);
+
+impl Fuel {
+    pub fn unlimited() -> Fuel {
+        Fuel(None)
+    }
+
+    pub fn limited(steps: u64) -> Fuel {
+        Fuel(Some(steps))
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Spends one step for the call at `location`.
+    pub fn consume(&mut self, location: &Location) -> Result<(), RuntimeError> {
+        stats::call();
+        match &mut self.0 {
+            Some(0) => Err(RuntimeError::BudgetExceeded {
+                location: location.clone(),
+            }),
+            Some(steps) => {
+                *steps -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Frees reference cycles, which reference counting alone never does.
+//!
+//! Values are immutable once built, so the only way for one to end up
+//! holding itself is through a ref, such as a closure stored in a ref it
+//! captures. Every ref is made with [`new_ref`], which remembers it; after
+//! enough of them have been made, [`collect`] looks at what the live ones
+//! hold. Anything the program still uses is reachable from somewhere the
+//! collector cannot see, like an environment of the running code or the
+//! VM stack, and so has more strong counts than the references found while
+//! tracing. Refs that are only held from inside cycles are emptied, which
+//! breaks the cycles and lets reference counting free them.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+
+use crate::value::{Closure, Value};
+
+/// Refs made since the last collection before another one runs, unless
+/// more than this many survived it.
+const MIN_THRESHOLD: usize = 1024;
+
+struct Heap {
+    refs: Vec<Weak<RefCell<Value>>>,
+    made: usize,
+    threshold: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const {
+        RefCell::new(Heap {
+            refs: Vec::new(),
+            made: 0,
+            threshold: MIN_THRESHOLD,
+        })
+    };
+}
+
+/// Makes a ref holding `value`, collecting cycles first when enough refs
+/// were made since the last time.
+pub(crate) fn new_ref(value: Value) -> Value {
+    let due = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        heap.made >= heap.threshold
+    });
+    if due {
+        collect();
+    }
+    let cell = Rc::new(RefCell::new(value));
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.refs.push(Rc::downgrade(&cell));
+        heap.made += 1;
+    });
+    Value::Ref(cell)
+}
+
+/// Frees every cycle of refs the program can no longer reach and returns
+/// how many refs were emptied to do it. Runs on its own after enough refs
+/// are made; call it directly once a program is done with its values.
+pub fn collect() -> usize {
+    let refs: Vec<_> = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.made = 0;
+        heap.refs.drain(..).filter_map(|r| r.upgrade()).collect()
+    });
+
+    let mut tracer = Tracer::default();
+    for cell in &refs {
+        tracer.trace_ref(cell);
+    }
+    let live = tracer.live();
+
+    let mut survivors = Vec::new();
+    let mut emptied = Vec::new();
+    for cell in &refs {
+        if live.contains(&address(cell)) {
+            survivors.push(Rc::downgrade(cell));
+        } else {
+            // Nothing can read the ref anymore, so what it holds instead
+            // does not matter.
+            emptied.push(cell.replace(Value::Boolean(false)));
+        }
+    }
+    let count = emptied.len();
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.threshold = MIN_THRESHOLD.max(survivors.len());
+        heap.refs = survivors;
+    });
+    drop(emptied);
+    count
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+struct Node {
+    /// Strong counts of the allocation, besides the one [`collect`] holds
+    /// of each ref.
+    strong: usize,
+    /// References to the allocation found while tracing.
+    found: usize,
+    edges: Vec<usize>,
+}
+
+/// The graph of allocations reachable from the refs, built by the `trace`
+/// methods of the types that hold values.
+#[derive(Default)]
+pub(crate) struct Tracer {
+    nodes: HashMap<usize, Node>,
+    /// The allocation whose contents are being traced.
+    parent: Option<usize>,
+}
+
+impl Tracer {
+    /// Records that the allocation being traced holds `rc`. The first time
+    /// `rc` is reached, makes it the one being traced and returns the
+    /// previous one, to be given back to [`Tracer::leave`] once its
+    /// contents are traced.
+    pub(crate) fn enter<T>(&mut self, rc: &Rc<T>) -> Option<Option<usize>> {
+        self.enter_at(address(rc), Rc::strong_count(rc))
+    }
+
+    fn enter_at(&mut self, address: usize, strong: usize) -> Option<Option<usize>> {
+        if let Some(parent) = self.parent {
+            if let Some(node) = self.nodes.get_mut(&parent) {
+                node.edges.push(address);
+            }
+        }
+        if let Some(node) = self.nodes.get_mut(&address) {
+            node.found += 1;
+            return None;
+        }
+        let node = Node {
+            strong,
+            found: usize::from(self.parent.is_some()),
+            edges: Vec::new(),
+        };
+        self.nodes.insert(address, node);
+        Some(self.parent.replace(address))
+    }
+
+    pub(crate) fn leave(&mut self, parent: Option<usize>) {
+        self.parent = parent;
+    }
+
+    pub(crate) fn trace_value(&mut self, value: &Value) {
+        match value {
+            Value::Tuple(tuple) => {
+                if let Some(parent) = self.enter(tuple) {
+                    self.trace_value(&tuple.0);
+                    self.trace_value(&tuple.1);
+                    self.leave(parent);
+                }
+            }
+            Value::Record(fields) => {
+                if let Some(parent) = self.enter(fields) {
+                    for value in fields.values() {
+                        self.trace_value(value);
+                    }
+                    self.leave(parent);
+                }
+            }
+            Value::List(list) => list.trace(self),
+            Value::Closure(Closure::Tree(closure)) => {
+                if let Some(parent) = self.enter(closure) {
+                    closure.trace(self);
+                    self.leave(parent);
+                }
+            }
+            Value::Closure(Closure::Bytecode(closure)) => {
+                if let Some(parent) = self.enter(closure) {
+                    for value in &closure.captures {
+                        self.trace_value(value);
+                    }
+                    self.leave(parent);
+                }
+            }
+            Value::Closure(Closure::Partial(partial)) => {
+                if let Some(parent) = self.enter(partial) {
+                    self.trace_value(&Value::Closure(partial.callee.clone()));
+                    for value in &partial.arguments {
+                        self.trace_value(value);
+                    }
+                    self.leave(parent);
+                }
+            }
+            Value::Ref(cell) => self.trace_ref(cell),
+            // Natives, strings and numbers hold no values.
+            _ => {}
+        }
+    }
+
+    /// Like [`Tracer::enter`] for a ref, of which [`collect`] holds one
+    /// strong count of its own.
+    fn trace_ref(&mut self, cell: &Rc<RefCell<Value>>) {
+        let Some(parent) = self.enter_at(address(cell), Rc::strong_count(cell) - 1) else {
+            return;
+        };
+        match cell.try_borrow() {
+            Ok(value) => self.trace_value(&value),
+            // Being written to, so certainly in use.
+            Err(_) => {
+                if let Some(node) = self.nodes.get_mut(&address(cell)) {
+                    node.strong = usize::MAX;
+                }
+            }
+        }
+        self.leave(parent);
+    }
+
+    /// Allocations reachable from one that is held from outside the graph.
+    fn live(&self) -> HashSet<usize> {
+        let mut live = HashSet::new();
+        let mut pending: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.strong > node.found)
+            .map(|(&address, _)| address)
+            .collect();
+        while let Some(address) = pending.pop() {
+            if live.insert(address) {
+                pending.extend(&self.nodes[&address].edges);
+            }
+        }
+        live
+    }
+}
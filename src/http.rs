@@ -0,0 +1,119 @@
+use std::io::Read;
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value as Json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use rinha::{interrupt, Engine, Fuel, InterpreterConfig};
+
+use crate::serve;
+
+/// Largest body of a request, in bytes. Larger ones are refused before
+/// they are parsed.
+const MAX_BODY: u64 = 16 << 20;
+
+fn error(message: &str) -> Json {
+    serve::result_json(&[], Err(message.to_string()), Duration::ZERO)
+}
+
+/// Runs `file`, interrupting it once it has run for `timeout`, if given.
+/// The interrupt is the one ctrl-c sets, taken back once the program
+/// stopped so the next request runs.
+fn run_file(
+    file: &rinha::File,
+    engine: Engine,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    timeout: Option<Duration>,
+) -> Json {
+    let Some(timeout) = timeout else {
+        return serve::run_file(file, engine, memoize, config, fuel);
+    };
+    let (done, finished) = mpsc::channel::<()>();
+    let watchdog = thread::spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+            interrupt::interrupt();
+        }
+    });
+    let json = serve::run_file(file, engine, memoize, config, fuel);
+    drop(done);
+    watchdog
+        .join()
+        .expect("Falha ao esperar pelo limite de tempo");
+    interrupt::reset();
+    json
+}
+
+/// Runs the JSON AST in the body of `POST /run`. Requests are answered
+/// one at a time, each program in a fresh session.
+fn answer(
+    request: &mut Request,
+    engine: Engine,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    timeout: Option<Duration>,
+) -> (u16, Json) {
+    match (request.method(), request.url()) {
+        (Method::Post, "/run") => {
+            let mut body = Vec::new();
+            // One byte more than allowed tells a body that is too large.
+            let mut reader = request.as_reader().take(MAX_BODY + 1);
+            if reader.read_to_end(&mut body).is_err() {
+                return (400, error("cannot read the body"));
+            }
+            if body.len() as u64 > MAX_BODY {
+                let message = format!("the body is larger than {} bytes", MAX_BODY);
+                return (413, error(&message));
+            }
+            let Ok(body) = String::from_utf8(body) else {
+                return (400, error("the body is not UTF-8"));
+            };
+            let file = match rinha::parse_json(&body) {
+                Ok(file) => file,
+                Err(err) => return (400, error(&format!("invalid JSON AST: {}", err))),
+            };
+            match serve::check_valid(&file, config) {
+                Ok(()) => (200, run_file(&file, engine, memoize, config, fuel, timeout)),
+                Err(message) => (400, error(&message)),
+            }
+        }
+        (_, "/run") => (405, error("only POST is allowed on /run")),
+        (_, url) => (404, error(&format!("no such endpoint: {}", url))),
+    }
+}
+
+/// Serves HTTP on `address` until the process is stopped.
+pub fn run(
+    address: &str,
+    engine: Engine,
+    memoize: bool,
+    config: InterpreterConfig,
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+) {
+    let fuel = max_steps.map_or(Fuel::unlimited(), Fuel::limited);
+    let server = match Server::http(address) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("error: cannot listen on {}: {}", address, err);
+            process::exit(1);
+        }
+    };
+    tracing::info!("listening on http://{}", address);
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("cabeçalho inválido");
+    for mut request in server.incoming_requests() {
+        let (status, body) = answer(&mut request, engine, memoize, config, fuel, timeout);
+        tracing::info!(method = %request.method(), url = request.url(), status, "request");
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type.clone());
+        // A client that went away does not stop the server.
+        let _ = request.respond(response);
+    }
+}
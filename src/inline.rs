@@ -0,0 +1,346 @@
+//! Inlining of small helper functions, part of [`crate::optimizer`].
+//!
+//! A call to a function bound by `let`, whose body is at most
+//! [`MAX_SIZE`] terms and never calls the function itself, is replaced by
+//! the body with the arguments bound to its parameters:
+//!
+//! ```text
+//! let double = fn (x) => x * 2; double(a + 1)
+//! let double = fn (x) => x * 2; let __x_1 = a + 1; __x_1 * 2
+//! ```
+//!
+//! Parameters are renamed to fresh names the program does not use, so
+//! that no argument sees them. A call is only inlined where every name
+//! the body uses still means what it meant where the function was
+//! defined, and with as many arguments as the function has parameters.
+
+use std::rc::Rc;
+
+use crate::complexity;
+use crate::symbol::{Fresh, Symbol};
+use crate::types::*;
+
+/// Most terms the body of an inlined function may have.
+pub const MAX_SIZE: usize = 16;
+
+struct Definition {
+    parameters: Vec<Parameter>,
+    body: Rc<Term>,
+    /// Names the function uses without binding them.
+    free: Vec<Symbol>,
+}
+
+struct Inliner {
+    /// Names bound around the term being inlined into, innermost last,
+    /// with the definitions of the functions that can be inlined.
+    scope: Vec<(Symbol, Option<Rc<Definition>>)>,
+    /// Names for renamed parameters.
+    fresh: Fresh,
+}
+
+fn size(body: &Rc<Term>) -> usize {
+    let file = File {
+        name: String::new(),
+        expression: body.clone(),
+        location: body.location().clone(),
+    };
+    complexity::analyze(&file).nodes()
+}
+
+/// Makes `term` use `to` wherever it uses `from` as bound outside it.
+fn rename(term: &mut Rc<Term>, from: Symbol, to: Symbol) {
+    match Rc::make_mut(term) {
+        Term::Var { text, index, .. } => {
+            if *text == from {
+                *text = to;
+                *index = None;
+            }
+        }
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } => {}
+        Term::Let {
+            name, value, next, ..
+        } => {
+            let is_function = matches!(value.as_ref(), Term::Function { .. });
+            if !(is_function && name.text == from) {
+                rename(value, from, to);
+            }
+            if name.text != from {
+                rename(next, from, to);
+            }
+        }
+        Term::LetTuple {
+            first,
+            second,
+            value,
+            next,
+            ..
+        } => {
+            rename(value, from, to);
+            if first.text != from && second.text != from {
+                rename(next, from, to);
+            }
+        }
+        Term::Function {
+            parameters, value, ..
+        } => {
+            if parameters.iter().all(|p| p.text != from) {
+                rename(value, from, to);
+            }
+        }
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => {
+            rename(condition, from, to);
+            rename(then, from, to);
+            rename(otherwise, from, to);
+        }
+        Term::Binary { lhs, rhs, .. } => {
+            rename(lhs, from, to);
+            rename(rhs, from, to);
+        }
+        Term::Call {
+            callee, arguments, ..
+        } => {
+            rename(callee, from, to);
+            for argument in arguments {
+                rename(argument, from, to);
+            }
+        }
+        Term::Tuple { first, second, .. } => {
+            rename(first, from, to);
+            rename(second, from, to);
+        }
+        Term::Print { values, .. } => {
+            for value in values {
+                rename(value, from, to);
+            }
+        }
+        Term::First { value, .. }
+        | Term::Second { value, .. }
+        | Term::Field { value, .. }
+        | Term::Ref { value, .. }
+        | Term::Deref { value, .. } => rename(value, from, to),
+        Term::Match { value, arms, .. } => {
+            rename(value, from, to);
+            for arm in arms {
+                if !arm.pattern.names().contains(&from) {
+                    rename(&mut arm.value, from, to);
+                }
+            }
+        }
+        Term::Record { fields, .. } => {
+            for field in fields {
+                rename(&mut field.value, from, to);
+            }
+        }
+        Term::Assign {
+            reference, value, ..
+        } => {
+            rename(reference, from, to);
+            rename(value, from, to);
+        }
+    }
+}
+
+impl Inliner {
+    fn bound(&mut self, names: impl IntoIterator<Item = Symbol>, term: &mut Rc<Term>) {
+        let depth = self.scope.len();
+        self.scope
+            .extend(names.into_iter().map(|name| (name, None)));
+        self.inline(term);
+        self.scope.truncate(depth);
+    }
+
+    /// The definition a call to `callee` with `arguments` can be replaced
+    /// with, if any.
+    fn definition(&self, callee: &Term, arguments: usize) -> Option<Rc<Definition>> {
+        let Term::Var { text, .. } = callee else {
+            return None;
+        };
+        let index = self.scope.iter().rposition(|(name, _)| name == text)?;
+        let definition = self.scope[index].1.clone()?;
+        let shadowed = self.scope[index + 1..]
+            .iter()
+            .any(|(name, _)| definition.free.contains(name));
+        (definition.parameters.len() == arguments && !shadowed).then_some(definition)
+    }
+
+    /// `definition` applied to `arguments`, as a term.
+    fn expand(
+        &mut self,
+        definition: &Definition,
+        arguments: &[Rc<Term>],
+        location: &Location,
+    ) -> Rc<Term> {
+        let mut body = definition.body.clone();
+        let mut bindings = Vec::new();
+        for (parameter, argument) in definition.parameters.iter().zip(arguments) {
+            let name = self.fresh.name(parameter.text.as_str());
+            rename(&mut body, parameter.text, name);
+            bindings.push((name, parameter.location.clone(), argument.clone()));
+        }
+        // Bound in order, so the arguments are still evaluated left to
+        // right.
+        for (name, parameter_location, argument) in bindings.into_iter().rev() {
+            body = Rc::new(Term::Let {
+                name: Parameter {
+                    text: name,
+                    location: parameter_location,
+                },
+                value: argument,
+                next: body,
+                location: location.clone(),
+            });
+        }
+        body
+    }
+
+    fn bindings(&mut self, term: &mut Rc<Term>) {
+        let depth = self.scope.len();
+        let rest = Term::let_chain_mut(term, |binding| match binding {
+            Term::Let { name, value, .. } => {
+                match Rc::make_mut(value) {
+                    // A function sees the name it is bound to.
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => {
+                        let mut names = vec![name.text];
+                        names.extend(parameters.iter().map(|p| p.text));
+                        self.bound(names, body);
+                    }
+                    _ => self.inline(value),
+                }
+                let definition = match value.as_ref() {
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => {
+                        let free = value.free_names();
+                        let recursive = free.contains(&name.text);
+                        (!recursive && size(body) <= MAX_SIZE).then(|| {
+                            Rc::new(Definition {
+                                parameters: parameters.clone(),
+                                body: body.clone(),
+                                free,
+                            })
+                        })
+                    }
+                    _ => None,
+                };
+                self.scope.push((name.text, definition));
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                ..
+            } => {
+                self.inline(value);
+                self.scope.extend([(first.text, None), (second.text, None)]);
+            }
+            _ => unreachable!(),
+        });
+        self.inline(rest);
+        self.scope.truncate(depth);
+    }
+
+    fn inline(&mut self, term: &mut Rc<Term>) {
+        if term.next().is_some() {
+            return self.bindings(term);
+        }
+        match Rc::make_mut(term) {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => {}
+            Term::Let { .. } | Term::LetTuple { .. } => unreachable!("inlined above"),
+            Term::Function {
+                parameters, value, ..
+            } => {
+                let names: Vec<_> = parameters.iter().map(|p| p.text).collect();
+                self.bound(names, value);
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.inline(condition);
+                self.inline(then);
+                self.inline(otherwise);
+            }
+            Term::Binary { lhs, rhs, .. } => {
+                self.inline(lhs);
+                self.inline(rhs);
+            }
+            Term::Call {
+                callee, arguments, ..
+            } => {
+                self.inline(callee);
+                for argument in arguments {
+                    self.inline(argument);
+                }
+            }
+            Term::Tuple { first, second, .. } => {
+                self.inline(first);
+                self.inline(second);
+            }
+            Term::Print { values, .. } => {
+                for value in values {
+                    self.inline(value);
+                }
+            }
+            Term::First { value, .. }
+            | Term::Second { value, .. }
+            | Term::Field { value, .. }
+            | Term::Ref { value, .. }
+            | Term::Deref { value, .. } => self.inline(value),
+            Term::Match { value, arms, .. } => {
+                self.inline(value);
+                for arm in arms {
+                    self.bound(arm.pattern.names(), &mut arm.value);
+                }
+            }
+            Term::Record { fields, .. } => {
+                for field in fields {
+                    self.inline(&mut field.value);
+                }
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                self.inline(reference);
+                self.inline(value);
+            }
+        }
+        if let Term::Call {
+            callee,
+            arguments,
+            location,
+            ..
+        } = term.as_ref()
+        {
+            if let Some(definition) = self.definition(callee, arguments.len()) {
+                *term = self.expand(&definition, arguments, location);
+            }
+        }
+    }
+}
+
+/// Inlines the small helper functions of `file` at their calls.
+pub fn inline(file: &File) -> File {
+    let mut inliner = Inliner {
+        scope: Vec::new(),
+        fresh: Fresh::new(file.expression.names()),
+    };
+    let mut expression = file.expression.clone();
+    inliner.inline(&mut expression);
+    File {
+        expression,
+        ..file.clone()
+    }
+}
@@ -0,0 +1,66 @@
+//! Where `read_line` reads from.
+//!
+//! Programs read stdin unless [`set`] gives them another [`Input`], such as
+//! lines written out ahead of time by a test. The input belongs to the
+//! thread running the program, so each thread starts from stdin.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use crate::error::RuntimeError;
+use crate::types::Location;
+use crate::value::Value;
+
+/// Source of the lines read by `read_line`.
+pub trait Input {
+    /// The next line, without its line ending, or `None` at the end of the
+    /// input.
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+}
+
+impl Input for io::Stdin {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+}
+
+/// Gives out the lines it holds, in order.
+impl Input for VecDeque<String> {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        Ok(self.pop_front())
+    }
+}
+
+thread_local! {
+    static INPUT: RefCell<Box<dyn Input>> = RefCell::new(Box::new(io::stdin()));
+}
+
+/// Makes `read_line` read from `input` from now on, on this thread.
+pub fn set(input: impl Input + 'static) {
+    INPUT.with(|current| *current.borrow_mut() = Box::new(input));
+}
+
+/// The builtin `read_line`, which fails at the end of the input.
+pub(crate) fn read_line(_: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let failed = |message: String| RuntimeError::Builtin {
+        name: "read_line".to_string(),
+        message,
+        location: location.clone(),
+    };
+    match INPUT.with(|input| input.borrow_mut().read_line()) {
+        Ok(Some(line)) => Ok(Value::string(line)),
+        Ok(None) => Err(failed("end of input".to_string())),
+        Err(err) => Err(failed(err.to_string())),
+    }
+}
@@ -1,268 +1,1473 @@
 use std::collections::HashMap;
+use std::ptr;
+use std::rc::Rc;
+use std::time::Instant;
 
+use tracing::Span;
+
+use crate::builtins;
+use crate::config::InterpreterConfig;
+use crate::depth;
+use crate::error::RuntimeError;
+use crate::fuel::Fuel;
+use crate::gc;
+use crate::interrupt;
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::memo::{is_pure, CallKey, Memo};
+use crate::memory;
+use crate::output::Output;
+use crate::profile::{Profile, Profiler};
+use crate::resolver::{defines_function, resolve, resolve_let, resolve_program};
+use crate::stats;
+use crate::symbol::Symbol;
+use crate::trace::Tracer;
 use crate::types::*;
+use crate::value::{self, *};
+
+mod parallel;
+
+use parallel::{Parallel, Pending};
 
-struct Env {
-    objects: HashMap<String, Value>,
+/// A persistent environment: each binding points to the scope it extends,
+/// so adding a binding is O(1) and closures share their enclosing scopes
+/// instead of copying them. A chain of `let` grows it one binding at a
+/// time rather than in one mutable frame, since the closures bound in such
+/// a frame would keep it, and so themselves, alive.
+#[derive(Debug, Default)]
+struct Env(Option<Rc<Binding>>);
+
+impl Clone for Env {
+    fn clone(&self) -> Env {
+        stats::env_clone();
+        Env(self.0.clone())
+    }
+}
+
+#[derive(Debug)]
+struct Binding {
+    name: Symbol,
+    value: Value,
+    parent: Env,
 }
 
 impl Env {
-    fn clone_env(&self) -> Env {
-        Env {
-            objects: self.objects.clone(),
+    fn get(&self, name: Symbol) -> Option<&Value> {
+        let mut env = self;
+        while let Some(binding) = &env.0 {
+            if binding.name == name {
+                return Some(&binding.value);
+            }
+            env = &binding.parent;
+        }
+        None
+    }
+
+    /// Value of the binding `index` links up the chain.
+    fn nth(&self, index: usize) -> Option<&Value> {
+        let mut env = self;
+        for _ in 0..index {
+            env = &env.0.as_ref()?.parent;
         }
+        env.0.as_ref().map(|binding| &binding.value)
+    }
+
+    /// Binds `name` on top of the bindings of `self`, taking them over
+    /// rather than copying them.
+    fn extend(self, name: Symbol, value: Value) -> Env {
+        Env(Some(Rc::new(Binding {
+            name,
+            value,
+            parent: self,
+        })))
     }
 }
 
-struct Closure {
-    body: Term,
-    parameters: Vec<String>,
-    env: Env,
+impl Env {
+    /// Traces the chain of bindings, iteratively like dropping it.
+    fn trace(&self, tracer: &mut gc::Tracer) {
+        let mut saved = None;
+        let mut env = self;
+        while let Some(binding) = &env.0 {
+            let Some(parent) = tracer.enter(binding) else {
+                break;
+            };
+            saved.get_or_insert(parent);
+            tracer.trace_value(&binding.value);
+            env = &binding.parent;
+        }
+        if let Some(parent) = saved {
+            tracer.leave(parent);
+        }
+    }
 }
 
-enum Value {
-    Boolean(bool),
-    String(String),
-    Number(f64),
-    Closure(Closure),
-    Tuple(Box<Value>, Box<Value>),
+impl Drop for Binding {
+    // Unlink long chains iteratively so dropping them cannot overflow the
+    // stack.
+    fn drop(&mut self) {
+        let mut parent = self.parent.0.take();
+        while let Some(binding) = parent {
+            match Rc::try_unwrap(binding) {
+                Ok(mut binding) => parent = binding.parent.0.take(),
+                Err(_) => break,
+            }
+        }
+    }
 }
 
-fn unimplemented() -> ! {
-    panic!("unimplemented yet");
+/// Owns the programs a [`Session`] evaluates, so that the tree-walker can
+/// borrow their terms for as long as the session lives instead of counting
+/// references to them on every step.
+///
+/// Only the roots go in it: the terms themselves are still the `Rc` nodes
+/// the parser and the passes build, and dropping the arena drops them node
+/// by node like any other `Rc<Term>`. Values are not kept in it either, as
+/// they outlive sessions in results, in the memo cache and on other
+/// threads.
+#[derive(Default)]
+pub struct Arena(typed_arena::Arena<Rc<Term>>);
+
+impl Arena {
+    pub fn new() -> Arena {
+        Arena::default()
+    }
+
+    fn alloc(&self, term: Rc<Term>) -> &Rc<Term> {
+        self.0.alloc(term)
+    }
 }
 
-pub fn interpret_file(file: &File) -> Value {
-    let env = Env {
-        objects: HashMap::new(),
-    };
-    interpret(&file.expression, env)
+#[derive(Debug)]
+pub struct Closure {
+    body: Rc<Term>,
+    /// Where the session that made the closure keeps a borrow of `body`.
+    slot: usize,
+    parameters: Vec<Symbol>,
+    /// Location of the function the closure was made from.
+    location: Location,
+    /// Only the bindings the body captures, on top of the globals.
+    env: Env,
+    /// Name the closure was bound to by `let`, under which its body sees
+    /// the closure itself. It is bound on each call rather than stored in
+    /// `env`, which would make the closure own itself.
+    name: Option<Symbol>,
 }
 
-fn type_mismatch(type_str: &str) -> ! {
-    panic!("not a {}", type_str);
+impl Closure {
+    pub(crate) fn trace(&self, tracer: &mut gc::Tracer) {
+        self.env.trace(tracer);
+    }
 }
 
-fn assert_int(value: &Value) -> f64 {
-    if let Value::Number(num) = value {
-        *num
-    } else {
-        type_mismatch("int")
+impl Drop for Closure {
+    fn drop(&mut self) {
+        stats::closure_dropped();
     }
 }
 
-fn assert_tuple(value: &Value) -> (&Value, &Value) {
-    if let Value::Tuple(fst, snd) = value {
-        (fst, snd)
-    } else {
-        type_mismatch("tuple")
+/// A function being called: a closure of the program, with whether its
+/// body is pure, or a builtin. With partial application, it may also be a
+/// partial application given the rest of its arguments, or a function
+/// given fewer arguments than it takes.
+enum Callee {
+    Tree {
+        closure: Rc<Closure>,
+        pure: bool,
+    },
+    Native(Rc<Native>),
+    Applied {
+        partial: Rc<Partial>,
+        callee: Box<Callee>,
+    },
+    Curry(value::Closure),
+}
+
+/// Whether `term` is a literal or a name, whose value takes no step of its
+/// own.
+fn is_immediate(term: &Term) -> bool {
+    matches!(
+        term,
+        Term::Str { .. } | Term::Bool { .. } | Term::Int { .. } | Term::Var { .. }
+    )
+}
+
+/// The value of the literal or name `term` in `env`, with the functions
+/// of the global table in `table`.
+fn immediate(term: &Term, env: &Env, table: &[Value]) -> Result<Value, RuntimeError> {
+    match term {
+        Term::Str { value, .. } => {
+            stats::allocation();
+            Ok(Value::string(value.clone()))
+        }
+        Term::Bool { value, .. } => Ok(Value::Boolean(*value)),
+        Term::Int { value, .. } => Ok(Value::Number(*value)),
+        Term::Var {
+            text,
+            index,
+            location,
+        } => {
+            let value = match index {
+                Some(Slot::Local(index)) => env.nth(*index),
+                Some(Slot::Global(slot)) => table.get(*slot),
+                None => env.get(*text),
+            };
+            value.cloned().ok_or_else(|| RuntimeError::UnboundVariable {
+                name: text.to_string(),
+                location: location.clone(),
+            })
+        }
+        _ => unreachable!("not an immediate term"),
     }
 }
 
-fn assert_closure(value: &Value) -> &Closure {
-    if let Value::Closure(closure) = value {
-        closure
-    } else {
-        type_mismatch("closure")
+/// Checks that `value` can be called by `call`. Each call remembers the
+/// last closure body it went to, so calling the same function again skips
+/// the arity check and the walk over its body for purity. Variables need
+/// no such cache, since the resolver already gave them their slots.
+fn assert_callee(
+    value: &Value,
+    call: &Term,
+    config: InterpreterConfig,
+) -> Result<Callee, RuntimeError> {
+    let Term::Call {
+        callee,
+        arguments,
+        location,
+        cache,
+    } = call
+    else {
+        unreachable!()
+    };
+    let Value::Closure(closure) = value else {
+        return Err(type_mismatch("closure", value, callee.location()));
+    };
+    // The cache only holds closures this call has given as many arguments
+    // as they take.
+    if let value::Closure::Tree(closure) = closure {
+        if let Some(pure) = cache.get(&closure.body) {
+            return Ok(Callee::Tree {
+                closure: closure.clone(),
+                pure,
+            });
+        }
+    }
+    let (function, applied) = match closure {
+        value::Closure::Partial(partial) => (&partial.callee, partial.arguments.len()),
+        function => (function, 0),
+    };
+    let expected = match function {
+        value::Closure::Tree(closure) => closure.parameters.len(),
+        value::Closure::Native(native) => native.arity,
+        _ => return Err(type_mismatch("closure", value, callee.location())),
+    };
+    let found = applied + arguments.len();
+    if found < expected && config.partial_application {
+        return Ok(Callee::Curry(closure.clone()));
+    }
+    if found != expected {
+        return Err(RuntimeError::ArityMismatch {
+            expected,
+            found,
+            location: location.clone(),
+        });
+    }
+    let target = match function {
+        value::Closure::Tree(function) => {
+            let pure = match cache.get(&function.body) {
+                Some(pure) => pure,
+                None => is_pure(&function.body),
+            };
+            if applied == 0 {
+                cache.set(&function.body, pure);
+            }
+            Callee::Tree {
+                closure: function.clone(),
+                pure,
+            }
+        }
+        value::Closure::Native(native) => Callee::Native(native.clone()),
+        _ => unreachable!("partial applications hold functions"),
+    };
+    match closure {
+        value::Closure::Partial(partial) => Ok(Callee::Applied {
+            partial: partial.clone(),
+            callee: Box::new(target),
+        }),
+        _ => Ok(target),
     }
 }
 
-fn assert_bool(value: &Value) -> bool {
-    if let Value::Boolean(b) = value {
-        *b
-    } else {
-        type_mismatch("closure")
+fn bind(closure: &Rc<Closure>, arguments: Vec<Value>) -> Env {
+    let mut function_env = closure.env.clone();
+    if let Some(name) = closure.name {
+        let itself = Value::Closure(value::Closure::Tree(closure.clone()));
+        function_env = function_env.extend(name, itself);
+    }
+    for (param, arg) in closure.parameters.iter().zip(arguments) {
+        function_env = function_env.extend(*param, arg);
     }
+    function_env
 }
 
-fn cast_to_string(value: &Value) -> String {
-    match value {
-        Value::Number(num) => num.to_string(),
-        Value::String(s) => s.clone(),
-        _ => type_mismatch("string or int"),
+/// An active call, as seen by a [`Debugger`] or in the backtrace of an
+/// error. A call in tail position takes over the frame of its caller, so
+/// tail-recursive programs keep running in constant space.
+#[derive(Clone, Debug)]
+pub struct Frame<'t> {
+    closure: Rc<Closure>,
+    call: &'t Term,
+    /// The `tracing` span of the call, open as long as the frame.
+    span: Span,
+}
+
+impl Frame<'_> {
+    /// Name the called closure was bound to by `let`, if any.
+    pub fn name(&self) -> Option<Symbol> {
+        self.closure.name
+    }
+
+    /// Location of the function that was called.
+    pub fn function(&self) -> &Location {
+        &self.closure.location
+    }
+
+    /// Location of the call.
+    pub fn location(&self) -> &Location {
+        self.call.location()
+    }
+
+    fn site(&self) -> CallSite {
+        CallSite {
+            name: self.name().map(|name| name.to_string()),
+            function: self.function().clone(),
+            location: self.location().clone(),
+        }
     }
 }
 
-fn is_equal(left: &Value, right: &Value) -> bool {
-    match (left, right) {
-        (Value::Number(l), Value::Number(r)) => (l - r).abs() < f64::EPSILON,
-        (Value::String(l), Value::String(r)) => l == r,
-        (Value::Boolean(l), Value::Boolean(r)) => l == r,
-        _ => type_mismatch("number or string or boolean"),
+/// A call in the backtrace of an error, which outlives the program and
+/// can come from another thread.
+#[derive(Clone, Debug)]
+pub struct CallSite {
+    /// Name the called closure was bound to by `let`, if any.
+    pub name: Option<String>,
+    /// Location of the function that was called.
+    pub function: Location,
+    /// Location of the call.
+    pub location: Location,
+}
+
+/// The bindings a term sees, as seen by a [`Debugger`].
+pub struct Scope<'e>(&'e Env);
+
+impl Scope<'_> {
+    /// Value of the innermost binding of `name`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(Symbol::intern(name))
     }
 }
 
-fn interpret_binary(left: &Value, right: &Value, op: &BinaryOp) -> Value {
-    match op {
-        BinaryOp::Add => {
-            if let (Value::Number(l), Value::Number(r)) = (left, right) {
-                Value::Number(l + r)
-            } else {
-                let left_val = cast_to_string(left);
-                let right_val = cast_to_string(right);
-                Value::String(format!("{}{}", left_val, right_val))
+/// Receives control before the tree-walker evaluates each term, which lets
+/// it stop the program until it returns.
+pub trait Debugger {
+    /// Called with the term about to be evaluated, the bindings it sees
+    /// and the active calls, innermost last.
+    fn before(&mut self, term: &Term, scope: &Scope, frames: &[Frame]);
+}
+
+/// The rest of the computation waiting for the value of a subterm. Each
+/// continuation keeps the term it was created for, which holds the subterms
+/// still to evaluate and the locations used in errors.
+enum Continuation<'t> {
+    /// Picks a branch of an `If` once the condition is known.
+    Branch {
+        term: &'t Term,
+        env: Env,
+    },
+    /// Evaluates the second element of a `Tuple`.
+    TupleSecond {
+        term: &'t Term,
+        env: Env,
+    },
+    Tuple {
+        first: Value,
+    },
+    /// Evaluates the first arm of a `Match` whose pattern the value
+    /// matches.
+    Match {
+        term: &'t Term,
+        env: Env,
+    },
+    /// Collects the fields of a `Record`, evaluated in order.
+    Record {
+        term: &'t Term,
+        env: Env,
+        values: Vec<Value>,
+    },
+    Field {
+        term: &'t Term,
+    },
+    Ref,
+    Deref {
+        term: &'t Term,
+    },
+    /// Evaluates the new value of an `Assign` once the reference is known.
+    AssignValue {
+        term: &'t Term,
+        env: Env,
+    },
+    Assign {
+        term: &'t Term,
+        reference: Value,
+    },
+    First {
+        term: &'t Term,
+    },
+    Second {
+        term: &'t Term,
+    },
+    /// Evaluates the right-hand side of a `Binary`, unless `&&` or `||` is
+    /// already decided by the left one.
+    Rhs {
+        term: &'t Term,
+        env: Env,
+    },
+    Binary {
+        term: &'t Term,
+        left: Value,
+    },
+    /// Checks that the right-hand side of `&&` or `||` is a boolean.
+    Logical {
+        term: &'t Term,
+    },
+    Print {
+        term: &'t Term,
+        env: Env,
+        values: Vec<Value>,
+    },
+    Let {
+        term: &'t Term,
+        env: Env,
+    },
+    /// Binds the elements of the tuple a `LetTuple` takes apart.
+    LetTuple {
+        term: &'t Term,
+        env: Env,
+    },
+    Callee {
+        term: &'t Term,
+        env: Env,
+    },
+    /// Collects the arguments of a call, evaluated left to right.
+    Argument {
+        term: &'t Term,
+        env: Env,
+        callee: Callee,
+        values: Vec<Value>,
+    },
+    /// Caches the result of a call to a pure closure, unless it printed or
+    /// used a reference.
+    Memoize {
+        key: CallKey,
+        effects: usize,
+    },
+    /// Pops the frame of a call once its body has a value.
+    Leave,
+    /// Tells the profiler that the call to the function with `body`
+    /// returned.
+    Profile {
+        body: *const Term,
+        start: Instant,
+    },
+    /// Reports the value of `term` to the tracer. `depth` is the call depth
+    /// when the term started, which is restored once it is done.
+    Trace {
+        term: &'t Term,
+        depth: usize,
+    },
+    /// Waits for the second operand of a binary operator or tuple, which a
+    /// worker is evaluating, once the first one has a value.
+    Join {
+        term: &'t Term,
+        env: Env,
+        pending: Pending,
+    },
+}
+
+enum State<'t> {
+    Eval(&'t Term, Env),
+    Return(Value),
+}
+
+/// Evaluates terms borrowed for `'t` from an [`Arena`], printing to an
+/// output borrowed for `'a`.
+struct Interpreter<'t, 'a> {
+    arena: &'t Arena,
+    /// Bodies of the closures made so far, which calls evaluate without
+    /// going through the `Rc` each closure keeps.
+    bodies: Vec<&'t Term>,
+    /// Slot in `bodies` of each body.
+    slots: HashMap<*const Term, usize>,
+    output: &'a mut dyn Output,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    /// Cache of pure calls, or `None` when memoization is disabled.
+    memo: Option<Memo>,
+    /// Number of values printed and of uses of references so far, used to
+    /// tell whether a call had effects and therefore must not be cached.
+    effects: usize,
+    /// Native code for the functions the JIT could compile.
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>,
+    tracer: Option<Tracer>,
+    debugger: Option<&'a mut dyn Debugger>,
+    /// Active calls, innermost last. They are left as they were when an
+    /// evaluation stops with an error.
+    frames: Vec<Frame<'t>>,
+    /// Calls that were active on a worker when an operand it evaluated
+    /// failed, which continue `frames`.
+    worker_frames: Vec<CallSite>,
+    profiler: Option<Profiler>,
+    /// Environment the evaluation started in, under which closures keep
+    /// the bindings they capture.
+    globals: Env,
+    /// Set when operands may be evaluated on other threads.
+    parallel: Option<Parallel>,
+    /// Functions defined at the start of the programs evaluated so far,
+    /// which variables reach by their slot instead of through closures.
+    table: Vec<Value>,
+    /// The `let` whose function goes in `table` next, until a binding of
+    /// something else ends the start of the program.
+    next_global: Option<&'t Term>,
+}
+
+/// An interpreter together with a top-level environment that outlives
+/// single evaluations, so definitions can be added between them.
+/// The programs it evaluates are kept in `arena` until it is dropped, as
+/// the closures they define may still be called.
+pub struct Session<'t, 'a> {
+    interpreter: Interpreter<'t, 'a>,
+    env: Env,
+}
+
+impl<'t, 'a> Session<'t, 'a> {
+    /// Starts with an empty environment, or with the builtins of
+    /// [`crate::builtins`] when `config` binds them, and runs every
+    /// evaluation with `config`.
+    pub fn new(
+        arena: &'t Arena,
+        memoize: bool,
+        config: InterpreterConfig,
+        fuel: Fuel,
+        output: &'a mut dyn Output,
+    ) -> Session<'t, 'a> {
+        let mut env = Env::default();
+        if config.builtins() {
+            for (name, value) in builtins::globals(config.clock) {
+                env = env.extend(name, value);
             }
         }
-        BinaryOp::Eq => {
-            let value = is_equal(left, right);
-            Value::Boolean(value)
-        }
-        BinaryOp::Neq => {
-            let value = !is_equal(left, right);
-            Value::Boolean(value)
-        }
-        BinaryOp::Sub => {
-            let left_val = assert_int(left);
-            let right_val = assert_int(right);
-            Value::Number(left_val - right_val)
-        }
-        BinaryOp::Mul => {
-            let left_val = assert_int(left);
-            let right_val = assert_int(right);
-            Value::Number(left_val * right_val)
-        }
-        BinaryOp::Div => {
-            let left_val = assert_int(left);
-            let right_val = assert_int(right);
-            Value::Number((left_val / right_val).floor())
-        }
-        BinaryOp::Rem => {
-            let left_val = assert_int(left);
-            let right_val = assert_int(right);
-            Value::Number((left_val % right_val).floor())
-        }
-        BinaryOp::Lt => {
-            let left_val = assert_int(left);
-            let right_val = assert_int(right);
-            Value::Boolean(left_val < right_val)
-        }
-        BinaryOp::Gt => {
-            let left_val = assert_int(left);
-            let right_val = assert_int(right);
-            Value::Boolean(left_val > right_val)
+        Session {
+            interpreter: Interpreter {
+                arena,
+                bodies: Vec::new(),
+                slots: HashMap::new(),
+                output,
+                config,
+                fuel,
+                memo: memoize.then(Memo::default),
+                effects: 0,
+                #[cfg(feature = "jit")]
+                jit: None,
+                tracer: None,
+                debugger: None,
+                frames: Vec::new(),
+                worker_frames: Vec::new(),
+                profiler: None,
+                globals: env.clone(),
+                parallel: None,
+                table: Vec::new(),
+                next_global: None,
+            },
+            env,
         }
-        BinaryOp::Lte => {
-            let left_val = assert_int(left);
-            let right_val = assert_int(right);
-            Value::Boolean(left_val <= right_val)
+    }
+
+    /// Binds `name` to a function implemented in Rust for later
+    /// evaluations. Calls with other than `arity` arguments fail before
+    /// reaching `function`, and an `Err` it returns stops the program with a
+    /// [`RuntimeError::Builtin`] at the call. Calls to pure closures may be
+    /// cached, including the builtins they call, so `function` should not
+    /// have side effects unless memoization is off.
+    ///
+    /// ```
+    /// use rinha::interpreter::{Arena, Session};
+    /// use rinha::{Fuel, InterpreterConfig, Value};
+    ///
+    /// let arena = Arena::new();
+    /// let mut lines = Vec::new();
+    /// let config = InterpreterConfig::STRICT;
+    /// let mut session = Session::new(&arena, true, config, Fuel::unlimited(), &mut lines);
+    /// session.register_builtin("double", 1, |arguments| match &arguments[0] {
+    ///     Value::Number(n) => Ok(Value::Number(n * 2)),
+    ///     _ => Err("expected an int".to_string()),
+    /// });
+    /// let file = rinha::parse("double(21)", "example.rinha").unwrap();
+    /// let value = session.eval(&file.expression).unwrap();
+    /// assert!(matches!(value, Value::Number(42)));
+    ///
+    /// let file = rinha::parse("double(\"21\")", "example.rinha").unwrap();
+    /// let err = session.eval(&file.expression).unwrap_err();
+    /// assert_eq!(err.to_string(), "double: expected an int");
+    /// ```
+    pub fn register_builtin(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        let builtin = name.to_string();
+        let native = Native::new(name, arity, move |arguments, location| {
+            function(arguments).map_err(|message| RuntimeError::Builtin {
+                name: builtin.clone(),
+                message,
+                location: location.clone(),
+            })
+        });
+        let value = Value::Closure(value::Closure::Native(Rc::new(native)));
+        self.env = Env(self.env.0.take()).extend(Symbol::intern(name), value);
+    }
+
+    pub fn eval(&mut self, term: &Rc<Term>) -> Result<Value, RuntimeError> {
+        self.interpreter.globals = self.env.clone();
+        // Workers get closures with the bindings they capture, so they
+        // would not find the functions in the table.
+        let term = match self.interpreter.parallel {
+            Some(_) => resolve(term),
+            None => resolve_program(term, self.interpreter.table.len()),
+        };
+        let term = self.interpreter.arena.alloc(term);
+        self.interpreter.next_global = defines_function(term).then_some(&**term);
+        self.interpreter.run(term, self.env.clone())
+    }
+
+    /// Evaluates `value` and binds it to `name` for later evaluations,
+    /// following the same rules as `let`.
+    pub fn define(&mut self, name: Symbol, value: &Rc<Term>) -> Result<(), RuntimeError> {
+        self.interpreter.globals = self.env.clone();
+        let value = self.interpreter.arena.alloc(resolve_let(name, value));
+        let value = self.interpreter.let_value(name, value, &self.env)?;
+        self.env = Env(self.env.0.take()).extend(name, value);
+        Ok(())
+    }
+}
+
+pub fn interpret_file(
+    file: &File,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    output: &mut dyn Output,
+) -> Result<Value, RuntimeError> {
+    let arena = Arena::new();
+    Session::new(&arena, memoize, config, fuel, output).eval(&file.expression)
+}
+
+/// Like [`interpret_file`], but reports every evaluated term to `tracer`.
+pub fn interpret_file_traced(
+    file: &File,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    output: &mut dyn Output,
+    tracer: Tracer,
+) -> Result<Value, RuntimeError> {
+    let arena = Arena::new();
+    let mut session = Session::new(&arena, memoize, config, fuel, output);
+    session.interpreter.tracer = Some(tracer);
+    session.eval(&file.expression)
+}
+
+/// Like [`interpret_file`], but hands control to `debugger` before every
+/// term and keeps track of the active calls for it.
+pub fn interpret_file_debug<'a>(
+    file: &File,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    output: &'a mut dyn Output,
+    debugger: &'a mut dyn Debugger,
+) -> Result<Value, RuntimeError> {
+    let arena = Arena::new();
+    let mut session = Session::new(&arena, memoize, config, fuel, output);
+    session.interpreter.debugger = Some(debugger);
+    session.eval(&file.expression)
+}
+
+/// Like [`interpret_file`], but also returns the calls that were active
+/// when the program stopped with an error, innermost last. With `parallel`
+/// it evaluates operands on other threads like [`interpret_file_parallel`],
+/// and an error on a worker comes back with the calls active there.
+pub fn interpret_file_backtrace(
+    file: &File,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    parallel: bool,
+    output: &mut dyn Output,
+) -> (Result<Value, RuntimeError>, Vec<CallSite>) {
+    let arena = Arena::new();
+    let mut session = Session::new(&arena, memoize, config, fuel, output);
+    if parallel && fuel.is_unlimited() {
+        session.interpreter.parallel = Some(Parallel {
+            memoize,
+            config,
+            depth: 0,
+        });
+    }
+    let result = session.eval(&file.expression);
+    let backtrace = match result {
+        Ok(_) => Vec::new(),
+        Err(_) => session.interpreter.backtrace(),
+    };
+    (result, backtrace)
+}
+
+/// Like [`interpret_file`], but evaluates the operands of binary operators
+/// and tuples on other threads when they make calls and cannot print, so
+/// that programs doing independent work use several cores. Workers do not
+/// count calls, so with a limited budget everything runs on one thread.
+pub fn interpret_file_parallel(
+    file: &File,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    output: &mut dyn Output,
+) -> Result<Value, RuntimeError> {
+    interpret_file_backtrace(file, memoize, config, fuel, true, output).0
+}
+
+/// Like [`interpret_file`], but also counts the calls to each function and
+/// the time they take. The profile covers the calls made until the program
+/// stopped, even if it stopped with an error.
+pub fn interpret_file_profiled(
+    file: &File,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    output: &mut dyn Output,
+) -> (Result<Value, RuntimeError>, Profile) {
+    let arena = Arena::new();
+    let term = arena.alloc(resolve(&file.expression));
+    let mut session = Session::new(&arena, memoize, config, fuel, output);
+    session.interpreter.profiler = Some(Profiler::default());
+    let result = session.interpreter.run(term, session.env.clone());
+    let profiler = session.interpreter.profiler.take().unwrap();
+    (result, profiler.report(term))
+}
+
+/// Like [`interpret_file`], but calls native code for the functions the
+/// JIT supports. Native code does not count calls, so with a limited
+/// budget everything runs in the interpreter.
+#[cfg(feature = "jit")]
+pub fn interpret_file_jit(
+    file: &File,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    output: &mut dyn Output,
+) -> Result<Value, RuntimeError> {
+    let arena = Arena::new();
+    let term = arena.alloc(resolve(&file.expression));
+    let mut session = Session::new(&arena, memoize, config, fuel, output);
+    if fuel.is_unlimited() {
+        session.interpreter.jit = Some(Jit::compile(term, memoize));
+    }
+    session.interpreter.run(term, session.env.clone())
+}
+
+impl<'t> Interpreter<'t, '_> {
+    /// Evaluates `term` with an explicit stack of continuations instead of
+    /// Rust recursion, so the depth of recursion in the program is only
+    /// limited by memory. Calls in tail position push nothing, so
+    /// tail-recursive programs also run in constant space, except when
+    /// tracing, which waits for the value of every term.
+    fn run(&mut self, term: &'t Term, env: Env) -> Result<Value, RuntimeError> {
+        self.frames.clear();
+        self.worker_frames.clear();
+        let mut stack = Vec::new();
+        let mut state = State::Eval(term, env);
+        loop {
+            state = match state {
+                State::Eval(term, env) => {
+                    if let Some(tracer) = &self.tracer {
+                        stack.push(Continuation::Trace {
+                            term,
+                            depth: tracer.depth,
+                        });
+                    }
+                    if let Some(debugger) = self.debugger.as_mut() {
+                        debugger.before(term, &Scope(&env), &self.frames);
+                    }
+                    self.eval(term, env, &mut stack)?
+                }
+                State::Return(value) => match stack.pop() {
+                    Some(continuation) => self.apply(continuation, value, &mut stack)?,
+                    None => return Ok(value),
+                },
+            };
         }
-        BinaryOp::Gte => {
-            let left_val = assert_int(left);
-            let right_val = assert_int(right);
-            Value::Boolean(left_val >= right_val)
+    }
+
+    /// Evaluates the value of `let name = value`. Functions are named after
+    /// the binding so that they can call themselves recursively.
+    fn let_value(
+        &mut self,
+        name: Symbol,
+        value: &'t Term,
+        env: &Env,
+    ) -> Result<Value, RuntimeError> {
+        match value {
+            Term::Function { .. } => Ok(self.make_closure(value, env, Some(name))),
+            _ => self.run(value, env.clone()),
         }
-        BinaryOp::And => {
-            let left_val = assert_bool(left);
-            let right_val = assert_bool(right);
-            Value::Boolean(left_val && right_val)
+    }
+
+    /// Creates a closure from `function` that keeps the bindings of `env`
+    /// it captures, on top of the globals so that names the program does
+    /// not bind are still found.
+    fn make_closure(&mut self, function: &'t Term, env: &Env, name: Option<Symbol>) -> Value {
+        let Term::Function {
+            parameters,
+            value: body,
+            location,
+            captures,
+        } = function
+        else {
+            unreachable!()
+        };
+        stats::closure_created();
+        // The first capture must end up innermost.
+        let mut closure_env = self.globals.clone();
+        for capture in captures.iter().rev() {
+            let value = env
+                .nth(capture.index)
+                .expect("captures are resolved in the enclosing scope")
+                .clone();
+            closure_env = closure_env.extend(capture.name, value);
         }
-        BinaryOp::Or => {
-            let left_val = assert_bool(left);
-            let right_val = assert_bool(right);
-            Value::Boolean(left_val || right_val)
+        let bodies = &mut self.bodies;
+        let slot = *self.slots.entry(Rc::as_ptr(body)).or_insert_with(|| {
+            bodies.push(body);
+            bodies.len() - 1
+        });
+        Value::Closure(value::Closure::Tree(Rc::new(Closure {
+            body: body.clone(),
+            slot,
+            parameters: parameters.iter().map(|p| p.text).collect(),
+            location: location.clone(),
+            env: closure_env,
+            name,
+        })))
+    }
+
+    /// The body of `closure`, borrowed from this session. Closures made by
+    /// other sessions, which builtins may return, have their body kept in
+    /// the arena first.
+    fn body(&self, closure: &Closure) -> &'t Term {
+        match self.bodies.get(closure.slot) {
+            Some(&body) if ptr::eq(body, Rc::as_ptr(&closure.body)) => body,
+            _ => self.arena.alloc(closure.body.clone()),
         }
     }
-}
 
-fn show_value(value: &Value) -> String {
-    match value {
-        Value::Number(num) => num.to_string(),
-        Value::Boolean(b) => b.to_string(),
-        Value::String(s) => s.clone(),
-        Value::Closure(_) => "<#closure>".to_string(),
-        Value::Tuple(fst, snd) => format!("({}, {})", show_value(fst), show_value(snd)),
+    /// Takes one step on `term`: either produces its value right away or
+    /// pushes what to do with the value of its first subterm.
+    fn eval(
+        &mut self,
+        term: &'t Term,
+        env: Env,
+        stack: &mut Vec<Continuation<'t>>,
+    ) -> Result<State<'t>, RuntimeError> {
+        let (subterm, continuation) = match term {
+            Term::Str { .. } | Term::Bool { .. } | Term::Int { .. } | Term::Var { .. } => {
+                return immediate(term, &env, &self.table).map(State::Return);
+            }
+            Term::Function { .. } => return Ok(State::Return(self.make_closure(term, &env, None))),
+            Term::Let {
+                name, value, next, ..
+            } if matches!(value.as_ref(), Term::Function { .. }) => {
+                let value = self.let_value(name.text, value, &env)?;
+                self.bound(name.text, &value);
+                if self.next_global.is_some_and(|global| ptr::eq(global, term)) {
+                    self.table.push(value.clone());
+                    self.next_global = defines_function(next).then_some(&**next);
+                }
+                return Ok(State::Eval(next, env.extend(name.text, value)));
+            }
+            // Literals and names are bound without a step of their own,
+            // unless a tracer or debugger must be shown every term.
+            Term::Let {
+                name, value, next, ..
+            } if is_immediate(value) && self.tracer.is_none() && self.debugger.is_none() => {
+                let value = immediate(value, &env, &self.table)?;
+                self.bound(name.text, &value);
+                return Ok(State::Eval(next, env.extend(name.text, value)));
+            }
+            Term::If { condition, .. } => (
+                condition,
+                Continuation::Branch {
+                    term,
+                    env: env.clone(),
+                },
+            ),
+            Term::Tuple { first, second, .. } => {
+                let continuation = match self.spawn(second, &env) {
+                    Some(pending) => Continuation::Join {
+                        term,
+                        env: env.clone(),
+                        pending,
+                    },
+                    None => Continuation::TupleSecond {
+                        term,
+                        env: env.clone(),
+                    },
+                };
+                (first, continuation)
+            }
+            Term::Match { value, .. } => (
+                value,
+                Continuation::Match {
+                    term,
+                    env: env.clone(),
+                },
+            ),
+            Term::Record { fields, .. } => match fields.first() {
+                Some(field) => (
+                    &field.value,
+                    Continuation::Record {
+                        term,
+                        env: env.clone(),
+                        values: Vec::new(),
+                    },
+                ),
+                None => return Ok(State::Return(Value::Record(Rc::default()))),
+            },
+            Term::Field { value, .. } => (value, Continuation::Field { term }),
+            Term::Ref { value, .. } => (value, Continuation::Ref),
+            Term::Deref { value, .. } => (value, Continuation::Deref { term }),
+            Term::Assign { reference, .. } => (
+                reference,
+                Continuation::AssignValue {
+                    term,
+                    env: env.clone(),
+                },
+            ),
+            Term::First { value, .. } => (value, Continuation::First { term }),
+            Term::Second { value, .. } => (value, Continuation::Second { term }),
+            Term::Binary { lhs, op, rhs, .. } => {
+                // The right operand of `&&` and `||` may not be needed.
+                let pending = match op {
+                    BinaryOp::And | BinaryOp::Or => None,
+                    _ => self.spawn(rhs, &env),
+                };
+                let continuation = match pending {
+                    Some(pending) => Continuation::Join {
+                        term,
+                        env: env.clone(),
+                        pending,
+                    },
+                    None => Continuation::Rhs {
+                        term,
+                        env: env.clone(),
+                    },
+                };
+                (lhs, continuation)
+            }
+            Term::Print { values, .. } => (
+                &values[0],
+                Continuation::Print {
+                    term,
+                    env: env.clone(),
+                    values: Vec::new(),
+                },
+            ),
+            Term::Let { value, .. } => (
+                value,
+                Continuation::Let {
+                    term,
+                    env: env.clone(),
+                },
+            ),
+            Term::LetTuple { value, .. } => (
+                value,
+                Continuation::LetTuple {
+                    term,
+                    env: env.clone(),
+                },
+            ),
+            Term::Call {
+                callee, location, ..
+            } => {
+                self.fuel.consume(location)?;
+                memory::check(location)?;
+                interrupt::check(location)?;
+                (
+                    callee,
+                    Continuation::Callee {
+                        term,
+                        env: env.clone(),
+                    },
+                )
+            }
+        };
+        stack.push(continuation);
+        Ok(State::Eval(subterm, env))
     }
-}
 
-fn interpret(term: &Term, env: Env) -> Value {
-    match term {
-        Term::Str { value, .. } => Value::String(value.clone()),
-        Term::Bool { value, .. } => Value::Boolean(*value),
-        Term::Int { value, .. } => Value::Number(*value),
-        Term::If {
-            condition,
-            then,
-            otherwise,
-            ..
-        } => {
-            let condition_value = interpret(condition, env.clone());
-            let boolean = assert_bool(&condition_value);
-            if boolean {
-                interpret(then, env)
-            } else {
-                interpret(otherwise, env)
+    /// Passes the value of a subterm to the continuation waiting for it.
+    fn apply(
+        &mut self,
+        continuation: Continuation<'t>,
+        value: Value,
+        stack: &mut Vec<Continuation<'t>>,
+    ) -> Result<State<'t>, RuntimeError> {
+        match continuation {
+            Continuation::Branch { term, env } => {
+                let Term::If {
+                    condition,
+                    then,
+                    otherwise,
+                    ..
+                } = term
+                else {
+                    unreachable!()
+                };
+                let branch = if assert_bool(&value, condition.location())? {
+                    then
+                } else {
+                    otherwise
+                };
+                Ok(State::Eval(branch, env))
+            }
+            Continuation::Match { term, env } => {
+                let Term::Match { arms, location, .. } = term else {
+                    unreachable!()
+                };
+                let mut bindings = Vec::new();
+                for arm in arms {
+                    bindings.clear();
+                    if value::match_pattern(&arm.pattern, &value, &mut bindings) {
+                        let names = arm.pattern.names();
+                        let env = names
+                            .into_iter()
+                            .zip(bindings)
+                            .fold(env, |env, (name, value)| env.extend(name, value));
+                        return Ok(State::Eval(&arm.value, env));
+                    }
+                }
+                Err(RuntimeError::NoMatch {
+                    location: location.clone(),
+                })
+            }
+            Continuation::Record {
+                term,
+                env,
+                mut values,
+            } => {
+                let Term::Record { fields, .. } = term else {
+                    unreachable!()
+                };
+                values.push(value);
+                if let Some(field) = fields.get(values.len()) {
+                    stack.push(Continuation::Record {
+                        term,
+                        env: env.clone(),
+                        values,
+                    });
+                    return Ok(State::Eval(&field.value, env));
+                }
+                stats::allocation();
+                let names = fields.iter().map(|field| field.name.text);
+                Ok(State::Return(Value::Record(Rc::new(
+                    names.zip(values).collect(),
+                ))))
+            }
+            Continuation::Field { term } => {
+                let Term::Field { name, location, .. } = term else {
+                    unreachable!()
+                };
+                Ok(State::Return(value::field(&value, name.text, location)?))
+            }
+            Continuation::Ref => {
+                stats::allocation();
+                self.effects += 1;
+                Ok(State::Return(gc::new_ref(value)))
+            }
+            Continuation::Deref { term } => {
+                let Term::Deref {
+                    value: reference, ..
+                } = term
+                else {
+                    unreachable!()
+                };
+                let cell = assert_ref(&value, reference.location())?;
+                self.effects += 1;
+                Ok(State::Return(cell.borrow().clone()))
+            }
+            Continuation::AssignValue { term, env } => {
+                let Term::Assign { value: next, .. } = term else {
+                    unreachable!()
+                };
+                stack.push(Continuation::Assign {
+                    term,
+                    reference: value,
+                });
+                Ok(State::Eval(next, env))
+            }
+            Continuation::Assign { term, reference } => {
+                let Term::Assign {
+                    reference: target, ..
+                } = term
+                else {
+                    unreachable!()
+                };
+                let cell = assert_ref(&reference, target.location())?;
+                self.effects += 1;
+                tracing::trace!(parent: self.span(), value = %value, "assign");
+                *cell.borrow_mut() = value.clone();
+                Ok(State::Return(value))
+            }
+            Continuation::TupleSecond { term, env } => {
+                let Term::Tuple { second, .. } = term else {
+                    unreachable!()
+                };
+                stack.push(Continuation::Tuple { first: value });
+                Ok(State::Eval(second, env))
+            }
+            Continuation::Tuple { first } => {
+                stats::allocation();
+                Ok(State::Return(Value::tuple(first, value)))
+            }
+            Continuation::First { term } => {
+                let Term::First { value: tuple, .. } = term else {
+                    unreachable!()
+                };
+                let (first, _) = assert_tuple(&value, tuple.location())?;
+                Ok(State::Return(first.clone()))
+            }
+            Continuation::Second { term } => {
+                let Term::Second { value: tuple, .. } = term else {
+                    unreachable!()
+                };
+                let (_, second) = assert_tuple(&value, tuple.location())?;
+                Ok(State::Return(second.clone()))
+            }
+            Continuation::Rhs { term, env } => {
+                let Term::Binary {
+                    op, rhs, location, ..
+                } = term
+                else {
+                    unreachable!()
+                };
+                if let BinaryOp::And | BinaryOp::Or = op {
+                    let left = assert_bool(&value, location)?;
+                    if left == matches!(op, BinaryOp::Or) {
+                        return Ok(State::Return(Value::Boolean(left)));
+                    }
+                    stack.push(Continuation::Logical { term });
+                } else {
+                    stack.push(Continuation::Binary { term, left: value });
+                }
+                Ok(State::Eval(rhs, env))
+            }
+            Continuation::Binary { term, left } => {
+                let Term::Binary { op, location, .. } = term else {
+                    unreachable!()
+                };
+                Ok(State::Return(interpret_binary(
+                    &left,
+                    &value,
+                    op,
+                    self.config,
+                    location,
+                )?))
+            }
+            Continuation::Logical { term } => {
+                let location = term.location();
+                Ok(State::Return(Value::Boolean(assert_bool(
+                    &value, location,
+                )?)))
+            }
+            Continuation::Print {
+                term,
+                env,
+                mut values,
+            } => {
+                let Term::Print {
+                    values: terms,
+                    location,
+                } = term
+                else {
+                    unreachable!()
+                };
+                values.push(value);
+                if let Some(next) = terms.get(values.len()) {
+                    stack.push(Continuation::Print {
+                        term,
+                        env: env.clone(),
+                        values,
+                    });
+                    return Ok(State::Eval(next, env));
+                }
+                let line = values
+                    .iter()
+                    .map(|value| value::show(value, self.config, location))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(" ");
+                self.output.print(&line);
+                self.effects += 1;
+                Ok(State::Return(values.pop().unwrap()))
+            }
+            Continuation::Let { term, env } => {
+                let Term::Let { name, next, .. } = term else {
+                    unreachable!()
+                };
+                self.bound(name.text, &value);
+                Ok(State::Eval(next, env.extend(name.text, value)))
+            }
+            Continuation::LetTuple { term, env } => {
+                let Term::LetTuple {
+                    first,
+                    second,
+                    value: tuple,
+                    next,
+                    ..
+                } = term
+                else {
+                    unreachable!()
+                };
+                let (first_value, second_value) = assert_tuple(&value, tuple.location())?;
+                self.bound(first.text, first_value);
+                self.bound(second.text, second_value);
+                let env = env
+                    .extend(first.text, first_value.clone())
+                    .extend(second.text, second_value.clone());
+                Ok(State::Eval(next, env))
+            }
+            Continuation::Callee { term, env } => {
+                let Term::Call { arguments, .. } = term else {
+                    unreachable!()
+                };
+                let callee = assert_callee(&value, term, self.config)?;
+                let values = Vec::with_capacity(arguments.len());
+                match arguments.first() {
+                    Some(first) => {
+                        stack.push(Continuation::Argument {
+                            term,
+                            env: env.clone(),
+                            callee,
+                            values,
+                        });
+                        Ok(State::Eval(first, env))
+                    }
+                    None => self.call(callee, values, term, stack),
+                }
+            }
+            Continuation::Argument {
+                term,
+                env,
+                callee,
+                mut values,
+            } => {
+                let Term::Call { arguments, .. } = term else {
+                    unreachable!()
+                };
+                values.push(value);
+                match arguments.get(values.len()) {
+                    Some(next) => {
+                        stack.push(Continuation::Argument {
+                            term,
+                            env: env.clone(),
+                            callee,
+                            values,
+                        });
+                        Ok(State::Eval(next, env))
+                    }
+                    None => self.call(callee, values, term, stack),
+                }
+            }
+            Continuation::Memoize { key, effects } => {
+                if let Some(memo) = self.memo.as_mut() {
+                    if self.effects == effects {
+                        memo.insert(key, value.clone());
+                    }
+                }
+                Ok(State::Return(value))
+            }
+            Continuation::Leave => {
+                self.frames.pop();
+                Ok(State::Return(value))
+            }
+            Continuation::Profile { body, start } => {
+                if let Some(profiler) = self.profiler.as_mut() {
+                    profiler.leave(body, start);
+                }
+                Ok(State::Return(value))
+            }
+            Continuation::Trace { term, depth } => {
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.record(term, &value, depth);
+                    tracer.depth = depth;
+                }
+                Ok(State::Return(value))
+            }
+            Continuation::Join { term, env, pending } => {
+                let second = match term {
+                    Term::Tuple { second, .. } => {
+                        stack.push(Continuation::Tuple { first: value });
+                        second
+                    }
+                    Term::Binary { rhs, .. } => {
+                        stack.push(Continuation::Binary { term, left: value });
+                        rhs
+                    }
+                    _ => unreachable!(),
+                };
+                match pending.recv().expect("a worker stopped without a result") {
+                    Ok(Some(value)) => Ok(State::Return(parallel::restore(value, self))),
+                    Ok(None) => Ok(State::Eval(second, env)),
+                    Err((err, frames)) => {
+                        self.worker_frames = frames;
+                        Err(err)
+                    }
+                }
             }
         }
-        Term::Tuple { first, second, .. } => {
-            let fst = interpret(first, env.clone());
-            let snd = interpret(second, env);
-            Value::Tuple(Box::new(fst), Box::new(snd))
-        }
-        Term::First { value, .. } => {
-            let fst = interpret(value, env);
-            let (first, _) = assert_tuple(&fst);
-            first.clone()
-        }
-        Term::Second { value, .. } => {
-            let fst = interpret(value, env);
-            let (_, second) = assert_tuple(&fst);
-            second.clone()
+    }
+
+    /// The span of the running call, which events of the program belong
+    /// to.
+    fn span(&self) -> Option<tracing::Id> {
+        self.frames.last().and_then(|frame| frame.span.id())
+    }
+
+    /// Tells `tracing` that `name` was bound to `value`.
+    fn bound(&self, name: Symbol, value: &Value) {
+        tracing::trace!(parent: self.span(), name = %name, value = %value, "bind");
+    }
+
+    /// The calls that were active when the evaluation stopped with an
+    /// error, innermost last, including those of the worker it failed on.
+    fn backtrace(&mut self) -> Vec<CallSite> {
+        let mut backtrace: Vec<_> = self.frames.iter().map(Frame::site).collect();
+        backtrace.append(&mut self.worker_frames);
+        backtrace
+    }
+
+    /// Sends `term` to a worker when evaluating in parallel.
+    fn spawn(&self, term: &Term, env: &Env) -> Option<Pending> {
+        let parallel = self.parallel?;
+        parallel::spawn(parallel, self.frames.len(), term, env, &self.globals)
+    }
+
+    fn call(
+        &mut self,
+        callee: Callee,
+        arguments: Vec<Value>,
+        call: &'t Term,
+        stack: &mut Vec<Continuation<'t>>,
+    ) -> Result<State<'t>, RuntimeError> {
+        match callee {
+            Callee::Tree { closure, pure } => self.enter(closure, pure, arguments, call, stack),
+            Callee::Native(native) => {
+                if native.effects {
+                    self.effects += 1;
+                }
+                let result = (native.function)(&arguments, call.location())?;
+                Ok(State::Return(result))
+            }
+            Callee::Applied { partial, callee } => {
+                let arguments = partial.arguments.iter().cloned().chain(arguments).collect();
+                self.call(*callee, arguments, call, stack)
+            }
+            Callee::Curry(callee) => {
+                let partial = Partial::new(&callee, arguments);
+                Ok(State::Return(Value::Closure(value::Closure::Partial(
+                    Rc::new(partial),
+                ))))
+            }
         }
-        Term::Binary { lhs, op, rhs, .. } => {
-            let left = interpret(lhs, env.clone());
-            let right = interpret(rhs, env);
-            interpret_binary(&left, &right, op)
+    }
+
+    /// Starts running the body of `closure`, reusing a cached result when
+    /// the closure is pure. Calls in tail position are not cached
+    /// individually; their result is cached as the result of the call
+    /// waiting for them.
+    fn enter(
+        &mut self,
+        closure: Rc<Closure>,
+        pure: bool,
+        arguments: Vec<Value>,
+        call: &'t Term,
+        stack: &mut Vec<Continuation<'t>>,
+    ) -> Result<State<'t>, RuntimeError> {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.depth += 1;
         }
-        Term::Print { value, .. } => {
-            let value = interpret(value, env.clone());
-            let value_str = show_value(&value);
-            println!("{}", value_str);
-            value
+        if let Some(profiler) = self.profiler.as_mut() {
+            let start = profiler.enter(&closure.body);
+            stack.push(Continuation::Profile {
+                body: Rc::as_ptr(&closure.body),
+                start,
+            });
         }
-        Term::Var { text, .. } => {
-            if let Some(value) = env.objects.get(text) {
-                value.clone()
-            } else {
-                panic!("cannot find variable {}", text);
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &self.jit {
+            if let Some(result) = jit.call(&closure.body, &arguments) {
+                return Ok(State::Return(result));
             }
         }
-        Term::Let {
-            name, value, next, ..
-        } => {
-            let mut new_env = env.clone_env();
-            let value = interpret(value, env.clone());
-            new_env.objects.insert(name.text.clone(), value);
-            interpret(next, new_env)
+        let tail = matches!(
+            stack.last(),
+            None | Some(Continuation::Memoize { .. } | Continuation::Leave)
+        );
+        if let (Some(memo), false) = (&self.memo, tail) {
+            if pure {
+                let callee = value::Closure::Tree(closure.clone());
+                let key = CallKey::new(&callee, &arguments);
+                if let Some(result) = memo.get(&key) {
+                    tracing::debug!(parent: self.span(), function = closure.name.map(Symbol::as_str), "cache hit");
+                    return Ok(State::Return(result));
+                }
+                stack.push(Continuation::Memoize {
+                    key,
+                    effects: self.effects,
+                });
+            }
         }
-        Term::Call {
-            callee, arguments, ..
-        } => {
-            let func = interpret(callee, env.clone());
-            let closure = assert_closure(&func);
-
-            if closure.parameters.len() != arguments.len() {
-                panic!(
-                    "expected {} arguments but instead got {}",
-                    closure.parameters.len(),
-                    arguments.len()
-                );
+        match (stack.last(), self.frames.last()) {
+            (Some(Continuation::Leave), Some(_)) => {
+                self.frames.pop();
             }
-
-            let mut function_env = closure.env.clone_env();
-
-            for (param, arg) in closure.parameters.iter().zip(arguments.iter()) {
-                let arg_value = interpret(arg, env.clone());
-                function_env.objects.insert(param.clone(), arg_value);
+            _ => {
+                depth::check(self.frames.len() + 1, call.location())?;
+                stack.push(Continuation::Leave);
             }
-
-            interpret(&closure.body, function_env)
         }
-        Term::Function {
-            parameters, value, ..
-        } => Value::Closure(Closure {
-            body: value.clone(),
-            parameters: parameters.iter().map(|p| p.text.clone()).collect(),
-            env,
-        }),
+        let span = tracing::trace_span!(
+            parent: self.span(),
+            "call",
+            function = closure.name.map(Symbol::as_str),
+            start = call.location().start,
+        );
+        self.frames.push(Frame {
+            closure: closure.clone(),
+            call,
+            span,
+        });
+        let body = self.body(&closure);
+        Ok(State::Eval(body, bind(&closure, arguments)))
     }
 }
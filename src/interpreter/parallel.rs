@@ -0,0 +1,317 @@
+//! Evaluation of the operands of binary operators and tuples on other
+//! threads, for [`super::interpret_file_parallel`].
+//!
+//! Values and terms cannot leave the thread that made them: they are shared
+//! with `Rc`, and names are interned per thread. An operand is therefore
+//! sent to a worker as text, as the JSON of its term together with the
+//! values of its free variables, including every function it can reach.
+//! Only operands that make a call and can never print are sent, so the
+//! output of a program stays the same.
+
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+
+use super::{Arena, CallSite, Env, Interpreter, Session};
+use crate::config::InterpreterConfig;
+use crate::error::RuntimeError;
+use crate::fuel::Fuel;
+use crate::list::List;
+use crate::memo::is_pure;
+use crate::resolver::{resolve_in, resolve_let_in};
+use crate::symbol::Symbol;
+use crate::types::{Parameter, Term};
+use crate::value::{self, Value};
+
+/// Number of operands being evaluated by workers. It never exceeds the
+/// number of threads of the pool, so a worker waiting for an operand it
+/// sent never waits for a thread that is not coming.
+static BUSY: AtomicUsize = AtomicUsize::new(0);
+
+/// Operands are only sent from the outermost calls, where they are
+/// largest: sending one costs far more than evaluating a small one.
+const MAX_DEPTH: usize = 12;
+
+#[derive(Clone, Copy)]
+pub(super) struct Parallel {
+    pub memoize: bool,
+    pub config: InterpreterConfig,
+    /// Calls that were active where the evaluation of a worker started.
+    pub depth: usize,
+}
+
+/// The value of an operand sent to a worker, or `None` when the value
+/// cannot be sent back and the operand has to be evaluated again here. An
+/// error comes back with the calls that were active on the worker.
+pub(super) type Pending = Receiver<Result<Option<Portable>, (RuntimeError, Vec<CallSite>)>>;
+
+/// A value that can be sent to another thread.
+pub(super) enum Portable {
+    Number(i64),
+    #[cfg(feature = "bigint")]
+    BigInt(num_bigint::BigInt),
+    Boolean(bool),
+    String(String),
+    Tuple(Box<Portable>, Box<Portable>),
+    List(Vec<Portable>),
+    Record(Vec<(String, Portable)>),
+    Closure {
+        /// JSON of the function the closure was made from.
+        function: String,
+        name: Option<String>,
+        /// Bindings the closure captured, outermost first.
+        env: Vec<(String, Portable)>,
+    },
+}
+
+fn same(a: &Env, b: &Env) -> bool {
+    match (&a.0, &b.0) {
+        (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// The bindings of `env` above `globals`, which workers have on their own,
+/// innermost first.
+fn bindings<'e>(env: &'e Env, globals: &Env) -> Vec<(Symbol, &'e Value)> {
+    let mut bindings = Vec::new();
+    let mut env = env;
+    while !same(env, globals) {
+        let Some(binding) = &env.0 else {
+            break;
+        };
+        bindings.push((binding.name, &binding.value));
+        env = &binding.parent;
+    }
+    bindings
+}
+
+/// Converts `value` to be sent, unless it is or holds a builtin or a
+/// function that may print.
+fn portable(value: &Value, globals: &Env) -> Option<Portable> {
+    Some(match value {
+        Value::Number(n) => Portable::Number(*n),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => Portable::BigInt((**n).clone()),
+        Value::Boolean(b) => Portable::Boolean(*b),
+        Value::String(s) => Portable::String(s.to_string()),
+        Value::Tuple(pair) => Portable::Tuple(
+            Box::new(portable(&pair.0, globals)?),
+            Box::new(portable(&pair.1, globals)?),
+        ),
+        Value::List(list) => Portable::List(
+            list.iter()
+                .map(|value| portable(value, globals))
+                .collect::<Option<_>>()?,
+        ),
+        Value::Record(fields) => Portable::Record(
+            fields
+                .iter()
+                .map(|(name, value)| Some((name.to_string(), portable(value, globals)?)))
+                .collect::<Option<_>>()?,
+        ),
+        Value::Closure(value::Closure::Tree(closure)) => {
+            if !is_pure(&closure.body) {
+                return None;
+            }
+            let function = Term::Function {
+                parameters: closure
+                    .parameters
+                    .iter()
+                    .map(|name| Parameter {
+                        text: *name,
+                        location: closure.location.clone(),
+                    })
+                    .collect(),
+                value: closure.body.clone(),
+                location: closure.location.clone(),
+                captures: Vec::new(),
+            };
+            let mut env = Vec::new();
+            for (name, value) in bindings(&closure.env, globals).into_iter().rev() {
+                env.push((name.to_string(), portable(value, globals)?));
+            }
+            Portable::Closure {
+                function: serde_json::to_string(&function).ok()?,
+                name: closure.name.map(|name| name.to_string()),
+                env,
+            }
+        }
+        Value::Closure(_) | Value::Ref(_) => return None,
+    })
+}
+
+/// Binds `bindings`, outermost first, on top of the globals.
+fn restore_env(
+    bindings: Vec<(String, Portable)>,
+    interpreter: &mut Interpreter,
+) -> (Vec<Symbol>, Env) {
+    let mut scope = Vec::new();
+    let mut env = interpreter.globals.clone();
+    for (name, value) in bindings {
+        let name = Symbol::intern(&name);
+        env = env.extend(name, restore(value, interpreter));
+        scope.push(name);
+    }
+    (scope, env)
+}
+
+fn parse(json: &str) -> Rc<Term> {
+    Rc::new(serde_json::from_str(json).expect("terms sent to workers are valid JSON"))
+}
+
+/// Rebuilds a value sent from another thread. Functions are kept in the
+/// arena of `interpreter`.
+pub(super) fn restore(value: Portable, interpreter: &mut Interpreter) -> Value {
+    match value {
+        Portable::Number(n) => Value::Number(n),
+        #[cfg(feature = "bigint")]
+        Portable::BigInt(n) => Value::BigInt(Rc::new(n)),
+        Portable::Boolean(b) => Value::Boolean(b),
+        Portable::String(s) => Value::string(s),
+        Portable::Tuple(first, second) => {
+            Value::tuple(restore(*first, interpreter), restore(*second, interpreter))
+        }
+        Portable::List(values) => {
+            let list = values
+                .into_iter()
+                .rev()
+                .fold(List::default(), |tail, value| {
+                    List::cons(restore(value, interpreter), tail)
+                });
+            Value::List(list)
+        }
+        Portable::Record(fields) => {
+            let fields = fields
+                .into_iter()
+                .map(|(name, value)| (Symbol::intern(&name), restore(value, interpreter)))
+                .collect();
+            Value::Record(Rc::new(fields))
+        }
+        Portable::Closure {
+            function,
+            name,
+            env,
+        } => {
+            let (scope, env) = restore_env(env, interpreter);
+            let function = parse(&function);
+            let name = name.map(|name| Symbol::intern(&name));
+            let function = match name {
+                Some(name) => resolve_let_in(&scope, name, &function),
+                None => resolve_in(&scope, &function),
+            };
+            let function = interpreter.arena.alloc(function);
+            interpreter.make_closure(function, &env, name)
+        }
+    }
+}
+
+fn has_call(term: &Term) -> bool {
+    match term {
+        Term::Call { .. } => true,
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => false,
+        // Calls in a function only run once it is called.
+        Term::Function { .. } => false,
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => has_call(condition) || has_call(then) || has_call(otherwise),
+        Term::Let { value, next, .. } | Term::LetTuple { value, next, .. } => {
+            has_call(value) || has_call(next)
+        }
+        Term::Binary { lhs, rhs, .. } => has_call(lhs) || has_call(rhs),
+        Term::Tuple { first, second, .. } => has_call(first) || has_call(second),
+        Term::First { value, .. } | Term::Second { value, .. } => has_call(value),
+        Term::Print { values, .. } => values.iter().any(|value| has_call(value)),
+        Term::Match { value, arms, .. } => {
+            has_call(value) || arms.iter().any(|arm| has_call(&arm.value))
+        }
+        Term::Record { fields, .. } => fields.iter().any(|field| has_call(&field.value)),
+        Term::Field { value, .. } | Term::Ref { value, .. } | Term::Deref { value, .. } => {
+            has_call(value)
+        }
+        Term::Assign {
+            reference, value, ..
+        } => has_call(reference) || has_call(value),
+    }
+}
+
+/// Takes a worker for an operand if one is free.
+fn reserve() -> bool {
+    let threads = rayon::current_num_threads();
+    BUSY.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |busy| {
+        (busy < threads).then_some(busy + 1)
+    })
+    .is_ok()
+}
+
+fn evaluate(
+    parallel: Parallel,
+    term: &str,
+    bindings: Vec<(String, Portable)>,
+) -> Result<Option<Portable>, (RuntimeError, Vec<CallSite>)> {
+    // Operands never print, but the session needs somewhere to print to.
+    let mut output = Vec::new();
+    let arena = Arena::new();
+    let mut session = Session::new(
+        &arena,
+        parallel.memoize,
+        parallel.config,
+        Fuel::unlimited(),
+        &mut output,
+    );
+    session.interpreter.parallel = Some(parallel);
+    let (scope, env) = restore_env(bindings, &mut session.interpreter);
+    let term = arena.alloc(resolve_in(&scope, &parse(term)));
+    let value = session
+        .interpreter
+        .run(term, env)
+        .map_err(|err| (err, session.interpreter.backtrace()))?;
+    Ok(portable(&value, &session.env))
+}
+
+/// Starts evaluating `term` on a worker when one is free and the operand
+/// is worth sending: it makes a call, cannot print and only uses values
+/// that can be sent.
+pub(super) fn spawn(
+    parallel: Parallel,
+    depth: usize,
+    term: &Term,
+    env: &Env,
+    globals: &Env,
+) -> Option<Pending> {
+    let depth = parallel.depth + depth;
+    if depth > MAX_DEPTH
+        || BUSY.load(Ordering::SeqCst) >= rayon::current_num_threads()
+        || !has_call(term)
+        || !is_pure(term)
+    {
+        return None;
+    }
+    let free = term.free_names();
+    let visible = bindings(env, globals);
+    let mut sent = Vec::new();
+    // Names bound by the globals are left for the worker to find in its
+    // own.
+    for name in free {
+        if let Some((_, value)) = visible.iter().find(|(n, _)| *n == name) {
+            sent.push((name.to_string(), portable(value, globals)?));
+        }
+    }
+    let term = serde_json::to_string(term).ok()?;
+    if !reserve() {
+        return None;
+    }
+    let parallel = Parallel { depth, ..parallel };
+    let (send, receive) = mpsc::channel();
+    rayon::spawn(move || {
+        let result = evaluate(parallel, &term, sent);
+        BUSY.fetch_sub(1, Ordering::SeqCst);
+        // The operand is no longer needed when the other one failed.
+        let _ = send.send(result);
+    });
+    Some(receive)
+}
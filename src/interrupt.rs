@@ -0,0 +1,66 @@
+//! Stopping programs cleanly on ctrl-c.
+//!
+//! Killing the process in the middle of a program loses the lines it
+//! printed but had not written yet, and may cut a line in half. Once
+//! [`install`] has run, SIGINT only sets a flag instead. Both engines call
+//! [`check`] on every function call and stop with
+//! [`RuntimeError::Interrupted`] when the flag is set, which gives the
+//! caller the chance to write out what was printed and to tell how far the
+//! program got.
+//!
+//! Native code of the JIT makes no such checks, so a function it compiled
+//! that never returns would ignore the flag. A second SIGINT, arriving
+//! while the flag is still set, kills the process as usual.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+
+#[cfg(not(target_arch = "wasm32"))]
+use signal_hook::consts::SIGINT;
+
+use crate::error::RuntimeError;
+use crate::stats;
+use crate::types::Location;
+
+static INTERRUPTED: LazyLock<Arc<AtomicBool>> = LazyLock::new(Arc::default);
+
+/// Makes SIGINT stop the running program at its next call instead of
+/// killing the process, unless the program was already interrupted.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn install() -> io::Result<()> {
+    // Registered first so that it sees the flag as it was before this
+    // signal set it.
+    signal_hook::flag::register_conditional_default(SIGINT, Arc::clone(&INTERRUPTED))?;
+    signal_hook::flag::register(SIGINT, Arc::clone(&INTERRUPTED))?;
+    Ok(())
+}
+
+/// WebAssembly has no signals, so there is nothing to install.
+#[cfg(target_arch = "wasm32")]
+pub fn install() -> io::Result<()> {
+    Ok(())
+}
+
+/// Stops the running program at its next call, as SIGINT does once
+/// [`install`] has run.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Lets programs run again after an interruption.
+pub fn reset() {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+}
+
+/// Fails when the program was interrupted, reporting the calls it made
+/// before the one at `location`.
+pub fn check(location: &Location) -> Result<(), RuntimeError> {
+    if INTERRUPTED.load(Ordering::Relaxed) {
+        return Err(RuntimeError::Interrupted {
+            calls: stats::snapshot().calls.saturating_sub(1),
+            location: location.clone(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,874 @@
+//! Native code for integer functions, compiled with Cranelift.
+//!
+//! The JIT engine is the tree-walker with a fast path: functions bound by
+//! `let` whose parameters are integers, whose body only does arithmetic,
+//! comparisons and `if` over integers and booleans, and which only call
+//! themselves or other such functions are compiled to native code up
+//! front. When the interpreter calls one of them with integer arguments,
+//! the native code runs instead.
+//!
+//! Native code never reports errors itself. On overflow, division by zero
+//! or recursion deeper than [`MAX_DEPTH`], it gives up and the call is
+//! evaluated again by the interpreter, which produces the usual error (or a
+//! big integer with the `bigint` feature). The function is then left to the
+//! interpreter for the rest of the run. Since the compiled functions cannot
+//! print, running a call twice is not observable.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cranelift_codegen::ir;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, InstBuilder, MemFlags, Signature, StackSlotData, StackSlotKind,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{FuncId, Linkage, Module};
+
+use crate::symbol::Symbol;
+use crate::types::*;
+use crate::value::Value;
+
+/// Depth of native recursion after which a call falls back to the
+/// interpreter, whose stack lives on the heap.
+pub const MAX_DEPTH: i64 = 5_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ty {
+    Int,
+    Bool,
+}
+
+/// What a name refers to while looking for functions to compile.
+#[derive(Clone, Copy)]
+enum Binding {
+    Int,
+    Bool,
+    /// A compiled function.
+    Native(usize),
+    /// The function being checked, with its assumed return type.
+    Itself {
+        index: usize,
+        arity: usize,
+        returns: Ty,
+    },
+    /// Anything the native code cannot use.
+    Other,
+}
+
+/// Results of calls made by native code, when memoization is enabled.
+struct MemoTable {
+    arity: usize,
+    results: RefCell<HashMap<Vec<i64>, i64>>,
+}
+
+extern "C" fn memo_get(table: *const MemoTable, args: *const i64, out: *mut i64) -> u8 {
+    // SAFETY: native code passes a table owned by the `Jit` and a buffer
+    // holding `arity` arguments.
+    let (table, args) = unsafe { (&*table, std::slice::from_raw_parts(args, (*table).arity)) };
+    match table.results.borrow().get(args) {
+        Some(result) => {
+            unsafe { *out = *result };
+            1
+        }
+        None => 0,
+    }
+}
+
+extern "C" fn memo_put(table: *const MemoTable, args: *const i64, result: i64) {
+    // SAFETY: as in `memo_get`.
+    let (table, args) = unsafe { (&*table, std::slice::from_raw_parts(args, (*table).arity)) };
+    table.results.borrow_mut().insert(args.to_vec(), result);
+}
+
+/// Set by native code when it gives up on a call.
+#[repr(C)]
+struct Context {
+    failed: u8,
+}
+
+type Entry = extern "C" fn(*mut Context, *const i64) -> i64;
+
+struct Native {
+    id: FuncId,
+    /// The function called from Rust, which takes the arguments as an
+    /// array.
+    entry_id: FuncId,
+    arity: usize,
+    returns: Ty,
+    entry: Option<Entry>,
+    /// Set once the native code has given up on a call.
+    deoptimized: Cell<bool>,
+}
+
+pub struct Jit {
+    module: JITModule,
+    memoize: bool,
+    functions: Vec<Native>,
+    /// Native code refers to the tables by address, so each one is boxed
+    /// to keep it in place as more are added.
+    #[allow(clippy::vec_box)]
+    tables: Vec<Box<MemoTable>>,
+    /// Compiled functions by the address of their body, which is how the
+    /// interpreter's closures refer to them.
+    bodies: HashMap<*const Term, usize>,
+    /// Keeps the terms in `bodies` alive.
+    _program: Rc<Term>,
+    memo_get: FuncId,
+    memo_put: FuncId,
+}
+
+fn lookup(scope: &[(Symbol, Binding)], name: Symbol) -> Binding {
+    scope
+        .iter()
+        .rev()
+        .find(|(n, _)| *n == name)
+        .map_or(Binding::Other, |(_, binding)| *binding)
+}
+
+impl Jit {
+    /// Compiles every supported function of a resolved program.
+    pub fn compile(program: &Rc<Term>, memoize: bool) -> Jit {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").unwrap();
+        let isa = cranelift_native::builder()
+            .expect("host machine is not supported by Cranelift")
+            .finish(settings::Flags::new(flags))
+            .unwrap();
+        let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        builder.symbol("rinha_memo_get", memo_get as *const u8);
+        builder.symbol("rinha_memo_put", memo_put as *const u8);
+        let mut module = JITModule::new(builder);
+
+        let ptr = module.target_config().pointer_type();
+        let mut signature = module.make_signature();
+        signature.params.extend([AbiParam::new(ptr); 3]);
+        signature.returns.push(AbiParam::new(types::I8));
+        let memo_get = module
+            .declare_function("rinha_memo_get", Linkage::Import, &signature)
+            .unwrap();
+        signature.params[2] = AbiParam::new(types::I64);
+        signature.returns.clear();
+        let memo_put = module
+            .declare_function("rinha_memo_put", Linkage::Import, &signature)
+            .unwrap();
+
+        let mut jit = Jit {
+            module,
+            memoize,
+            functions: Vec::new(),
+            tables: Vec::new(),
+            bodies: HashMap::new(),
+            _program: program.clone(),
+            memo_get,
+            memo_put,
+        };
+        jit.scan(program, &mut Vec::new());
+        jit.module.finalize_definitions().unwrap();
+        for native in &mut jit.functions {
+            let entry = jit.module.get_finalized_function(native.entry_id);
+            // SAFETY: `entry` was compiled with the signature of `Entry`.
+            native.entry = Some(unsafe { std::mem::transmute::<*const u8, Entry>(entry) });
+        }
+        jit
+    }
+
+    /// Runs the native code of the closure with `body`, if there is any and
+    /// it can handle these arguments.
+    pub fn call(&self, body: &Rc<Term>, arguments: &[Value]) -> Option<Value> {
+        let native = &self.functions[*self.bodies.get(&Rc::as_ptr(body))?];
+        if native.deoptimized.get() {
+            return None;
+        }
+        let arguments = arguments
+            .iter()
+            .map(|argument| match argument {
+                Value::Number(n) => Some(*n),
+                _ => None,
+            })
+            .collect::<Option<Vec<i64>>>()?;
+        let mut context = Context { failed: 0 };
+        let result = native.entry.unwrap()(&mut context, arguments.as_ptr());
+        if context.failed != 0 {
+            native.deoptimized.set(true);
+            return None;
+        }
+        Some(match native.returns {
+            Ty::Int => Value::Number(result),
+            Ty::Bool => Value::Boolean(result != 0),
+        })
+    }
+
+    /// Looks for functions to compile, keeping track of what each name in
+    /// scope refers to.
+    fn scan(&mut self, term: &Rc<Term>, scope: &mut Vec<(Symbol, Binding)>) {
+        match term.as_ref() {
+            Term::Let {
+                name, value, next, ..
+            } => {
+                let binding = match value.as_ref() {
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => {
+                        let binding = self.function(name.text, parameters, body, scope);
+                        scope.push((name.text, binding));
+                        scope.extend(parameters.iter().map(|p| (p.text, Binding::Other)));
+                        self.scan(body, scope);
+                        scope.truncate(scope.len() - parameters.len() - 1);
+                        binding
+                    }
+                    _ => {
+                        self.scan(value, scope);
+                        Binding::Other
+                    }
+                };
+                scope.push((name.text, binding));
+                self.scan(next, scope);
+                scope.pop();
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                next,
+                ..
+            } => {
+                self.scan(value, scope);
+                scope.push((first.text, Binding::Other));
+                scope.push((second.text, Binding::Other));
+                self.scan(next, scope);
+                scope.truncate(scope.len() - 2);
+            }
+            Term::Function {
+                parameters, value, ..
+            } => {
+                scope.extend(parameters.iter().map(|p| (p.text, Binding::Other)));
+                self.scan(value, scope);
+                scope.truncate(scope.len() - parameters.len());
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.scan(condition, scope);
+                self.scan(then, scope);
+                self.scan(otherwise, scope);
+            }
+            Term::Binary { lhs, rhs, .. } => {
+                self.scan(lhs, scope);
+                self.scan(rhs, scope);
+            }
+            Term::Call {
+                callee, arguments, ..
+            } => {
+                self.scan(callee, scope);
+                for argument in arguments {
+                    self.scan(argument, scope);
+                }
+            }
+            Term::Tuple { first, second, .. } => {
+                self.scan(first, scope);
+                self.scan(second, scope);
+            }
+            Term::First { value, .. } | Term::Second { value, .. } => self.scan(value, scope),
+            Term::Print { values, .. } => {
+                for value in values {
+                    self.scan(value, scope);
+                }
+            }
+            Term::Match { value, arms, .. } => {
+                self.scan(value, scope);
+                for arm in arms {
+                    let names = arm.pattern.names();
+                    let depth = scope.len();
+                    scope.extend(names.into_iter().map(|name| (name, Binding::Other)));
+                    self.scan(&arm.value, scope);
+                    scope.truncate(depth);
+                }
+            }
+            Term::Record { fields, .. } => {
+                for field in fields {
+                    self.scan(&field.value, scope);
+                }
+            }
+            Term::Field { value, .. } | Term::Ref { value, .. } | Term::Deref { value, .. } => {
+                self.scan(value, scope)
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                self.scan(reference, scope);
+                self.scan(value, scope);
+            }
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => {}
+        }
+    }
+
+    /// Compiles the function bound to `name` if the native code supports
+    /// it, returning what `name` refers to.
+    fn function(
+        &mut self,
+        name: Symbol,
+        parameters: &[Parameter],
+        body: &Rc<Term>,
+        scope: &[(Symbol, Binding)],
+    ) -> Binding {
+        let index = self.functions.len();
+        let mut local = scope.to_vec();
+        let arity = parameters.len();
+        let returns = [Ty::Int, Ty::Bool].into_iter().find(|&returns| {
+            local.push((
+                name,
+                Binding::Itself {
+                    index,
+                    arity,
+                    returns,
+                },
+            ));
+            local.extend(parameters.iter().map(|p| (p.text, Binding::Int)));
+            let found = self.check(body, &mut local);
+            local.truncate(scope.len());
+            found == Some(returns)
+        });
+        let Some(returns) = returns else {
+            return Binding::Other;
+        };
+
+        let signature = self.signature(arity);
+        let id = self
+            .module
+            .declare_function(&format!("rinha_{}", index), Linkage::Local, &signature)
+            .unwrap();
+        let entry_id = self.entry(id, arity, index);
+        self.functions.push(Native {
+            id,
+            entry_id,
+            arity,
+            returns,
+            entry: None,
+            deoptimized: Cell::new(false),
+        });
+        self.tables.push(Box::new(MemoTable {
+            arity,
+            results: RefCell::default(),
+        }));
+        local.push((
+            name,
+            Binding::Itself {
+                index,
+                arity,
+                returns,
+            },
+        ));
+        self.translate(index, signature, parameters, body, local);
+        self.bodies.insert(Rc::as_ptr(body), index);
+        Binding::Native(index)
+    }
+
+    /// Type of `term` if native code can compute it.
+    fn check(&self, term: &Term, scope: &mut Vec<(Symbol, Binding)>) -> Option<Ty> {
+        match term {
+            Term::Int { .. } => Some(Ty::Int),
+            Term::Bool { .. } => Some(Ty::Bool),
+            Term::Var { text, .. } => match lookup(scope, *text) {
+                Binding::Int => Some(Ty::Int),
+                Binding::Bool => Some(Ty::Bool),
+                _ => None,
+            },
+            Term::Binary { lhs, op, rhs, .. } => {
+                let operands = (self.check(lhs, scope)?, self.check(rhs, scope)?);
+                match (op, operands) {
+                    (
+                        BinaryOp::Add
+                        | BinaryOp::Sub
+                        | BinaryOp::Mul
+                        | BinaryOp::Div
+                        | BinaryOp::Rem,
+                        (Ty::Int, Ty::Int),
+                    ) => Some(Ty::Int),
+                    (
+                        BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Lte | BinaryOp::Gte,
+                        (Ty::Int, Ty::Int),
+                    ) => Some(Ty::Bool),
+                    (BinaryOp::Eq | BinaryOp::Neq, (left, right)) if left == right => {
+                        Some(Ty::Bool)
+                    }
+                    (BinaryOp::And | BinaryOp::Or, (Ty::Bool, Ty::Bool)) => Some(Ty::Bool),
+                    _ => None,
+                }
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                if self.check(condition, scope)? != Ty::Bool {
+                    return None;
+                }
+                let ty = self.check(then, scope)?;
+                (self.check(otherwise, scope)? == ty).then_some(ty)
+            }
+            Term::Let {
+                name, value, next, ..
+            } => {
+                let binding = match self.check(value, scope)? {
+                    Ty::Int => Binding::Int,
+                    Ty::Bool => Binding::Bool,
+                };
+                scope.push((name.text, binding));
+                let ty = self.check(next, scope);
+                scope.pop();
+                ty
+            }
+            Term::Call {
+                callee, arguments, ..
+            } => {
+                let Term::Var { text, .. } = callee.as_ref() else {
+                    return None;
+                };
+                let (arity, returns) = match lookup(scope, *text) {
+                    Binding::Native(index) => {
+                        let native = &self.functions[index];
+                        (native.arity, native.returns)
+                    }
+                    Binding::Itself { arity, returns, .. } => (arity, returns),
+                    _ => return None,
+                };
+                if arguments.len() != arity {
+                    return None;
+                }
+                for argument in arguments {
+                    if self.check(argument, scope)? != Ty::Int {
+                        return None;
+                    }
+                }
+                Some(returns)
+            }
+            _ => None,
+        }
+    }
+
+    /// `(context, depth, arguments...) -> result`
+    fn signature(&self, arity: usize) -> Signature {
+        let mut signature = self.module.make_signature();
+        let ptr = self.module.target_config().pointer_type();
+        signature.params.push(AbiParam::new(ptr));
+        signature.params.push(AbiParam::new(types::I64));
+        signature
+            .params
+            .extend(std::iter::repeat_n(AbiParam::new(types::I64), arity));
+        signature.returns.push(AbiParam::new(types::I64));
+        signature
+    }
+
+    fn translate(
+        &mut self,
+        index: usize,
+        signature: Signature,
+        parameters: &[Parameter],
+        body: &Term,
+        scope: Vec<(Symbol, Binding)>,
+    ) {
+        let mut context = self.module.make_context();
+        context.func.signature = signature;
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let arity = parameters.len();
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        let bail = builder.create_block();
+        let start = builder.create_block();
+        let exit = builder.create_block();
+        builder.append_block_param(exit, types::I64);
+        for _ in 0..arity {
+            builder.append_block_param(start, types::I64);
+        }
+
+        builder.switch_to_block(entry);
+        let params = builder.block_params(entry).to_vec();
+        let (ctx, depth, arguments) = (params[0], params[1], params[2..].to_vec());
+        let memo = self.memoize && arity > 0;
+        let mut saved = None;
+        let check_depth = builder.create_block();
+        builder.ins().brif(depth, check_depth, &[], bail, &[]);
+        builder.switch_to_block(check_depth);
+        let ptr = self.module.target_config().pointer_type();
+        let table = &*self.tables[index] as *const MemoTable as i64;
+        if memo {
+            // Keep the original arguments to cache the result under them,
+            // even after tail calls replaced the parameters.
+            let slot = builder.create_sized_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                8 * arity as u32 + 8,
+                3,
+            ));
+            for (i, argument) in arguments.iter().enumerate() {
+                builder.ins().stack_store(*argument, slot, 8 * i as i32);
+            }
+            let args = builder.ins().stack_addr(ptr, slot, 0);
+            let out = builder.ins().stack_addr(ptr, slot, 8 * arity as i32);
+            let table = builder.ins().iconst(ptr, table);
+            let get = self
+                .module
+                .declare_func_in_func(self.memo_get, builder.func);
+            let call = builder.ins().call(get, &[table, args, out]);
+            let hit = builder.inst_results(call)[0];
+            let cached = builder.create_block();
+            builder.ins().brif(hit, cached, &[], start, &arguments);
+            builder.switch_to_block(cached);
+            let result = builder.ins().stack_load(types::I64, slot, 8 * arity as i32);
+            builder.ins().return_(&[result]);
+            saved = Some(args);
+        } else {
+            builder.ins().jump(start, &arguments);
+        }
+
+        builder.switch_to_block(start);
+        let mut scope = scope;
+        let values = builder.block_params(start).to_vec();
+        scope.extend(parameters.iter().map(|p| (p.text, Binding::Int)));
+        let mut translator = Translator {
+            builder,
+            jit: self,
+            this: index,
+            scope: scope
+                .into_iter()
+                .map(|(name, binding)| (name, Slot::from(binding)))
+                .collect(),
+            ctx,
+            depth,
+            start,
+            exit,
+            bail,
+        };
+        let len = translator.scope.len();
+        for (i, value) in values.into_iter().enumerate() {
+            translator.scope[len - arity + i].1 = Slot::Value(value);
+        }
+        translator.tail(body);
+        let mut builder = translator.builder;
+
+        builder.switch_to_block(exit);
+        let result = builder.block_params(exit)[0];
+        if let Some(args) = saved {
+            let table = builder.ins().iconst(ptr, table);
+            let put = self
+                .module
+                .declare_func_in_func(self.memo_put, builder.func);
+            builder.ins().call(put, &[table, args, result]);
+        }
+        builder.ins().return_(&[result]);
+
+        builder.switch_to_block(bail);
+        let one = builder.ins().iconst(types::I8, 1);
+        builder.ins().store(MemFlags::trusted(), one, ctx, 0);
+        let zero = builder.ins().iconst(types::I64, 0);
+        builder.ins().return_(&[zero]);
+
+        builder.seal_all_blocks();
+        builder.finalize();
+        self.module
+            .define_function(self.functions[index].id, &mut context)
+            .unwrap();
+    }
+
+    /// Defines the function called from Rust for the native function `id`,
+    /// which reads the arguments from an array.
+    fn entry(&mut self, id: FuncId, arity: usize, index: usize) -> FuncId {
+        let function = id;
+        let ptr = self.module.target_config().pointer_type();
+        let mut signature = self.module.make_signature();
+        signature.params.extend([AbiParam::new(ptr); 2]);
+        signature.returns.push(AbiParam::new(types::I64));
+        let id = self
+            .module
+            .declare_function(
+                &format!("rinha_entry_{}", index),
+                Linkage::Local,
+                &signature,
+            )
+            .unwrap();
+
+        let mut context = self.module.make_context();
+        context.func.signature = signature;
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        let (ctx, args) = (
+            builder.block_params(block)[0],
+            builder.block_params(block)[1],
+        );
+        let mut arguments = vec![ctx, builder.ins().iconst(types::I64, MAX_DEPTH)];
+        for i in 0..arity {
+            arguments.push(
+                builder
+                    .ins()
+                    .load(types::I64, MemFlags::trusted(), args, 8 * i as i32),
+            );
+        }
+        let callee = self.module.declare_func_in_func(function, builder.func);
+        let call = builder.ins().call(callee, &arguments);
+        let result = builder.inst_results(call)[0];
+        builder.ins().return_(&[result]);
+        builder.seal_all_blocks();
+        builder.finalize();
+        self.module.define_function(id, &mut context).unwrap();
+        id
+    }
+}
+
+/// Where the native code finds the value of a name.
+#[derive(Clone, Copy)]
+enum Slot {
+    Value(ir::Value),
+    Function(usize),
+    Other,
+}
+
+impl From<Binding> for Slot {
+    fn from(binding: Binding) -> Slot {
+        match binding {
+            Binding::Native(index) | Binding::Itself { index, .. } => Slot::Function(index),
+            _ => Slot::Other,
+        }
+    }
+}
+
+/// Emits the body of one function. Integers are `i64` and booleans `i8`.
+struct Translator<'a, 'b> {
+    builder: FunctionBuilder<'b>,
+    jit: &'a mut Jit,
+    /// Index of the function being translated.
+    this: usize,
+    scope: Vec<(Symbol, Slot)>,
+    ctx: ir::Value,
+    depth: ir::Value,
+    /// Block taking the parameters, where self tail calls jump to.
+    start: Block,
+    /// Block returning its parameter.
+    exit: Block,
+    /// Block that gives up on the call.
+    bail: Block,
+}
+
+impl Translator<'_, '_> {
+    fn lookup(&self, name: Symbol) -> Slot {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map_or(Slot::Other, |(_, slot)| *slot)
+    }
+
+    /// Gives up on the call when `condition` is true.
+    fn guard(&mut self, condition: ir::Value) {
+        let next = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, self.bail, &[], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    fn callee(&self, callee: &Term) -> usize {
+        match callee {
+            Term::Var { text, .. } => match self.lookup(*text) {
+                Slot::Function(index) => index,
+                _ => unreachable!("checked by `Jit::check`"),
+            },
+            _ => unreachable!("checked by `Jit::check`"),
+        }
+    }
+
+    fn arguments(&mut self, arguments: &[Rc<Term>]) -> Vec<ir::Value> {
+        arguments.iter().map(|a| self.value(a)).collect()
+    }
+
+    fn call(&mut self, index: usize, arguments: Vec<ir::Value>) -> ir::Value {
+        let depth = self.builder.ins().iadd_imm(self.depth, -1);
+        let callee = self
+            .jit
+            .module
+            .declare_func_in_func(self.jit.functions[index].id, self.builder.func);
+        let mut values = vec![self.ctx, depth];
+        values.extend(arguments);
+        let call = self.builder.ins().call(callee, &values);
+        let result = self.builder.inst_results(call)[0];
+        let failed = self
+            .builder
+            .ins()
+            .load(types::I8, MemFlags::trusted(), self.ctx, 0);
+        self.guard(failed);
+        match self.jit.functions[index].returns {
+            Ty::Int => result,
+            Ty::Bool => self.builder.ins().ireduce(types::I8, result),
+        }
+    }
+
+    /// Emits `term` in tail position: its value is returned.
+    fn tail(&mut self, term: &Term) {
+        match term {
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let condition = self.value(condition);
+                let (then_block, else_block) =
+                    (self.builder.create_block(), self.builder.create_block());
+                self.builder
+                    .ins()
+                    .brif(condition, then_block, &[], else_block, &[]);
+                self.builder.switch_to_block(then_block);
+                self.tail(then);
+                self.builder.switch_to_block(else_block);
+                self.tail(otherwise);
+            }
+            Term::Let {
+                name, value, next, ..
+            } => {
+                let value = self.value(value);
+                self.scope.push((name.text, Slot::Value(value)));
+                self.tail(next);
+                self.scope.pop();
+            }
+            Term::Call {
+                callee, arguments, ..
+            } if self.callee(callee) == self.this => {
+                let arguments = self.arguments(arguments);
+                self.builder.ins().jump(self.start, &arguments);
+            }
+            _ => {
+                let mut value = self.value(term);
+                if self.builder.func.dfg.value_type(value) == types::I8 {
+                    value = self.builder.ins().uextend(types::I64, value);
+                }
+                self.builder.ins().jump(self.exit, &[value]);
+            }
+        }
+    }
+
+    fn value(&mut self, term: &Term) -> ir::Value {
+        match term {
+            Term::Int { value, .. } => self.builder.ins().iconst(types::I64, *value),
+            Term::Bool { value, .. } => self.builder.ins().iconst(types::I8, *value as i64),
+            Term::Var { text, .. } => match self.lookup(*text) {
+                Slot::Value(value) => value,
+                _ => unreachable!("checked by `Jit::check`"),
+            },
+            Term::Binary {
+                lhs,
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                rhs,
+                ..
+            } => {
+                let left = self.value(lhs);
+                let (rhs_block, merge) = (self.builder.create_block(), self.builder.create_block());
+                self.builder.append_block_param(merge, types::I8);
+                if let BinaryOp::And = op {
+                    self.builder
+                        .ins()
+                        .brif(left, rhs_block, &[], merge, &[left]);
+                } else {
+                    self.builder
+                        .ins()
+                        .brif(left, merge, &[left], rhs_block, &[]);
+                }
+                self.builder.switch_to_block(rhs_block);
+                let right = self.value(rhs);
+                self.builder.ins().jump(merge, &[right]);
+                self.builder.switch_to_block(merge);
+                self.builder.block_params(merge)[0]
+            }
+            Term::Binary { lhs, op, rhs, .. } => {
+                let (x, y) = (self.value(lhs), self.value(rhs));
+                let ins = self.builder.ins();
+                match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                        let (result, overflow) = match op {
+                            BinaryOp::Add => ins.sadd_overflow(x, y),
+                            BinaryOp::Sub => ins.ssub_overflow(x, y),
+                            _ => ins.smul_overflow(x, y),
+                        };
+                        self.guard(overflow);
+                        result
+                    }
+                    BinaryOp::Div | BinaryOp::Rem => {
+                        let zero = ins.icmp_imm(IntCC::Equal, y, 0);
+                        self.guard(zero);
+                        let min = self.builder.ins().icmp_imm(IntCC::Equal, x, i64::MIN);
+                        let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, y, -1);
+                        let overflow = self.builder.ins().band(min, minus_one);
+                        self.guard(overflow);
+                        if let BinaryOp::Div = op {
+                            self.builder.ins().sdiv(x, y)
+                        } else {
+                            self.builder.ins().srem(x, y)
+                        }
+                    }
+                    BinaryOp::Eq => ins.icmp(IntCC::Equal, x, y),
+                    BinaryOp::Neq => ins.icmp(IntCC::NotEqual, x, y),
+                    BinaryOp::Lt => ins.icmp(IntCC::SignedLessThan, x, y),
+                    BinaryOp::Gt => ins.icmp(IntCC::SignedGreaterThan, x, y),
+                    BinaryOp::Lte => ins.icmp(IntCC::SignedLessThanOrEqual, x, y),
+                    BinaryOp::Gte => ins.icmp(IntCC::SignedGreaterThanOrEqual, x, y),
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                }
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let condition = self.value(condition);
+                let (then_block, else_block, merge) = (
+                    self.builder.create_block(),
+                    self.builder.create_block(),
+                    self.builder.create_block(),
+                );
+                self.builder
+                    .ins()
+                    .brif(condition, then_block, &[], else_block, &[]);
+                self.builder.switch_to_block(then_block);
+                let value = self.value(then);
+                let ty = self.builder.func.dfg.value_type(value);
+                self.builder.append_block_param(merge, ty);
+                self.builder.ins().jump(merge, &[value]);
+                self.builder.switch_to_block(else_block);
+                let value = self.value(otherwise);
+                self.builder.ins().jump(merge, &[value]);
+                self.builder.switch_to_block(merge);
+                self.builder.block_params(merge)[0]
+            }
+            Term::Let {
+                name, value, next, ..
+            } => {
+                let value = self.value(value);
+                self.scope.push((name.text, Slot::Value(value)));
+                let value = self.value(next);
+                self.scope.pop();
+                value
+            }
+            Term::Call {
+                callee, arguments, ..
+            } => {
+                let index = self.callee(callee);
+                let arguments = self.arguments(arguments);
+                self.call(index, arguments)
+            }
+            _ => unreachable!("checked by `Jit::check`"),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Loading programs from the JSON AST, with errors that say where in the
+//! input they are.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::de::{self, DeserializeOwned, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value as Json};
+
+use crate::types::{CallCache, File, Term};
+
+/// Every `kind` of term the JSON AST may contain.
+pub const KINDS: [&str; 20] = [
+    "Int", "Str", "Bool", "If", "Let", "Binary", "Call", "Function", "First", "Print", "Second",
+    "Tuple", "Var", "Match", "Record", "Field", "Ref", "Deref", "Assign", "LetTuple",
+];
+
+/// Why a JSON AST could not be loaded.
+#[derive(Debug)]
+pub struct JsonError {
+    pub message: String,
+    /// Byte offset into the input where the problem was found. For
+    /// problems with a whole term, such as a missing field, this is the end
+    /// of the term.
+    pub offset: usize,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte offset {}", self.message, self.offset)
+    }
+}
+
+fn offset(source: &str, line: usize, column: usize) -> usize {
+    let start: usize = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    (start + column.saturating_sub(1)).min(source.len())
+}
+
+/// Reads the AST in `source`. Chains of `let` nest as deep as they are
+/// long in JSON, so there is no limit on how deep terms go, and the stack
+/// grows on the heap as they are read instead.
+pub fn parse(source: &str) -> Result<File, JsonError> {
+    let mut deserializer = serde_json::Deserializer::from_str(source);
+    deserializer.disable_recursion_limit();
+    let file = File::deserialize(serde_stacker::Deserializer::new(&mut deserializer))
+        .and_then(|file| deserializer.end().map(|()| file));
+    file.map_err(|err| {
+        // serde_json reports lines and columns, which the offset replaces.
+        let position = format!(" at line {} column {}", err.line(), err.column());
+        let message = err.to_string();
+        JsonError {
+            message: message
+                .strip_suffix(&position)
+                .unwrap_or(&message)
+                .to_string(),
+            offset: offset(source, err.line(), err.column()),
+        }
+    })
+}
+
+/// The fields of each kind of term that hold other terms.
+fn term_fields(kind: &str) -> &'static [&'static str] {
+    match kind {
+        "If" => &["condition", "then", "otherwise"],
+        "Let" | "LetTuple" => &["value", "next"],
+        "Binary" => &["lhs", "rhs"],
+        "Call" => &["callee", "arguments"],
+        "Function" | "First" | "Second" | "Match" | "Field" | "Ref" | "Deref" => &["value"],
+        "Print" => &["value", "values"],
+        "Assign" => &["reference", "value"],
+        "Tuple" => &["first", "second"],
+        _ => &[],
+    }
+}
+
+enum Child {
+    Term(Rc<Term>),
+    Terms(Vec<Rc<Term>>),
+}
+
+/// The fields of a term other than `kind`. Terms inside it are read as
+/// they come once the kind is known, which the reference parser always
+/// writes first, so errors inside them keep their position; everything
+/// else is kept as JSON until the whole term has been read.
+struct Fields<'a> {
+    kind: &'a str,
+    json: Map<String, Json>,
+    children: HashMap<&'static str, Child>,
+}
+
+impl Fields<'_> {
+    fn take<T: DeserializeOwned, E: de::Error>(&mut self, name: &str) -> Result<T, E> {
+        let json = self
+            .json
+            .remove(name)
+            .ok_or_else(|| E::custom(format!("missing field `{}` in {} term", name, self.kind)))?;
+        T::deserialize(json).map_err(|err| {
+            E::custom(format!(
+                "invalid field `{}` in {} term: {}",
+                name, self.kind, err
+            ))
+        })
+    }
+
+    fn term<E: de::Error>(&mut self, name: &str) -> Result<Rc<Term>, E> {
+        match self.children.remove(name) {
+            Some(Child::Term(term)) => Ok(term),
+            _ => self.take(name),
+        }
+    }
+
+    fn terms<E: de::Error>(&mut self, name: &str) -> Result<Vec<Rc<Term>>, E> {
+        match self.children.remove(name) {
+            Some(Child::Terms(terms)) => Ok(terms),
+            _ => self.take(name),
+        }
+    }
+}
+
+struct TermVisitor;
+
+impl<'de> Visitor<'de> for TermVisitor {
+    type Value = Term;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a term")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Term, A::Error> {
+        let mut kind: Option<String> = None;
+        let mut json = Map::new();
+        let mut children = HashMap::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let field = kind
+                .as_deref()
+                .and_then(|kind| term_fields(kind).iter().find(|field| **field == key));
+            if key == "kind" {
+                let name: String = map.next_value()?;
+                if !KINDS.contains(&name.as_str()) {
+                    return Err(de::Error::custom(format!(
+                        "unknown term kind `{}`, expected one of {}",
+                        name,
+                        KINDS.join(", ")
+                    )));
+                }
+                kind = Some(name);
+            } else if let Some(&field) = field {
+                let child = if field == "arguments" || field == "values" {
+                    Child::Terms(map.next_value()?)
+                } else {
+                    Child::Term(map.next_value()?)
+                };
+                children.insert(field, child);
+            } else {
+                json.insert(key, map.next_value()?);
+            }
+        }
+        let kind = kind.ok_or_else(|| de::Error::custom("missing field `kind` in term"))?;
+        let mut fields = Fields {
+            kind: &kind,
+            json,
+            children,
+        };
+        let location = fields.take("location")?;
+        let term = match kind.as_str() {
+            "Int" => Term::Int {
+                value: fields.take("value")?,
+                location,
+            },
+            "Str" => Term::Str {
+                value: fields.take("value")?,
+                location,
+            },
+            "Bool" => Term::Bool {
+                value: fields.take("value")?,
+                location,
+            },
+            "If" => Term::If {
+                condition: fields.term("condition")?,
+                then: fields.term("then")?,
+                otherwise: fields.term("otherwise")?,
+                location,
+            },
+            "Let" => Term::Let {
+                name: fields.take("name")?,
+                value: fields.term("value")?,
+                next: fields.term("next")?,
+                location,
+            },
+            "LetTuple" => Term::LetTuple {
+                first: fields.take("first")?,
+                second: fields.take("second")?,
+                value: fields.term("value")?,
+                next: fields.term("next")?,
+                location,
+            },
+            "Binary" => Term::Binary {
+                lhs: fields.term("lhs")?,
+                op: fields.take("op")?,
+                rhs: fields.term("rhs")?,
+                location,
+            },
+            "Call" => Term::Call {
+                callee: fields.term("callee")?,
+                arguments: fields.terms("arguments")?,
+                location,
+                cache: CallCache::default(),
+            },
+            "Function" => Term::Function {
+                parameters: fields.take("parameters")?,
+                value: fields.term("value")?,
+                location,
+                captures: Vec::new(),
+            },
+            "First" => Term::First {
+                value: fields.term("value")?,
+                location,
+            },
+            "Print" => {
+                let values = match fields.children.contains_key("values") {
+                    true => fields.terms("values")?,
+                    false => vec![fields.term("value")?],
+                };
+                if values.is_empty() {
+                    return Err(de::Error::custom("empty field `values` in Print term"));
+                }
+                Term::Print { values, location }
+            }
+            "Second" => Term::Second {
+                value: fields.term("value")?,
+                location,
+            },
+            "Tuple" => Term::Tuple {
+                first: fields.term("first")?,
+                second: fields.term("second")?,
+                location,
+            },
+            "Var" => Term::Var {
+                text: fields.take("text")?,
+                index: None,
+                location,
+            },
+            "Match" => Term::Match {
+                value: fields.term("value")?,
+                arms: fields.take("arms")?,
+                location,
+            },
+            "Record" => Term::Record {
+                fields: fields.take("fields")?,
+                location,
+            },
+            "Field" => Term::Field {
+                value: fields.term("value")?,
+                name: fields.take("name")?,
+                location,
+            },
+            "Ref" => Term::Ref {
+                value: fields.term("value")?,
+                location,
+            },
+            "Deref" => Term::Deref {
+                value: fields.term("value")?,
+                location,
+            },
+            "Assign" => Term::Assign {
+                reference: fields.term("reference")?,
+                value: fields.term("value")?,
+                location,
+            },
+            _ => unreachable!("kinds are checked when read"),
+        };
+        Ok(term)
+    }
+}
+
+impl<'de> Deserialize<'de> for Term {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Term, D::Error> {
+        deserializer.deserialize_map(TermVisitor)
+    }
+}
@@ -0,0 +1,150 @@
+//! Tokens of Rinha source as tools that color it see them, for `rinha
+//! highlight`.
+//!
+//! Unlike the tokens the parser reads, these cover every byte of the
+//! source: whitespace and comments are tokens too, and text the parser
+//! would reject becomes an error token rather than stopping the lexer, so
+//! code being typed can still be colored.
+
+use std::ops::Range;
+
+use crate::parser::{self, Lexer};
+
+/// What a token is, as far as tools that show code need to know.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    /// A `//` comment, without the line break ending it, or a `/* */` one.
+    Comment,
+    Int,
+    Str,
+    Ident,
+    Keyword,
+    Punctuation,
+    /// Text the lexer rejected, such as an unterminated string.
+    Error,
+}
+
+impl TokenKind {
+    /// The name of the kind, which `rinha highlight` uses in its output.
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenKind::Whitespace => "whitespace",
+            TokenKind::Comment => "comment",
+            TokenKind::Int => "int",
+            TokenKind::Str => "string",
+            TokenKind::Ident => "ident",
+            TokenKind::Keyword => "keyword",
+            TokenKind::Punctuation => "punctuation",
+            TokenKind::Error => "error",
+        }
+    }
+}
+
+/// The tokens of `source` in order with their byte ranges, which put
+/// together cover all of it.
+pub fn tokenize(source: &str) -> Vec<(TokenKind, Range<usize>)> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(lexeme) = lex(source, pos, "") {
+        pos = lexeme.range.end;
+        tokens.push((lexeme.kind, lexeme.range));
+    }
+    tokens
+}
+
+/// `source` as an HTML `pre` element, each token other than whitespace in
+/// a `span` of the class `rinha-` followed by the name of its kind.
+pub fn html(source: &str) -> String {
+    let mut html = String::from("<pre class=\"rinha\">");
+    for (kind, range) in tokenize(source) {
+        let text = escape(&source[range]);
+        match kind {
+            TokenKind::Whitespace => html.push_str(&text),
+            kind => html.push_str(&format!(
+                "<span class=\"rinha-{}\">{}</span>",
+                kind.name(),
+                text
+            )),
+        }
+    }
+    html.push_str("</pre>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A token as [`lex`] reads it.
+pub(crate) struct Lexeme {
+    pub(crate) kind: TokenKind,
+    pub(crate) range: Range<usize>,
+    /// The token the parser reads, unless this one is trivia or an error.
+    pub(crate) token: Option<parser::Token>,
+    /// The message of an error, and where the text it is about starts.
+    pub(crate) error: Option<(String, usize)>,
+}
+
+/// The token of `text` starting at the byte offset `start`, or `None` at
+/// the end of the text.
+pub(crate) fn lex(text: &str, start: usize, filename: &str) -> Option<Lexeme> {
+    let rest = &text[start..];
+    let c = rest.chars().next()?;
+    let mut end = start;
+    let mut token = None;
+    let mut error = None;
+    let kind = if c.is_whitespace() {
+        end += rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        TokenKind::Whitespace
+    } else if rest.starts_with("//") {
+        end += rest.find('\n').unwrap_or(rest.len());
+        TokenKind::Comment
+    } else if let Some(body) = rest.strip_prefix("/*") {
+        match body.find("*/") {
+            Some(len) => end += len + 4,
+            None => {
+                end = text.len();
+                error = Some(("unterminated comment".to_string(), start));
+            }
+        }
+        TokenKind::Comment
+    } else {
+        let mut lexer = Lexer::new(text, start, filename);
+        match lexer.next_token() {
+            Ok((lexed, _, lexed_end)) => {
+                end = lexed_end;
+                let kind = kind(&lexed);
+                token = Some(lexed);
+                kind
+            }
+            Err(err) => {
+                end = lexer.pos().max(start + c.len_utf8());
+                error = Some((err.message, err.location.start));
+                TokenKind::Error
+            }
+        }
+    };
+    Some(Lexeme {
+        kind,
+        range: start..end,
+        token,
+        error,
+    })
+}
+
+fn kind(token: &parser::Token) -> TokenKind {
+    use parser::Token::*;
+    match token {
+        Int(_) => TokenKind::Int,
+        Str(_) => TokenKind::Str,
+        Ident(_) => TokenKind::Ident,
+        Let | Fn | If | Else | True | False | Print | First | Second => TokenKind::Keyword,
+        _ => TokenKind::Punctuation,
+    }
+}
@@ -0,0 +1,203 @@
+//! Interpreter for the Rinha language.
+//!
+//! Programs are loaded either from `.rinha` source with [`parse`] or from
+//! the JSON AST produced by the reference parser with [`parse_json`], and
+//! then evaluated with [`interpret`]:
+//!
+//! ```
+//! let file = rinha::parse("let x = 20; x + 22", "example.rinha").unwrap();
+//! let value = rinha::interpret(&file).unwrap();
+//! assert!(matches!(value, rinha::Value::Number(42)));
+//! ```
+
+#[cfg(feature = "bigint")]
+mod bigint;
+pub mod builtins;
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod checkpoint;
+pub mod codegen;
+pub mod color;
+pub mod compiler;
+pub mod complexity;
+pub mod config;
+pub mod conformance;
+pub mod cse;
+pub mod cst;
+pub mod depth;
+pub mod difftest;
+pub mod error;
+mod escape;
+pub mod fuel;
+pub mod gc;
+pub mod inline;
+pub mod input;
+pub mod interpreter;
+pub mod interrupt;
+#[cfg(feature = "jit")]
+mod jit;
+pub mod json;
+pub mod lexer;
+pub mod lint;
+pub mod list;
+mod memo;
+pub mod memory;
+pub mod modules;
+#[cfg(feature = "napi")]
+pub mod node;
+pub mod optimizer;
+pub mod output;
+pub mod packed;
+pub mod parser;
+pub mod peval;
+#[cfg(feature = "playground")]
+pub mod playground;
+pub mod printer;
+pub mod profile;
+#[cfg(feature = "pyo3")]
+mod python;
+pub mod random;
+pub mod resolver;
+pub mod resources;
+pub mod stats;
+pub mod symbol;
+pub mod trace;
+pub mod typecheck;
+pub mod types;
+pub mod validate;
+pub mod value;
+pub mod vm;
+
+pub use config::InterpreterConfig;
+pub use error::RuntimeError;
+pub use fuel::Fuel;
+pub use input::Input;
+pub use json::JsonError;
+pub use output::Output;
+pub use parser::ParseError;
+pub use types::File;
+pub use value::Value;
+
+/// Strategy used to execute a program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Engine {
+    /// The tree-walking interpreter.
+    Tree,
+    /// The bytecode compiler and virtual machine.
+    Vm,
+    /// The tree-walker running integer functions as native code. Without
+    /// the `jit` feature this is the same as [`Engine::Tree`].
+    Jit,
+}
+
+impl Engine {
+    pub const ALL: [Engine; 3] = [Engine::Tree, Engine::Vm, Engine::Jit];
+
+    /// The name of the engine in `--engine=<e>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Engine::Tree => "tree",
+            Engine::Vm => "vm",
+            Engine::Jit => "jit",
+        }
+    }
+
+    pub fn run(
+        self,
+        file: &File,
+        memoize: bool,
+        config: InterpreterConfig,
+        fuel: Fuel,
+        output: &mut dyn Output,
+    ) -> Result<Value, RuntimeError> {
+        match self {
+            Engine::Tree => interpreter::interpret_file(file, memoize, config, fuel, output),
+            Engine::Vm => vm::run(
+                &compiler::compile(file, config),
+                memoize,
+                config,
+                fuel,
+                output,
+            ),
+            #[cfg(feature = "jit")]
+            Engine::Jit => interpreter::interpret_file_jit(file, memoize, config, fuel, output),
+            #[cfg(not(feature = "jit"))]
+            Engine::Jit => interpreter::interpret_file(file, memoize, config, fuel, output),
+        }
+    }
+}
+
+/// Parses `.rinha` source code. `filename` is recorded in the locations of
+/// the resulting AST.
+pub fn parse(source: &str, filename: &str) -> Result<File, ParseError> {
+    parser::parse(source, filename)
+}
+
+/// Like [`parse`], also accepting the language extensions in `syntax`.
+pub fn parse_with(
+    source: &str,
+    filename: &str,
+    syntax: parser::Syntax,
+) -> Result<File, ParseError> {
+    parser::parse_with(source, filename, syntax)
+}
+
+/// Loads a program from the JSON AST format of the reference parser.
+pub fn parse_json(json: &str) -> Result<File, JsonError> {
+    json::parse(json)
+}
+
+/// Loads a program given either as source or, when it starts with `{`, as
+/// a JSON AST, and checks it with [`validate::validate`], for hosts that
+/// take programs in both forms and run them as the specification says.
+/// Fails with the message of the first error.
+pub fn load(program: &str, filename: &str) -> Result<File, String> {
+    if program.trim_start().starts_with('{') {
+        return load_json(program);
+    }
+    valid(parse(program, filename).map_err(|err| err.to_string())?)
+}
+
+/// Like [`load`], for hosts that only take JSON ASTs.
+pub fn load_json(json: &str) -> Result<File, String> {
+    valid(parse_json(json).map_err(|err| format!("invalid JSON AST: {}", err))?)
+}
+
+fn valid(file: File) -> Result<File, String> {
+    match validate::validate(&file, InterpreterConfig::STRICT)
+        .into_iter()
+        .next()
+    {
+        Some(violation) => Err(violation.message),
+        None => Ok(file),
+    }
+}
+
+/// Runs a program [`load`] gave back the way every host does: optimized,
+/// by the tree-walker with memoization, under
+/// [`InterpreterConfig::STRICT`].
+pub fn run_loaded(file: &File, output: &mut dyn Output) -> Result<Value, RuntimeError> {
+    let file = optimizer::optimize(file);
+    let config = InterpreterConfig::STRICT;
+    Engine::Tree.run(&file, true, config, Fuel::unlimited(), output)
+}
+
+/// Runs a program with the tree-walking interpreter and returns the value
+/// of its top-level expression. `print` writes to stdout.
+pub fn interpret(file: &File) -> Result<Value, RuntimeError> {
+    interpret_with_output(file, &mut std::io::stdout())
+}
+
+/// Like [`interpret`], but sends everything `print` writes to `output`.
+///
+/// ```
+/// let file = rinha::parse("print(1 + 1)", "example.rinha").unwrap();
+/// let mut lines = Vec::new();
+/// rinha::interpret_with_output(&file, &mut lines).unwrap();
+/// assert_eq!(lines, ["2"]);
+/// ```
+pub fn interpret_with_output(file: &File, output: &mut dyn Output) -> Result<Value, RuntimeError> {
+    let config = InterpreterConfig::STRICT;
+    interpreter::interpret_file(file, true, config, Fuel::unlimited(), output)
+}
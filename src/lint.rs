@@ -0,0 +1,452 @@
+//! Warnings about operations that will fail at runtime.
+//!
+//! Unlike [`crate::typecheck`], the linter accepts every program: it only
+//! follows values whose shape is evident from the code, such as a name
+//! bound to a literal or to a function, and warns when one of them is used
+//! in a way the interpreter will reject. Anything it cannot tell is left
+//! alone, so a program without warnings may still fail.
+
+use crate::config::{Coercion, InterpreterConfig};
+use crate::symbol::Symbol;
+use crate::types::*;
+
+#[derive(Debug)]
+pub struct Warning {
+    pub message: String,
+    pub location: Location,
+}
+
+/// What is known about a value.
+#[derive(Clone, Debug, PartialEq)]
+enum Shape {
+    Int,
+    Bool,
+    Str,
+    Tuple(Box<Shape>, Box<Shape>),
+    Closure {
+        arity: usize,
+    },
+    Record(Vec<(Symbol, Shape)>),
+    /// What a reference holds may change, so it is not tracked.
+    Ref,
+    Unknown,
+}
+
+impl Shape {
+    fn name(&self) -> Option<&'static str> {
+        match self {
+            Shape::Int => Some("int"),
+            Shape::Bool => Some("bool"),
+            Shape::Str => Some("string"),
+            Shape::Tuple(_, _) => Some("tuple"),
+            Shape::Closure { .. } => Some("closure"),
+            Shape::Record(_) => Some("record"),
+            Shape::Ref => Some("ref"),
+            Shape::Unknown => None,
+        }
+    }
+
+    /// The shape of either of two values.
+    fn join(self, other: Shape) -> Shape {
+        match (self, other) {
+            (Shape::Tuple(a1, a2), Shape::Tuple(b1, b2)) => {
+                Shape::Tuple(Box::new(a1.join(*b1)), Box::new(a2.join(*b2)))
+            }
+            (a, b) if a == b => a,
+            _ => Shape::Unknown,
+        }
+    }
+}
+
+/// A pattern as exhaustiveness checking sees it, where names are the same
+/// as `_`.
+#[derive(Clone, PartialEq)]
+enum Pat {
+    Any,
+    Int(i64),
+    Str(String),
+    Bool(bool),
+    Tuple(Box<Pat>, Box<Pat>),
+}
+
+impl Pat {
+    fn new(pattern: &Pattern) -> Pat {
+        match pattern {
+            Pattern::Wildcard { .. } | Pattern::Var { .. } => Pat::Any,
+            Pattern::Int { value, .. } => Pat::Int(*value),
+            Pattern::Str { value, .. } => Pat::Str(value.clone()),
+            Pattern::Bool { value, .. } => Pat::Bool(*value),
+            Pattern::Tuple { first, second, .. } => {
+                Pat::Tuple(Box::new(Pat::new(first)), Box::new(Pat::new(second)))
+            }
+        }
+    }
+}
+
+/// The rows of `rows` that match what `head` matches first, with their
+/// first pattern replaced by what is left to match inside it.
+fn specialize(rows: &[Vec<Pat>], head: &Pat) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| {
+            let inner = match (head, &row[0]) {
+                (Pat::Tuple(..), Pat::Tuple(first, second)) => {
+                    vec![(**first).clone(), (**second).clone()]
+                }
+                (Pat::Tuple(..), Pat::Any) => vec![Pat::Any, Pat::Any],
+                (_, Pat::Any) => Vec::new(),
+                (head, first) if head == first => Vec::new(),
+                _ => return None,
+            };
+            Some(inner.into_iter().chain(row[1..].iter().cloned()).collect())
+        })
+        .collect()
+}
+
+/// Whether some value matches `vector` but none of `rows`.
+fn useful(rows: &[Vec<Pat>], vector: &[Pat]) -> bool {
+    let Some(head) = vector.first() else {
+        return rows.is_empty();
+    };
+    if *head != Pat::Any {
+        let rows = specialize(rows, head);
+        return useful(&rows, &specialize(&[vector.to_vec()], head)[0]);
+    }
+    let heads: Vec<&Pat> = rows.iter().map(|row| &row[0]).collect();
+    // Only tuples and booleans have finitely many values to try; every
+    // other type has values no list of literals covers.
+    let complete = if heads.iter().any(|head| matches!(head, Pat::Tuple(..))) {
+        vec![Pat::Tuple(Box::new(Pat::Any), Box::new(Pat::Any))]
+    } else if heads.contains(&&Pat::Bool(true)) && heads.contains(&&Pat::Bool(false)) {
+        vec![Pat::Bool(true), Pat::Bool(false)]
+    } else {
+        let rest: Vec<Vec<Pat>> = rows
+            .iter()
+            .filter(|row| row[0] == Pat::Any)
+            .map(|row| row[1..].to_vec())
+            .collect();
+        return useful(&rest, &vector[1..]);
+    };
+    complete.iter().any(|head| {
+        let mut vector = vector.to_vec();
+        vector[0] = head.clone();
+        let rows = specialize(rows, head);
+        useful(&rows, &specialize(&[vector], head)[0])
+    })
+}
+
+#[derive(Default)]
+struct Linter {
+    config: InterpreterConfig,
+    scope: Vec<(Symbol, Shape)>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn warn(&mut self, message: String, location: &Location) {
+        self.warnings.push(Warning {
+            message,
+            location: location.clone(),
+        });
+    }
+
+    /// Warns when `shape` is known not to be `expected`, with the message
+    /// of the error the interpreter would stop with.
+    fn expect(&mut self, shape: &Shape, expected: &'static str, location: &Location) {
+        self.expect_all(&[shape], expected, location);
+    }
+
+    /// Warns once when any of `shapes` is known not to be `expected`, as
+    /// the interpreter stops at the first of them.
+    fn expect_all(&mut self, shapes: &[&Shape], expected: &'static str, location: &Location) {
+        let found = shapes
+            .iter()
+            .find_map(|shape| shape.name().filter(|n| *n != expected));
+        if let Some(found) = found {
+            self.warn(
+                format!("expected {} but found {}", expected, found),
+                location,
+            );
+        }
+    }
+
+    fn function(&mut self, name: Option<Symbol>, parameters: &[Parameter], body: &Term) {
+        let depth = self.scope.len();
+        if let Some(name) = name {
+            let arity = parameters.len();
+            self.scope.push((name, Shape::Closure { arity }));
+        }
+        self.scope
+            .extend(parameters.iter().map(|p| (p.text, Shape::Unknown)));
+        self.lint(body);
+        self.scope.truncate(depth);
+    }
+
+    /// Warns about arms no value reaches and about values no arm matches,
+    /// which the interpreter stops on.
+    fn check_arms(&mut self, arms: &[Arm], location: &Location) {
+        let mut rows = Vec::new();
+        for arm in arms {
+            let row = vec![Pat::new(&arm.pattern)];
+            if !useful(&rows, &row) {
+                self.warn(
+                    "this arm is never reached".to_string(),
+                    arm.pattern.location(),
+                );
+            }
+            rows.push(row);
+        }
+        if useful(&rows, &[Pat::Any]) {
+            self.warn(
+                "this match does not cover every value".to_string(),
+                location,
+            );
+        }
+    }
+
+    /// Lints the value of the `let` term `binding` and brings its names
+    /// into scope.
+    fn binding(&mut self, binding: &Term) {
+        match binding {
+            Term::Let { name, value, .. } => {
+                let shape = match value.as_ref() {
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => {
+                        self.function(Some(name.text), parameters, body);
+                        Shape::Closure {
+                            arity: parameters.len(),
+                        }
+                    }
+                    _ => self.lint(value),
+                };
+                self.scope.push((name.text, shape));
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                ..
+            } => {
+                let shape = self.lint(value);
+                self.expect(&shape, "tuple", value.location());
+                let (first_shape, second_shape) = match shape {
+                    Shape::Tuple(first, second) => (*first, *second),
+                    _ => (Shape::Unknown, Shape::Unknown),
+                };
+                self.scope.push((first.text, first_shape));
+                self.scope.push((second.text, second_shape));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn lint(&mut self, term: &Term) -> Shape {
+        match term {
+            Term::Int { .. } => Shape::Int,
+            Term::Str { .. } => Shape::Str,
+            Term::Bool { .. } => Shape::Bool,
+            Term::Var { text, .. } => self
+                .scope
+                .iter()
+                .rev()
+                .find(|(n, _)| n == text)
+                .map_or(Shape::Unknown, |(_, shape)| shape.clone()),
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let shape = self.lint(condition);
+                self.expect(&shape, "bool", condition.location());
+                let then = self.lint(then);
+                then.join(self.lint(otherwise))
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => {
+                let depth = self.scope.len();
+                let mut chain = term.let_chain();
+                chain.by_ref().for_each(|binding| self.binding(binding));
+                let shape = self.lint(chain.rest());
+                self.scope.truncate(depth);
+                shape
+            }
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => {
+                let left = self.lint(lhs);
+                let right = self.lint(rhs);
+                self.binary(op, &left, &right, location)
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                let shape = self.lint(callee);
+                match shape {
+                    Shape::Closure { arity } if arity != arguments.len() => self.warn(
+                        format!(
+                            "expected {} arguments but instead got {}",
+                            arity,
+                            arguments.len()
+                        ),
+                        location,
+                    ),
+                    _ => self.expect(&shape, "closure", location),
+                }
+                for argument in arguments {
+                    self.lint(argument);
+                }
+                Shape::Unknown
+            }
+            Term::Function {
+                parameters, value, ..
+            } => {
+                self.function(None, parameters, value);
+                Shape::Closure {
+                    arity: parameters.len(),
+                }
+            }
+            Term::Tuple { first, second, .. } => {
+                Shape::Tuple(Box::new(self.lint(first)), Box::new(self.lint(second)))
+            }
+            Term::First { value, .. } | Term::Second { value, .. } => {
+                let shape = self.lint(value);
+                self.expect(&shape, "tuple", value.location());
+                match (shape, term) {
+                    (Shape::Tuple(first, _), Term::First { .. }) => *first,
+                    (Shape::Tuple(_, second), _) => *second,
+                    _ => Shape::Unknown,
+                }
+            }
+            Term::Print { values, .. } => values
+                .iter()
+                .map(|value| self.lint(value))
+                .last()
+                .unwrap_or(Shape::Unknown),
+            Term::Record { fields, .. } => Shape::Record(
+                fields
+                    .iter()
+                    .map(|field| (field.name.text, self.lint(&field.value)))
+                    .collect(),
+            ),
+            Term::Field {
+                value,
+                name,
+                location,
+            } => {
+                let shape = self.lint(value);
+                self.expect(&shape, "record", value.location());
+                match shape {
+                    Shape::Record(fields) => {
+                        match fields.into_iter().find(|(n, _)| *n == name.text) {
+                            Some((_, shape)) => shape,
+                            None => {
+                                self.warn(format!("record has no field {}", name.text), location);
+                                Shape::Unknown
+                            }
+                        }
+                    }
+                    _ => Shape::Unknown,
+                }
+            }
+            Term::Ref { value, .. } => {
+                self.lint(value);
+                Shape::Ref
+            }
+            Term::Deref { value, .. } => {
+                let shape = self.lint(value);
+                self.expect(&shape, "ref", value.location());
+                Shape::Unknown
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                let shape = self.lint(reference);
+                let value = self.lint(value);
+                self.expect(&shape, "ref", reference.location());
+                value
+            }
+            Term::Match {
+                value,
+                arms,
+                location,
+            } => {
+                self.lint(value);
+                self.check_arms(arms, location);
+                let mut shapes = arms.iter().map(|arm| {
+                    let depth = self.scope.len();
+                    let names = arm.pattern.names();
+                    self.scope
+                        .extend(names.into_iter().map(|name| (name, Shape::Unknown)));
+                    let shape = self.lint(&arm.value);
+                    self.scope.truncate(depth);
+                    shape
+                });
+                let first = shapes.next().unwrap_or(Shape::Unknown);
+                shapes.fold(first, Shape::join)
+            }
+        }
+    }
+
+    fn binary(&mut self, op: &BinaryOp, left: &Shape, right: &Shape, location: &Location) -> Shape {
+        match op {
+            BinaryOp::Add => {
+                // Coerced values only fail away from strings, which the
+                // shapes cannot always tell.
+                let coercion = self.config.coercion;
+                let found = [left, right].iter().find_map(|shape| match shape.name() {
+                    Some("bool") if coercion >= Coercion::Booleans => None,
+                    Some(_) if coercion == Coercion::All => None,
+                    Some(found @ ("bool" | "tuple" | "closure")) => Some(found),
+                    _ => None,
+                });
+                if let Some(found) = found {
+                    let message = format!("expected string or int but found {}", found);
+                    self.warn(message, location);
+                }
+                match (left, right) {
+                    (Shape::Int, Shape::Int) => Shape::Int,
+                    (Shape::Str, _) | (_, Shape::Str) => Shape::Str,
+                    _ => Shape::Unknown,
+                }
+            }
+            BinaryOp::Sub
+            | BinaryOp::Mul
+            | BinaryOp::Div
+            | BinaryOp::Rem
+            | BinaryOp::Lt
+            | BinaryOp::Gt
+            | BinaryOp::Lte
+            | BinaryOp::Gte => {
+                self.expect_all(&[left, right], "int", location);
+                match op {
+                    BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => Shape::Int,
+                    _ => Shape::Bool,
+                }
+            }
+            BinaryOp::Eq | BinaryOp::Neq => Shape::Bool,
+            BinaryOp::And | BinaryOp::Or => {
+                self.expect_all(&[left, right], "bool", location);
+                Shape::Bool
+            }
+        }
+    }
+}
+
+/// Looks for likely runtime errors in a program run with `config`, in
+/// source order.
+pub fn lint(file: &File, config: InterpreterConfig) -> Vec<Warning> {
+    let mut linter = Linter {
+        config,
+        ..Linter::default()
+    };
+    linter.lint(&file.expression);
+    linter.warnings
+}
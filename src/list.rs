@@ -0,0 +1,124 @@
+//! Lists, available with `--extensions`.
+//!
+//! Programs build lists from `nil` with `cons(x, list)` and take them apart
+//! with `head`, `tail` and `length`, which [`crate::builtins`] binds like
+//! any other name, so they can be shadowed.
+
+use std::rc::Rc;
+
+use crate::error::RuntimeError;
+use crate::gc::Tracer;
+use crate::types::Location;
+use crate::value::{type_mismatch, Value};
+
+/// An immutable singly linked list. `cons` shares the list it extends, so
+/// building and taking apart lists never copies them.
+#[derive(Clone, Debug, Default)]
+pub struct List(Option<Rc<Node>>);
+
+#[derive(Debug)]
+struct Node {
+    head: Value,
+    tail: List,
+    len: usize,
+}
+
+impl List {
+    pub fn cons(head: Value, tail: List) -> List {
+        let len = tail.len() + 1;
+        List(Some(Rc::new(Node { head, tail, len })))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |node| node.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn head(&self) -> Option<&Value> {
+        self.0.as_ref().map(|node| &node.head)
+    }
+
+    pub fn tail(&self) -> Option<&List> {
+        self.0.as_ref().map(|node| &node.tail)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        let mut list = self;
+        std::iter::from_fn(move || {
+            let node = list.0.as_ref()?;
+            list = &node.tail;
+            Some(&node.head)
+        })
+    }
+}
+
+impl List {
+    /// Traces the nodes of the list, iteratively like dropping it.
+    pub(crate) fn trace(&self, tracer: &mut Tracer) {
+        let mut saved = None;
+        let mut list = self;
+        while let Some(node) = &list.0 {
+            let Some(parent) = tracer.enter(node) else {
+                break;
+            };
+            saved.get_or_insert(parent);
+            tracer.trace_value(&node.head);
+            list = &node.tail;
+        }
+        if let Some(parent) = saved {
+            tracer.leave(parent);
+        }
+    }
+}
+
+impl Drop for Node {
+    // Unlink long lists iteratively so dropping them cannot overflow the
+    // stack.
+    fn drop(&mut self) {
+        let mut tail = self.tail.0.take();
+        while let Some(node) = tail {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => tail = node.tail.0.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+fn assert_list<'a>(value: &'a Value, location: &Location) -> Result<&'a List, RuntimeError> {
+    match value {
+        Value::List(list) => Ok(list),
+        value => Err(type_mismatch("list", value, location)),
+    }
+}
+
+fn empty(operation: &'static str, location: &Location) -> RuntimeError {
+    RuntimeError::EmptyList {
+        operation,
+        location: location.clone(),
+    }
+}
+
+pub(crate) fn head(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let list = assert_list(&arguments[0], location)?;
+    list.head().cloned().ok_or_else(|| empty("head", location))
+}
+
+pub(crate) fn tail(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let list = assert_list(&arguments[0], location)?;
+    let tail = list.tail().ok_or_else(|| empty("tail", location))?;
+    Ok(Value::List(tail.clone()))
+}
+
+pub(crate) fn cons(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let list = assert_list(&arguments[1], location)?;
+    Ok(Value::List(List::cons(arguments[0].clone(), list.clone())))
+}
+
+pub(crate) fn length(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let list = assert_list(&arguments[0], location)?;
+    Ok(Value::Number(list.len() as i64))
+}
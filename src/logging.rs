@@ -0,0 +1,46 @@
+//! Where the events and spans of `tracing` go: stderr, as text or as one
+//! JSON object per line.
+
+use std::io::{self, IsTerminal};
+
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+/// What is logged when neither `--log-level` nor `RUST_LOG` says: what
+/// rinha itself logs, and nothing of the crates it uses, such as the code
+/// generator behind `--engine=jit`.
+const DEFAULT_FILTER: &str = "rinha=info";
+
+/// Logs what `level`, or else the `RUST_LOG` environment variable, lets
+/// through. Both take the directives of `EnvFilter`, such as `debug` or
+/// `rinha::interpreter=trace`. Spans are logged when they close, with how
+/// long they took. When `quiet`, nothing is logged at all, whatever
+/// `RUST_LOG` says.
+pub fn init(level: Option<&str>, json: bool, quiet: bool) -> Result<(), String> {
+    if quiet {
+        return Ok(());
+    }
+    let filter = match level {
+        // A bare level is about rinha, not about every crate it uses.
+        Some(level) if level.parse::<LevelFilter>().is_ok() => {
+            EnvFilter::new(format!("rinha={}", level))
+        }
+        Some(level) => EnvFilter::try_new(level)
+            .map_err(|err| format!("invalid value for `--log-level`: {}", err))?,
+        None => {
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER))
+        }
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(io::stderr);
+    if json {
+        builder.json().init();
+    } else {
+        builder.init();
+    }
+    Ok(())
+}
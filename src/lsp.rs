@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rinha::cst::Cst;
+use rinha::parser::Syntax;
+use rinha::symbol::Symbol;
+use rinha::typecheck;
+use rinha::types::{self, Term};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{Client, LanguageServer, LspService, Server};
+
+/// Converts a byte offset into `text` to an LSP position, which counts
+/// characters in UTF-16 code units.
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= position.character as usize || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, location: &types::Location) -> Range {
+    Range {
+        start: position(text, location.start),
+        end: position(text, location.end),
+    }
+}
+
+fn contains(location: &types::Location, offset: usize) -> bool {
+    location.start <= offset && offset <= location.end
+}
+
+fn diagnostic(
+    text: &str,
+    message: String,
+    location: &types::Location,
+    severity: DiagnosticSeverity,
+) -> Diagnostic {
+    Diagnostic {
+        range: range(text, location),
+        severity: Some(severity),
+        source: Some("rinha".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// The first parse error of each top-level item, or the first type error
+/// when the program parses.
+fn diagnostics(cst: &Cst) -> Vec<Diagnostic> {
+    let text = cst.text();
+    let errors = cst.errors();
+    if !errors.is_empty() {
+        return errors
+            .into_iter()
+            .map(|err| diagnostic(text, err.message, &err.location, DiagnosticSeverity::ERROR))
+            .collect();
+    }
+    match cst.file().map(|file| typecheck::check(&file)) {
+        // Programs that do not typecheck may still run, so type errors
+        // are only warnings.
+        Ok(Err(err)) => vec![diagnostic(
+            text,
+            err.message,
+            &err.location,
+            DiagnosticSeverity::WARNING,
+        )],
+        _ => Vec::new(),
+    }
+}
+
+/// The inferred type of the innermost name at `offset`.
+fn hover(cst: &Cst, offset: usize) -> Option<Hover> {
+    let text = cst.text();
+    let file = cst.file().ok()?;
+    let (_, names) = typecheck::check_names(&file);
+    let (location, ty) = names
+        .iter()
+        .filter(|(location, _)| contains(location, offset))
+        .min_by_key(|(location, _)| location.end - location.start)?;
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```\n{}: {}\n```", &text[location.start..location.end], ty),
+        }),
+        range: Some(range(text, location)),
+    })
+}
+
+/// The names `pattern` binds with the location that binds them.
+fn pattern_names(pattern: &types::Pattern, names: &mut Vec<(Symbol, types::Location)>) {
+    match pattern {
+        types::Pattern::Var { text, location } => names.push((*text, location.clone())),
+        types::Pattern::Tuple { first, second, .. } => {
+            pattern_names(first, names);
+            pattern_names(second, names);
+        }
+        _ => {}
+    }
+}
+
+/// Finds the binding of the variable at `offset`. `scope` holds the names
+/// visible at `term` with the location that binds them.
+fn definition(
+    term: &Term,
+    offset: usize,
+    scope: &mut Vec<(Symbol, types::Location)>,
+) -> Option<types::Location> {
+    match term {
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } => None,
+        Term::Var { text, location, .. } if contains(location, offset) => scope
+            .iter()
+            .rev()
+            .find(|(name, _)| name == text)
+            .map(|(_, location)| location.clone()),
+        Term::Var { .. } => None,
+        Term::Let {
+            name, value, next, ..
+        } => {
+            if contains(&name.location, offset) {
+                return Some(name.location.clone());
+            }
+            let recursive = matches!(value.as_ref(), Term::Function { .. });
+            if recursive {
+                scope.push((name.text, name.location.clone()));
+            }
+            let found = definition(value, offset, scope);
+            if !recursive {
+                scope.push((name.text, name.location.clone()));
+            }
+            let found = found.or_else(|| definition(next, offset, scope));
+            scope.pop();
+            found
+        }
+        Term::LetTuple {
+            first,
+            second,
+            value,
+            next,
+            ..
+        } => {
+            if let Some(name) = [first, second]
+                .into_iter()
+                .find(|name| contains(&name.location, offset))
+            {
+                return Some(name.location.clone());
+            }
+            let found = definition(value, offset, scope);
+            scope.push((first.text, first.location.clone()));
+            scope.push((second.text, second.location.clone()));
+            let found = found.or_else(|| definition(next, offset, scope));
+            scope.truncate(scope.len() - 2);
+            found
+        }
+        Term::Function {
+            parameters, value, ..
+        } => {
+            if let Some(parameter) = parameters.iter().find(|p| contains(&p.location, offset)) {
+                return Some(parameter.location.clone());
+            }
+            let depth = scope.len();
+            scope.extend(parameters.iter().map(|p| (p.text, p.location.clone())));
+            let found = definition(value, offset, scope);
+            scope.truncate(depth);
+            found
+        }
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => definition(condition, offset, scope)
+            .or_else(|| definition(then, offset, scope))
+            .or_else(|| definition(otherwise, offset, scope)),
+        Term::Binary { lhs, rhs, .. } => {
+            definition(lhs, offset, scope).or_else(|| definition(rhs, offset, scope))
+        }
+        Term::Call {
+            callee, arguments, ..
+        } => definition(callee, offset, scope).or_else(|| {
+            arguments
+                .iter()
+                .find_map(|argument| definition(argument, offset, scope))
+        }),
+        Term::Tuple { first, second, .. } => {
+            definition(first, offset, scope).or_else(|| definition(second, offset, scope))
+        }
+        Term::First { value, .. }
+        | Term::Second { value, .. }
+        | Term::Field { value, .. }
+        | Term::Ref { value, .. }
+        | Term::Deref { value, .. } => definition(value, offset, scope),
+        Term::Print { values, .. } => values
+            .iter()
+            .find_map(|value| definition(value, offset, scope)),
+        Term::Assign {
+            reference, value, ..
+        } => definition(reference, offset, scope).or_else(|| definition(value, offset, scope)),
+        Term::Record { fields, .. } => fields
+            .iter()
+            .find_map(|field| definition(&field.value, offset, scope)),
+        Term::Match { value, arms, .. } => definition(value, offset, scope).or_else(|| {
+            arms.iter().find_map(|arm| {
+                let mut names = Vec::new();
+                pattern_names(&arm.pattern, &mut names);
+                if let Some((_, location)) = names
+                    .iter()
+                    .find(|(_, location)| contains(location, offset))
+                {
+                    return Some(location.clone());
+                }
+                let depth = scope.len();
+                scope.extend(names);
+                let found = definition(&arm.value, offset, scope);
+                scope.truncate(depth);
+                found
+            })
+        }),
+    }
+}
+
+struct Backend {
+    client: Client,
+    /// Syntax trees of the open documents, which edits update in place.
+    /// Their ASTs are rebuilt from the trees on each request, since they
+    /// are not `Send`.
+    documents: Mutex<HashMap<Url, Cst>>,
+}
+
+impl Backend {
+    async fn publish(&self, uri: Url) {
+        let diagnostics = match self.documents.lock().unwrap().get(&uri) {
+            Some(cst) => diagnostics(cst),
+            None => return,
+        };
+        self.client
+            .publish_diagnostics(uri, diagnostics, None)
+            .await;
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                    TextDocumentSyncKind::INCREMENTAL,
+                )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                ..ServerCapabilities::default()
+            },
+            server_info: Some(ServerInfo {
+                name: "rinha".to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            }),
+        })
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let document = params.text_document;
+        let cst = Cst::parse(&document.text, document.uri.path(), Syntax::all());
+        self.documents
+            .lock()
+            .unwrap()
+            .insert(document.uri.clone(), cst);
+        self.publish(document.uri).await;
+    }
+
+    /// Applies the changes in order, each to the text the previous one
+    /// left. Changes without a range replace the whole document.
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        {
+            let mut documents = self.documents.lock().unwrap();
+            let Some(cst) = documents.get_mut(&uri) else {
+                return;
+            };
+            for change in params.content_changes {
+                match change.range {
+                    Some(range) => {
+                        let start = offset(cst.text(), range.start);
+                        let end = offset(cst.text(), range.end).max(start);
+                        cst.edit(start..end, &change.text);
+                    }
+                    None => *cst = Cst::parse(&change.text, uri.path(), Syntax::all()),
+                }
+            }
+        }
+        self.publish(uri).await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.lock().unwrap().remove(&uri);
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let params = params.text_document_position_params;
+        let uri = params.text_document.uri;
+        let documents = self.documents.lock().unwrap();
+        Ok(documents
+            .get(&uri)
+            .and_then(|cst| hover(cst, offset(cst.text(), params.position))))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let params = params.text_document_position_params;
+        let uri = params.text_document.uri;
+        let documents = self.documents.lock().unwrap();
+        let Some(cst) = documents.get(&uri) else {
+            return Ok(None);
+        };
+        let text = cst.text();
+        let location = cst.file().ok().and_then(|file| {
+            definition(
+                &file.expression,
+                offset(text, params.position),
+                &mut Vec::new(),
+            )
+        });
+        Ok(location.map(|location| {
+            GotoDefinitionResponse::Scalar(Location {
+                uri: uri.clone(),
+                range: range(text, &location),
+            })
+        }))
+    }
+}
+
+/// Serves a single client over stdin and stdout until it closes stdin.
+pub fn run() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Falha ao iniciar o runtime");
+    let (service, socket) = LspService::new(|client| Backend {
+        client,
+        documents: Mutex::new(HashMap::new()),
+    });
+    runtime.block_on(Server::new(tokio::io::stdin(), tokio::io::stdout(), socket).serve(service));
+}
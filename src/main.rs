@@ -1,19 +1,863 @@
-use serde::Deserialize;
-use serde_json::json;
-use serde_json::Value;
+use std::env;
 use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-mod interpreter;
-mod types;
+use rinha::cache::Cache;
+use rinha::color::{self, Style};
+use rinha::compiler::{self, Program};
+use rinha::conformance::{self, Outcome};
+use rinha::difftest;
+use rinha::error::{RuntimeError, SourceMap};
+use rinha::interpreter::CallSite;
+use rinha::parser::Syntax;
+use rinha::resources::Resources;
+use rinha::trace::Tracer;
+use rinha::vm::{Exit, Snapshot};
+use rinha::{
+    checkpoint, complexity, depth, error, interpreter, interrupt, lexer, lint, memory, modules,
+    optimizer, packed, peval, random, stats, typecheck, types, validate, vm, Engine, Fuel,
+    InterpreterConfig, Output, Value,
+};
+use signal_hook::consts::SIGTERM;
+
+mod cli;
+mod debug;
+#[cfg(feature = "server")]
+mod http;
+mod logging;
+#[cfg(feature = "lsp")]
+mod lsp;
+mod repl;
+mod serve;
+#[cfg(feature = "watch")]
+mod watch;
+
+use cli::{BuildOptions, Command, Format, Options, Target, TraceTo};
+
+#[global_allocator]
+static ALLOCATOR: memory::Counting = memory::Counting;
 
 fn main() {
-    let json_str = fs::read_to_string("path/to/fib.json").expect("Falha ao ler o arquivo JSON");
-    let json: Value = serde_json::from_str(&json_str).expect("Falha ao fazer o parsing do JSON");
-    interpret_file(&json);
+    let command = cli::parse_args(env::args().skip(1));
+    let logged = match &command {
+        Ok(Command::Run(options)) => logging::init(
+            options.log_level.as_deref(),
+            options.log_json,
+            options.quiet,
+        ),
+        _ => logging::init(None, false, false),
+    };
+    if let Err(message) = logged {
+        eprintln!("error: {}\n\n{}", message, cli::USAGE);
+        process::exit(2);
+    }
+    let options = match command {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Repl { memoize }) => {
+            repl::run(memoize);
+            return;
+        }
+        Ok(Command::Test { dir, engine }) => run_tests(&dir, engine),
+        Ok(Command::Fmt {
+            input,
+            format,
+            check,
+        }) => {
+            let contents = read_input(&input);
+            let file = parse_input(
+                &contents,
+                &input,
+                format,
+                Syntax::all(),
+                &mut SourceMap::new(),
+            );
+            let formatted = format!("{}\n", file.expression.to_source());
+            if !check {
+                print!("{}", formatted);
+            } else if formatted.as_bytes() != contents {
+                eprintln!("{} is not formatted", input);
+                process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::EmitAst { input }) => {
+            let file = parse_input(
+                &read_input(&input),
+                &input,
+                Format::Source,
+                Syntax::all(),
+                &mut SourceMap::new(),
+            );
+            println!("{}", file.to_json());
+            return;
+        }
+        Ok(Command::Pack {
+            input,
+            format,
+            output,
+        }) => {
+            let file = parse_input(
+                &read_input(&input),
+                &input,
+                format,
+                Syntax::all(),
+                &mut SourceMap::new(),
+            );
+            fs::write(&output, packed::pack(&file)).expect("Falha ao escrever o arquivo");
+            return;
+        }
+        Ok(Command::Unpack { input, output }) => {
+            let file = parse_input(
+                &read_input(&input),
+                &input,
+                Format::Packed,
+                Syntax::all(),
+                &mut SourceMap::new(),
+            );
+            match output {
+                Some(output) => {
+                    fs::write(&output, file.to_json()).expect("Falha ao escrever o arquivo")
+                }
+                None => println!("{}", file.to_json()),
+            }
+            return;
+        }
+        Ok(Command::Check { input, format }) => {
+            let mut sources = SourceMap::new();
+            let file = load_file(&input, format, Syntax::all(), &mut sources);
+            match typecheck::check(&file) {
+                Ok(ty) => println!("{}", ty),
+                Err(err) => report(&sources, &err, &err.location),
+            }
+            return;
+        }
+        Ok(Command::Lint { input, format }) => {
+            let mut sources = SourceMap::new();
+            let file = load_file(&input, format, Syntax::all(), &mut sources);
+            print_warnings(&sources, &file, InterpreterConfig::STRICT);
+            return;
+        }
+        Ok(Command::Stats { input, format }) => {
+            let file = load_file(&input, format, Syntax::all(), &mut SourceMap::new());
+            println!("{}", complexity::analyze(&file));
+            return;
+        }
+        Ok(Command::Highlight { input, html }) => {
+            let contents = read_input(&input);
+            let source = text(&contents, &input);
+            if html {
+                print!("{}", lexer::html(source));
+            } else {
+                let tokens = lexer::tokenize(source)
+                    .into_iter()
+                    .map(|(kind, range)| {
+                        serde_json::json!({
+                            "kind": kind.name(),
+                            "start": range.start,
+                            "end": range.end,
+                        })
+                    })
+                    .collect();
+                println!("{}", serde_json::Value::Array(tokens));
+            }
+            return;
+        }
+        Ok(Command::Debug {
+            input,
+            format,
+            memoize,
+        }) => {
+            let mut sources = SourceMap::new();
+            let file = load_program(
+                &input,
+                format,
+                Syntax::default(),
+                InterpreterConfig::STRICT,
+                &mut sources,
+            );
+            debug::run(&file, memoize, &sources);
+            return;
+        }
+        Ok(Command::Difftest {
+            input,
+            format,
+            memoize,
+            config: interpreter_config,
+            max_steps,
+        }) => {
+            let file = optimizer::optimize(&load_program(
+                &input,
+                format,
+                Syntax::default(),
+                interpreter_config,
+                &mut SourceMap::new(),
+            ));
+            let fuel = max_steps.map_or(Fuel::unlimited(), Fuel::limited);
+            difftest(&file, memoize, interpreter_config, fuel);
+        }
+        Ok(Command::Serve {
+            engine,
+            memoize,
+            config: interpreter_config,
+            max_steps,
+        }) => {
+            serve::run(engine, memoize, interpreter_config, max_steps);
+            return;
+        }
+        Ok(Command::Http {
+            listen,
+            engine,
+            memoize,
+            config: interpreter_config,
+            max_steps,
+            timeout,
+        }) => {
+            serve_http(
+                &listen,
+                engine,
+                memoize,
+                interpreter_config,
+                max_steps,
+                timeout,
+            );
+            return;
+        }
+        Ok(Command::Build(options)) => {
+            build(&options);
+            return;
+        }
+        Ok(Command::Lsp) => {
+            serve_lsp();
+            return;
+        }
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    if options.watch {
+        watch(&options);
+    }
+    color::set(options.color);
+    match options.timeout {
+        Some(limit) => run_with_timeout(options, limit),
+        None => run(&options),
+    }
+}
+
+fn run(options: &Options) {
+    if options.resume {
+        resume(options);
+        return;
+    }
+    if let Some(dir) = &options.cache {
+        // Packed programs already load without parsing.
+        let uncached = options.lint
+            || options.show_opt
+            || options.show_passes
+            || options.peval
+            || options.syntax.modules
+            || options.format == Format::Packed;
+        if options.engine == Engine::Vm && !uncached {
+            run_cached(options, &Cache::new(dir));
+            return;
+        }
+    }
+    let mut sources = SourceMap::new();
+    let file = if options.eval {
+        eval_program(options, &mut sources)
+    } else {
+        load_program(
+            &options.input,
+            options.format,
+            options.syntax,
+            options.config,
+            &mut sources,
+        )
+    };
+    if options.lint {
+        print_warnings(&sources, &file, options.config);
+    }
+    if options.show_passes {
+        show_passes(&file);
+        return;
+    }
+    let mut file = optimizer::optimize(&file);
+    if options.peval {
+        // Calls evaluated ahead of time leave the bindings of their
+        // inlined arguments unused.
+        let specialized = peval::specialize(&file, options.config);
+        file = optimizer::remove_dead_lets(&specialized);
+    }
+    if options.show_opt {
+        println!("{}", file.expression.to_source());
+        return;
+    }
+    if options.checkpoint.is_some() {
+        let program = compiler::compile(&file, options.config);
+        interpret_file(Code::Bytecode(&program, None), options, &sources);
+        return;
+    }
+    interpret_file(Code::Ast(&file), options, &sources);
+}
+
+/// Prints `file` and what each optimizer pass makes of it, for
+/// `--show-passes`.
+fn show_passes(file: &types::File) {
+    println!("// parsed\n{}", file.expression.to_source());
+    let mut file = file.clone();
+    for (name, pass) in optimizer::PASSES {
+        file = pass(&file);
+        println!("\n// after {}\n{}", name, file.expression.to_source());
+    }
+}
+
+/// Continues the program saved in the checkpoint given to `--resume`.
+fn resume(options: &Options) {
+    let (program, snapshot) = match checkpoint::load(&options.input) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("error: cannot resume from {}: {}", options.input, err);
+            process::exit(1);
+        }
+    };
+    interpret_file(
+        Code::Bytecode(&program, Some(snapshot)),
+        options,
+        &SourceMap::new(),
+    );
+}
+
+/// Runs the program with the VM, compiling it only when `cache` does not
+/// have it yet.
+fn run_cached(options: &Options, cache: &Cache) {
+    let filename = if options.input == "-" {
+        "<stdin>"
+    } else {
+        &options.input
+    };
+    let contents = read_input(&options.input);
+    let contents = text(&contents, filename);
+    let json = options.format == Format::AstJson;
+    let key = Cache::key(
+        filename,
+        contents,
+        json,
+        options.syntax,
+        options.config.builtins(),
+    );
+    let mut sources = SourceMap::new();
+    let program = match cache.load(&key, contents) {
+        Some(program) => {
+            if !json {
+                sources.add(filename, contents);
+            }
+            program
+        }
+        None => {
+            let file = parse_input(
+                contents.as_bytes(),
+                &options.input,
+                options.format,
+                options.syntax,
+                &mut sources,
+            );
+            check_valid(&file, options.config, &sources);
+            let program = compiler::compile(&optimizer::optimize(&file), options.config);
+            if let Err(err) = cache.store(&key, contents, &program) {
+                if !options.quiet {
+                    tracing::warn!(error = %err, "cannot write to the cache");
+                }
+            }
+            program
+        }
+    };
+    interpret_file(Code::Bytecode(&program, None), options, &sources);
+}
+
+/// What [`interpret_file`] runs: a program to evaluate with any engine, or
+/// one already compiled for the VM, from the start or from a checkpoint.
+enum Code<'a> {
+    Ast(&'a types::File),
+    Bytecode(&'a Program, Option<Snapshot>),
+}
+
+/// Runs the program on another thread and stops the process if it has not
+/// finished after `limit`. Values cannot leave the thread that created
+/// them, so the worker loads and reports on the program by itself.
+fn run_with_timeout(options: Options, limit: Duration) {
+    let (done, finished) = mpsc::channel();
+    let worker = thread::Builder::new()
+        // The default for the main thread, which deeply nested programs
+        // may need while being parsed and optimized.
+        .stack_size(8 << 20)
+        .spawn(move || {
+            run(&options);
+            let _ = done.send(());
+        })
+        .expect("Falha ao iniciar a thread do interpretador");
+    if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(limit) {
+        eprintln!("error: time limit exceeded");
+        process::exit(1);
+    }
+    if worker.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn report(sources: &SourceMap, message: &dyn std::fmt::Display, location: &types::Location) -> ! {
+    error::print_diagnostic(sources, message, location);
+    process::exit(1);
+}
+
+fn print_warnings(sources: &SourceMap, file: &types::File, config: InterpreterConfig) {
+    for warning in lint::lint(file, config) {
+        error::print_warning(sources, &warning.message, &warning.location);
+    }
+}
+
+fn read_input(input: &str) -> Vec<u8> {
+    if input == "-" {
+        let mut contents = Vec::new();
+        io::stdin()
+            .read_to_end(&mut contents)
+            .expect("Falha ao ler a entrada padrão");
+        contents
+    } else {
+        fs::read(input).expect("Falha ao ler o arquivo")
+    }
+}
+
+/// The `contents` of `filename` as text, for the formats that are not
+/// binary.
+fn text<'a>(contents: &'a [u8], filename: &str) -> &'a str {
+    match std::str::from_utf8(contents) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("error: {} is not valid UTF-8: {}", filename, err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Like [`load_file`], for programs about to run with `config`: stops with
+/// every violation [`validate::validate`] finds instead of running into
+/// them.
+fn load_program(
+    input: &str,
+    format: Format,
+    syntax: Syntax,
+    config: InterpreterConfig,
+    sources: &mut SourceMap,
+) -> types::File {
+    let file = load_file(input, format, syntax, sources);
+    check_valid(&file, config, sources);
+    file
+}
+
+/// Like [`load_program`], for the program given as the argument of
+/// `rinha eval`.
+fn eval_program(options: &Options, sources: &mut SourceMap) -> types::File {
+    let input = "<eval>";
+    let syntax = options.syntax;
+    let file = parse_input(
+        options.input.as_bytes(),
+        input,
+        options.format,
+        syntax,
+        sources,
+    );
+    if !syntax.modules {
+        check_valid(&file, options.config, sources);
+        return file;
+    }
+    let file = match modules::link(file, input, syntax, sources) {
+        Ok(file) => file,
+        Err(err) => report(sources, &err.message, &err.location),
+    };
+    check_valid(&file, options.config, sources);
+    file
+}
+
+fn check_valid(file: &types::File, config: InterpreterConfig, sources: &SourceMap) {
+    let violations = validate::validate(file, config);
+    if violations.is_empty() {
+        return;
+    }
+    for violation in &violations {
+        error::print_diagnostic(sources, &violation.message, &violation.location);
+    }
+    process::exit(1);
+}
+
+/// Reads and parses `input`, adding its source to `sources`, and the
+/// files it imports when `syntax` allows modules.
+fn load_file(input: &str, format: Format, syntax: Syntax, sources: &mut SourceMap) -> types::File {
+    let file = parse_input(&read_input(input), input, format, syntax, sources);
+    if !syntax.modules {
+        return file;
+    }
+    match modules::link(file, input, syntax, sources) {
+        Ok(file) => file,
+        Err(err) => report(sources, &err.message, &err.location),
+    }
+}
+
+fn parse_input(
+    contents: &[u8],
+    input: &str,
+    format: Format,
+    syntax: Syntax,
+    sources: &mut SourceMap,
+) -> types::File {
+    let filename = if input == "-" { "<stdin>" } else { input };
+
+    match format {
+        Format::Source => {
+            let contents = text(contents, filename);
+            sources.add(filename, contents);
+            match rinha::parse_with(contents, filename, syntax) {
+                Ok(file) => file,
+                Err(err) => report(sources, &err.message, &err.location),
+            }
+        }
+        Format::AstJson => match rinha::parse_json(text(contents, filename)) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("error: invalid JSON AST in {}: {}", filename, err);
+                process::exit(1);
+            }
+        },
+        Format::Packed => match packed::unpack(contents) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("error: invalid packed AST in {}: {}", filename, err);
+                process::exit(1);
+            }
+        },
+    }
+}
+
+fn interpret_file(code: Code, options: &Options, sources: &SourceMap) {
+    let fuel = options.max_steps.map_or(Fuel::unlimited(), Fuel::limited);
+    stats::reset();
+    memory::set_limit(options.max_memory);
+    depth::set_limit(options.max_depth);
+    if let Some(seed) = options.seed {
+        random::seed(seed);
+    }
+    interrupt::install().expect("Falha ao registrar o tratador de SIGINT");
+    let mut stdout = io::stdout();
+    let mut lines = Vec::new();
+    let mut buffered =
+        (!options.line_buffered && !options.json_result && options.timeout.is_none())
+            .then(|| io::BufWriter::new(io::stdout().lock()));
+    let output: &mut dyn Output = match buffered.as_mut() {
+        Some(buffered) => buffered,
+        None if options.json_result => &mut lines,
+        None => &mut stdout,
+    };
+    let start = Instant::now();
+    let mut backtrace = Vec::new();
+    let result = match code {
+        Code::Bytecode(program, snapshot) => run_bytecode(program, snapshot, options, fuel, output),
+        Code::Ast(file) => match &options.trace {
+            None if options.profile || options.flamegraph.is_some() => {
+                let (result, profile) = interpreter::interpret_file_profiled(
+                    file,
+                    options.memoize,
+                    options.config,
+                    fuel,
+                    output,
+                );
+                if options.profile {
+                    eprint!("{}", profile);
+                }
+                if let Some(path) = &options.flamegraph {
+                    fs::write(path, profile.folded()).expect("Falha ao escrever o flamegraph");
+                }
+                result
+            }
+            Some(to) => {
+                let out: Box<dyn io::Write> = match to {
+                    TraceTo::Stderr => Box::new(io::stderr()),
+                    TraceTo::File(path) => Box::new(io::BufWriter::new(
+                        fs::File::create(path).expect("Falha ao criar o arquivo de trace"),
+                    )),
+                };
+                let tracer = Tracer::new(out);
+                interpreter::interpret_file_traced(
+                    file,
+                    options.memoize,
+                    options.config,
+                    fuel,
+                    output,
+                    tracer,
+                )
+            }
+            None if options.parallel || options.engine == Engine::Tree => {
+                let (result, calls) = interpreter::interpret_file_backtrace(
+                    file,
+                    options.memoize,
+                    options.config,
+                    fuel,
+                    options.parallel,
+                    output,
+                );
+                backtrace = calls;
+                result
+            }
+            None => options
+                .engine
+                .run(file, options.memoize, options.config, fuel, output),
+        },
+    };
+    let elapsed = start.elapsed();
+    if let Some(mut buffered) = buffered {
+        Write::flush(&mut buffered).expect("Falha ao escrever na saída padrão");
+    }
+    if options.stats {
+        eprint!("{}", stats::snapshot());
+    }
+    let resources = options.report.then(|| Resources::measure(elapsed));
+    if options.json_result {
+        print_resources(resources);
+        print_json_result(&lines, &result, elapsed);
+    }
+    match result {
+        Ok(value) => {
+            if !options.quiet {
+                print_value(&value);
+            }
+            print_resources(resources);
+        }
+        Err(err) => {
+            error::print_diagnostic(sources, &err, err.location());
+            print_backtrace(sources, &backtrace);
+            print_resources(resources);
+            // The status of a process killed by SIGINT, as shells expect.
+            let interrupted = matches!(err, RuntimeError::Interrupted { .. });
+            process::exit(if interrupted { 130 } else { 1 });
+        }
+    }
+}
+
+/// Prints the value the program ended with, set apart from what it
+/// printed when stdout is colored.
+fn print_value(value: &Value) {
+    if color::stdout() {
+        let shown = color::paint(&value.to_string(), Style::Value, true);
+        println!("{} {}", color::paint("value:", Style::Note, true), shown);
+    } else {
+        println!("value: {:?}", value);
+    }
+}
+
+/// Prints the trailer of `--report`, when it was given.
+fn print_resources(resources: Option<Resources>) {
+    if let Some(resources) = resources {
+        eprintln!("{}", resources.to_json());
+    }
+}
+
+/// Runs compiled code with the VM. With `--checkpoint`, SIGTERM stops the
+/// program at its next call and saves it to be resumed later.
+fn run_bytecode(
+    program: &Program,
+    snapshot: Option<Snapshot>,
+    options: &Options,
+    fuel: Fuel,
+    output: &mut dyn Output,
+) -> Result<Value, RuntimeError> {
+    let stop = Arc::new(AtomicBool::new(false));
+    if options.checkpoint.is_some() {
+        signal_hook::flag::register(SIGTERM, Arc::clone(&stop))
+            .expect("Falha ao registrar o tratador de SIGTERM");
+    }
+    let snapshot = match vm::run_until(
+        program,
+        snapshot,
+        options.memoize,
+        options.config,
+        fuel,
+        output,
+        &stop,
+    )? {
+        Exit::Finished(value) => return Ok(value),
+        Exit::Suspended(snapshot) => snapshot,
+    };
+    let path = options.checkpoint.as_deref().unwrap();
+    output.flush();
+    if let Err(err) = checkpoint::save(path, program, &snapshot) {
+        eprintln!("error: cannot write the checkpoint {}: {}", path, err);
+        process::exit(1);
+    }
+    if !options.quiet {
+        eprintln!("stopped; continue with `rinha --resume {}`", path);
+    }
+    process::exit(143);
+}
+
+fn print_json_result(
+    lines: &[String],
+    result: &Result<Value, RuntimeError>,
+    elapsed: Duration,
+) -> ! {
+    let ok = result.is_ok();
+    let result = result.as_ref().map_err(|err| err.to_string());
+    println!("{}", serve::result_json(lines, result, elapsed));
+    process::exit(if ok { 0 } else { 1 });
+}
+
+/// Number of calls a backtrace shows before leaving the rest out.
+const BACKTRACE_LIMIT: usize = 10;
+
+fn print_backtrace(sources: &SourceMap, calls: &[CallSite]) {
+    if calls.is_empty() {
+        return;
+    }
+    eprintln!("backtrace:");
+    for call in calls.iter().rev().take(BACKTRACE_LIMIT) {
+        let name = call.name.as_deref().unwrap_or("<anonymous>");
+        eprintln!(
+            "  {} ({}) called at {}",
+            name,
+            sources.position(&call.function),
+            sources.position(&call.location)
+        );
+    }
+    if calls.len() > BACKTRACE_LIMIT {
+        eprintln!("  ... and {} more", calls.len() - BACKTRACE_LIMIT);
+    }
+}
+
+fn difftest(file: &types::File, memoize: bool, config: InterpreterConfig, fuel: Fuel) -> ! {
+    let runs = difftest::run_all(file, memoize, config, fuel);
+    let Some(divergence) = difftest::divergence(&runs) else {
+        let names: Vec<_> = runs.iter().map(|run| run.engine.name()).collect();
+        let lines = runs[0].lines.len();
+        let plural = if lines == 1 { "" } else { "s" };
+        println!("{} agree on {} line{}", names.join(", "), lines, plural);
+        process::exit(0);
+    };
+    println!("engines diverge at line {}:", divergence.line + 1);
+    for (engine, line) in &divergence.lines {
+        let line = line.as_deref().unwrap_or("<no more output>");
+        println!("  {:<5} {}", format!("{}:", engine.name()), line);
+    }
+    process::exit(1);
+}
+
+fn build(options: &BuildOptions) {
+    let file = optimizer::optimize(&load_program(
+        &options.input,
+        options.format,
+        Syntax::default(),
+        InterpreterConfig::STRICT,
+        &mut SourceMap::new(),
+    ));
+    let bytes = match options.target {
+        Target::C => rinha::codegen::c::compile(&file).into_bytes(),
+        Target::Wasm => compile_wasm(&file),
+    };
+    fs::write(&options.output, bytes).expect("Falha ao escrever o arquivo de saída");
+}
+
+#[cfg(feature = "wasm")]
+fn compile_wasm(file: &types::File) -> Vec<u8> {
+    rinha::codegen::wasm::compile(file)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn compile_wasm(_: &types::File) -> Vec<u8> {
+    eprintln!("error: this build of rinha has no wasm backend; rebuild it with `--features wasm`");
+    process::exit(2);
+}
+
+#[cfg(feature = "server")]
+fn serve_http(
+    listen: &str,
+    engine: Engine,
+    memoize: bool,
+    config: InterpreterConfig,
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+) {
+    http::run(listen, engine, memoize, config, max_steps, timeout);
+}
+
+#[cfg(not(feature = "server"))]
+fn serve_http(
+    _: &str,
+    _: Engine,
+    _: bool,
+    _: InterpreterConfig,
+    _: Option<u64>,
+    _: Option<Duration>,
+) {
+    eprintln!("error: this build of rinha has no HTTP server; rebuild it with `--features server`");
+    process::exit(2);
+}
+
+#[cfg(feature = "watch")]
+fn watch(options: &Options) -> ! {
+    watch::run(options)
+}
+
+#[cfg(not(feature = "watch"))]
+fn watch(_: &Options) -> ! {
+    eprintln!("error: this build of rinha has no watch mode; rebuild it with `--features watch`");
+    process::exit(2);
+}
+
+#[cfg(feature = "lsp")]
+fn serve_lsp() {
+    lsp::run();
+}
+
+#[cfg(not(feature = "lsp"))]
+fn serve_lsp() {
+    eprintln!(
+        "error: this build of rinha has no language server; rebuild it with `--features lsp`"
+    );
+    process::exit(2);
 }
 
-fn interpret_file(json: &Value) {
-    let file = types::File::from_json(json);
-    let value = interpreter::interpret_file(&file);
-    println!("value: {:?}", value);
+fn run_tests(dir: &str, engine: rinha::Engine) -> ! {
+    let cases = conformance::run_dir(Path::new(dir), engine).expect("Falha ao ler o diretório");
+    let mut failed = 0;
+    for case in &cases {
+        match &case.outcome {
+            Outcome::Pass => println!("PASS {}", case.path.display()),
+            Outcome::Fail { expected, actual } => {
+                println!("FAIL {}", case.path.display());
+                println!("  expected:");
+                for line in expected.lines() {
+                    println!("    {}", line);
+                }
+                println!("  actual:");
+                for line in actual.lines() {
+                    println!("    {}", line);
+                }
+            }
+            Outcome::Broken(message) => println!("FAIL {}: {}", case.path.display(), message),
+        }
+        if !case.passed() {
+            failed += 1;
+        }
+    }
+    println!("\n{} passed, {} failed", cases.len() - failed, failed);
+    process::exit(if failed == 0 { 0 } else { 1 });
 }
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::builtins;
+use crate::types::Term;
+use crate::value::{self, Value};
+
+/// Address of the closure, which identifies it while it is alive.
+fn address(closure: &value::Closure) -> usize {
+    match closure {
+        value::Closure::Tree(closure) => Rc::as_ptr(closure) as usize,
+        value::Closure::Bytecode(closure) => Rc::as_ptr(closure) as usize,
+        value::Closure::Native(native) => Rc::as_ptr(native) as usize,
+        value::Closure::Partial(partial) => Rc::as_ptr(partial) as usize,
+    }
+}
+
+/// A closure compared and hashed by identity. Holding it keeps the closure
+/// alive, so its address is never reused by another closure while cached.
+struct Identity(value::Closure);
+
+impl PartialEq for Identity {
+    fn eq(&self, other: &Identity) -> bool {
+        address(&self.0) == address(&other.0)
+    }
+}
+
+impl Eq for Identity {}
+
+impl Hash for Identity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        address(&self.0).hash(state);
+    }
+}
+
+/// An argument compared and hashed by structure, like `==` compares
+/// values. It shares the value instead of copying it, so closures it holds
+/// stay alive, as with [`Identity`].
+struct ArgKey(Value);
+
+impl PartialEq for ArgKey {
+    fn eq(&self, other: &ArgKey) -> bool {
+        value::is_equal(&self.0, &other.0)
+    }
+}
+
+impl Eq for ArgKey {}
+
+impl Hash for ArgKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_value(&self.0, state);
+    }
+}
+
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    std::mem::discriminant(value).hash(state);
+    match value {
+        Value::Boolean(b) => b.hash(state),
+        Value::String(s) => s.hash(state),
+        Value::Number(n) => n.hash(state),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => n.hash(state),
+        Value::Closure(closure) => address(closure).hash(state),
+        Value::Tuple(pair) => {
+            hash_value(&pair.0, state);
+            hash_value(&pair.1, state);
+        }
+        Value::List(list) => {
+            list.len().hash(state);
+            for element in list.iter() {
+                hash_value(element, state);
+            }
+        }
+        Value::Record(fields) => {
+            for (name, value) in value::sorted_fields(fields) {
+                name.hash(state);
+                hash_value(value, state);
+            }
+        }
+        Value::Ref(cell) => (Rc::as_ptr(cell) as usize).hash(state),
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+pub struct CallKey {
+    callee: Identity,
+    arguments: Vec<ArgKey>,
+}
+
+impl CallKey {
+    pub fn new(callee: &value::Closure, arguments: &[Value]) -> CallKey {
+        CallKey {
+            callee: Identity(callee.clone()),
+            arguments: arguments.iter().cloned().map(ArgKey).collect(),
+        }
+    }
+}
+
+/// Whether evaluating `term` can never print, use a reference, read input
+/// or draw a random number by itself. Calls are not followed, so callers
+/// must still check that no effect happened while a call ran before caching
+/// its result.
+pub fn is_pure(term: &Term) -> bool {
+    let mut bindings = term.let_chain();
+    for binding in &mut bindings {
+        let (Term::Let { value, .. } | Term::LetTuple { value, .. }) = binding else {
+            unreachable!()
+        };
+        if !is_pure(value) {
+            return false;
+        }
+    }
+    match bindings.rest() {
+        Term::Print { .. } => false,
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => true,
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => is_pure(condition) && is_pure(then) && is_pure(otherwise),
+        Term::Let { .. } | Term::LetTuple { .. } => unreachable!("checked above"),
+        Term::Binary { lhs, rhs, .. } => is_pure(lhs) && is_pure(rhs),
+        Term::Call {
+            callee, arguments, ..
+        } => !calls_effects(callee) && is_pure(callee) && arguments.iter().all(|arg| is_pure(arg)),
+        Term::Function { value, .. } => is_pure(value),
+        Term::First { value, .. } | Term::Second { value, .. } => is_pure(value),
+        Term::Tuple { first, second, .. } => is_pure(first) && is_pure(second),
+        Term::Match { value, arms, .. } => {
+            is_pure(value) && arms.iter().all(|arm| is_pure(&arm.value))
+        }
+        Term::Record { fields, .. } => fields.iter().all(|field| is_pure(&field.value)),
+        Term::Field { value, .. } => is_pure(value),
+        Term::Ref { .. } | Term::Deref { .. } | Term::Assign { .. } => false,
+    }
+}
+
+/// Whether `callee` names a builtin with effects. Whatever the name is
+/// bound to, a call to it is taken to have effects.
+fn calls_effects(callee: &Term) -> bool {
+    match callee {
+        Term::Var { text, .. } => builtins::EFFECTS.contains(&text.as_str()),
+        _ => false,
+    }
+}
+
+/// Results of calls to pure closures.
+#[derive(Default)]
+pub struct Memo {
+    results: HashMap<CallKey, Value>,
+}
+
+impl Memo {
+    pub fn get(&self, key: &CallKey) -> Option<Value> {
+        self.results.get(key).cloned()
+    }
+
+    pub fn insert(&mut self, key: CallKey, result: Value) {
+        self.results.insert(key, result);
+    }
+}
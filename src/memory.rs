@@ -0,0 +1,72 @@
+//! Heap accounting for `--max-memory`.
+//!
+//! A binary that wants its memory limited installs [`Counting`] as its
+//! global allocator, which keeps track of how many bytes are in use. Both
+//! engines call [`check`] on every function call and stop with
+//! [`RuntimeError::OutOfMemory`] once the limit set with [`set_limit`] is
+//! passed. Without the allocator nothing is counted and the limit never
+//! triggers.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::RuntimeError;
+use crate::types::Location;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// The system allocator, counting the bytes it hands out.
+pub struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new
+    }
+}
+
+/// Bytes currently allocated through [`Counting`].
+pub fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// Sets the most bytes programs may have allocated, or removes the limit.
+pub fn set_limit(bytes: Option<usize>) {
+    LIMIT.store(bytes.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// Fails when more memory is in use than the limit allows.
+pub fn check(location: &Location) -> Result<(), RuntimeError> {
+    if allocated() > LIMIT.load(Ordering::Relaxed) {
+        return Err(RuntimeError::OutOfMemory {
+            location: location.clone(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! Programs split across files, with `--extensions modules`.
+//!
+//! A call `import("path")`, where `import` is not bound by the program and
+//! `path` is a string literal, stands for the value of the file at `path`,
+//! relative to the file making the call. The parser reads it as any other
+//! call; [`link`] loads every file the program imports, parses it the same
+//! way and binds its value around the program, so each file runs once,
+//! before the program, however many times it is imported.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::error::SourceMap;
+use crate::parser::{self, ParseError, Syntax};
+use crate::symbol::Symbol;
+use crate::types::*;
+
+struct Linker<'a> {
+    syntax: Syntax,
+    sources: &'a mut SourceMap,
+    /// Names bound to the files loaded so far, by canonical path.
+    loaded: HashMap<PathBuf, Symbol>,
+    /// Values of the loaded files, each after the files it imports.
+    modules: Vec<(Parameter, Rc<Term>)>,
+    /// Canonical paths of the files being loaded, innermost last.
+    loading: Vec<PathBuf>,
+    /// Names bound around the term being linked, innermost last.
+    scope: Vec<Symbol>,
+}
+
+impl Linker<'_> {
+    fn error(message: String, location: &Location) -> ParseError {
+        ParseError {
+            message,
+            location: location.clone(),
+        }
+    }
+
+    /// The name bound to the value of the file `path` imports from `dir`,
+    /// loading the file the first time.
+    fn import(
+        &mut self,
+        dir: &Path,
+        path: &str,
+        location: &Location,
+    ) -> Result<Symbol, ParseError> {
+        let joined = dir.join(path);
+        let canonical = fs::canonicalize(&joined)
+            .map_err(|err| Self::error(format!("cannot import {}: {}", path, err), location))?;
+        if self.loading.contains(&canonical) {
+            return Err(Self::error(format!("{} imports itself", path), location));
+        }
+        if let Some(name) = self.loaded.get(&canonical) {
+            return Ok(*name);
+        }
+        let filename = joined.display().to_string();
+        let contents = fs::read_to_string(&joined)
+            .map_err(|err| Self::error(format!("cannot import {}: {}", path, err), location))?;
+        let file = if filename.ends_with(".rinha") {
+            self.sources.add(&filename, &contents);
+            parser::parse_with(&contents, &filename, self.syntax)?
+        } else {
+            crate::parse_json(&contents).map_err(|err| {
+                Self::error(format!("invalid JSON AST in {}: {}", path, err), location)
+            })?
+        };
+
+        self.loading.push(canonical.clone());
+        let scope = std::mem::take(&mut self.scope);
+        let mut value = file.expression;
+        let dir = joined.parent().unwrap_or(Path::new(""));
+        self.link(&mut value, dir)?;
+        self.scope = scope;
+        self.loading.pop();
+
+        let name = Symbol::intern(&format!("<{}>", filename));
+        let parameter = Parameter {
+            text: name,
+            location: location.clone(),
+        };
+        self.modules.push((parameter, value));
+        self.loaded.insert(canonical, name);
+        Ok(name)
+    }
+
+    fn bound(
+        &mut self,
+        names: &[Symbol],
+        term: &mut Rc<Term>,
+        dir: &Path,
+    ) -> Result<(), ParseError> {
+        let depth = self.scope.len();
+        self.scope.extend(names);
+        let result = self.link(term, dir);
+        self.scope.truncate(depth);
+        result
+    }
+
+    /// Replaces the imports in the `let` term `binding` and brings its
+    /// names into scope.
+    fn binding(&mut self, binding: &mut Term, dir: &Path) -> Result<(), ParseError> {
+        match binding {
+            Term::Let { name, value, .. } => {
+                match Rc::make_mut(value) {
+                    // A function sees the name it is bound to.
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => {
+                        let mut names = vec![name.text];
+                        names.extend(parameters.iter().map(|p| p.text));
+                        self.bound(&names, body, dir)?;
+                    }
+                    _ => self.link(value, dir)?,
+                }
+                self.scope.push(name.text);
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                ..
+            } => {
+                self.link(value, dir)?;
+                self.scope.extend([first.text, second.text]);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Replaces the imports in `term`, made from a file in `dir`.
+    fn link(&mut self, term: &mut Rc<Term>, dir: &Path) -> Result<(), ParseError> {
+        if term.next().is_some() {
+            let depth = self.scope.len();
+            let mut result = Ok(());
+            let rest = Term::let_chain_mut(term, |binding| {
+                if result.is_ok() {
+                    result = self.binding(binding, dir);
+                }
+            });
+            let result = result.and_then(|()| self.link(rest, dir));
+            self.scope.truncate(depth);
+            return result;
+        }
+        match Rc::make_mut(term) {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => {}
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                let import = Symbol::intern("import");
+                if let (Term::Var { text, .. }, [argument]) = (callee.as_ref(), &arguments[..]) {
+                    if *text == import && !self.scope.contains(&import) {
+                        let Term::Str { value: path, .. } = argument.as_ref() else {
+                            let message = "import needs a string literal".to_string();
+                            return Err(Self::error(message, argument.location()));
+                        };
+                        let location = location.clone();
+                        let text = self.import(dir, path, &location)?;
+                        *term = Rc::new(Term::Var {
+                            text,
+                            index: None,
+                            location,
+                        });
+                        return Ok(());
+                    }
+                }
+                self.link(callee, dir)?;
+                for argument in arguments {
+                    self.link(argument, dir)?;
+                }
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.link(condition, dir)?;
+                self.link(then, dir)?;
+                self.link(otherwise, dir)?;
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => unreachable!("linked above"),
+            Term::Binary { lhs, rhs, .. } => {
+                self.link(lhs, dir)?;
+                self.link(rhs, dir)?;
+            }
+            Term::Function {
+                parameters, value, ..
+            } => {
+                let names: Vec<_> = parameters.iter().map(|p| p.text).collect();
+                self.bound(&names, value, dir)?;
+            }
+            Term::Tuple { first, second, .. } => {
+                self.link(first, dir)?;
+                self.link(second, dir)?;
+            }
+            Term::Print { values, .. } => {
+                for value in values {
+                    self.link(value, dir)?;
+                }
+            }
+            Term::First { value, .. }
+            | Term::Second { value, .. }
+            | Term::Field { value, .. }
+            | Term::Ref { value, .. }
+            | Term::Deref { value, .. } => self.link(value, dir)?,
+            Term::Match { value, arms, .. } => {
+                self.link(value, dir)?;
+                for arm in arms {
+                    let names = arm.pattern.names();
+                    self.bound(&names, &mut arm.value, dir)?;
+                }
+            }
+            Term::Record { fields, .. } => {
+                for field in fields {
+                    self.link(&mut field.value, dir)?;
+                }
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                self.link(reference, dir)?;
+                self.link(value, dir)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Loads the files `file` imports, which is read from `path`, and binds
+/// their values around it. Imported source is parsed with `syntax` and
+/// added to `sources`. Programs read from stdin import relative to the
+/// working directory.
+pub fn link(
+    file: File,
+    path: &str,
+    syntax: Syntax,
+    sources: &mut SourceMap,
+) -> Result<File, ParseError> {
+    let (dir, loading) = if path == "-" {
+        (PathBuf::new(), Vec::new())
+    } else {
+        let path = Path::new(path);
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        (dir, fs::canonicalize(path).into_iter().collect())
+    };
+    let mut linker = Linker {
+        syntax,
+        sources,
+        loaded: HashMap::new(),
+        modules: Vec::new(),
+        loading,
+        scope: Vec::new(),
+    };
+    let mut expression = file.expression;
+    linker.link(&mut expression, &dir)?;
+    for (name, value) in linker.modules.into_iter().rev() {
+        let location = name.location.clone();
+        expression = Rc::new(Term::Let {
+            name,
+            value,
+            next: expression,
+            location,
+        });
+    }
+    Ok(File { expression, ..file })
+}
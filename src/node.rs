@@ -0,0 +1,38 @@
+//! Node.js bindings, for the `napi` feature.
+//!
+//! The library builds into a native addon, packaged for npm in `node/`,
+//! whose `run` function runs a program with [`crate::run_loaded`], like the
+//! other bindings do, so JavaScript tools that produce JSON ASTs can run
+//! them without starting a process for each.
+
+use napi::{Error, Result, Status};
+use napi_derive::napi;
+
+use crate::output;
+
+const FILENAME: &str = "<node>";
+
+/// What `run` returns.
+#[napi(object)]
+pub struct Run {
+    /// Every line the program printed, each ending in a line break.
+    pub stdout: String,
+    /// The value of the program as [`crate::Value::to_json`] describes it.
+    pub value: serde_json::Value,
+}
+
+/// Runs `program`, source or a JSON AST. Programs that do not parse throw
+/// with the status `InvalidArg`, and programs that fail with
+/// `GenericFailure`.
+#[napi]
+pub fn run(program: String) -> Result<Run> {
+    let file = crate::load(&program, FILENAME)
+        .map_err(|message| Error::new(Status::InvalidArg, message))?;
+    let mut lines = Vec::new();
+    let value = crate::run_loaded(&file, &mut lines)
+        .map_err(|err| Error::new(Status::GenericFailure, err.to_string()))?;
+    Ok(Run {
+        stdout: output::joined(&lines),
+        value: value.to_json(),
+    })
+}
@@ -0,0 +1,366 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+use crate::types::*;
+use crate::{cse, inline};
+
+/// Names used by a term without being bound inside it.
+type Free = HashSet<Symbol>;
+
+struct Optimizer {
+    /// Names bound around the term being optimized, innermost last.
+    scope: Vec<Symbol>,
+}
+
+impl Optimizer {
+    /// Whether evaluating `term` can be skipped: it cannot print, fail or
+    /// run forever, so only its value matters.
+    fn is_trivial(&self, term: &Term) -> bool {
+        match term {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Function { .. } => true,
+            Term::Var { text, .. } => self.scope.contains(text),
+            Term::Tuple { first, second, .. } => self.is_trivial(first) && self.is_trivial(second),
+            Term::Record { fields, .. } => fields.iter().all(|field| self.is_trivial(&field.value)),
+            _ => false,
+        }
+    }
+
+    fn bound<T>(
+        &mut self,
+        names: impl IntoIterator<Item = Symbol>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let depth = self.scope.len();
+        self.scope.extend(names);
+        let result = f(self);
+        self.scope.truncate(depth);
+        result
+    }
+
+    fn function(
+        &mut self,
+        name: Option<Symbol>,
+        parameters: &[Parameter],
+        body: &Rc<Term>,
+        location: &Location,
+    ) -> (Rc<Term>, Free) {
+        let names = name.into_iter().chain(parameters.iter().map(|p| p.text));
+        let (body, mut free) = self.bound(names, |this| this.optimize(body));
+        for parameter in parameters {
+            free.remove(&parameter.text);
+        }
+        if let Some(name) = name {
+            free.remove(&name);
+        }
+        let function = Term::Function {
+            parameters: parameters.to_vec(),
+            value: body,
+            location: location.clone(),
+            captures: Vec::new(),
+        };
+        (Rc::new(function), free)
+    }
+
+    /// Bindings are dropped last to first, as what follows a binding tells
+    /// whether it is used.
+    fn bindings(&mut self, term: &Rc<Term>) -> (Rc<Term>, Free) {
+        let mut chain = term.let_chain();
+        let mut bindings = Vec::new();
+        for binding in &mut chain {
+            match binding {
+                Term::Let { name, .. } => self.scope.push(name.text),
+                Term::LetTuple { first, second, .. } => {
+                    self.scope.extend([first.text, second.text])
+                }
+                _ => unreachable!(),
+            }
+            bindings.push(binding);
+        }
+        let rest = chain.shared_rest().expect("a chain of `let`");
+        let (mut optimized, mut free) = self.optimize(rest);
+        for binding in bindings.into_iter().rev() {
+            let (value, value_free) = match binding {
+                Term::Let { name, value, .. } => {
+                    self.scope.pop();
+                    if !free.contains(&name.text) && self.is_trivial(value) {
+                        continue;
+                    }
+                    free.remove(&name.text);
+                    match value.as_ref() {
+                        Term::Function {
+                            parameters,
+                            value: body,
+                            location,
+                            ..
+                        } => self.function(Some(name.text), parameters, body, location),
+                        _ => self.optimize(value),
+                    }
+                }
+                Term::LetTuple {
+                    first,
+                    second,
+                    value,
+                    ..
+                } => {
+                    self.scope.truncate(self.scope.len() - 2);
+                    free.remove(&first.text);
+                    free.remove(&second.text);
+                    self.optimize(value)
+                }
+                _ => unreachable!(),
+            };
+            free.extend(value_free);
+            optimized = Rc::new(binding.rebind(value, optimized));
+        }
+        (optimized, free)
+    }
+
+    fn optimize(&mut self, term: &Rc<Term>) -> (Rc<Term>, Free) {
+        let (optimized, free) = match term.as_ref() {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } => {
+                return (term.clone(), Free::new())
+            }
+            Term::Var { text, .. } => return (term.clone(), Free::from([*text])),
+            Term::Let { .. } | Term::LetTuple { .. } => return self.bindings(term),
+            Term::Function {
+                parameters,
+                value,
+                location,
+                ..
+            } => return self.function(None, parameters, value, location),
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                location,
+            } => {
+                let (condition, mut free) = self.optimize(condition);
+                let (then, then_free) = self.optimize(then);
+                let (otherwise, otherwise_free) = self.optimize(otherwise);
+                free.extend(then_free);
+                free.extend(otherwise_free);
+                let term = Term::If {
+                    condition,
+                    then,
+                    otherwise,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => {
+                let (lhs, mut free) = self.optimize(lhs);
+                let (rhs, rhs_free) = self.optimize(rhs);
+                free.extend(rhs_free);
+                let term = Term::Binary {
+                    lhs,
+                    op: op.clone(),
+                    rhs,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                let (callee, mut free) = self.optimize(callee);
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| {
+                        let (argument, argument_free) = self.optimize(argument);
+                        free.extend(argument_free);
+                        argument
+                    })
+                    .collect();
+                let term = Term::Call {
+                    callee,
+                    arguments,
+                    location: location.clone(),
+                    cache: CallCache::default(),
+                };
+                (term, free)
+            }
+            Term::Tuple {
+                first,
+                second,
+                location,
+            } => {
+                let (first, mut free) = self.optimize(first);
+                let (second, second_free) = self.optimize(second);
+                free.extend(second_free);
+                let term = Term::Tuple {
+                    first,
+                    second,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::First { value, location } => {
+                let (value, free) = self.optimize(value);
+                let term = Term::First {
+                    value,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Second { value, location } => {
+                let (value, free) = self.optimize(value);
+                let term = Term::Second {
+                    value,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Print { values, location } => {
+                let mut free = Free::new();
+                let values = values
+                    .iter()
+                    .map(|value| {
+                        let (value, value_free) = self.optimize(value);
+                        free.extend(value_free);
+                        value
+                    })
+                    .collect();
+                let term = Term::Print {
+                    values,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Match {
+                value,
+                arms,
+                location,
+            } => {
+                let (value, mut free) = self.optimize(value);
+                let arms = arms
+                    .iter()
+                    .map(|arm| {
+                        let names = arm.pattern.names();
+                        let (value, mut arm_free) =
+                            self.bound(names.iter().copied(), |this| this.optimize(&arm.value));
+                        for name in &names {
+                            arm_free.remove(name);
+                        }
+                        free.extend(arm_free);
+                        Arm {
+                            pattern: arm.pattern.clone(),
+                            value,
+                        }
+                    })
+                    .collect();
+                let term = Term::Match {
+                    value,
+                    arms,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Record { fields, location } => {
+                let mut free = Free::new();
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        let (value, value_free) = self.optimize(&field.value);
+                        free.extend(value_free);
+                        RecordField {
+                            name: field.name.clone(),
+                            value,
+                        }
+                    })
+                    .collect();
+                let term = Term::Record {
+                    fields,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Field {
+                value,
+                name,
+                location,
+            } => {
+                let (value, free) = self.optimize(value);
+                let term = Term::Field {
+                    value,
+                    name: name.clone(),
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Ref { value, location } => {
+                let (value, free) = self.optimize(value);
+                let term = Term::Ref {
+                    value,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Deref { value, location } => {
+                let (value, free) = self.optimize(value);
+                let term = Term::Deref {
+                    value,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+            Term::Assign {
+                reference,
+                value,
+                location,
+            } => {
+                let (reference, mut free) = self.optimize(reference);
+                let (value, value_free) = self.optimize(value);
+                free.extend(value_free);
+                let term = Term::Assign {
+                    reference,
+                    value,
+                    location: location.clone(),
+                };
+                (term, free)
+            }
+        };
+        (Rc::new(optimized), free)
+    }
+}
+
+/// A rewrite of a whole program that keeps what it does.
+pub type Pass = fn(&File) -> File;
+
+/// The passes [`optimize`] makes, in order, with their names.
+pub const PASSES: [(&str, Pass); 3] = [
+    ("inline", inline::inline),
+    ("cse", cse::eliminate),
+    ("dead-lets", remove_dead_lets),
+];
+
+/// Inlines small helper functions, as [`inline`] describes, binds pure
+/// terms evaluated twice once, as [`cse`] describes, and then removes the
+/// `let` bindings that are never used and whose value has no effect.
+pub fn optimize(file: &File) -> File {
+    PASSES
+        .iter()
+        .fold(file.clone(), |file, (_, pass)| pass(&file))
+}
+
+/// Removes `let` bindings that are never used and whose value has no
+/// effect: it cannot print, fail or loop, so skipping it is unobservable.
+/// Bindings such as `let _ = print(x)` are kept for their output, while
+/// helpers inlined at every call go away.
+pub fn remove_dead_lets(file: &File) -> File {
+    let mut optimizer = Optimizer { scope: Vec::new() };
+    let (expression, _) = optimizer.optimize(&file.expression);
+    File {
+        name: file.name.clone(),
+        expression,
+        location: file.location.clone(),
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+
+/// Destination of the values written by `print`.
+pub trait Output {
+    /// Receives the text of one `print`, without a trailing newline.
+    fn print(&mut self, text: &str);
+
+    /// Writes out any lines still held back, for when the process is
+    /// about to exit without returning.
+    fn flush(&mut self) {}
+}
+
+impl Output for io::Stdout {
+    fn print(&mut self, text: &str) {
+        println!("{}", text);
+    }
+}
+
+/// Writes each printed line to the underlying writer, which only reaches
+/// it once the buffer fills up or is flushed.
+impl<W: io::Write> Output for io::BufWriter<W> {
+    fn print(&mut self, text: &str) {
+        writeln!(self, "{}", text).expect("Falha ao escrever na saída padrão");
+    }
+
+    fn flush(&mut self) {
+        Write::flush(self).expect("Falha ao escrever na saída padrão");
+    }
+}
+
+/// Collects each printed line, for callers that want to inspect the output.
+impl Output for Vec<String> {
+    fn print(&mut self, text: &str) {
+        self.push(text.to_string());
+    }
+}
+
+/// The lines an output collected as the text they make on stdout, each
+/// ending in a line break.
+///
+/// ```
+/// let lines = vec!["1".to_string(), "2".to_string()];
+/// assert_eq!(rinha::output::joined(&lines), "1\n2\n");
+/// ```
+pub fn joined(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
@@ -0,0 +1,687 @@
+//! A compact binary form of the AST, for `rinha pack` and `rinha unpack`.
+//!
+//! Large JSON ASTs spend most of their loading time in the JSON parser.
+//! The packed form holds the same terms and locations, so a packed program
+//! runs and reports errors exactly like the JSON it came from, but it reads
+//! without any parsing: each term is a tag byte followed by its fields,
+//! integers are variable-length and every string is written once, with later
+//! uses referring back to it.
+//!
+//! The layout, after the [`MAGIC`] bytes and a version byte:
+//!
+//! - an unsigned integer is LEB128, a signed one zigzag-encoded first;
+//! - a string is `0`, its length and its UTF-8 bytes the first time it
+//!   appears, and `n` for the `n`-th different string after that;
+//! - a location is its start, its end and its filename;
+//! - a file is its name, its location and its expression;
+//! - a term is its index in [`KINDS`](crate::json::KINDS) followed by its
+//!   fields in the order they are declared in [`Term`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+use crate::types::*;
+
+/// Bytes every packed program starts with.
+pub const MAGIC: &[u8; 4] = b"RNHB";
+
+/// Bumped whenever the layout changes, so old files are rejected instead of
+/// read wrong.
+const VERSION: u8 = 2;
+
+const OPS: [BinaryOp; 13] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Rem,
+    BinaryOp::Eq,
+    BinaryOp::Neq,
+    BinaryOp::Lt,
+    BinaryOp::Gt,
+    BinaryOp::Lte,
+    BinaryOp::Gte,
+    BinaryOp::And,
+    BinaryOp::Or,
+];
+
+/// Why packed bytes could not be loaded.
+#[derive(Debug)]
+pub struct PackError {
+    pub message: String,
+    /// Byte offset into the input where the problem was found.
+    pub offset: usize,
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte offset {}", self.message, self.offset)
+    }
+}
+
+/// Whether `bytes` start like a packed program.
+pub fn is_packed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+struct Writer<'a> {
+    bytes: Vec<u8>,
+    strings: HashMap<&'a str, usize>,
+}
+
+impl<'a> Writer<'a> {
+    fn unsigned(&mut self, mut n: u64) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn signed(&mut self, n: i64) {
+        self.unsigned(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    fn string(&mut self, s: &'a str) {
+        if let Some(&index) = self.strings.get(s) {
+            self.unsigned(index as u64);
+            return;
+        }
+        self.strings.insert(s, self.strings.len() + 1);
+        self.unsigned(0);
+        self.unsigned(s.len() as u64);
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn location(&mut self, location: &'a Location) {
+        self.unsigned(location.start as u64);
+        self.unsigned(location.end as u64);
+        self.string(&location.filename);
+    }
+
+    fn parameter(&mut self, parameter: &'a Parameter) {
+        self.string(parameter.text.as_str());
+        self.location(&parameter.location);
+    }
+
+    fn pattern(&mut self, pattern: &'a Pattern) {
+        match pattern {
+            Pattern::Wildcard { location } => {
+                self.bytes.push(0);
+                self.location(location);
+            }
+            Pattern::Var { text, location } => {
+                self.bytes.push(1);
+                self.string(text.as_str());
+                self.location(location);
+            }
+            Pattern::Int { value, location } => {
+                self.bytes.push(2);
+                self.signed(*value);
+                self.location(location);
+            }
+            Pattern::Str { value, location } => {
+                self.bytes.push(3);
+                self.string(value);
+                self.location(location);
+            }
+            Pattern::Bool { value, location } => {
+                self.bytes.push(4);
+                self.bytes.push(u8::from(*value));
+                self.location(location);
+            }
+            Pattern::Tuple {
+                first,
+                second,
+                location,
+            } => {
+                self.bytes.push(5);
+                self.pattern(first);
+                self.pattern(second);
+                self.location(location);
+            }
+        }
+    }
+
+    fn tag(&mut self, term: &Term) {
+        let tag = crate::json::KINDS
+            .iter()
+            .position(|kind| *kind == term.kind())
+            .expect("every kind of term is listed");
+        self.bytes.push(tag as u8);
+    }
+
+    fn term(&mut self, term: &'a Term) {
+        if term.next().is_some() {
+            // A chain of `let`s is written in a loop, so that long programs
+            // fit the stack, in the same layout as one term at a time: the
+            // locations come after the term the chain ends in.
+            let mut chain = term.let_chain();
+            let bindings: Vec<&Term> = chain.by_ref().collect();
+            for binding in &bindings {
+                self.tag(binding);
+                match binding {
+                    Term::Let { name, value, .. } => {
+                        self.parameter(name);
+                        self.term(value);
+                    }
+                    Term::LetTuple {
+                        first,
+                        second,
+                        value,
+                        ..
+                    } => {
+                        self.parameter(first);
+                        self.parameter(second);
+                        self.term(value);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            self.term(chain.rest());
+            for binding in bindings.iter().rev() {
+                self.location(binding.location());
+            }
+            return;
+        }
+        self.tag(term);
+        match term {
+            Term::Int { value, location } => {
+                self.signed(*value);
+                self.location(location);
+            }
+            Term::Str { value, location } => {
+                self.string(value);
+                self.location(location);
+            }
+            Term::Bool { value, location } => {
+                self.bytes.push(u8::from(*value));
+                self.location(location);
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                location,
+            } => {
+                self.term(condition);
+                self.term(then);
+                self.term(otherwise);
+                self.location(location);
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => unreachable!("written above"),
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => {
+                self.term(lhs);
+                let op = OPS
+                    .iter()
+                    .position(|o| o == op)
+                    .expect("every operator is listed");
+                self.bytes.push(op as u8);
+                self.term(rhs);
+                self.location(location);
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                self.term(callee);
+                self.unsigned(arguments.len() as u64);
+                for argument in arguments {
+                    self.term(argument);
+                }
+                self.location(location);
+            }
+            Term::Function {
+                parameters,
+                value,
+                location,
+                ..
+            } => {
+                self.unsigned(parameters.len() as u64);
+                for parameter in parameters {
+                    self.parameter(parameter);
+                }
+                self.term(value);
+                self.location(location);
+            }
+            Term::Print { values, location } => {
+                self.unsigned(values.len() as u64);
+                for value in values {
+                    self.term(value);
+                }
+                self.location(location);
+            }
+            Term::First { value, location }
+            | Term::Second { value, location }
+            | Term::Ref { value, location }
+            | Term::Deref { value, location } => {
+                self.term(value);
+                self.location(location);
+            }
+            Term::Tuple {
+                first,
+                second,
+                location,
+            } => {
+                self.term(first);
+                self.term(second);
+                self.location(location);
+            }
+            Term::Var { text, location, .. } => {
+                self.string(text.as_str());
+                self.location(location);
+            }
+            Term::Match {
+                value,
+                arms,
+                location,
+            } => {
+                self.term(value);
+                self.unsigned(arms.len() as u64);
+                for arm in arms {
+                    self.pattern(&arm.pattern);
+                    self.term(&arm.value);
+                }
+                self.location(location);
+            }
+            Term::Record { fields, location } => {
+                self.unsigned(fields.len() as u64);
+                for field in fields {
+                    self.parameter(&field.name);
+                    self.term(&field.value);
+                }
+                self.location(location);
+            }
+            Term::Field {
+                value,
+                name,
+                location,
+            } => {
+                self.term(value);
+                self.parameter(name);
+                self.location(location);
+            }
+            Term::Assign {
+                reference,
+                value,
+                location,
+            } => {
+                self.term(reference);
+                self.term(value);
+                self.location(location);
+            }
+        }
+    }
+}
+
+/// The packed form of `file`.
+pub fn pack(file: &File) -> Vec<u8> {
+    let mut writer = Writer {
+        bytes: MAGIC.to_vec(),
+        strings: HashMap::new(),
+    };
+    writer.bytes.push(VERSION);
+    writer.string(&file.name);
+    writer.location(&file.location);
+    writer.term(&file.expression);
+    writer.bytes
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    strings: Vec<&'a str>,
+}
+
+impl<'a> Reader<'a> {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, PackError> {
+        Err(PackError {
+            message: message.into(),
+            offset: self.offset,
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8, PackError> {
+        match self.bytes.get(self.offset) {
+            Some(&byte) => {
+                self.offset += 1;
+                Ok(byte)
+            }
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    fn unsigned(&mut self) -> Result<u64, PackError> {
+        let mut n = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift > 63 {
+                return self.error("integer too large");
+            }
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+            shift += 7;
+        }
+    }
+
+    fn size(&mut self) -> Result<usize, PackError> {
+        let n = self.unsigned()?;
+        usize::try_from(n).or_else(|_| self.error("integer too large"))
+    }
+
+    fn signed(&mut self) -> Result<i64, PackError> {
+        let n = self.unsigned()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn bool(&mut self) -> Result<bool, PackError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => {
+                self.offset -= 1;
+                self.error("invalid boolean")
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a str, PackError> {
+        let index = self.size()?;
+        if index > 0 {
+            return match self.strings.get(index - 1) {
+                Some(s) => Ok(s),
+                None => self.error(format!("unknown string {}", index)),
+            };
+        }
+        let len = self.size()?;
+        let Some(bytes) = self.bytes.get(self.offset..self.offset.saturating_add(len)) else {
+            return self.error("unexpected end of input");
+        };
+        let Ok(s) = std::str::from_utf8(bytes) else {
+            return self.error("invalid UTF-8 in string");
+        };
+        self.offset += len;
+        self.strings.push(s);
+        Ok(s)
+    }
+
+    fn symbol(&mut self) -> Result<Symbol, PackError> {
+        Ok(Symbol::intern(self.string()?))
+    }
+
+    fn location(&mut self) -> Result<Location, PackError> {
+        Ok(Location {
+            start: self.size()?,
+            end: self.size()?,
+            filename: self.string()?.to_string(),
+        })
+    }
+
+    fn parameter(&mut self) -> Result<Parameter, PackError> {
+        Ok(Parameter {
+            text: self.symbol()?,
+            location: self.location()?,
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, PackError> {
+        let pattern = match self.byte()? {
+            0 => Pattern::Wildcard {
+                location: self.location()?,
+            },
+            1 => Pattern::Var {
+                text: self.symbol()?,
+                location: self.location()?,
+            },
+            2 => Pattern::Int {
+                value: self.signed()?,
+                location: self.location()?,
+            },
+            3 => Pattern::Str {
+                value: self.string()?.to_string(),
+                location: self.location()?,
+            },
+            4 => Pattern::Bool {
+                value: self.bool()?,
+                location: self.location()?,
+            },
+            5 => Pattern::Tuple {
+                first: Box::new(self.pattern()?),
+                second: Box::new(self.pattern()?),
+                location: self.location()?,
+            },
+            tag => {
+                self.offset -= 1;
+                return self.error(format!("unknown pattern {}", tag));
+            }
+        };
+        Ok(pattern)
+    }
+
+    /// Reads `count` items with `read`, without trusting `count` to size
+    /// the vector up front.
+    fn many<T>(
+        &mut self,
+        read: impl Fn(&mut Self) -> Result<T, PackError>,
+    ) -> Result<Vec<T>, PackError> {
+        let count = self.size()?;
+        let mut items = Vec::with_capacity(count.min(self.bytes.len() - self.offset));
+        for _ in 0..count {
+            items.push(read(self)?);
+        }
+        Ok(items)
+    }
+
+    fn kind(&mut self) -> Result<&'static str, PackError> {
+        let tag = self.byte()?;
+        let Some(kind) = crate::json::KINDS.get(usize::from(tag)) else {
+            self.offset -= 1;
+            return self.error(format!("unknown term {}", tag));
+        };
+        Ok(kind)
+    }
+
+    fn term(&mut self) -> Result<Rc<Term>, PackError> {
+        enum Binding {
+            Let(Parameter, Rc<Term>),
+            LetTuple(Parameter, Parameter, Rc<Term>),
+        }
+        // A chain of `let`s is read in a loop, so that long programs fit
+        // the stack, and each binding is closed once the term the chain
+        // ends in is read.
+        let mut bindings = Vec::new();
+        let mut term = loop {
+            match self.kind()? {
+                "Let" => {
+                    let name = self.parameter()?;
+                    bindings.push(Binding::Let(name, self.term()?));
+                }
+                "LetTuple" => {
+                    let first = self.parameter()?;
+                    let second = self.parameter()?;
+                    bindings.push(Binding::LetTuple(first, second, self.term()?));
+                }
+                kind => break self.node(kind)?,
+            }
+        };
+        for binding in bindings.into_iter().rev() {
+            let location = self.location()?;
+            term = Rc::new(match binding {
+                Binding::Let(name, value) => Term::Let {
+                    name,
+                    value,
+                    next: term,
+                    location,
+                },
+                Binding::LetTuple(first, second, value) => Term::LetTuple {
+                    first,
+                    second,
+                    value,
+                    next: term,
+                    location,
+                },
+            });
+        }
+        Ok(term)
+    }
+
+    /// Reads the rest of a term of `kind` other than a `let`.
+    fn node(&mut self, kind: &str) -> Result<Rc<Term>, PackError> {
+        let term = match kind {
+            "Int" => Term::Int {
+                value: self.signed()?,
+                location: self.location()?,
+            },
+            "Str" => Term::Str {
+                value: self.string()?.to_string(),
+                location: self.location()?,
+            },
+            "Bool" => Term::Bool {
+                value: self.bool()?,
+                location: self.location()?,
+            },
+            "If" => Term::If {
+                condition: self.term()?,
+                then: self.term()?,
+                otherwise: self.term()?,
+                location: self.location()?,
+            },
+            "Binary" => {
+                let lhs = self.term()?;
+                let op = self.byte()?;
+                let Some(op) = OPS.get(usize::from(op)) else {
+                    self.offset -= 1;
+                    return self.error(format!("unknown operator {}", op));
+                };
+                Term::Binary {
+                    lhs,
+                    op: op.clone(),
+                    rhs: self.term()?,
+                    location: self.location()?,
+                }
+            }
+            "Call" => Term::Call {
+                callee: self.term()?,
+                arguments: self.many(Self::term)?,
+                location: self.location()?,
+                cache: CallCache::default(),
+            },
+            "Function" => Term::Function {
+                parameters: self.many(Self::parameter)?,
+                value: self.term()?,
+                location: self.location()?,
+                captures: Vec::new(),
+            },
+            "First" => Term::First {
+                value: self.term()?,
+                location: self.location()?,
+            },
+            "Print" => {
+                let values = self.many(Self::term)?;
+                if values.is_empty() {
+                    return self.error("print without values");
+                }
+                Term::Print {
+                    values,
+                    location: self.location()?,
+                }
+            }
+            "Second" => Term::Second {
+                value: self.term()?,
+                location: self.location()?,
+            },
+            "Tuple" => Term::Tuple {
+                first: self.term()?,
+                second: self.term()?,
+                location: self.location()?,
+            },
+            "Var" => Term::Var {
+                text: self.symbol()?,
+                index: None,
+                location: self.location()?,
+            },
+            "Match" => Term::Match {
+                value: self.term()?,
+                arms: self.many(|reader| {
+                    Ok(Arm {
+                        pattern: reader.pattern()?,
+                        value: reader.term()?,
+                    })
+                })?,
+                location: self.location()?,
+            },
+            "Record" => Term::Record {
+                fields: self.many(|reader| {
+                    Ok(RecordField {
+                        name: reader.parameter()?,
+                        value: reader.term()?,
+                    })
+                })?,
+                location: self.location()?,
+            },
+            "Field" => Term::Field {
+                value: self.term()?,
+                name: self.parameter()?,
+                location: self.location()?,
+            },
+            "Ref" => Term::Ref {
+                value: self.term()?,
+                location: self.location()?,
+            },
+            "Deref" => Term::Deref {
+                value: self.term()?,
+                location: self.location()?,
+            },
+            "Assign" => Term::Assign {
+                reference: self.term()?,
+                value: self.term()?,
+                location: self.location()?,
+            },
+            kind => unreachable!("{} is not a kind of term", kind),
+        };
+        Ok(Rc::new(term))
+    }
+}
+
+/// Loads a program packed by [`pack`].
+pub fn unpack(bytes: &[u8]) -> Result<File, PackError> {
+    let mut reader = Reader {
+        bytes,
+        offset: 0,
+        strings: Vec::new(),
+    };
+    if !is_packed(bytes) {
+        return reader.error("not a packed program");
+    }
+    reader.offset = MAGIC.len();
+    let version = reader.byte()?;
+    if version != VERSION {
+        reader.offset -= 1;
+        return reader.error(format!("unsupported version {}", version));
+    }
+    let file = File {
+        name: reader.string()?.to_string(),
+        location: reader.location()?,
+        expression: reader.term()?,
+    };
+    if reader.offset != bytes.len() {
+        return reader.error("trailing bytes after the program");
+    }
+    Ok(file)
+}
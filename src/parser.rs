@@ -0,0 +1,1089 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+use crate::types::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Token {
+    /// The digits of an integer literal, which a `-` before them may
+    /// negate, so they may be one more than `i64::MAX`.
+    Int(u64),
+    Str(String),
+    Ident(String),
+    Let,
+    Fn,
+    If,
+    Else,
+    True,
+    False,
+    Print,
+    First,
+    Second,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Dot,
+    Colon,
+    ColonEq,
+    Bang,
+    Semicolon,
+    Assign,
+    Arrow,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    EqEq,
+    NotEq,
+    Lt,
+    Gt,
+    Lte,
+    Gte,
+    AndAnd,
+    OrOr,
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Int(n) => write!(f, "{}", n),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Let => write!(f, "let"),
+            Token::Fn => write!(f, "fn"),
+            Token::If => write!(f, "if"),
+            Token::Else => write!(f, "else"),
+            Token::True => write!(f, "true"),
+            Token::False => write!(f, "false"),
+            Token::Print => write!(f, "print"),
+            Token::First => write!(f, "first"),
+            Token::Second => write!(f, "second"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::LBrace => write!(f, "{{"),
+            Token::RBrace => write!(f, "}}"),
+            Token::Comma => write!(f, ","),
+            Token::Dot => write!(f, "."),
+            Token::Colon => write!(f, ":"),
+            Token::ColonEq => write!(f, ":="),
+            Token::Bang => write!(f, "!"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Assign => write!(f, "="),
+            Token::Arrow => write!(f, "=>"),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Star => write!(f, "*"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::EqEq => write!(f, "=="),
+            Token::NotEq => write!(f, "!="),
+            Token::Lt => write!(f, "<"),
+            Token::Gt => write!(f, ">"),
+            Token::Lte => write!(f, "<="),
+            Token::Gte => write!(f, ">="),
+            Token::AndAnd => write!(f, "&&"),
+            Token::OrOr => write!(f, "||"),
+            Token::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub location: Location,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.location.filename, self.location.start, self.message
+        )
+    }
+}
+
+pub(crate) struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    filename: &'a str,
+}
+
+impl<'a> Lexer<'a> {
+    /// A lexer reading `source` from the byte offset `pos`.
+    pub(crate) fn new(source: &'a str, pos: usize, filename: &'a str) -> Lexer<'a> {
+        Lexer {
+            source,
+            pos,
+            filename,
+        }
+    }
+
+    /// Where the lexer stopped, which is past the text of a token that
+    /// failed to lex.
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn error(&self, message: String, start: usize) -> ParseError {
+        ParseError {
+            message,
+            location: Location {
+                start,
+                end: self.pos,
+                filename: self.filename.to_string(),
+            },
+        }
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), ParseError> {
+        loop {
+            let rest = &self.source[self.pos..];
+            if let Some(c) = rest.chars().next().filter(|c| c.is_whitespace()) {
+                self.pos += c.len_utf8();
+            } else if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(body) = rest.strip_prefix("/*") {
+                let start = self.pos;
+                match body.find("*/") {
+                    Some(end) => self.pos += end + 4,
+                    None => {
+                        self.pos = self.source.len();
+                        return Err(self.error("unterminated comment".to_string(), start));
+                    }
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    pub(crate) fn next_token(&mut self) -> Result<(Token, usize, usize), ParseError> {
+        self.skip_whitespace_and_comments()?;
+        let start = self.pos;
+        let c = match self.peek_char() {
+            Some(c) => c,
+            None => return Ok((Token::Eof, start, start)),
+        };
+
+        let token = if c.is_ascii_digit() {
+            self.lex_int(start)?
+        } else if c.is_alphabetic() || c == '_' {
+            self.lex_word()
+        } else if c == '"' {
+            self.lex_string(start)?
+        } else {
+            self.lex_symbol(start)?
+        };
+
+        Ok((token, start, self.pos))
+    }
+
+    fn lex_int(&mut self, start: usize) -> Result<Token, ParseError> {
+        while self.peek_char().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let text = &self.source[start..self.pos];
+        text.parse()
+            .map(Token::Int)
+            .map_err(|_| self.error(format!("integer literal {} is too large", text), start))
+    }
+
+    fn lex_word(&mut self) -> Token {
+        let start = self.pos;
+        while let Some(c) = self
+            .peek_char()
+            .filter(|c| c.is_alphanumeric() || *c == '_')
+        {
+            self.pos += c.len_utf8();
+        }
+        match &self.source[start..self.pos] {
+            "let" => Token::Let,
+            "fn" => Token::Fn,
+            "if" => Token::If,
+            "else" => Token::Else,
+            "true" => Token::True,
+            "false" => Token::False,
+            "print" => Token::Print,
+            "first" => Token::First,
+            "second" => Token::Second,
+            word => Token::Ident(word.to_string()),
+        }
+    }
+
+    fn lex_string(&mut self, start: usize) -> Result<Token, ParseError> {
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            let c = match self.peek_char() {
+                Some(c) => c,
+                None => return Err(self.error("unterminated string".to_string(), start)),
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(Token::Str(value)),
+                '\\' => {
+                    let escaped = match self.peek_char() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(other) => {
+                            return Err(self.error(format!("unknown escape \\{}", other), start))
+                        }
+                        None => return Err(self.error("unterminated string".to_string(), start)),
+                    };
+                    self.pos += 1;
+                    value.push(escaped);
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn lex_symbol(&mut self, start: usize) -> Result<Token, ParseError> {
+        let rest = &self.source[self.pos..];
+        let two_char = [
+            ("=>", Token::Arrow),
+            ("==", Token::EqEq),
+            ("!=", Token::NotEq),
+            ("<=", Token::Lte),
+            (">=", Token::Gte),
+            ("&&", Token::AndAnd),
+            ("||", Token::OrOr),
+            (":=", Token::ColonEq),
+        ];
+        for (text, token) in two_char {
+            if rest.starts_with(text) {
+                self.pos += 2;
+                return Ok(token);
+            }
+        }
+
+        let c = rest.chars().next().unwrap();
+        let token = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
+            ',' => Token::Comma,
+            '.' => Token::Dot,
+            ':' => Token::Colon,
+            '!' => Token::Bang,
+            ';' => Token::Semicolon,
+            '=' => Token::Assign,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '<' => Token::Lt,
+            '>' => Token::Gt,
+            _ => {
+                self.pos += c.len_utf8();
+                return Err(self.error(format!("unexpected character {:?}", c), start));
+            }
+        };
+        self.pos += 1;
+        Ok(token)
+    }
+}
+
+fn tokenize(source: &str, filename: &str) -> Result<Vec<(Token, usize, usize)>, ParseError> {
+    let mut lexer = Lexer::new(source, 0, filename);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token()?;
+        let done = token.0 == Token::Eof;
+        tokens.push(token);
+        if done {
+            return Ok(tokens);
+        }
+    }
+}
+
+/// Language extensions accepted by the parser on top of the competition
+/// subset.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Syntax {
+    /// Record literals `{ x: 1, y: 2 }` and field access `point.x`.
+    pub records: bool,
+    /// References: `ref(value)`, `!reference` and `reference := value`.
+    pub refs: bool,
+    /// `import("path")`, which the parser reads as any other call and
+    /// [`crate::modules::link`] replaces with the value of the file.
+    pub modules: bool,
+}
+
+impl Syntax {
+    /// Every extension, for tools that read programs without running them.
+    pub fn all() -> Syntax {
+        Syntax {
+            records: true,
+            refs: true,
+            modules: true,
+        }
+    }
+}
+
+/// One `let` or `let (first, second)` binding up to its `;`, without the
+/// term it is bound in.
+pub(crate) struct Binding {
+    start: usize,
+    names: (Parameter, Option<Parameter>),
+    value: Rc<Term>,
+}
+
+/// Binds `next` with `bindings`, the first of them outermost. Each binding
+/// spans from its `let` to `end`, the end of `next`.
+pub(crate) fn bind(bindings: Vec<Binding>, next: Term, end: usize, filename: &str) -> Term {
+    let mut term = next;
+    for Binding {
+        start,
+        names,
+        value,
+    } in bindings.into_iter().rev()
+    {
+        let location = Location {
+            start,
+            end,
+            filename: filename.to_string(),
+        };
+        term = match names {
+            (name, None) => Term::Let {
+                name,
+                value,
+                next: Rc::new(term),
+                location,
+            },
+            (first, Some(second)) => Term::LetTuple {
+                first,
+                second,
+                value,
+                next: Rc::new(term),
+                location,
+            },
+        };
+    }
+    term
+}
+
+pub(crate) struct Parser<'a> {
+    tokens: Vec<(Token, usize, usize)>,
+    pos: usize,
+    filename: &'a str,
+    syntax: Syntax,
+}
+
+fn binary_op(token: &Token) -> Option<(BinaryOp, u8)> {
+    let op = match token {
+        Token::OrOr => BinaryOp::Or,
+        Token::AndAnd => BinaryOp::And,
+        Token::EqEq => BinaryOp::Eq,
+        Token::NotEq => BinaryOp::Neq,
+        Token::Lt => BinaryOp::Lt,
+        Token::Gt => BinaryOp::Gt,
+        Token::Lte => BinaryOp::Lte,
+        Token::Gte => BinaryOp::Gte,
+        Token::Plus => BinaryOp::Add,
+        Token::Minus => BinaryOp::Sub,
+        Token::Star => BinaryOp::Mul,
+        Token::Slash => BinaryOp::Div,
+        Token::Percent => BinaryOp::Rem,
+        _ => return None,
+    };
+    let precedence = op.precedence();
+    Some((op, precedence))
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn start(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn last_end(&self) -> usize {
+        if self.pos == 0 {
+            0
+        } else {
+            self.tokens[self.pos - 1].2
+        }
+    }
+
+    fn location(&self, start: usize) -> Location {
+        Location {
+            start,
+            end: self.last_end(),
+            filename: self.filename.to_string(),
+        }
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if token != Token::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// The value of the literal `digits` just read, negated if `negative`,
+    /// which must fit in 64 bits.
+    fn int(&self, digits: u64, negative: bool, start: usize) -> Result<i64, ParseError> {
+        let value = if negative {
+            0i64.checked_sub_unsigned(digits)
+        } else {
+            i64::try_from(digits).ok()
+        };
+        value.ok_or_else(|| ParseError {
+            message: format!(
+                "integer literal {}{} is too large",
+                if negative { "-" } else { "" },
+                digits
+            ),
+            location: self.location(start),
+        })
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, ParseError> {
+        let (token, start, end) = &self.tokens[self.pos];
+        Err(ParseError {
+            message: format!("expected {} but found {}", expected, token),
+            location: Location {
+                start: *start,
+                end: *end,
+                filename: self.filename.to_string(),
+            },
+        })
+    }
+
+    /// Fails on a token that starts `what`, which the extension `name`
+    /// enables.
+    fn needs<T>(&self, what: &str, name: &str) -> Result<T, ParseError> {
+        let (_, start, end) = self.tokens[self.pos];
+        Err(ParseError {
+            message: format!("{} need `--extensions {}`", what, name),
+            location: Location {
+                start,
+                end,
+                filename: self.filename.to_string(),
+            },
+        })
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        if *self.peek() == token {
+            self.advance();
+            Ok(())
+        } else {
+            self.error(&token.to_string())
+        }
+    }
+
+    fn parameter(&mut self) -> Result<Parameter, ParseError> {
+        let start = self.start();
+        if let Token::Ident(text) = self.peek().clone() {
+            self.advance();
+            Ok(Parameter {
+                text: Symbol::intern(&text),
+                location: self.location(start),
+            })
+        } else {
+            self.error("identifier")
+        }
+    }
+
+    pub(crate) fn term(&mut self) -> Result<Term, ParseError> {
+        let start = self.start();
+        let term = match self.peek() {
+            Token::Let => return self.let_term(),
+            _ => self.binary(0)?,
+        };
+        if !(self.syntax.refs && *self.peek() == Token::ColonEq) {
+            return Ok(term);
+        }
+        self.advance();
+        let value = self.term()?;
+        Ok(Term::Assign {
+            reference: Rc::new(term),
+            value: Rc::new(value),
+            location: self.location(start),
+        })
+    }
+
+    /// Parses `let` and `let (first, second)` bindings and the term they
+    /// are bound in. Consecutive bindings are read in a loop, so programs
+    /// made of thousands of them do not nest a call for each.
+    fn let_term(&mut self) -> Result<Term, ParseError> {
+        let mut bindings = Vec::new();
+        while *self.peek() == Token::Let {
+            bindings.push(self.binding()?);
+        }
+        let term = self.term()?;
+        Ok(bind(bindings, term, self.last_end(), self.filename))
+    }
+
+    /// Parses a single binding, from its `let` to its `;`.
+    pub(crate) fn binding(&mut self) -> Result<Binding, ParseError> {
+        let start = self.start();
+        self.expect(Token::Let)?;
+        let names = if *self.peek() == Token::LParen {
+            self.advance();
+            let first = self.parameter()?;
+            self.expect(Token::Comma)?;
+            let second = self.parameter()?;
+            self.expect(Token::RParen)?;
+            (first, Some(second))
+        } else {
+            (self.parameter()?, None)
+        };
+        self.expect(Token::Assign)?;
+        let value = Rc::new(self.term()?);
+        self.expect(Token::Semicolon)?;
+        Ok(Binding {
+            start,
+            names,
+            value,
+        })
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Term, ParseError> {
+        let start = self.start();
+        let mut lhs = self.call()?;
+        while let Some((op, precedence)) = binary_op(self.peek()) {
+            if precedence <= min_precedence {
+                break;
+            }
+            self.advance();
+            let rhs = self.binary(precedence)?;
+            lhs = Term::Binary {
+                lhs: Rc::new(lhs),
+                op,
+                rhs: Rc::new(rhs),
+                location: self.location(start),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn call(&mut self) -> Result<Term, ParseError> {
+        let start = self.start();
+        let mut callee = self.primary()?;
+        loop {
+            match self.peek() {
+                Token::LParen => {
+                    self.advance();
+                    let arguments = self.arguments()?;
+                    callee = Term::Call {
+                        callee: Rc::new(callee),
+                        arguments,
+                        location: self.location(start),
+                        cache: CallCache::default(),
+                    };
+                }
+                Token::Dot if self.syntax.records => {
+                    self.advance();
+                    let name = self.parameter()?;
+                    callee = Term::Field {
+                        value: Rc::new(callee),
+                        name,
+                        location: self.location(start),
+                    };
+                }
+                _ => return Ok(callee),
+            }
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Rc<Term>>, ParseError> {
+        let mut arguments = Vec::new();
+        while *self.peek() != Token::RParen {
+            arguments.push(Rc::new(self.term()?));
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RParen)?;
+        Ok(arguments)
+    }
+
+    fn block(&mut self) -> Result<Term, ParseError> {
+        if self.at_record() {
+            return self.record();
+        }
+        self.expect(Token::LBrace)?;
+        let term = self.term()?;
+        self.expect(Token::RBrace)?;
+        Ok(term)
+    }
+
+    /// Whether a record literal starts here: `{` followed by `}` or by a
+    /// name and `:`, which no block can start with.
+    fn at_record(&self) -> bool {
+        let token = |offset: usize| self.tokens.get(self.pos + offset).map(|(t, _, _)| t);
+        self.syntax.records
+            && *self.peek() == Token::LBrace
+            && match token(1) {
+                Some(Token::RBrace) => true,
+                Some(Token::Ident(_)) => token(2) == Some(&Token::Colon),
+                _ => false,
+            }
+    }
+
+    fn record(&mut self) -> Result<Term, ParseError> {
+        let start = self.start();
+        self.expect(Token::LBrace)?;
+        let mut fields: Vec<RecordField> = Vec::new();
+        while *self.peek() != Token::RBrace {
+            let name = self.parameter()?;
+            if fields.iter().any(|field| field.name.text == name.text) {
+                return Err(ParseError {
+                    message: format!("field {} is given twice", name.text),
+                    location: name.location,
+                });
+            }
+            self.expect(Token::Colon)?;
+            let value = self.term()?;
+            fields.push(RecordField {
+                name,
+                value: Rc::new(value),
+            });
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RBrace)?;
+        Ok(Term::Record {
+            fields,
+            location: self.location(start),
+        })
+    }
+
+    fn builtin(&mut self) -> Result<Rc<Term>, ParseError> {
+        self.advance();
+        self.expect(Token::LParen)?;
+        let value = self.term()?;
+        self.expect(Token::RParen)?;
+        Ok(Rc::new(value))
+    }
+
+    fn primary(&mut self) -> Result<Term, ParseError> {
+        let start = self.start();
+        match self.peek().clone() {
+            Token::Int(digits) => {
+                self.advance();
+                Ok(Term::Int {
+                    value: self.int(digits, false, start)?,
+                    location: self.location(start),
+                })
+            }
+            Token::Minus => {
+                self.advance();
+                if let Token::Int(digits) = *self.peek() {
+                    self.advance();
+                    Ok(Term::Int {
+                        value: self.int(digits, true, start)?,
+                        location: self.location(start),
+                    })
+                } else {
+                    self.error("integer literal")
+                }
+            }
+            Token::Str(value) => {
+                self.advance();
+                Ok(Term::Str {
+                    value,
+                    location: self.location(start),
+                })
+            }
+            Token::True | Token::False => {
+                let value = self.advance() == Token::True;
+                Ok(Term::Bool {
+                    value,
+                    location: self.location(start),
+                })
+            }
+            Token::Ident(text) if text == "match" => match self.match_term()? {
+                Some(term) => Ok(term),
+                None => {
+                    self.advance();
+                    Ok(Term::Var {
+                        text: Symbol::intern(&text),
+                        index: None,
+                        location: self.location(start),
+                    })
+                }
+            },
+            Token::Ident(text)
+                if text == "ref"
+                    && self.syntax.refs
+                    && self.tokens[self.pos + 1].0 == Token::LParen =>
+            {
+                let value = self.builtin()?;
+                Ok(Term::Ref {
+                    value,
+                    location: self.location(start),
+                })
+            }
+            Token::Ident(text) => {
+                self.advance();
+                Ok(Term::Var {
+                    text: Symbol::intern(&text),
+                    index: None,
+                    location: self.location(start),
+                })
+            }
+            Token::Print => {
+                self.advance();
+                self.expect(Token::LParen)?;
+                let mut values = vec![Rc::new(self.term()?)];
+                while *self.peek() == Token::Comma {
+                    self.advance();
+                    values.push(Rc::new(self.term()?));
+                }
+                self.expect(Token::RParen)?;
+                Ok(Term::Print {
+                    values,
+                    location: self.location(start),
+                })
+            }
+            Token::First => {
+                let value = self.builtin()?;
+                Ok(Term::First {
+                    value,
+                    location: self.location(start),
+                })
+            }
+            Token::Second => {
+                let value = self.builtin()?;
+                Ok(Term::Second {
+                    value,
+                    location: self.location(start),
+                })
+            }
+            Token::LParen => {
+                self.advance();
+                let first = self.term()?;
+                if *self.peek() == Token::Comma {
+                    self.advance();
+                    let second = self.term()?;
+                    self.expect(Token::RParen)?;
+                    Ok(Term::Tuple {
+                        first: Rc::new(first),
+                        second: Rc::new(second),
+                        location: self.location(start),
+                    })
+                } else {
+                    self.expect(Token::RParen)?;
+                    Ok(first)
+                }
+            }
+            Token::LBrace if self.syntax.records => self.record(),
+            Token::LBrace => self.needs("record literals", "records"),
+            Token::Bang if self.syntax.refs => {
+                self.advance();
+                let value = self.primary()?;
+                Ok(Term::Deref {
+                    value: Rc::new(value),
+                    location: self.location(start),
+                })
+            }
+            Token::Bang => self.needs("references", "refs"),
+            Token::Fn => self.function(),
+            Token::If => self.if_term(),
+            Token::Let => self.let_term(),
+            _ => self.error("expression"),
+        }
+    }
+
+    fn function(&mut self) -> Result<Term, ParseError> {
+        let start = self.start();
+        self.expect(Token::Fn)?;
+        self.expect(Token::LParen)?;
+        let mut parameters = Vec::new();
+        while *self.peek() != Token::RParen {
+            parameters.push(self.parameter()?);
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RParen)?;
+        self.expect(Token::Arrow)?;
+        let value = if *self.peek() == Token::LBrace {
+            self.block()?
+        } else {
+            self.term()?
+        };
+        Ok(Term::Function {
+            parameters,
+            value: Rc::new(value),
+            location: self.location(start),
+            captures: Vec::new(),
+        })
+    }
+
+    /// Parses `match (value) { pattern => value, ... }`. `match` is not a
+    /// keyword, so when what follows it is not the start of a match it is
+    /// left to be read as a name and `None` is returned.
+    fn match_term(&mut self) -> Result<Option<Term>, ParseError> {
+        let start = self.start();
+        let saved = self.pos;
+        self.advance();
+        let value = match self.peek() {
+            Token::LParen => {
+                self.advance();
+                self.term().ok()
+            }
+            _ => None,
+        };
+        let value = match value {
+            Some(value) if *self.peek() == Token::RParen => {
+                self.advance();
+                value
+            }
+            _ => {
+                self.pos = saved;
+                return Ok(None);
+            }
+        };
+        if *self.peek() != Token::LBrace {
+            self.pos = saved;
+            return Ok(None);
+        }
+        self.advance();
+        let mut arms = Vec::new();
+        loop {
+            let pattern_start = self.start();
+            let pattern = self.pattern()?;
+            let names = pattern.names();
+            let repeated = (1..names.len()).find(|&i| names[..i].contains(&names[i]));
+            if let Some(i) = repeated {
+                let name = names[i];
+                return Err(ParseError {
+                    message: format!("{} is bound twice in this pattern", name),
+                    location: self.location(pattern_start),
+                });
+            }
+            self.expect(Token::Arrow)?;
+            let value = if *self.peek() == Token::LBrace {
+                self.block()?
+            } else {
+                self.term()?
+            };
+            arms.push(Arm {
+                pattern,
+                value: Rc::new(value),
+            });
+            if *self.peek() == Token::Comma {
+                self.advance();
+            } else if *self.peek() != Token::RBrace {
+                return self.error(", or }");
+            }
+            if *self.peek() == Token::RBrace {
+                break;
+            }
+        }
+        self.expect(Token::RBrace)?;
+        Ok(Some(Term::Match {
+            value: Rc::new(value),
+            arms,
+            location: self.location(start),
+        }))
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        let start = self.start();
+        match self.peek().clone() {
+            Token::Ident(text) => {
+                self.advance();
+                let location = self.location(start);
+                Ok(if text == "_" {
+                    Pattern::Wildcard { location }
+                } else {
+                    Pattern::Var {
+                        text: Symbol::intern(&text),
+                        location,
+                    }
+                })
+            }
+            Token::Int(digits) => {
+                self.advance();
+                Ok(Pattern::Int {
+                    value: self.int(digits, false, start)?,
+                    location: self.location(start),
+                })
+            }
+            Token::Minus => {
+                self.advance();
+                if let Token::Int(digits) = *self.peek() {
+                    self.advance();
+                    Ok(Pattern::Int {
+                        value: self.int(digits, true, start)?,
+                        location: self.location(start),
+                    })
+                } else {
+                    self.error("integer literal")
+                }
+            }
+            Token::Str(value) => {
+                self.advance();
+                Ok(Pattern::Str {
+                    value,
+                    location: self.location(start),
+                })
+            }
+            Token::True | Token::False => {
+                let value = self.advance() == Token::True;
+                Ok(Pattern::Bool {
+                    value,
+                    location: self.location(start),
+                })
+            }
+            Token::LParen => {
+                self.advance();
+                let first = self.pattern()?;
+                if *self.peek() != Token::Comma {
+                    self.expect(Token::RParen)?;
+                    return Ok(first);
+                }
+                self.advance();
+                let second = self.pattern()?;
+                self.expect(Token::RParen)?;
+                Ok(Pattern::Tuple {
+                    first: Box::new(first),
+                    second: Box::new(second),
+                    location: self.location(start),
+                })
+            }
+            _ => self.error("pattern"),
+        }
+    }
+
+    fn if_term(&mut self) -> Result<Term, ParseError> {
+        let start = self.start();
+        self.expect(Token::If)?;
+        self.expect(Token::LParen)?;
+        let condition = self.term()?;
+        self.expect(Token::RParen)?;
+        let then = self.block()?;
+        self.expect(Token::Else)?;
+        let otherwise = if *self.peek() == Token::If {
+            self.if_term()?
+        } else {
+            self.block()?
+        };
+        Ok(Term::If {
+            condition: Rc::new(condition),
+            then: Rc::new(then),
+            otherwise: Rc::new(otherwise),
+            location: self.location(start),
+        })
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &str, filename: &'a str, syntax: Syntax) -> Result<Parser<'a>, ParseError> {
+        Ok(Parser {
+            tokens: tokenize(source, filename)?,
+            pos: 0,
+            filename,
+            syntax,
+        })
+    }
+
+    /// A parser reading `tokens`, already lexed, which end with
+    /// [`Token::Eof`].
+    pub(crate) fn from_tokens(
+        tokens: Vec<(Token, usize, usize)>,
+        filename: &'a str,
+        syntax: Syntax,
+    ) -> Parser<'a> {
+        Parser {
+            tokens,
+            pos: 0,
+            filename,
+            syntax,
+        }
+    }
+
+    pub(crate) fn finish<T>(&self, result: T) -> Result<T, ParseError> {
+        if *self.peek() != Token::Eof {
+            return self.error("end of file");
+        }
+        Ok(result)
+    }
+}
+
+/// A line entered at the REPL: either a binding that should stay visible
+/// to the following lines or an expression to evaluate.
+pub enum Statement {
+    Let { name: Parameter, value: Term },
+    Expression(Term),
+}
+
+/// Parses a REPL input. Besides regular expressions, it accepts
+/// `let name = value` with an optional trailing `;` and no continuation.
+pub fn parse_statement(source: &str, filename: &str) -> Result<Statement, ParseError> {
+    let mut parser = Parser::new(source, filename, Syntax::default())?;
+    if *parser.peek() != Token::Let {
+        let expression = parser.term()?;
+        return parser.finish(Statement::Expression(expression));
+    }
+
+    let start = parser.start();
+    parser.advance();
+    let name = parser.parameter()?;
+    parser.expect(Token::Assign)?;
+    let value = parser.term()?;
+    if *parser.peek() != Token::Eof {
+        parser.expect(Token::Semicolon)?;
+    }
+    if *parser.peek() == Token::Eof {
+        return Ok(Statement::Let { name, value });
+    }
+
+    let next = parser.term()?;
+    let expression = Term::Let {
+        name,
+        value: Rc::new(value),
+        next: Rc::new(next),
+        location: parser.location(start),
+    };
+    parser.finish(Statement::Expression(expression))
+}
+
+pub fn parse(source: &str, filename: &str) -> Result<File, ParseError> {
+    parse_with(source, filename, Syntax::default())
+}
+
+/// Parses a program accepting the extensions enabled in `syntax`.
+pub fn parse_with(source: &str, filename: &str, syntax: Syntax) -> Result<File, ParseError> {
+    let mut parser = Parser::new(source, filename, syntax)?;
+    let expression = parser.term()?;
+    parser.finish(())?;
+    Ok(File {
+        name: filename.to_string(),
+        expression: Rc::new(expression),
+        location: Location {
+            start: 0,
+            end: source.len(),
+            filename: filename.to_string(),
+        },
+    })
+}
@@ -0,0 +1,309 @@
+//! Calls evaluated before the program runs, for `--peval`.
+//!
+//! A call that only uses literals and functions bound by `let` around it,
+//! such as `fib(30)` or `combination(30, 15)`, gives the same value every
+//! time the program runs. [`specialize`] runs each such call ahead of time,
+//! together with the definitions it needs, and puts its value in its
+//! place, so the loops it would have run are unrolled down to their result.
+//! Operators on literals are folded, and `if` on a literal condition is
+//! replaced by the branch it takes.
+//!
+//! Calls are run by the tree-walker, memoizing pure functions, for at most
+//! [`BUDGET`] calls each. A call that prints, fails, takes longer or gives
+//! a value that has no literal, such as a function, is left as it was, to
+//! run with the program.
+
+use std::rc::Rc;
+
+use crate::builtins;
+use crate::config::InterpreterConfig;
+use crate::fuel::Fuel;
+use crate::symbol::Symbol;
+use crate::types::*;
+use crate::value::{self, Value};
+use crate::Engine;
+
+/// Most calls a call evaluated ahead of time may make.
+pub const BUDGET: u64 = 100_000;
+
+struct Specializer {
+    /// Names bound around the term being specialized, innermost last,
+    /// with the value of those bound by `let` to a literal or a function.
+    /// The values of the others are only known once the program runs.
+    scope: Vec<(Symbol, Option<Rc<Term>>)>,
+    config: InterpreterConfig,
+}
+
+/// Whether `term` is a literal, a tuple of literals or a function, whose
+/// value does not depend on when it is evaluated.
+fn is_known(term: &Term) -> bool {
+    match term {
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Function { .. } => true,
+        Term::Tuple { first, second, .. } => is_literal(first) && is_literal(second),
+        _ => false,
+    }
+}
+
+fn is_literal(term: &Term) -> bool {
+    !matches!(term, Term::Function { .. }) && is_known(term)
+}
+
+/// The term that evaluates to `value`, if there is one.
+fn literal(value: &Value, location: &Location) -> Option<Rc<Term>> {
+    let location = location.clone();
+    let term = match value {
+        Value::Number(value) => Term::Int {
+            value: *value,
+            location,
+        },
+        Value::String(value) => Term::Str {
+            value: value.to_string(),
+            location,
+        },
+        Value::Boolean(value) => Term::Bool {
+            value: *value,
+            location,
+        },
+        Value::Tuple(pair) => Term::Tuple {
+            first: literal(&pair.0, &location)?,
+            second: literal(&pair.1, &location)?,
+            location,
+        },
+        _ => return None,
+    };
+    Some(Rc::new(term))
+}
+
+/// The value of a literal that is an integer, a string or a boolean.
+fn scalar(term: &Term) -> Option<Value> {
+    match term {
+        Term::Int { value, .. } => Some(Value::Number(*value)),
+        Term::Str { value, .. } => Some(Value::string(value.as_str())),
+        Term::Bool { value, .. } => Some(Value::Boolean(*value)),
+        _ => None,
+    }
+}
+
+/// The literal `lhs op rhs` evaluates to with `config`, when both are
+/// literals and it does not fail.
+fn fold(
+    lhs: &Term,
+    op: &BinaryOp,
+    rhs: &Term,
+    config: InterpreterConfig,
+    location: &Location,
+) -> Option<Rc<Term>> {
+    let (lhs, rhs) = (scalar(lhs)?, scalar(rhs)?);
+    let value = value::interpret_binary(&lhs, &rhs, op, config, location).ok()?;
+    literal(&value, location)
+}
+
+impl Specializer {
+    fn bound(&mut self, names: impl IntoIterator<Item = Symbol>, term: &mut Rc<Term>) {
+        let depth = self.scope.len();
+        self.scope
+            .extend(names.into_iter().map(|name| (name, None)));
+        self.specialize(term);
+        self.scope.truncate(depth);
+    }
+
+    /// The value of `call`, found by running it with the definitions it
+    /// uses, when that is possible ahead of time.
+    fn evaluate(&self, call: &Rc<Term>) -> Option<Rc<Term>> {
+        let mut needed = vec![false; self.scope.len()];
+        let mut pending: Vec<(Symbol, usize)> = call
+            .free_names()
+            .into_iter()
+            .map(|name| (name, self.scope.len()))
+            .collect();
+        while let Some((name, before)) = pending.pop() {
+            // Names bound nowhere are builtins, or fail when run.
+            let Some(index) = self.scope[..before].iter().rposition(|(n, _)| *n == name) else {
+                if builtins::EFFECTS.contains(&name.as_str()) {
+                    return None;
+                }
+                continue;
+            };
+            let value = self.scope[index].1.as_ref()?;
+            if !needed[index] {
+                needed[index] = true;
+                // A function bound by `let` sees its own name.
+                let names = value.free_names().into_iter();
+                pending.extend(names.map(|name| (name, index + 1)));
+            }
+        }
+
+        let mut expression = call.clone();
+        for (index, (name, value)) in self.scope.iter().enumerate().rev() {
+            let Some(value) = value.as_ref().filter(|_| needed[index]) else {
+                continue;
+            };
+            let location = value.location().clone();
+            expression = Rc::new(Term::Let {
+                name: Parameter {
+                    text: *name,
+                    location: location.clone(),
+                },
+                value: value.clone(),
+                next: expression,
+                location,
+            });
+        }
+        let file = File {
+            name: String::new(),
+            expression,
+            location: call.location().clone(),
+        };
+        let mut lines = Vec::new();
+        let fuel = Fuel::limited(BUDGET);
+        let value = Engine::Tree
+            .run(&file, true, self.config, fuel, &mut lines)
+            .ok()?;
+        if !lines.is_empty() {
+            return None;
+        }
+        literal(&value, call.location())
+    }
+
+    fn bindings(&mut self, term: &mut Rc<Term>) {
+        let depth = self.scope.len();
+        let rest = Term::let_chain_mut(term, |binding| match binding {
+            Term::Let { name, value, .. } => {
+                match Rc::make_mut(value) {
+                    // A function sees the name it is bound to.
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => {
+                        let mut names = vec![name.text];
+                        names.extend(parameters.iter().map(|p| p.text));
+                        self.bound(names, body);
+                    }
+                    _ => self.specialize(value),
+                }
+                let known = is_known(value).then(|| value.clone());
+                self.scope.push((name.text, known));
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                ..
+            } => {
+                self.specialize(value);
+                self.scope.extend([(first.text, None), (second.text, None)]);
+            }
+            _ => unreachable!(),
+        });
+        self.specialize(rest);
+        self.scope.truncate(depth);
+    }
+
+    fn specialize(&mut self, term: &mut Rc<Term>) {
+        if term.next().is_some() {
+            return self.bindings(term);
+        }
+        match Rc::make_mut(term) {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => {}
+            Term::Let { .. } | Term::LetTuple { .. } => unreachable!("specialized above"),
+            Term::Function {
+                parameters, value, ..
+            } => {
+                let names: Vec<_> = parameters.iter().map(|p| p.text).collect();
+                self.bound(names, value);
+            }
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.specialize(condition);
+                self.specialize(then);
+                self.specialize(otherwise);
+            }
+            Term::Binary { lhs, rhs, .. } => {
+                self.specialize(lhs);
+                self.specialize(rhs);
+            }
+            Term::Call {
+                callee, arguments, ..
+            } => {
+                self.specialize(callee);
+                for argument in arguments {
+                    self.specialize(argument);
+                }
+            }
+            Term::Tuple { first, second, .. } => {
+                self.specialize(first);
+                self.specialize(second);
+            }
+            Term::Print { values, .. } => {
+                for value in values {
+                    self.specialize(value);
+                }
+            }
+            Term::First { value, .. }
+            | Term::Second { value, .. }
+            | Term::Field { value, .. }
+            | Term::Ref { value, .. }
+            | Term::Deref { value, .. } => self.specialize(value),
+            Term::Match { value, arms, .. } => {
+                self.specialize(value);
+                for arm in arms {
+                    self.bound(arm.pattern.names(), &mut arm.value);
+                }
+            }
+            Term::Record { fields, .. } => {
+                for field in fields {
+                    self.specialize(&mut field.value);
+                }
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                self.specialize(reference);
+                self.specialize(value);
+            }
+        }
+        let value = match term.as_ref() {
+            Term::Call { .. } => self.evaluate(term),
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => fold(lhs, op, rhs, self.config, location),
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => match condition.as_ref() {
+                Term::Bool { value: true, .. } => Some(then.clone()),
+                Term::Bool { value: false, .. } => Some(otherwise.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(value) = value {
+            *term = value;
+        }
+    }
+}
+
+/// Replaces the calls of `file` that can be evaluated ahead of time with
+/// their values, as they evaluate with `config` in [`Engine::run`].
+pub fn specialize(file: &File, config: InterpreterConfig) -> File {
+    let mut specializer = Specializer {
+        scope: Vec::new(),
+        config,
+    };
+    let mut expression = file.expression.clone();
+    specializer.specialize(&mut expression);
+    File {
+        expression,
+        ..file.clone()
+    }
+}
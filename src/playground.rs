@@ -0,0 +1,132 @@
+//! The interpreter as a WebAssembly module for browsers, for the page in
+//! `playground/`.
+//!
+//! Built with
+//!
+//! ```text
+//! wasm-pack build --target web --out-dir playground/pkg -- --features playground
+//! ```
+//!
+//! the module exports [`run_source`], which runs a program the way `rinha
+//! <file>` does and hands back what it printed and how it ended, so
+//! programs run entirely on the page.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{self, SourceMap};
+use crate::output::{self, Output};
+use crate::{gc, optimizer, parser, random, validate, Engine, Fuel, InterpreterConfig};
+
+const FILENAME: &str = "playground.rinha";
+
+#[wasm_bindgen]
+extern "C" {
+    /// Any JavaScript object with a `print(text)` method, which is given
+    /// each line a program prints as soon as it prints it.
+    pub type Sink;
+
+    #[wasm_bindgen(method)]
+    fn print(this: &Sink, text: &str);
+
+    #[wasm_bindgen(js_namespace = Math, js_name = random)]
+    fn math_random() -> f64;
+}
+
+/// How a run of [`run_source`] went.
+#[wasm_bindgen(getter_with_clone)]
+pub struct Run {
+    /// Every line the program printed, each ending in a line break.
+    pub stdout: String,
+    /// The value of the program as `print` shows it, unless it failed.
+    pub result: Option<String>,
+    /// The diagnostics of what stopped the program, if anything did.
+    pub error: Option<String>,
+}
+
+/// Passes each line on to a JavaScript sink.
+struct JsSink<'a>(&'a Sink);
+
+impl Output for JsSink<'_> {
+    fn print(&mut self, text: &str) {
+        self.0.print(text);
+    }
+}
+
+/// Collects the printed lines, also passing them on to the sink, if any.
+struct Lines<'a> {
+    lines: Vec<String>,
+    sink: Option<&'a mut dyn Output>,
+}
+
+impl Output for Lines<'_> {
+    fn print(&mut self, text: &str) {
+        if let Some(sink) = self.sink.as_mut() {
+            sink.print(text);
+        }
+        self.lines.push(text.to_string());
+    }
+}
+
+/// Runs the program in `source`, sending what it prints to `output` too
+/// when one is given.
+#[wasm_bindgen]
+pub fn run_source(source: &str, output: Option<Sink>) -> Run {
+    random::seed((math_random() * u64::MAX as f64) as u64);
+    match &output {
+        Some(sink) => run_program(source, Some(&mut JsSink(sink))),
+        None => run_program(source, None),
+    }
+}
+
+/// What [`run_source`] does once `random` is seeded, with any sink of
+/// output.
+pub fn run_program(source: &str, output: Option<&mut dyn Output>) -> Run {
+    let mut sources = SourceMap::new();
+    sources.add(FILENAME, source);
+    let mut lines = Lines {
+        lines: Vec::new(),
+        sink: output,
+    };
+    let result = run(source, &sources, &mut lines);
+    // Cycles the program left behind would otherwise pile up for as long
+    // as the page is open.
+    gc::collect();
+    let stdout = output::joined(&lines.lines);
+    match result {
+        Ok(value) => Run {
+            stdout,
+            result: Some(value),
+            error: None,
+        },
+        Err(error) => Run {
+            stdout,
+            result: None,
+            error: Some(error),
+        },
+    }
+}
+
+fn run(source: &str, sources: &SourceMap, output: &mut dyn Output) -> Result<String, String> {
+    let file = parser::parse(source, FILENAME)
+        .map_err(|err| error::diagnostic(sources, &err.message, &err.location, false))?;
+    let violations = validate::validate(&file, InterpreterConfig::STRICT);
+    if !violations.is_empty() {
+        return Err(violations
+            .iter()
+            .map(|violation| {
+                error::diagnostic(sources, &violation.message, &violation.location, false)
+            })
+            .collect());
+    }
+    let file = optimizer::optimize(&file);
+    let value = Engine::Tree
+        .run(
+            &file,
+            true,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            output,
+        )
+        .map_err(|err| error::diagnostic(sources, &err, err.location(), false))?;
+    Ok(value.to_string())
+}
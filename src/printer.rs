@@ -0,0 +1,319 @@
+use std::rc::Rc;
+
+use crate::types::*;
+
+const INDENT: &str = "  ";
+
+/// Lines longer than this are wrapped where possible.
+const WIDTH: usize = 80;
+
+/// Precedence of the operands that never need parentheses.
+const ATOM: u8 = u8::MAX;
+
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[line_start..].chars().count()
+    }
+
+    fn block(&mut self, term: &Term) {
+        self.out.push('{');
+        self.indent += 1;
+        self.newline();
+        self.term(term);
+        self.indent -= 1;
+        self.newline();
+        self.out.push('}');
+    }
+
+    fn string(&mut self, value: &str) {
+        self.out.push('"');
+        for c in value.chars() {
+            match c {
+                '\n' => self.out.push_str("\\n"),
+                '\t' => self.out.push_str("\\t"),
+                '\r' => self.out.push_str("\\r"),
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard { .. } => self.out.push('_'),
+            Pattern::Var { text, .. } => self.out.push_str(text.as_str()),
+            Pattern::Int { value, .. } => self.out.push_str(&value.to_string()),
+            Pattern::Str { value, .. } => self.string(value),
+            Pattern::Bool { value, .. } => self.out.push_str(&value.to_string()),
+            Pattern::Tuple { first, second, .. } => {
+                self.out.push('(');
+                self.pattern(first);
+                self.out.push_str(", ");
+                self.pattern(second);
+                self.out.push(')');
+            }
+        }
+    }
+
+    /// Prints `term` where an operand binding at least as tightly as
+    /// `precedence` is expected, adding parentheses when it does not.
+    fn operand(&mut self, term: &Term, precedence: u8) {
+        let binds = match term {
+            Term::Binary { op, .. } => op.precedence(),
+            // The body of a `let` and the value of an assignment extend as
+            // far as possible.
+            Term::Let { .. } | Term::LetTuple { .. } | Term::Assign { .. } => 0,
+            // The literal `i64::MIN` has no positive counterpart to negate.
+            Term::Int {
+                value: i64::MIN, ..
+            } => 0,
+            _ => ATOM,
+        };
+        if binds >= precedence {
+            self.term(term);
+        } else if let Term::Let { .. } | Term::LetTuple { .. } = term {
+            self.out.push('(');
+            self.indent += 1;
+            self.newline();
+            self.term(term);
+            self.indent -= 1;
+            self.newline();
+            self.out.push(')');
+        } else {
+            self.parenthesized(term);
+        }
+    }
+
+    fn parenthesized(&mut self, term: &Term) {
+        self.out.push('(');
+        self.term(term);
+        self.out.push(')');
+    }
+
+    fn builtin(&mut self, name: &str, value: &Term) {
+        self.out.push_str(name);
+        self.out.push('(');
+        self.term(value);
+        self.out.push(')');
+    }
+
+    /// Prints the arguments of a call on one line, or one per line when
+    /// that line would be too long. Arguments that already span several
+    /// lines, like functions, are left as they are.
+    fn arguments(&mut self, arguments: &[Rc<Term>]) {
+        let start = self.out.len();
+        self.out.push('(');
+        for (i, argument) in arguments.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.term(argument);
+        }
+        self.out.push(')');
+        if arguments.is_empty() || self.out[start..].contains('\n') || self.column() <= WIDTH {
+            return;
+        }
+
+        self.out.truncate(start);
+        self.out.push('(');
+        self.indent += 1;
+        for (i, argument) in arguments.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.newline();
+            self.term(argument);
+        }
+        self.indent -= 1;
+        self.newline();
+        self.out.push(')');
+    }
+
+    fn term(&mut self, term: &Term) {
+        let mut chain = term.let_chain();
+        for binding in &mut chain {
+            let value = match binding {
+                Term::Let { name, value, .. } => {
+                    self.out.push_str(&format!("let {} = ", name.text));
+                    value
+                }
+                Term::LetTuple {
+                    first,
+                    second,
+                    value,
+                    ..
+                } => {
+                    self.out
+                        .push_str(&format!("let ({}, {}) = ", first.text, second.text));
+                    value
+                }
+                _ => unreachable!(),
+            };
+            match value.as_ref() {
+                Term::Assign { .. } => self.term(value),
+                _ => self.operand(value, 1),
+            }
+            self.out.push(';');
+            self.newline();
+        }
+        let term = chain.rest();
+        match term {
+            Term::Int {
+                value: i64::MIN, ..
+            } => {
+                self.out.push_str(&format!("{} - 1", i64::MIN + 1));
+            }
+            Term::Int { value, .. } => self.out.push_str(&value.to_string()),
+            Term::Str { value, .. } => self.string(value),
+            Term::Bool { value, .. } => self.out.push_str(&value.to_string()),
+            Term::Var { text, .. } => self.out.push_str(text.as_str()),
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.out.push_str("if (");
+                self.term(condition);
+                self.out.push_str(") ");
+                self.block(then);
+                self.out.push_str(" else ");
+                match otherwise.as_ref() {
+                    Term::If { .. } => self.term(otherwise),
+                    _ => self.block(otherwise),
+                }
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => unreachable!("printed above"),
+            Term::Binary { lhs, op, rhs, .. } => {
+                let precedence = op.precedence();
+                self.operand(lhs, precedence);
+                self.out.push_str(&format!(" {} ", op));
+                self.operand(rhs, precedence + 1);
+            }
+            Term::Call {
+                callee, arguments, ..
+            } => {
+                match callee.as_ref() {
+                    // Legal without parentheses, but hard to read.
+                    Term::Function { .. } => self.parenthesized(callee),
+                    _ => self.operand(callee, ATOM),
+                }
+                self.arguments(arguments);
+            }
+            Term::Function {
+                parameters, value, ..
+            } => {
+                self.out.push_str("fn (");
+                for (i, parameter) in parameters.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.out.push_str(parameter.text.as_str());
+                }
+                self.out.push_str(") => ");
+                self.block(value);
+            }
+            Term::Print { values, .. } => match values.as_slice() {
+                [value] => self.builtin("print", value),
+                values => {
+                    self.out.push_str("print");
+                    self.arguments(values);
+                }
+            },
+            Term::First { value, .. } => self.builtin("first", value),
+            Term::Second { value, .. } => self.builtin("second", value),
+            Term::Tuple { first, second, .. } => {
+                self.out.push('(');
+                self.term(first);
+                self.out.push_str(", ");
+                self.term(second);
+                self.out.push(')');
+            }
+            Term::Record { fields, .. } => {
+                self.out.push('{');
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.out.push_str(field.name.text.as_str());
+                    self.out.push_str(": ");
+                    self.term(&field.value);
+                }
+                self.out.push('}');
+            }
+            Term::Field { value, name, .. } => {
+                match value.as_ref() {
+                    Term::Function { .. } => self.parenthesized(value),
+                    _ => self.operand(value, ATOM),
+                }
+                self.out.push('.');
+                self.out.push_str(name.text.as_str());
+            }
+            Term::Ref { value, .. } => self.builtin("ref", value),
+            Term::Deref { value, .. } => {
+                self.out.push('!');
+                match value.as_ref() {
+                    // `!` applies before calls and field accesses.
+                    Term::Function { .. } | Term::Call { .. } | Term::Field { .. } => {
+                        self.parenthesized(value)
+                    }
+                    _ => self.operand(value, ATOM),
+                }
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                self.operand(reference, 1);
+                self.out.push_str(" := ");
+                self.term(value);
+            }
+            Term::Match { value, arms, .. } => {
+                self.out.push_str("match (");
+                self.term(value);
+                self.out.push_str(") {");
+                self.indent += 1;
+                for arm in arms {
+                    self.newline();
+                    self.pattern(&arm.pattern);
+                    self.out.push_str(" => ");
+                    match arm.value.as_ref() {
+                        Term::Let { .. } | Term::LetTuple { .. } => self.block(&arm.value),
+                        _ => self.term(&arm.value),
+                    }
+                    self.out.push(',');
+                }
+                self.indent -= 1;
+                self.newline();
+                self.out.push('}');
+            }
+        }
+    }
+}
+
+impl Term {
+    /// Renders the term as `.rinha` source code that parses back to the
+    /// same tree.
+    pub fn to_source(&self) -> String {
+        let mut printer = Printer {
+            out: String::new(),
+            indent: 0,
+        };
+        printer.term(self);
+        printer.out
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::symbol::Symbol;
+use crate::types::{Location, Term};
+
+/// Calls to one function, identified by the address of its body.
+#[derive(Default)]
+struct Stats {
+    calls: u64,
+    time: Duration,
+    /// Calls to the function still running, so recursive calls are not
+    /// timed twice.
+    active: usize,
+}
+
+/// Calls made from one stack of calls to the same function, a node of the
+/// tree of every stack seen.
+struct Call {
+    body: *const Term,
+    parent: Option<usize>,
+    children: HashMap<*const Term, usize>,
+    /// Time spent in the function itself, not in the calls it made.
+    time: Duration,
+}
+
+/// Counts the calls the tree-walker makes and how long each function takes
+/// to return, including the time spent in the functions it calls, and how
+/// long each stack of calls ran.
+#[derive(Default)]
+pub struct Profiler {
+    stats: HashMap<*const Term, Stats>,
+    calls: Vec<Call>,
+    /// The calls made from the top level of the program.
+    roots: HashMap<*const Term, usize>,
+    /// The calls still running, innermost last, each with the time spent
+    /// so far in the calls it made.
+    active: Vec<(usize, Duration)>,
+}
+
+impl Profiler {
+    pub(crate) fn enter(&mut self, body: &Term) -> Instant {
+        let stats = self.stats.entry(body).or_default();
+        stats.calls += 1;
+        stats.active += 1;
+        let call = self.call(body);
+        self.active.push((call, Duration::ZERO));
+        Instant::now()
+    }
+
+    /// The node of a call to the function with `body` from the innermost
+    /// running call. A function calling itself stays in the same node, so
+    /// deep recursion does not make deep stacks.
+    fn call(&mut self, body: *const Term) -> usize {
+        let parent = self.active.last().map(|&(call, _)| call);
+        if let Some(parent) = parent.filter(|&parent| self.calls[parent].body == body) {
+            return parent;
+        }
+        let next = self.calls.len();
+        let children = match parent {
+            Some(parent) => &mut self.calls[parent].children,
+            None => &mut self.roots,
+        };
+        let call = *children.entry(body).or_insert(next);
+        if call == next {
+            self.calls.push(Call {
+                body,
+                parent,
+                children: HashMap::new(),
+                time: Duration::ZERO,
+            });
+        }
+        call
+    }
+
+    pub(crate) fn leave(&mut self, body: *const Term, start: Instant) {
+        let elapsed = start.elapsed();
+        let stats = self
+            .stats
+            .get_mut(&body)
+            .expect("left a call never entered");
+        stats.active -= 1;
+        if stats.active == 0 {
+            stats.time += elapsed;
+        }
+        let (call, inner) = self.active.pop().expect("left a call never entered");
+        self.calls[call].time += elapsed.saturating_sub(inner);
+        if let Some((_, outer)) = self.active.last_mut() {
+            *outer += elapsed;
+        }
+    }
+
+    /// Matches the recorded calls with the functions of `program`, which
+    /// must be the term that ran, slowest first.
+    pub(crate) fn report(&self, program: &Term) -> Profile {
+        let mut entries = Vec::new();
+        let mut frames = HashMap::new();
+        collect(program, None, &mut |name, function, body| {
+            if let Some(stats) = self.stats.get(&(body as *const Term)) {
+                let location = function.location();
+                frames.insert(
+                    body as *const Term,
+                    format!(
+                        "{} at {}:{}..{}",
+                        name.map_or("<anonymous>", |name| name.as_str()),
+                        location.filename,
+                        location.start,
+                        location.end
+                    ),
+                );
+                entries.push(Entry {
+                    name,
+                    location: location.clone(),
+                    calls: stats.calls,
+                    time: stats.time,
+                });
+            }
+        });
+        entries.sort_by(|a, b| b.time.cmp(&a.time).then(b.calls.cmp(&a.calls)));
+        let mut stacks: Vec<Stack> = (0..self.calls.len())
+            .map(|call| {
+                let mut stack = Vec::new();
+                let mut next = Some(call);
+                while let Some(call) = next {
+                    let frame = frames.get(&self.calls[call].body);
+                    stack.push(frame.map_or("<unknown>".to_string(), String::clone));
+                    next = self.calls[call].parent;
+                }
+                stack.reverse();
+                Stack {
+                    frames: stack,
+                    time: self.calls[call].time,
+                }
+            })
+            .collect();
+        stacks.sort_by(|a, b| a.frames.cmp(&b.frames));
+        Profile { entries, stacks }
+    }
+}
+
+/// Calls `f` with every function in `term`, the name it is bound to by
+/// `let` and its body.
+fn collect(term: &Term, name: Option<Symbol>, f: &mut dyn FnMut(Option<Symbol>, &Term, &Term)) {
+    match term {
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => {}
+        Term::Function { value, .. } => {
+            f(name, term, value);
+            collect(value, None, f);
+        }
+        Term::Let {
+            name, value, next, ..
+        } => {
+            collect(value, Some(name.text), f);
+            collect(next, None, f);
+        }
+        Term::LetTuple { value, next, .. } => {
+            collect(value, None, f);
+            collect(next, None, f);
+        }
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => {
+            collect(condition, None, f);
+            collect(then, None, f);
+            collect(otherwise, None, f);
+        }
+        Term::Binary { lhs, rhs, .. } => {
+            collect(lhs, None, f);
+            collect(rhs, None, f);
+        }
+        Term::Call {
+            callee, arguments, ..
+        } => {
+            collect(callee, None, f);
+            for argument in arguments {
+                collect(argument, None, f);
+            }
+        }
+        Term::Tuple { first, second, .. } => {
+            collect(first, None, f);
+            collect(second, None, f);
+        }
+        Term::First { value, .. } | Term::Second { value, .. } => collect(value, None, f),
+        Term::Print { values, .. } => {
+            for value in values {
+                collect(value, None, f);
+            }
+        }
+        Term::Match { value, arms, .. } => {
+            collect(value, None, f);
+            for arm in arms {
+                collect(&arm.value, None, f);
+            }
+        }
+        Term::Record { fields, .. } => {
+            for field in fields {
+                collect(&field.value, None, f);
+            }
+        }
+        Term::Field { value, .. } | Term::Ref { value, .. } | Term::Deref { value, .. } => {
+            collect(value, None, f)
+        }
+        Term::Assign {
+            reference, value, ..
+        } => {
+            collect(reference, None, f);
+            collect(value, None, f);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    /// Name the function is bound to by `let`, if any.
+    pub name: Option<Symbol>,
+    /// Location of the function definition.
+    pub location: Location,
+    pub calls: u64,
+    /// Time from entering the outermost call to the function until it
+    /// returned, summed over all such calls.
+    pub time: Duration,
+}
+
+/// A stack of calls and the time spent in its innermost function.
+#[derive(Debug)]
+pub struct Stack {
+    /// The functions called, outermost first, each named after where it
+    /// is defined. Calls of a function to itself count as one frame.
+    pub frames: Vec<String>,
+    /// Time spent running the innermost function itself, leaving out the
+    /// calls it made.
+    pub time: Duration,
+}
+
+/// Functions that were called while profiling, slowest first.
+#[derive(Debug)]
+pub struct Profile {
+    pub entries: Vec<Entry>,
+    /// Every stack of calls that was entered, in the order of its frames.
+    pub stacks: Vec<Stack>,
+}
+
+impl Profile {
+    /// The stacks in the folded format of `inferno-flamegraph` and
+    /// speedscope: one line per stack, its frames joined by `;` and
+    /// followed by the microseconds spent in it. Stacks that took less
+    /// than a microsecond are left out.
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        for stack in &self.stacks {
+            let micros = stack.time.as_micros();
+            if micros > 0 {
+                folded.push_str(&format!("{} {}\n", stack.frames.join(";"), micros));
+            }
+        }
+        folded
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>10}  {:>12}  function", "calls", "time")?;
+        for entry in &self.entries {
+            let name = entry.name.map_or("<anonymous>", |name| name.as_str());
+            writeln!(
+                f,
+                "{:>10}  {:>12}  {} at {}:{}..{}",
+                entry.calls,
+                format!("{:.3?}", entry.time),
+                name,
+                entry.location.filename,
+                entry.location.start,
+                entry.location.end
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+//! Python bindings, for the `pyo3` feature.
+//!
+//! The library builds into the extension module `rinha`, whose `run`
+//! function runs a program and returns what it printed with its value as
+//! a Python object, for scripts that check programs without starting a
+//! process for each.
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyTuple};
+
+use crate::{output, Value};
+
+const FILENAME: &str = "<python>";
+
+/// The value as Python sees it: integers, strings and booleans as
+/// themselves, tuples as tuples of two, lists as lists and records as
+/// dicts. Closures and references, which Python cannot use, are `None`.
+fn to_python<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Number(num) => num.into_pyobject(py)?.into_any(),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(num) => py
+            .get_type::<pyo3::types::PyInt>()
+            .call1((num.to_string(),))?,
+        Value::Boolean(b) => b.into_pyobject(py)?.to_owned().into_any(),
+        Value::String(s) => s.as_str().into_pyobject(py)?.into_any(),
+        Value::Closure(_) | Value::Ref(_) => py.None().into_bound(py),
+        Value::Tuple(pair) => {
+            PyTuple::new(py, [to_python(py, &pair.0)?, to_python(py, &pair.1)?])?.into_any()
+        }
+        Value::List(list) => {
+            let elements = list
+                .iter()
+                .map(|element| to_python(py, element))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, elements)?.into_any()
+        }
+        Value::Record(fields) => {
+            let dict = PyDict::new(py);
+            for (name, value) in fields.iter() {
+                dict.set_item(name.to_string(), to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+/// Runs `program`, source or a JSON AST, and returns what it printed, each
+/// line ending in a line break, with its value. Programs that do not parse
+/// raise `ValueError`, and programs that fail `RuntimeError`.
+#[pyfunction]
+fn run(py: Python<'_>, program: &str) -> PyResult<(String, PyObject)> {
+    let file = crate::load(program, FILENAME).map_err(PyValueError::new_err)?;
+    let mut lines = Vec::new();
+    let value = crate::run_loaded(&file, &mut lines)
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+    let stdout = output::joined(&lines);
+    Ok((stdout, to_python(py, &value)?.unbind()))
+}
+
+#[pymodule]
+fn rinha(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(run, module)?)
+}
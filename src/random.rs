@@ -0,0 +1,60 @@
+//! The numbers given out by the builtin `random`, for `--seed`.
+//!
+//! They come from a SplitMix64 generator shared by the whole process. It
+//! starts from the clock, so each run differs, unless [`seed`] fixes where
+//! it starts, which makes a program give out the same numbers every run.
+//! WebAssembly has no clock to start from, so there the host has to seed
+//! it.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::RuntimeError;
+use crate::types::Location;
+use crate::value::{assert_int, Value};
+
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+#[cfg(target_arch = "wasm32")]
+static STATE: LazyLock<AtomicU64> = LazyLock::new(AtomicU64::default);
+
+#[cfg(not(target_arch = "wasm32"))]
+static STATE: LazyLock<AtomicU64> = LazyLock::new(|| {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    AtomicU64::new(now ^ u64::from(std::process::id()).rotate_left(32))
+});
+
+/// Makes the numbers given out from now on depend only on `seed`.
+pub fn seed(seed: u64) {
+    STATE.store(seed, Ordering::Relaxed);
+}
+
+fn next() -> u64 {
+    let mut z = STATE
+        .fetch_add(GAMMA, Ordering::Relaxed)
+        .wrapping_add(GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The builtin `random(n)`: an integer from 0 up to, but not including,
+/// `n`, which must be positive.
+pub(crate) fn random(arguments: &[Value], location: &Location) -> Result<Value, RuntimeError> {
+    let bound = assert_int(&arguments[0], location)?;
+    if bound <= 0 {
+        return Err(RuntimeError::Builtin {
+            name: "random".to_string(),
+            message: format!("the bound must be positive, found {}", bound),
+            location: location.clone(),
+        });
+    }
+    // Scales the 64 random bits down to the bound rather than taking the
+    // remainder, which would favor small numbers more.
+    let n = (u128::from(next()) * bound as u128) >> 64;
+    Ok(Value::Number(n as i64))
+}
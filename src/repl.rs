@@ -0,0 +1,116 @@
+use serde::Deserialize;
+use serde_json::Value as Json;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use rinha::error::{print_diagnostic, SourceMap};
+use rinha::interpreter::{Arena, Session};
+use rinha::parser::{self, Statement};
+use rinha::types::{File, Term};
+use rinha::{Fuel, InterpreterConfig};
+
+/// The name errors give the `entry`th input, counted from 1, so that
+/// they can quote it.
+fn filename(entry: usize) -> String {
+    format!("<repl:{}>", entry)
+}
+
+enum Input {
+    Statement(Statement),
+    /// The input ends in the middle of an expression; keep reading lines.
+    Incomplete,
+    Invalid,
+}
+
+fn read_json(source: &str) -> Input {
+    let json: Json = match serde_json::from_str(source) {
+        Ok(json) => json,
+        Err(err) if err.is_eof() => return Input::Incomplete,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return Input::Invalid;
+        }
+    };
+    let term = if json.get("expression").is_some() {
+        File::from_json(&json).map(|file| file.expression.as_ref().clone())
+    } else {
+        Term::deserialize(&json)
+    };
+    match term {
+        Ok(term) => Input::Statement(Statement::Expression(term)),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            Input::Invalid
+        }
+    }
+}
+
+fn read(source: &str, filename: &str, sources: &SourceMap) -> Input {
+    if source.trim_start().starts_with('{') {
+        return read_json(source);
+    }
+    match parser::parse_statement(source, filename) {
+        Ok(statement) => Input::Statement(statement),
+        // Errors reaching the end of the input mean the expression is not
+        // finished yet rather than wrong.
+        Err(err) if err.location.end >= source.trim_end().len() => Input::Incomplete,
+        Err(err) => {
+            print_diagnostic(sources, &err.message, &err.location);
+            Input::Invalid
+        }
+    }
+}
+
+fn eval(session: &mut Session, statement: Statement, sources: &SourceMap) {
+    let result = match statement {
+        Statement::Let { name, value } => session.define(name.text, &Rc::new(value)),
+        Statement::Expression(term) => session
+            .eval(&Rc::new(term))
+            .map(|value| println!("{}", value)),
+    };
+    // Runtime errors may point into any of the inputs entered so far, each
+    // kept under a name of its own.
+    if let Err(err) = result {
+        print_diagnostic(sources, &err, err.location());
+    }
+}
+
+pub fn run(memoize: bool) {
+    let mut stdout = io::stdout();
+    let arena = Arena::new();
+    let config = InterpreterConfig::STRICT;
+    let mut session = Session::new(&arena, memoize, config, Fuel::unlimited(), &mut stdout);
+    let mut lines = io::stdin().lock().lines();
+    let mut buffer = String::new();
+    let mut sources = SourceMap::new();
+    let mut entry = 1;
+
+    loop {
+        print!("{}", if buffer.is_empty() { "> " } else { "... " });
+        io::stdout()
+            .flush()
+            .expect("Falha ao escrever na saída padrão");
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        buffer.push_str(&line);
+        buffer.push('\n');
+        if buffer.trim().is_empty() {
+            buffer.clear();
+            continue;
+        }
+
+        let filename = filename(entry);
+        sources.add(&filename, &buffer);
+        match read(&buffer, &filename, &sources) {
+            Input::Incomplete => continue,
+            Input::Invalid => {}
+            Input::Statement(statement) => eval(&mut session, statement, &sources),
+        }
+        buffer.clear();
+        entry += 1;
+    }
+    println!();
+}
@@ -0,0 +1,357 @@
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+use crate::types::*;
+
+/// The bindings visible inside one function.
+#[derive(Default)]
+struct Frame {
+    /// Names bound in the function, innermost last, in the same order the
+    /// tree-walker pushes bindings onto its environment.
+    scope: Vec<Symbol>,
+    /// Bindings taken from the functions around it, which the tree-walker
+    /// keeps right below `scope`, the first one innermost.
+    captures: Vec<Capture>,
+}
+
+/// Frames of the functions being resolved, innermost last. The first one
+/// is the whole term.
+struct Resolver {
+    frames: Vec<Frame>,
+    /// Positions in the scope of the first frame of the functions that go
+    /// in the global table.
+    globals: Range<usize>,
+    /// Slot in the global table of the first of them.
+    base: usize,
+    /// Number of functions at the start of the term still to be put in
+    /// the table.
+    prefix: usize,
+}
+
+impl Resolver {
+    fn new(scope: &[Symbol]) -> Resolver {
+        let frame = Frame {
+            scope: scope.to_vec(),
+            captures: Vec::new(),
+        };
+        Resolver {
+            frames: vec![frame],
+            globals: scope.len()..scope.len(),
+            base: 0,
+            prefix: 0,
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    /// Where the innermost frame finds the binding of `name`, or `None`
+    /// when the name is not bound in the term.
+    fn index(&mut self, name: Symbol) -> Option<Slot> {
+        self.lookup(self.frames.len() - 1, name)
+    }
+
+    /// Finds `name` in the frame at `level`, capturing it from the
+    /// enclosing frames when needed. Functions in the global table are
+    /// never captured.
+    fn lookup(&mut self, level: usize, name: Symbol) -> Option<Slot> {
+        let frame = &self.frames[level];
+        if let Some(index) = frame.scope.iter().rev().position(|n| *n == name) {
+            let position = frame.scope.len() - 1 - index;
+            if level == 0 && self.globals.contains(&position) {
+                return Some(Slot::Global(self.base + position - self.globals.start));
+            }
+            return Some(Slot::Local(index));
+        }
+        let depth = frame.scope.len();
+        if let Some(position) = frame.captures.iter().position(|c| c.name == name) {
+            return Some(Slot::Local(depth + position));
+        }
+        if level == 0 {
+            return None;
+        }
+
+        let index = match self.lookup(level - 1, name)? {
+            Slot::Local(index) => index,
+            global => return Some(global),
+        };
+        let captures = &mut self.frames[level].captures;
+        captures.push(Capture { name, index });
+        Some(Slot::Local(depth + captures.len() - 1))
+    }
+
+    fn function(
+        &mut self,
+        name: Option<Symbol>,
+        parameters: &[Parameter],
+        body: &Rc<Term>,
+    ) -> (Rc<Term>, Vec<Capture>) {
+        let mut scope: Vec<Symbol> = name.into_iter().collect();
+        scope.extend(parameters.iter().map(|p| p.text));
+        self.frames.push(Frame {
+            scope,
+            captures: Vec::new(),
+        });
+        let body = self.resolve(body);
+        let frame = self.frames.pop().unwrap();
+        (body, frame.captures)
+    }
+
+    /// Resolves the value of `let name = value`, where a function sees
+    /// itself bound to `name` right outside its parameters.
+    fn let_value(&mut self, name: Symbol, value: &Rc<Term>) -> Rc<Term> {
+        match value.as_ref() {
+            Term::Function {
+                parameters,
+                value: body,
+                location,
+                ..
+            } => {
+                let (body, captures) = self.function(Some(name), parameters, body);
+                Rc::new(Term::Function {
+                    parameters: parameters.clone(),
+                    value: body,
+                    location: location.clone(),
+                    captures,
+                })
+            }
+            _ => self.resolve(value),
+        }
+    }
+
+    fn bindings(&mut self, term: &Rc<Term>) -> Rc<Term> {
+        let depth = self.frame().scope.len();
+        let mut chain = term.let_chain();
+        let mut bindings = Vec::new();
+        for binding in &mut chain {
+            let value = match binding {
+                Term::Let { name, value, .. } => {
+                    let value = self.let_value(name.text, value);
+                    // While functions at the start of the term are left,
+                    // the first frame binds nothing else.
+                    if self.prefix > 0 && self.frames.len() == 1 {
+                        self.prefix -= 1;
+                        self.globals.end += 1;
+                    }
+                    self.frame().scope.push(name.text);
+                    value
+                }
+                Term::LetTuple {
+                    first,
+                    second,
+                    value,
+                    ..
+                } => {
+                    let value = self.resolve(value);
+                    self.frame().scope.extend([first.text, second.text]);
+                    value
+                }
+                _ => unreachable!(),
+            };
+            bindings.push((binding, value));
+        }
+        let rest = chain.shared_rest().expect("a chain of `let`");
+        let mut resolved = self.resolve(rest);
+        self.frame().scope.truncate(depth);
+        for (binding, value) in bindings.into_iter().rev() {
+            resolved = Rc::new(binding.rebind(value, resolved));
+        }
+        resolved
+    }
+
+    fn resolve(&mut self, term: &Rc<Term>) -> Rc<Term> {
+        let resolved = match term.as_ref() {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } => return term.clone(),
+            Term::Var { text, location, .. } => Term::Var {
+                text: *text,
+                index: self.index(*text),
+                location: location.clone(),
+            },
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                location,
+            } => Term::If {
+                condition: self.resolve(condition),
+                then: self.resolve(then),
+                otherwise: self.resolve(otherwise),
+                location: location.clone(),
+            },
+            Term::Let { .. } | Term::LetTuple { .. } => return self.bindings(term),
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => Term::Binary {
+                lhs: self.resolve(lhs),
+                op: op.clone(),
+                rhs: self.resolve(rhs),
+                location: location.clone(),
+            },
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => Term::Call {
+                callee: self.resolve(callee),
+                arguments: arguments.iter().map(|arg| self.resolve(arg)).collect(),
+                location: location.clone(),
+                cache: CallCache::default(),
+            },
+            Term::Function {
+                parameters,
+                value,
+                location,
+                ..
+            } => {
+                let (value, captures) = self.function(None, parameters, value);
+                Term::Function {
+                    parameters: parameters.clone(),
+                    value,
+                    location: location.clone(),
+                    captures,
+                }
+            }
+            Term::First { value, location } => Term::First {
+                value: self.resolve(value),
+                location: location.clone(),
+            },
+            Term::Second { value, location } => Term::Second {
+                value: self.resolve(value),
+                location: location.clone(),
+            },
+            Term::Print { values, location } => Term::Print {
+                values: values.iter().map(|value| self.resolve(value)).collect(),
+                location: location.clone(),
+            },
+            Term::Tuple {
+                first,
+                second,
+                location,
+            } => Term::Tuple {
+                first: self.resolve(first),
+                second: self.resolve(second),
+                location: location.clone(),
+            },
+            Term::Match {
+                value,
+                arms,
+                location,
+            } => {
+                let value = self.resolve(value);
+                let arms = arms
+                    .iter()
+                    .map(|arm| {
+                        let names = arm.pattern.names();
+                        let depth = self.frame().scope.len();
+                        self.frame().scope.extend(names);
+                        let value = self.resolve(&arm.value);
+                        self.frame().scope.truncate(depth);
+                        Arm {
+                            pattern: arm.pattern.clone(),
+                            value,
+                        }
+                    })
+                    .collect();
+                Term::Match {
+                    value,
+                    arms,
+                    location: location.clone(),
+                }
+            }
+            Term::Record { fields, location } => Term::Record {
+                fields: fields
+                    .iter()
+                    .map(|field| RecordField {
+                        name: field.name.clone(),
+                        value: self.resolve(&field.value),
+                    })
+                    .collect(),
+                location: location.clone(),
+            },
+            Term::Field {
+                value,
+                name,
+                location,
+            } => Term::Field {
+                value: self.resolve(value),
+                name: name.clone(),
+                location: location.clone(),
+            },
+            Term::Ref { value, location } => Term::Ref {
+                value: self.resolve(value),
+                location: location.clone(),
+            },
+            Term::Deref { value, location } => Term::Deref {
+                value: self.resolve(value),
+                location: location.clone(),
+            },
+            Term::Assign {
+                reference,
+                value,
+                location,
+            } => Term::Assign {
+                reference: self.resolve(reference),
+                value: self.resolve(value),
+                location: location.clone(),
+            },
+        };
+        Rc::new(resolved)
+    }
+}
+
+/// Annotates every variable with its De Bruijn index, the number of
+/// bindings between its use and the binding it refers to, so the
+/// tree-walker finds it without comparing names. Variables bound outside
+/// `term` are left without an index and are looked up by name.
+///
+/// Every function also gets the list of bindings its body uses from
+/// outside it, so that closures keep only those instead of the whole
+/// environment. Indices in the body count these captures right below the
+/// bindings of the function itself.
+pub fn resolve(term: &Rc<Term>) -> Rc<Term> {
+    resolve_in(&[], term)
+}
+
+/// Like [`resolve`], for a term evaluated where the names of `scope` are
+/// already bound, innermost last.
+pub fn resolve_in(scope: &[Symbol], term: &Rc<Term>) -> Rc<Term> {
+    Resolver::new(scope).resolve(term)
+}
+
+/// Like [`resolve`], but the functions bound by the `let` that start
+/// `term`, as most programs define theirs, are referred to by their slot
+/// in a table, starting at `base`, instead of being captured. Closures
+/// then keep only what they use besides them. The tree-walker fills the
+/// table as it binds them, see [`defines_function`].
+pub fn resolve_program(term: &Rc<Term>, base: usize) -> Rc<Term> {
+    let mut resolver = Resolver::new(&[]);
+    resolver.base = base;
+    let mut binding = term.as_ref();
+    while defines_function(binding) {
+        resolver.prefix += 1;
+        binding = binding.next().unwrap();
+    }
+    resolver.resolve(term)
+}
+
+/// Whether `term` is a `let` binding a function, which goes in the global
+/// table when every binding before it does too.
+pub fn defines_function(term: &Term) -> bool {
+    matches!(term, Term::Let { value, .. } if matches!(value.as_ref(), Term::Function { .. }))
+}
+
+/// Resolves a value bound to `name` outside of any term, as the REPL does.
+pub fn resolve_let(name: Symbol, value: &Rc<Term>) -> Rc<Term> {
+    resolve_let_in(&[], name, value)
+}
+
+/// Like [`resolve_let`], where the names of `scope` are already bound.
+pub fn resolve_let_in(scope: &[Symbol], name: Symbol, value: &Rc<Term>) -> Rc<Term> {
+    Resolver::new(scope).let_value(name, value)
+}
@@ -0,0 +1,64 @@
+//! What running a program cost, for `--report`.
+//!
+//! The judge ranks submissions that print the same output by how much
+//! they spent getting there, so the report is a single JSON object that
+//! scripts can read from the last line of stderr.
+
+use std::fs;
+use std::time::Duration;
+
+use serde_json::{json, Value as Json};
+
+use crate::stats;
+
+/// The resources a program used, measured once it stops.
+#[derive(Clone, Copy, Debug)]
+pub struct Resources {
+    pub wall: Duration,
+    /// Most memory the process held at once, in kilobytes, where the
+    /// system tells.
+    pub peak_rss_kb: Option<u64>,
+    pub allocations: u64,
+    /// Function calls made, as `--max-steps` counts them.
+    pub steps: u64,
+    /// Most calls active at once.
+    pub max_depth: u64,
+}
+
+impl Resources {
+    /// Measures the program that has just run for `wall`, from the counters
+    /// of [`stats`], which must have been reset before it started.
+    pub fn measure(wall: Duration) -> Resources {
+        let stats = stats::snapshot();
+        Resources {
+            wall,
+            peak_rss_kb: peak_rss_kb(),
+            allocations: stats.allocations,
+            steps: stats.calls,
+            max_depth: stats.peak_depth,
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        json!({
+            "wall_ms": self.wall.as_millis() as u64,
+            "peak_rss_kb": self.peak_rss_kb,
+            "allocations": self.allocations,
+            "steps": self.steps,
+            "max_depth": self.max_depth,
+        })
+    }
+}
+
+/// The high-water mark of the resident set of the process, which Linux
+/// keeps in `/proc/self/status`.
+fn peak_rss_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line["VmHWM:".len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
@@ -0,0 +1,121 @@
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+
+use base64::Engine as _;
+use serde_json::Value as Json;
+
+use rinha::types::File;
+use rinha::{gc, optimizer, output, validate, Engine, Fuel, InterpreterConfig, Value};
+
+const FILENAME: &str = "<serve>";
+
+/// The object `--json-result` and `rinha serve` describe a run with.
+/// `result` is the value of the program as `print` shows it, or the
+/// message of the error that stopped it, and `value` the same value as
+/// JSON, or `null` after an error.
+pub fn result_json(lines: &[String], result: Result<&Value, String>, elapsed: Duration) -> Json {
+    let stdout = output::joined(lines);
+    let (status, result, value) = match result {
+        Ok(value) => ("ok", value.to_string(), value.to_json()),
+        Err(message) => ("error", message, Json::Null),
+    };
+    serde_json::json!({
+        "stdout": stdout,
+        "result": result,
+        "value": value,
+        "elapsed_ms": elapsed.as_millis() as u64,
+        "status": status,
+    })
+}
+
+/// Reads a program from one line of input: a JSON AST document, or
+/// source encoded in base64.
+fn read_program(line: &str, config: InterpreterConfig) -> Result<File, String> {
+    let file = if line.starts_with('{') {
+        rinha::parse_json(line).map_err(|err| format!("invalid JSON AST: {}", err))?
+    } else {
+        parse_base64(line)?
+    };
+    check_valid(&file, config)?;
+    Ok(file)
+}
+
+fn parse_base64(line: &str) -> Result<File, String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(line)
+        .map_err(|err| format!("invalid base64: {}", err))?;
+    let source = String::from_utf8(bytes).map_err(|_| "source is not UTF-8".to_string())?;
+    rinha::parse(&source, FILENAME).map_err(|err| err.message)
+}
+
+/// Checks `file` with [`validate`] before it runs with `config`, as a
+/// normal run does.
+/// The message holds every violation, one per line, each after the file
+/// and offset it is found at, as the errors of [`rinha::load`] are.
+pub fn check_valid(file: &File, config: InterpreterConfig) -> Result<(), String> {
+    let violations = validate::validate(file, config);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let messages: Vec<_> = violations
+        .iter()
+        .map(|violation| {
+            format!(
+                "{}:{}: {}",
+                violation.location.filename, violation.location.start, violation.message
+            )
+        })
+        .collect();
+    Err(messages.join("\n"))
+}
+
+/// Optimizes and runs `file` like a normal run, collecting what it prints.
+pub fn run_file(
+    file: &File,
+    engine: Engine,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+) -> Json {
+    let file = optimizer::optimize(file);
+    let mut lines = Vec::new();
+    let start = Instant::now();
+    let result = engine.run(&file, memoize, config, fuel, &mut lines);
+    let elapsed = start.elapsed();
+    let json = result_json(
+        &lines,
+        result.as_ref().map_err(|err| err.to_string()),
+        elapsed,
+    );
+    drop(result);
+    // Cycles the program left behind would otherwise pile up for as long
+    // as the server runs.
+    gc::collect();
+    json
+}
+
+fn serve(line: &str, engine: Engine, memoize: bool, config: InterpreterConfig, fuel: Fuel) -> Json {
+    match read_program(line, config) {
+        Ok(file) => run_file(&file, engine, memoize, config, fuel),
+        Err(message) => result_json(&[], Err(message), Duration::ZERO),
+    }
+}
+
+/// Answers each line of stdin with the result of the program on it, until
+/// stdin closes. Every program starts from a fresh session, so nothing one
+/// of them defines or memoizes is seen by the next.
+pub fn run(engine: Engine, memoize: bool, config: InterpreterConfig, max_steps: Option<u64>) {
+    let fuel = max_steps.map_or(Fuel::unlimited(), Fuel::limited);
+    let mut stdout = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("Falha ao ler a entrada padrão");
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let response = serve(line, engine, memoize, config, fuel);
+        writeln!(stdout, "{}", response)
+            .and_then(|()| stdout.flush())
+            .expect("Falha ao escrever na saída padrão");
+    }
+}
@@ -0,0 +1,93 @@
+use std::cell::Cell;
+use std::fmt;
+
+/// Counts of the work both engines do, for comparing them and measuring
+/// optimizations. The counters are global to the thread and always on;
+/// call [`reset`] before running a program and [`snapshot`] after.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// Strings, tuples and closures created by the program.
+    pub allocations: u64,
+    /// Copies of a tree-walker environment. The VM keeps locals on its
+    /// stack and never copies environments.
+    pub env_clones: u64,
+    /// Closures alive right now.
+    pub live_closures: u64,
+    /// Most closures alive at the same time.
+    pub peak_closures: u64,
+    /// Function calls made, counted like `--max-steps` counts them.
+    pub calls: u64,
+    /// Most calls active at the same time.
+    pub peak_depth: u64,
+}
+
+const ZERO: Stats = Stats {
+    allocations: 0,
+    env_clones: 0,
+    live_closures: 0,
+    peak_closures: 0,
+    calls: 0,
+    peak_depth: 0,
+};
+
+thread_local! {
+    static STATS: Cell<Stats> = const { Cell::new(ZERO) };
+}
+
+fn update(f: impl FnOnce(&mut Stats)) {
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        f(&mut current);
+        stats.set(current);
+    });
+}
+
+pub(crate) fn allocation() {
+    update(|stats| stats.allocations += 1);
+}
+
+pub(crate) fn env_clone() {
+    update(|stats| stats.env_clones += 1);
+}
+
+/// Records a new closure, which is also an allocation.
+pub(crate) fn closure_created() {
+    update(|stats| {
+        stats.allocations += 1;
+        stats.live_closures += 1;
+        stats.peak_closures = stats.peak_closures.max(stats.live_closures);
+    });
+}
+
+pub(crate) fn closure_dropped() {
+    update(|stats| stats.live_closures = stats.live_closures.saturating_sub(1));
+}
+
+pub(crate) fn call() {
+    update(|stats| stats.calls += 1);
+}
+
+/// Records that `calls` calls are active at once.
+pub(crate) fn depth(calls: usize) {
+    update(|stats| stats.peak_depth = stats.peak_depth.max(calls as u64));
+}
+
+/// Starts counting from zero. Closures still alive from an earlier run are
+/// forgotten; dropping them later is not counted against the new run.
+pub fn reset() {
+    STATS.with(|stats| stats.set(ZERO));
+}
+
+pub fn snapshot() -> Stats {
+    STATS.with(Cell::get)
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "allocations:   {}", self.allocations)?;
+        writeln!(f, "env clones:    {}", self.env_clones)?;
+        writeln!(f, "peak closures: {}", self.peak_closures)?;
+        writeln!(f, "calls:         {}", self.calls)?;
+        writeln!(f, "peak depth:    {}", self.peak_depth)
+    }
+}
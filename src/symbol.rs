@@ -0,0 +1,91 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// An interned name. Names are interned once when the AST is loaded, so
+/// comparing them at runtime is an integer comparison.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+thread_local! {
+    // Names are leaked so they can be handed out as `&'static str`; a
+    // program only has as many distinct names as its source spells out.
+    static INTERNER: RefCell<Interner> = RefCell::default();
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(symbol) = interner.symbols.get(name) {
+                return *symbol;
+            }
+            let symbol = Symbol(interner.names.len() as u32);
+            let name: &'static str = Box::leak(name.into());
+            interner.names.push(name);
+            interner.symbols.insert(name, symbol);
+            symbol
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+        String::deserialize(deserializer).map(|name| Symbol::intern(&name))
+    }
+}
+
+/// Makes up names for the bindings a pass adds to a program. They are
+/// spelled like any other name, so the program can still be printed as
+/// source and compiled to C, but are kept apart from every name `taken`.
+pub struct Fresh {
+    taken: HashSet<Symbol>,
+    count: usize,
+}
+
+impl Fresh {
+    pub fn new(taken: HashSet<Symbol>) -> Fresh {
+        Fresh { taken, count: 0 }
+    }
+
+    /// A name used nowhere else, made from `base`: `__base_1`,
+    /// `__base_2`, and so on.
+    pub fn name(&mut self, base: &str) -> Symbol {
+        loop {
+            self.count += 1;
+            let name = Symbol::intern(&format!("__{}_{}", base, self.count));
+            if self.taken.insert(name) {
+                return name;
+            }
+        }
+    }
+}
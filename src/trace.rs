@@ -0,0 +1,36 @@
+use std::io::Write;
+
+use crate::types::Term;
+use crate::value::Value;
+
+/// Writes a line for every term the tree-walker evaluates, once its value
+/// is known. Lines are indented by the number of calls active when the
+/// term started, so the body of a call sits below the call itself.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    /// Number of calls entered and not yet returned.
+    pub(crate) depth: usize,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Tracer {
+        Tracer { out, depth: 0 }
+    }
+
+    pub(crate) fn record(&mut self, term: &Term, value: &Value, depth: usize) {
+        let location = term.location();
+        // A trace that cannot be written is not worth stopping the program.
+        let _ = writeln!(
+            self.out,
+            "{:indent$}[{}] {} {}:{}..{} => {}",
+            "",
+            depth,
+            term.kind(),
+            location.filename,
+            location.start,
+            location.end,
+            value,
+            indent = depth * 2
+        );
+    }
+}
@@ -0,0 +1,795 @@
+//! Hindley–Milner type inference.
+//!
+//! Programs are checked before they run, so that most of the errors the
+//! interpreter would find (calling an integer, adding a boolean, passing
+//! the wrong number of arguments) are reported without executing anything.
+//! Bindings made with `let` are generalized, so a function like
+//! `fn (x) => x` can be used at several types.
+//!
+//! Rinha's `+` works on integers and strings alike. Its operands are
+//! resolved once the types around it are known. An addition whose types
+//! are still unknown when the enclosing `let` is generalized stays with
+//! the scheme, so `fn (x) => x + 1` adds integers or joins strings
+//! depending on what each use passes it; one on types that nothing else
+//! refers to is taken to be on integers. Equality requires both sides to have the same type,
+//! which rejects comparisons the interpreter would answer with `false`.
+//!
+//! The type variables in what a reference is created with are never
+//! generalized, so a reference cannot be used at several types: after
+//! `let r = ref(fn (x) => x)`, storing a function on integers in `r` makes
+//! it one for good.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::symbol::Symbol;
+use crate::types::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Int,
+    Bool,
+    Str,
+    Tuple(Box<Type>, Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    /// Fields sorted by name.
+    Record(Vec<(Symbol, Type)>),
+    Ref(Box<Type>),
+    Var(usize),
+}
+
+impl Type {
+    fn vars(&self, vars: &mut Vec<usize>) {
+        match self {
+            Type::Int | Type::Bool | Type::Str => {}
+            Type::Tuple(first, second) => {
+                first.vars(vars);
+                second.vars(vars);
+            }
+            Type::Function(parameters, result) => {
+                for parameter in parameters {
+                    parameter.vars(vars);
+                }
+                result.vars(vars);
+            }
+            Type::Record(fields) => {
+                for (_, ty) in fields {
+                    ty.vars(vars);
+                }
+            }
+            Type::Ref(ty) => ty.vars(vars),
+            Type::Var(var) => {
+                if !vars.contains(var) {
+                    vars.push(*var);
+                }
+            }
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, names: &[usize]) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "string"),
+            Type::Tuple(first, second) => {
+                write!(f, "(")?;
+                first.write(f, names)?;
+                write!(f, ", ")?;
+                second.write(f, names)?;
+                write!(f, ")")
+            }
+            Type::Function(parameters, result) => {
+                write!(f, "fn (")?;
+                for (i, parameter) in parameters.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    parameter.write(f, names)?;
+                }
+                write!(f, ") => ")?;
+                result.write(f, names)
+            }
+            Type::Record(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, ty)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", name)?;
+                    ty.write(f, names)?;
+                }
+                write!(f, "}}")
+            }
+            Type::Ref(ty) => {
+                write!(f, "ref(")?;
+                ty.write(f, names)?;
+                write!(f, ")")
+            }
+            Type::Var(var) => {
+                let index = names.iter().position(|v| v == var).unwrap();
+                let letter = (b'a' + (index % 26) as u8) as char;
+                match index / 26 {
+                    0 => write!(f, "'{}", letter),
+                    n => write!(f, "'{}{}", letter, n),
+                }
+            }
+        }
+    }
+}
+
+/// Type variables are named `'a`, `'b`, ... in order of appearance.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names = Vec::new();
+        self.vars(&mut names);
+        self.write(f, &names)
+    }
+}
+
+#[derive(Debug)]
+pub struct TypeError {
+    pub message: String,
+    pub location: Location,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A type with variables that are instantiated afresh at each use.
+#[derive(Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+    /// The additions on `vars` still to be resolved, anew at each use.
+    additions: Vec<Addition>,
+}
+
+/// A pending `lhs + rhs`, resolved once the operand types are known.
+#[derive(Clone)]
+struct Addition {
+    lhs: Type,
+    rhs: Type,
+    result: Type,
+    location: Location,
+}
+
+#[derive(Default)]
+struct Checker {
+    /// What each type variable was unified with, if anything.
+    bindings: Vec<Option<Type>>,
+    env: Vec<(Symbol, Scheme)>,
+    additions: Vec<Addition>,
+    /// Type of each name where it is bound or used.
+    names: Vec<(Location, Type)>,
+    /// Variables in the types references were created with, which are
+    /// never generalized.
+    weak: Vec<usize>,
+}
+
+fn error(message: String, location: &Location) -> TypeError {
+    TypeError {
+        message,
+        location: location.clone(),
+    }
+}
+
+impl Addition {
+    /// The variables of its types, which must have been applied.
+    fn vars(&self) -> Vec<usize> {
+        let mut vars = Vec::new();
+        for ty in [&self.lhs, &self.rhs, &self.result] {
+            ty.vars(&mut vars);
+        }
+        vars
+    }
+}
+
+impl Checker {
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    /// Follows variable bindings at the top of `ty`.
+    fn shallow(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(var) = ty {
+            match &self.bindings[var] {
+                Some(bound) => ty = bound.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// Replaces every bound variable in `ty` by what it is bound to.
+    fn apply(&self, ty: &Type) -> Type {
+        match self.shallow(ty) {
+            Type::Tuple(first, second) => {
+                Type::Tuple(Box::new(self.apply(&first)), Box::new(self.apply(&second)))
+            }
+            Type::Function(parameters, result) => Type::Function(
+                parameters.iter().map(|p| self.apply(p)).collect(),
+                Box::new(self.apply(&result)),
+            ),
+            Type::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (*name, self.apply(ty)))
+                    .collect(),
+            ),
+            Type::Ref(ty) => Type::Ref(Box::new(self.apply(&ty))),
+            ty => ty,
+        }
+    }
+
+    fn occurs(&self, var: usize, ty: &Type) -> bool {
+        let mut vars = Vec::new();
+        self.apply(ty).vars(&mut vars);
+        vars.contains(&var)
+    }
+
+    /// Makes `found` equal to `expected`, reporting a mismatch at
+    /// `location`.
+    fn unify(
+        &mut self,
+        expected: &Type,
+        found: &Type,
+        location: &Location,
+    ) -> Result<(), TypeError> {
+        let (expected, found) = (self.shallow(expected), self.shallow(found));
+        match (&expected, &found) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => {
+                if self.occurs(*var, ty) {
+                    return Err(error(
+                        "cannot construct an infinite type".to_string(),
+                        location,
+                    ));
+                }
+                self.bindings[*var] = Some(ty.clone());
+                Ok(())
+            }
+            (Type::Int, Type::Int) | (Type::Bool, Type::Bool) | (Type::Str, Type::Str) => Ok(()),
+            (Type::Tuple(a1, a2), Type::Tuple(b1, b2)) => {
+                self.unify(a1, b1, location)?;
+                self.unify(a2, b2, location)
+            }
+            (Type::Function(a_params, a_result), Type::Function(b_params, b_result))
+                if a_params.len() == b_params.len() =>
+            {
+                for (a, b) in a_params.iter().zip(b_params) {
+                    self.unify(a, b, location)?;
+                }
+                self.unify(a_result, b_result, location)
+            }
+            (Type::Record(a), Type::Record(b))
+                if a.len() == b.len() && a.iter().zip(b).all(|((a, _), (b, _))| a == b) =>
+            {
+                for ((_, a), (_, b)) in a.iter().zip(b) {
+                    self.unify(a, b, location)?;
+                }
+                Ok(())
+            }
+            (Type::Ref(a), Type::Ref(b)) => self.unify(a, b, location),
+            _ => Err(self.mismatch(&expected, &found, location)),
+        }
+    }
+
+    fn mismatch(&self, expected: &Type, found: &Type, location: &Location) -> TypeError {
+        error(
+            format!(
+                "expected {} but found {}",
+                self.apply(expected),
+                self.apply(found)
+            ),
+            location,
+        )
+    }
+
+    /// Type variables free in the environment, which must not be
+    /// generalized.
+    fn env_vars(&self) -> Vec<usize> {
+        let mut vars = Vec::new();
+        for (_, scheme) in &self.env {
+            let mut scheme_vars = Vec::new();
+            self.apply(&scheme.ty).vars(&mut scheme_vars);
+            vars.extend(scheme_vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+        }
+        vars
+    }
+
+    fn generalize(&mut self, ty: &Type) -> Result<Scheme, TypeError> {
+        // A type without variables has nothing to generalize, which spares
+        // the walk over the environment on each binding of a long program.
+        let ground = self.apply(ty);
+        let mut vars = Vec::new();
+        ground.vars(&mut vars);
+        if vars.is_empty() && self.additions.is_empty() {
+            return Ok(Scheme {
+                vars,
+                ty: ground,
+                additions: Vec::new(),
+            });
+        }
+        let mut env_vars = self.env_vars();
+        for var in &self.weak {
+            self.apply(&Type::Var(*var)).vars(&mut env_vars);
+        }
+        let mut kept = Vec::new();
+        self.apply(ty).vars(&mut kept);
+        kept.retain(|v| !env_vars.contains(v));
+        self.solve_additions(Some(&env_vars), &kept)?;
+        let ty = self.apply(ty);
+        let mut vars = Vec::new();
+        ty.vars(&mut vars);
+        vars.retain(|v| !env_vars.contains(v));
+        // What is left on the variables of the scheme alone goes with it.
+        let mut additions = Vec::new();
+        for addition in std::mem::take(&mut self.additions) {
+            let addition = Addition {
+                lhs: self.apply(&addition.lhs),
+                rhs: self.apply(&addition.rhs),
+                result: self.apply(&addition.result),
+                location: addition.location,
+            };
+            if addition.vars().iter().all(|v| vars.contains(v)) {
+                additions.push(addition);
+            } else {
+                self.additions.push(addition);
+            }
+        }
+        Ok(Scheme {
+            vars,
+            ty,
+            additions,
+        })
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: HashMap<usize, Type> = scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+        for addition in &scheme.additions {
+            self.additions.push(Addition {
+                lhs: substitute(&addition.lhs, &fresh),
+                rhs: substitute(&addition.rhs, &fresh),
+                result: substitute(&addition.result, &fresh),
+                location: addition.location.clone(),
+            });
+        }
+        substitute(&scheme.ty, &fresh)
+    }
+
+    /// Resolves the pending additions whose operand types are known. With
+    /// `defaults`, the remaining ones that involve neither the variables in
+    /// it nor only those in `kept` are taken to be on integers.
+    fn solve_additions(
+        &mut self,
+        defaults: Option<&[usize]>,
+        kept: &[usize],
+    ) -> Result<(), TypeError> {
+        loop {
+            let mut progress = false;
+            for addition in std::mem::take(&mut self.additions) {
+                if self.solve_addition(&addition)? {
+                    progress = true;
+                } else {
+                    self.additions.push(addition);
+                }
+            }
+            if progress {
+                continue;
+            }
+            let Some(env_vars) = defaults else {
+                return Ok(());
+            };
+            let position = self.additions.iter().position(|addition| {
+                let mut vars = Vec::new();
+                for ty in [&addition.lhs, &addition.rhs, &addition.result] {
+                    self.apply(ty).vars(&mut vars);
+                }
+                !vars.iter().any(|v| env_vars.contains(v)) && !vars.iter().all(|v| kept.contains(v))
+            });
+            match position {
+                Some(index) => {
+                    let addition = self.additions.remove(index);
+                    for ty in [&addition.lhs, &addition.rhs, &addition.result] {
+                        self.unify(&Type::Int, ty, &addition.location)?;
+                    }
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Decides the type of an addition if its operands allow it.
+    fn solve_addition(&mut self, addition: &Addition) -> Result<bool, TypeError> {
+        let lhs = self.shallow(&addition.lhs);
+        let rhs = self.shallow(&addition.rhs);
+        let location = &addition.location;
+        for operand in [&lhs, &rhs] {
+            if !matches!(operand, Type::Int | Type::Str | Type::Var(_)) {
+                return Err(error(
+                    format!("expected string or int but found {}", self.apply(operand)),
+                    location,
+                ));
+            }
+        }
+        match (&lhs, &rhs, self.shallow(&addition.result)) {
+            (Type::Int, Type::Int, _) => self.unify(&addition.result, &Type::Int, location)?,
+            (Type::Str, _, _) | (_, Type::Str, _) => {
+                self.unify(&addition.result, &Type::Str, location)?
+            }
+            (_, _, Type::Int) => {
+                self.unify(&Type::Int, &lhs, location)?;
+                self.unify(&Type::Int, &rhs, location)?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<&Scheme> {
+        self.env
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, scheme)| scheme)
+    }
+
+    fn bind(&mut self, name: Symbol, ty: Type) {
+        self.env.push((
+            name,
+            Scheme {
+                vars: Vec::new(),
+                ty,
+                additions: Vec::new(),
+            },
+        ));
+    }
+
+    fn function(&mut self, parameters: &[Parameter], body: &Term) -> Result<Type, TypeError> {
+        let types: Vec<Type> = parameters.iter().map(|_| self.fresh()).collect();
+        for (parameter, ty) in parameters.iter().zip(&types) {
+            self.names.push((parameter.location.clone(), ty.clone()));
+            self.bind(parameter.text, ty.clone());
+        }
+        let result = self.infer(body);
+        self.env.truncate(self.env.len() - parameters.len());
+        Ok(Type::Function(types, Box::new(result?)))
+    }
+
+    /// The type of the values `pattern` matches. Its names are bound with
+    /// a single type for the arm.
+    fn pattern(&mut self, pattern: &Pattern) -> Type {
+        match pattern {
+            Pattern::Wildcard { .. } => self.fresh(),
+            Pattern::Var { text, location } => {
+                let ty = self.fresh();
+                self.names.push((location.clone(), ty.clone()));
+                self.bind(*text, ty.clone());
+                ty
+            }
+            Pattern::Int { .. } => Type::Int,
+            Pattern::Str { .. } => Type::Str,
+            Pattern::Bool { .. } => Type::Bool,
+            Pattern::Tuple { first, second, .. } => Type::Tuple(
+                Box::new(self.pattern(first)),
+                Box::new(self.pattern(second)),
+            ),
+        }
+    }
+
+    /// Checks the `let` term `binding` and brings its names into scope.
+    fn binding(&mut self, binding: &Term) -> Result<(), TypeError> {
+        match binding {
+            Term::Let { name, value, .. } => {
+                let ty = match value.as_ref() {
+                    // The function sees itself with a single type while its
+                    // body is checked.
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        location,
+                        ..
+                    } => {
+                        let itself = self.fresh();
+                        self.bind(name.text, itself.clone());
+                        let ty = self.function(parameters, body);
+                        self.env.pop();
+                        let ty = ty?;
+                        self.unify(&itself, &ty, location)?;
+                        ty
+                    }
+                    _ => self.infer(value)?,
+                };
+                let scheme = self.generalize(&ty)?;
+                self.names.push((name.location.clone(), scheme.ty.clone()));
+                self.env.push((name.text, scheme));
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                ..
+            } => {
+                let ty = self.infer(value)?;
+                let (first_ty, second_ty) = (self.fresh(), self.fresh());
+                let tuple = Type::Tuple(Box::new(first_ty.clone()), Box::new(second_ty.clone()));
+                self.unify(&tuple, &ty, value.location())?;
+                let first_scheme = self.generalize(&first_ty)?;
+                let second_scheme = self.generalize(&second_ty)?;
+                self.names
+                    .push((first.location.clone(), first_scheme.ty.clone()));
+                self.names
+                    .push((second.location.clone(), second_scheme.ty.clone()));
+                self.env.push((first.text, first_scheme));
+                self.env.push((second.text, second_scheme));
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn infer(&mut self, term: &Term) -> Result<Type, TypeError> {
+        match term {
+            Term::Int { .. } => Ok(Type::Int),
+            Term::Str { .. } => Ok(Type::Str),
+            Term::Bool { .. } => Ok(Type::Bool),
+            Term::Var { text, location, .. } => match self.lookup(*text) {
+                Some(scheme) => {
+                    let scheme = scheme.clone();
+                    let ty = self.instantiate(&scheme);
+                    self.names.push((location.clone(), ty.clone()));
+                    Ok(ty)
+                }
+                None => Err(error(format!("cannot find variable {}", text), location)),
+            },
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                location,
+            } => {
+                let ty = self.infer(condition)?;
+                self.unify(&Type::Bool, &ty, condition.location())?;
+                let then = self.infer(then)?;
+                let otherwise = self.infer(otherwise)?;
+                self.unify(&then, &otherwise, location)?;
+                Ok(then)
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => {
+                let depth = self.env.len();
+                let mut chain = term.let_chain();
+                let result = chain
+                    .by_ref()
+                    .try_for_each(|binding| self.binding(binding))
+                    .and_then(|()| self.infer(chain.rest()));
+                self.env.truncate(depth);
+                result
+            }
+            Term::Binary {
+                lhs,
+                op,
+                rhs,
+                location,
+            } => {
+                let left = self.infer(lhs)?;
+                let right = self.infer(rhs)?;
+                match op {
+                    BinaryOp::Add => {
+                        let result = self.fresh();
+                        self.additions.push(Addition {
+                            lhs: left,
+                            rhs: right,
+                            result: result.clone(),
+                            location: location.clone(),
+                        });
+                        self.solve_additions(None, &[])?;
+                        Ok(result)
+                    }
+                    BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+                        self.unify(&Type::Int, &left, location)?;
+                        self.unify(&Type::Int, &right, location)?;
+                        Ok(Type::Int)
+                    }
+                    BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Lte | BinaryOp::Gte => {
+                        self.unify(&Type::Int, &left, location)?;
+                        self.unify(&Type::Int, &right, location)?;
+                        Ok(Type::Bool)
+                    }
+                    BinaryOp::Eq | BinaryOp::Neq => {
+                        self.unify(&left, &right, location)?;
+                        Ok(Type::Bool)
+                    }
+                    BinaryOp::And | BinaryOp::Or => {
+                        self.unify(&Type::Bool, &left, location)?;
+                        self.unify(&Type::Bool, &right, location)?;
+                        Ok(Type::Bool)
+                    }
+                }
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                let callee_ty = self.infer(callee)?;
+                let result = match self.shallow(&callee_ty) {
+                    Type::Function(parameters, result) => {
+                        if parameters.len() != arguments.len() {
+                            return Err(error(
+                                format!(
+                                    "expected {} arguments but instead got {}",
+                                    parameters.len(),
+                                    arguments.len()
+                                ),
+                                location,
+                            ));
+                        }
+                        for (parameter, argument) in parameters.iter().zip(arguments) {
+                            let ty = self.infer(argument)?;
+                            self.unify(parameter, &ty, argument.location())?;
+                        }
+                        *result
+                    }
+                    Type::Var(_) => {
+                        let mut parameters = Vec::new();
+                        for argument in arguments {
+                            parameters.push(self.infer(argument)?);
+                        }
+                        let result = self.fresh();
+                        let ty = Type::Function(parameters, Box::new(result.clone()));
+                        self.unify(&callee_ty, &ty, location)?;
+                        result
+                    }
+                    ty => {
+                        return Err(error(
+                            format!("expected closure but found {}", self.apply(&ty)),
+                            callee.location(),
+                        ))
+                    }
+                };
+                Ok(result)
+            }
+            Term::Function {
+                parameters, value, ..
+            } => self.function(parameters, value),
+            Term::Tuple { first, second, .. } => Ok(Type::Tuple(
+                Box::new(self.infer(first)?),
+                Box::new(self.infer(second)?),
+            )),
+            Term::First { value, .. } | Term::Second { value, .. } => {
+                let ty = self.infer(value)?;
+                let (first, second) = (self.fresh(), self.fresh());
+                let tuple = Type::Tuple(Box::new(first.clone()), Box::new(second.clone()));
+                self.unify(&tuple, &ty, value.location())?;
+                Ok(if let Term::First { .. } = term {
+                    first
+                } else {
+                    second
+                })
+            }
+            Term::Print { values, .. } => {
+                let mut ty = None;
+                for value in values {
+                    ty = Some(self.infer(value)?);
+                }
+                Ok(ty.expect("prints are never empty"))
+            }
+            Term::Record { fields, .. } => {
+                let mut types = Vec::new();
+                for field in fields {
+                    types.push((field.name.text, self.infer(&field.value)?));
+                }
+                types.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+                Ok(Type::Record(types))
+            }
+            Term::Field {
+                value,
+                name,
+                location,
+            } => {
+                let ty = self.infer(value)?;
+                match self.shallow(&ty) {
+                    Type::Record(fields) => match fields.iter().find(|(n, _)| *n == name.text) {
+                        Some((_, ty)) => Ok(ty.clone()),
+                        None => Err(error(
+                            format!("{} has no field {}", self.apply(&ty), name.text),
+                            location,
+                        )),
+                    },
+                    // Without row types, a field of a record whose type is
+                    // not known yet is left unchecked.
+                    Type::Var(_) => Ok(self.fresh()),
+                    ty => Err(error(
+                        format!("expected record but found {}", self.apply(&ty)),
+                        value.location(),
+                    )),
+                }
+            }
+            Term::Ref { value, .. } => {
+                let ty = self.infer(value)?;
+                self.apply(&ty).vars(&mut self.weak);
+                Ok(Type::Ref(Box::new(ty)))
+            }
+            Term::Deref { value, .. } => {
+                let ty = self.infer(value)?;
+                let content = self.fresh();
+                self.unify(&Type::Ref(Box::new(content.clone())), &ty, value.location())?;
+                Ok(content)
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                let reference_ty = self.infer(reference)?;
+                let content = self.fresh();
+                let expected = Type::Ref(Box::new(content.clone()));
+                self.unify(&expected, &reference_ty, reference.location())?;
+                let ty = self.infer(value)?;
+                self.unify(&content, &ty, value.location())?;
+                Ok(ty)
+            }
+            Term::Match { value, arms, .. } => {
+                let ty = self.infer(value)?;
+                let result = self.fresh();
+                for arm in arms {
+                    let depth = self.env.len();
+                    let pattern = self.pattern(&arm.pattern);
+                    let arm_ty = self
+                        .unify(&pattern, &ty, arm.pattern.location())
+                        .and_then(|()| self.infer(&arm.value));
+                    self.env.truncate(depth);
+                    self.unify(&result, &arm_ty?, arm.value.location())?;
+                }
+                Ok(result)
+            }
+        }
+    }
+}
+
+fn substitute(ty: &Type, vars: &HashMap<usize, Type>) -> Type {
+    match ty {
+        Type::Var(var) => vars.get(var).cloned().unwrap_or(Type::Var(*var)),
+        Type::Tuple(first, second) => Type::Tuple(
+            Box::new(substitute(first, vars)),
+            Box::new(substitute(second, vars)),
+        ),
+        Type::Function(parameters, result) => Type::Function(
+            parameters.iter().map(|p| substitute(p, vars)).collect(),
+            Box::new(substitute(result, vars)),
+        ),
+        Type::Record(fields) => Type::Record(
+            fields
+                .iter()
+                .map(|(name, ty)| (*name, substitute(ty, vars)))
+                .collect(),
+        ),
+        Type::Ref(ty) => Type::Ref(Box::new(substitute(ty, vars))),
+        ty => ty.clone(),
+    }
+}
+
+/// Infers the type of a program, or returns the first type error in it.
+pub fn check(file: &File) -> Result<Type, TypeError> {
+    check_names(file).0
+}
+
+/// Like [`check`], but also returns the type of every name at each place
+/// it is bound or used. After a type error, only the names checked before
+/// it are included.
+pub fn check_names(file: &File) -> (Result<Type, TypeError>, Vec<(Location, Type)>) {
+    let mut checker = Checker::default();
+    let result = checker
+        .infer(&file.expression)
+        .and_then(|ty| checker.solve_additions(Some(&[]), &[]).map(|()| ty));
+    let names = checker
+        .names
+        .iter()
+        .map(|(location, ty)| (location.clone(), checker.apply(ty)))
+        .collect();
+    (result.map(|ty| checker.apply(&ty)), names)
+}
@@ -1,14 +1,21 @@
-use serde::{Deserialize, Serialize};
-use std::rc::Rc;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+use std::rc::{Rc, Weak};
 
-#[derive(Clone, Deserialize)]
-struct Location {
-    start: i32,
-    end: i32,
-    filename: String,
+use crate::symbol::Symbol;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Location {
+    pub start: usize,
+    pub end: usize,
+    pub filename: String,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -25,92 +32,707 @@ pub enum BinaryOp {
     Or,
 }
 
-#[derive(Clone, Deserialize)]
+impl BinaryOp {
+    /// How tightly the operator binds its operands. All operators are
+    /// left-associative.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::Neq => 3,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Lte | BinaryOp::Gte => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 6,
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Neq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Lte => "<=",
+            BinaryOp::Gte => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        };
+        f.write_str(symbol)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Parameter {
-    text: String,
-    location: Location,
+    pub text: Symbol,
+    pub location: Location,
+}
+
+/// A binding a function keeps from where it is created: its name and its
+/// De Bruijn index there.
+#[derive(Clone, Debug)]
+pub struct Capture {
+    pub name: Symbol,
+    pub index: usize,
 }
 
-#[derive(Clone, Deserialize)]
+/// Where a variable finds the binding it refers to.
+#[derive(Clone, Copy, Debug)]
+pub enum Slot {
+    /// The De Bruijn index of the binding.
+    Local(usize),
+    /// The position of a top-level function in the table the tree-walker
+    /// keeps for them, see [`crate::resolver::resolve_program`].
+    Global(usize),
+}
+
+/// What an arm of a `Match` compares the value with. Names match anything
+/// and bind it; `_` matches anything without binding it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind")]
+pub enum Pattern {
+    Wildcard {
+        location: Location,
+    },
+    Var {
+        text: Symbol,
+        location: Location,
+    },
+    Int {
+        value: i64,
+        location: Location,
+    },
+    Str {
+        value: String,
+        location: Location,
+    },
+    Bool {
+        value: bool,
+        location: Location,
+    },
+    Tuple {
+        first: Box<Pattern>,
+        second: Box<Pattern>,
+        location: Location,
+    },
+}
+
+impl Pattern {
+    pub fn location(&self) -> &Location {
+        match self {
+            Pattern::Wildcard { location }
+            | Pattern::Var { location, .. }
+            | Pattern::Int { location, .. }
+            | Pattern::Str { location, .. }
+            | Pattern::Bool { location, .. }
+            | Pattern::Tuple { location, .. } => location,
+        }
+    }
+
+    /// Names the pattern binds, left to right, which is the order the
+    /// engines bind them in.
+    pub fn names(&self) -> Vec<Symbol> {
+        let mut names = Vec::new();
+        self.collect_names(&mut names);
+        names
+    }
+
+    fn collect_names(&self, names: &mut Vec<Symbol>) {
+        match self {
+            Pattern::Var { text, .. } => names.push(*text),
+            Pattern::Tuple { first, second, .. } => {
+                first.collect_names(names);
+                second.collect_names(names);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// One `pattern => value` of a `Match`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub value: Rc<Term>,
+}
+
+/// One `name: value` of a `Record`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordField {
+    pub name: Parameter,
+    pub value: Rc<Term>,
+}
+
+/// Deserialized by hand in [`crate::json`].
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind")]
 pub enum Term {
     Int {
-        kind: String,
-        value: f64,
+        value: i64,
         location: Location,
     },
     Str {
-        kind: String,
         value: String,
         location: Location,
     },
     Bool {
-        kind: String,
         value: bool,
         location: Location,
     },
     If {
-        kind: String,
         condition: Rc<Term>,
         then: Rc<Term>,
         otherwise: Rc<Term>,
         location: Location,
     },
     Let {
-        kind: String,
         name: Parameter,
         value: Rc<Term>,
+        #[serde(serialize_with = "serialize_next")]
+        next: Rc<Term>,
+        location: Location,
+    },
+    /// Binds `first` and `second` to the elements of the tuple `value` in
+    /// `next`. Not produced by the reference parser.
+    LetTuple {
+        first: Parameter,
+        second: Parameter,
+        value: Rc<Term>,
+        #[serde(serialize_with = "serialize_next")]
         next: Rc<Term>,
         location: Location,
     },
     Binary {
-        kind: String,
         lhs: Rc<Term>,
         op: BinaryOp,
         rhs: Rc<Term>,
         location: Location,
     },
     Call {
-        kind: String,
         callee: Rc<Term>,
         arguments: Vec<Rc<Term>>,
         location: Location,
+        #[serde(skip)]
+        cache: CallCache,
     },
     Function {
-        kind: String,
         parameters: Vec<Parameter>,
         value: Rc<Term>,
         location: Location,
+        /// Bindings the body uses from outside the function, filled in by
+        /// the resolver; not part of the JSON format.
+        #[serde(skip)]
+        captures: Vec<Capture>,
     },
     First {
-        kind: String,
         value: Rc<Term>,
         location: Location,
     },
+    /// Prints its values separated by spaces. Only one value is allowed
+    /// without `--extensions`, which JSON holds in `value`, as the
+    /// reference parser writes it, rather than in `values`.
     Print {
-        kind: String,
-        value: Rc<Term>,
+        #[serde(flatten, serialize_with = "serialize_print")]
+        values: Vec<Rc<Term>>,
         location: Location,
     },
     Second {
-        kind: String,
         value: Rc<Term>,
         location: Location,
     },
     Tuple {
-        kind: String,
         first: Rc<Term>,
         second: Rc<Term>,
         location: Location,
     },
     Var {
-        kind: String,
-        text: String,
+        text: Symbol,
+        /// Slot filled in by the resolver; not part of the JSON format.
+        #[serde(skip)]
+        index: Option<Slot>,
+        location: Location,
+    },
+    /// Evaluates the `value` of the first arm whose pattern matches
+    /// `value`, with the names of the pattern bound. Not produced by the
+    /// reference parser.
+    Match {
+        value: Rc<Term>,
+        arms: Vec<Arm>,
+        location: Location,
+    },
+    /// Makes a record from `fields`, evaluated in order. Not produced by
+    /// the reference parser.
+    Record {
+        fields: Vec<RecordField>,
         location: Location,
     },
+    /// Reads the field `name` of the record `value`. Not produced by the
+    /// reference parser.
+    Field {
+        value: Rc<Term>,
+        name: Parameter,
+        location: Location,
+    },
+    /// Makes a new mutable reference holding `value`. Not produced by the
+    /// reference parser.
+    Ref {
+        value: Rc<Term>,
+        location: Location,
+    },
+    /// Reads the value held by the reference `value`. Not produced by the
+    /// reference parser.
+    Deref {
+        value: Rc<Term>,
+        location: Location,
+    },
+    /// Replaces the value held by `reference` with `value`, evaluated in
+    /// that order, and returns it. Not produced by the reference parser.
+    Assign {
+        reference: Rc<Term>,
+        value: Rc<Term>,
+        location: Location,
+    },
+}
+
+thread_local! {
+    /// Put in place of the rest of a chain of `let` while it is dropped.
+    static HOLE: Rc<Term> = Rc::new(Term::Bool {
+        value: false,
+        location: Location {
+            start: 0,
+            end: 0,
+            filename: String::new(),
+        },
+    });
+}
+
+/// Takes the rest of a chain of `let` out of `next`, if anything is left
+/// to put in its place.
+fn take_next(next: &mut Rc<Term>) -> Option<Rc<Term>> {
+    HOLE.try_with(|hole| mem::replace(next, hole.clone())).ok()
+}
+
+impl Drop for Term {
+    // Unlink long chains of `let` iteratively so dropping them cannot
+    // overflow the stack.
+    fn drop(&mut self) {
+        let mut next = match self {
+            Term::Let { next, .. } | Term::LetTuple { next, .. } => take_next(next),
+            _ => None,
+        };
+        while let Some(term) = next {
+            let Ok(mut term) = Rc::try_unwrap(term) else {
+                break;
+            };
+            next = match &mut term {
+                Term::Let { next, .. } | Term::LetTuple { next, .. } => take_next(next),
+                _ => None,
+            };
+        }
+    }
+}
+
+impl Term {
+    /// The term a `let` or `let (first, second)` binds its names in.
+    pub fn next(&self) -> Option<&Rc<Term>> {
+        match self {
+            Term::Let { next, .. } | Term::LetTuple { next, .. } => Some(next),
+            _ => None,
+        }
+    }
+
+    /// A copy of this `let` or `let (first, second)` binding `value` and
+    /// followed by `next`, for passes that rebuild chains of them.
+    pub fn rebind(&self, value: Rc<Term>, next: Rc<Term>) -> Term {
+        match self {
+            Term::Let { name, location, .. } => Term::Let {
+                name: name.clone(),
+                value,
+                next,
+                location: location.clone(),
+            },
+            Term::LetTuple {
+                first,
+                second,
+                location,
+                ..
+            } => Term::LetTuple {
+                first: first.clone(),
+                second: second.clone(),
+                value,
+                next,
+                location: location.clone(),
+            },
+            _ => panic!("only `let` terms can be rebound"),
+        }
+    }
+
+    /// The `let` and `let (first, second)` terms this one starts a chain
+    /// of, see [`LetChain`].
+    pub fn let_chain(&self) -> LetChain<'_> {
+        LetChain {
+            rest: self,
+            shared: None,
+        }
+    }
+
+    /// Like [`Term::let_chain`], for passes that rewrite the chain in
+    /// place: calls `binding` on each of its `let` terms, outermost first,
+    /// after making it unique, and returns the term the last one binds its
+    /// names in.
+    pub fn let_chain_mut(
+        mut term: &mut Rc<Term>,
+        mut binding: impl FnMut(&mut Term),
+    ) -> &mut Rc<Term> {
+        while term.next().is_some() {
+            let unique = Rc::make_mut(term);
+            binding(unique);
+            term = match unique {
+                Term::Let { next, .. } | Term::LetTuple { next, .. } => next,
+                _ => unreachable!(),
+            };
+        }
+        term
+    }
+
+    /// Names the term uses without binding them, in order of first use.
+    pub fn free_names(&self) -> Vec<Symbol> {
+        let mut free = Vec::new();
+        collect_free_names(self, &mut Vec::new(), &mut free);
+        free
+    }
+
+    /// Every name the term binds or uses, walked with a stack of its own
+    /// so that no term is too deep for it.
+    pub fn names(&self) -> HashSet<Symbol> {
+        let mut names = HashSet::new();
+        let mut pending = vec![self];
+        while let Some(term) = pending.pop() {
+            match term {
+                Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } => {}
+                Term::Var { text, .. } => {
+                    names.insert(*text);
+                }
+                Term::Let {
+                    name, value, next, ..
+                } => {
+                    names.insert(name.text);
+                    pending.extend([&**value, &**next]);
+                }
+                Term::LetTuple {
+                    first,
+                    second,
+                    value,
+                    next,
+                    ..
+                } => {
+                    names.extend([first.text, second.text]);
+                    pending.extend([&**value, &**next]);
+                }
+                Term::Function {
+                    parameters, value, ..
+                } => {
+                    names.extend(parameters.iter().map(|p| p.text));
+                    pending.push(value);
+                }
+                Term::If {
+                    condition,
+                    then,
+                    otherwise,
+                    ..
+                } => pending.extend([&**condition, &**then, &**otherwise]),
+                Term::Binary { lhs, rhs, .. } => pending.extend([&**lhs, &**rhs]),
+                Term::Call {
+                    callee, arguments, ..
+                } => {
+                    pending.push(callee);
+                    pending.extend(arguments.iter().map(|argument| &**argument));
+                }
+                Term::Tuple { first, second, .. } => pending.extend([&**first, &**second]),
+                Term::Print { values, .. } => pending.extend(values.iter().map(|v| &**v)),
+                Term::Match { value, arms, .. } => {
+                    pending.push(value);
+                    for arm in arms {
+                        names.extend(arm.pattern.names());
+                        pending.push(&arm.value);
+                    }
+                }
+                Term::Record { fields, .. } => {
+                    pending.extend(fields.iter().map(|field| &*field.value));
+                }
+                Term::Assign {
+                    reference, value, ..
+                } => pending.extend([&**reference, &**value]),
+                Term::First { value, .. }
+                | Term::Second { value, .. }
+                | Term::Field { value, .. }
+                | Term::Ref { value, .. }
+                | Term::Deref { value, .. } => pending.push(value),
+            }
+        }
+        names
+    }
+
+    pub fn location(&self) -> &Location {
+        match self {
+            Term::Int { location, .. }
+            | Term::Str { location, .. }
+            | Term::Bool { location, .. }
+            | Term::If { location, .. }
+            | Term::Let { location, .. }
+            | Term::LetTuple { location, .. }
+            | Term::Binary { location, .. }
+            | Term::Call { location, .. }
+            | Term::Function { location, .. }
+            | Term::First { location, .. }
+            | Term::Print { location, .. }
+            | Term::Second { location, .. }
+            | Term::Tuple { location, .. }
+            | Term::Var { location, .. }
+            | Term::Match { location, .. }
+            | Term::Record { location, .. }
+            | Term::Field { location, .. }
+            | Term::Ref { location, .. }
+            | Term::Deref { location, .. }
+            | Term::Assign { location, .. } => location,
+        }
+    }
+
+    /// Name of the term as written in the `kind` field of the JSON AST.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Term::Int { .. } => "Int",
+            Term::Str { .. } => "Str",
+            Term::Bool { .. } => "Bool",
+            Term::If { .. } => "If",
+            Term::Let { .. } => "Let",
+            Term::LetTuple { .. } => "LetTuple",
+            Term::Binary { .. } => "Binary",
+            Term::Call { .. } => "Call",
+            Term::Function { .. } => "Function",
+            Term::First { .. } => "First",
+            Term::Print { .. } => "Print",
+            Term::Second { .. } => "Second",
+            Term::Tuple { .. } => "Tuple",
+            Term::Var { .. } => "Var",
+            Term::Match { .. } => "Match",
+            Term::Record { .. } => "Record",
+            Term::Field { .. } => "Field",
+            Term::Ref { .. } => "Ref",
+            Term::Deref { .. } => "Deref",
+            Term::Assign { .. } => "Assign",
+        }
+    }
 }
 
-#[derive(Clone, Deserialize)]
+/// The `let` and `let (first, second)` terms of a chain of them, outermost
+/// first, each binding its names in the next. Programs made of thousands of
+/// bindings nest that deep, so passes walk chains with this in a loop
+/// rather than with a call for each binding; what the last one binds its
+/// names in is then left in [`LetChain::rest`].
+pub struct LetChain<'t> {
+    rest: &'t Term,
+    shared: Option<&'t Rc<Term>>,
+}
+
+impl<'t> LetChain<'t> {
+    /// The term after the bindings taken so far.
+    pub fn rest(&self) -> &'t Term {
+        self.rest
+    }
+
+    /// The term after the bindings taken so far, as shared by the last of
+    /// them, if any was taken.
+    pub fn shared_rest(&self) -> Option<&'t Rc<Term>> {
+        self.shared
+    }
+}
+
+impl<'t> Iterator for LetChain<'t> {
+    type Item = &'t Term;
+
+    fn next(&mut self) -> Option<&'t Term> {
+        let binding = self.rest;
+        let next = binding.next()?;
+        self.rest = next;
+        self.shared = Some(next);
+        Some(binding)
+    }
+}
+
+/// The function a call went to the last time, with whether its body is
+/// pure, so that calling it again skips checking it. Filled in by the
+/// interpreter; not part of the JSON format.
+#[derive(Default)]
+pub struct CallCache(RefCell<Option<(Weak<Term>, bool)>>);
+
+impl CallCache {
+    /// Whether `body` is pure, if it is the body of the cached function.
+    pub fn get(&self, body: &Rc<Term>) -> Option<bool> {
+        match &*self.0.borrow() {
+            // The weak reference keeps the address of the body from being
+            // reused by another term.
+            Some((cached, pure)) if cached.as_ptr() == Rc::as_ptr(body) => Some(*pure),
+            _ => None,
+        }
+    }
+
+    pub fn set(&self, body: &Rc<Term>, pure: bool) {
+        *self.0.borrow_mut() = Some((Rc::downgrade(body), pure));
+    }
+}
+
+/// Copies of a term start with an empty cache.
+impl Clone for CallCache {
+    fn clone(&self) -> CallCache {
+        CallCache::default()
+    }
+}
+
+impl fmt::Debug for CallCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CallCache")
+    }
+}
+
+/// Long chains of `let`s nest as deep as they are long, so each goes on a
+/// stack grown on the heap once the current one runs low.
+fn serialize_next<S: Serializer>(next: &Rc<Term>, serializer: S) -> Result<S::Ok, S::Error> {
+    stacker::maybe_grow(64 * 1024, 2 * 1024 * 1024, || next.serialize(serializer))
+}
+
+fn serialize_print<S: Serializer>(values: &[Rc<Term>], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(1))?;
+    match values {
+        [value] => map.serialize_entry("value", value)?,
+        values => map.serialize_entry("values", values)?,
+    }
+    map.end()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct File {
-    expression: Rc<Term>,
-    location: Location,
+    pub name: String,
+    pub expression: Rc<Term>,
+    pub location: Location,
+}
+
+impl File {
+    pub fn from_json(json: &serde_json::Value) -> serde_json::Result<File> {
+        File::deserialize(json)
+    }
+
+    /// Renders the file in the JSON AST format of the reference parser,
+    /// with fields in the same order.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("the AST always serializes")
+    }
+}
+
+fn collect_free_names(term: &Term, bound: &mut Vec<Symbol>, free: &mut Vec<Symbol>) {
+    match term {
+        Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } => {}
+        Term::Var { text, .. } => {
+            if !bound.contains(text) && !free.contains(text) {
+                free.push(*text);
+            }
+        }
+        Term::Let {
+            name, value, next, ..
+        } => {
+            let depth = bound.len();
+            if let Term::Function { .. } = value.as_ref() {
+                bound.push(name.text);
+            }
+            collect_free_names(value, bound, free);
+            bound.truncate(depth);
+            bound.push(name.text);
+            collect_free_names(next, bound, free);
+            bound.truncate(depth);
+        }
+        Term::LetTuple {
+            first,
+            second,
+            value,
+            next,
+            ..
+        } => {
+            collect_free_names(value, bound, free);
+            let depth = bound.len();
+            bound.extend([first.text, second.text]);
+            collect_free_names(next, bound, free);
+            bound.truncate(depth);
+        }
+        Term::Function {
+            parameters, value, ..
+        } => {
+            let depth = bound.len();
+            bound.extend(parameters.iter().map(|p| p.text));
+            collect_free_names(value, bound, free);
+            bound.truncate(depth);
+        }
+        Term::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => {
+            collect_free_names(condition, bound, free);
+            collect_free_names(then, bound, free);
+            collect_free_names(otherwise, bound, free);
+        }
+        Term::Binary { lhs, rhs, .. } => {
+            collect_free_names(lhs, bound, free);
+            collect_free_names(rhs, bound, free);
+        }
+        Term::Call {
+            callee, arguments, ..
+        } => {
+            collect_free_names(callee, bound, free);
+            for argument in arguments {
+                collect_free_names(argument, bound, free);
+            }
+        }
+        Term::Tuple { first, second, .. } => {
+            collect_free_names(first, bound, free);
+            collect_free_names(second, bound, free);
+        }
+        Term::First { value, .. } | Term::Second { value, .. } => {
+            collect_free_names(value, bound, free);
+        }
+        Term::Print { values, .. } => {
+            for value in values {
+                collect_free_names(value, bound, free);
+            }
+        }
+        Term::Match { value, arms, .. } => {
+            collect_free_names(value, bound, free);
+            for arm in arms {
+                let depth = bound.len();
+                bound.extend(arm.pattern.names());
+                collect_free_names(&arm.value, bound, free);
+                bound.truncate(depth);
+            }
+        }
+        Term::Record { fields, .. } => {
+            for field in fields {
+                collect_free_names(&field.value, bound, free);
+            }
+        }
+        Term::Field { value, .. } | Term::Ref { value, .. } | Term::Deref { value, .. } => {
+            collect_free_names(value, bound, free)
+        }
+        Term::Assign {
+            reference, value, ..
+        } => {
+            collect_free_names(reference, bound, free);
+            collect_free_names(value, bound, free);
+        }
+    }
 }
@@ -0,0 +1,234 @@
+//! Checks a program for mistakes the engines assume it does not have.
+//!
+//! Most of them only come from a JSON AST written by another tool: a
+//! binding with an empty name, or a function that names the same parameter
+//! twice. A call that gives a function the wrong number of arguments is
+//! also caught here when the function is known, unless the config allows
+//! partial application and it is given too few. Every violation is
+//! reported, in source order, so they can all be shown before the program
+//! starts.
+
+use crate::config::InterpreterConfig;
+use crate::symbol::Symbol;
+use crate::types::*;
+
+#[derive(Debug)]
+pub struct Violation {
+    pub message: String,
+    pub location: Location,
+}
+
+#[derive(Default)]
+struct Validator {
+    config: InterpreterConfig,
+    /// Names in scope, innermost last, with the arity of the function they
+    /// are bound to when that is evident.
+    scope: Vec<(Symbol, Option<usize>)>,
+    violations: Vec<Violation>,
+}
+
+impl Validator {
+    fn report(&mut self, message: String, location: &Location) {
+        self.violations.push(Violation {
+            message,
+            location: location.clone(),
+        });
+    }
+
+    fn name(&mut self, parameter: &Parameter) {
+        if parameter.text.as_str().is_empty() {
+            self.report("names must not be empty".to_string(), &parameter.location);
+        }
+    }
+
+    fn function(&mut self, name: Option<Symbol>, parameters: &[Parameter], body: &Term) {
+        for (i, parameter) in parameters.iter().enumerate() {
+            self.name(parameter);
+            if parameters[..i].iter().any(|p| p.text == parameter.text) {
+                let message = format!("parameter {} is given twice", parameter.text);
+                self.report(message, &parameter.location);
+            }
+        }
+        let depth = self.scope.len();
+        if let Some(name) = name {
+            self.scope.push((name, Some(parameters.len())));
+        }
+        self.scope.extend(parameters.iter().map(|p| (p.text, None)));
+        self.validate(body);
+        self.scope.truncate(depth);
+    }
+
+    /// The arity of the function `callee` evaluates to, if it is evident.
+    fn arity(&self, callee: &Term) -> Option<usize> {
+        match callee {
+            Term::Function { parameters, .. } => Some(parameters.len()),
+            Term::Var { text, .. } => self
+                .scope
+                .iter()
+                .rev()
+                .find(|(n, _)| n == text)
+                .and_then(|(_, arity)| *arity),
+            _ => None,
+        }
+    }
+
+    /// Checks the `let` term `binding` and brings its names into scope.
+    fn binding(&mut self, binding: &Term) {
+        match binding {
+            Term::Let { name, value, .. } => {
+                self.name(name);
+                let arity = match value.as_ref() {
+                    Term::Function {
+                        parameters,
+                        value: body,
+                        ..
+                    } => {
+                        self.function(Some(name.text), parameters, body);
+                        Some(parameters.len())
+                    }
+                    _ => {
+                        self.validate(value);
+                        None
+                    }
+                };
+                self.scope.push((name.text, arity));
+            }
+            Term::LetTuple {
+                first,
+                second,
+                value,
+                ..
+            } => {
+                self.name(first);
+                self.name(second);
+                self.validate(value);
+                self.scope.push((first.text, None));
+                self.scope.push((second.text, None));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn validate(&mut self, term: &Term) {
+        let depth = self.scope.len();
+        let mut chain = term.let_chain();
+        for binding in &mut chain {
+            self.binding(binding);
+        }
+        let term = chain.rest();
+        match term {
+            Term::Int { .. } | Term::Str { .. } | Term::Bool { .. } | Term::Var { .. } => {}
+            Term::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.validate(condition);
+                self.validate(then);
+                self.validate(otherwise);
+            }
+            Term::Let { .. } | Term::LetTuple { .. } => unreachable!("bound above"),
+            Term::Binary { lhs, rhs, .. } => {
+                self.validate(lhs);
+                self.validate(rhs);
+            }
+            Term::Call {
+                callee,
+                arguments,
+                location,
+                ..
+            } => {
+                if let Some(arity) = self.arity(callee) {
+                    let partial = self.config.partial_application;
+                    if arity < arguments.len() || (arity > arguments.len() && !partial) {
+                        let message = format!(
+                            "expected {} arguments but instead got {}",
+                            arity,
+                            arguments.len()
+                        );
+                        self.report(message, location);
+                    }
+                }
+                self.validate(callee);
+                for argument in arguments {
+                    self.validate(argument);
+                }
+            }
+            Term::Function {
+                parameters, value, ..
+            } => self.function(None, parameters, value),
+            Term::Tuple { first, second, .. } => {
+                self.validate(first);
+                self.validate(second);
+            }
+            Term::Print { values, location } => {
+                if values.len() > 1 && self.config.strict {
+                    let message = "`print` takes one value without `--extensions`".to_string();
+                    self.report(message, location);
+                }
+                for value in values {
+                    self.validate(value);
+                }
+            }
+            Term::First { value, .. }
+            | Term::Second { value, .. }
+            | Term::Ref { value, .. }
+            | Term::Deref { value, .. } => self.validate(value),
+            Term::Match { value, arms, .. } => {
+                self.validate(value);
+                for arm in arms {
+                    let depth = self.scope.len();
+                    self.pattern(&arm.pattern);
+                    self.validate(&arm.value);
+                    self.scope.truncate(depth);
+                }
+            }
+            Term::Record { fields, .. } => {
+                for field in fields {
+                    self.name(&field.name);
+                    self.validate(&field.value);
+                }
+            }
+            Term::Field { value, name, .. } => {
+                self.validate(value);
+                self.name(name);
+            }
+            Term::Assign {
+                reference, value, ..
+            } => {
+                self.validate(reference);
+                self.validate(value);
+            }
+        }
+        self.scope.truncate(depth);
+    }
+
+    /// Checks the names `pattern` binds and brings them into scope.
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Var { text, location } => {
+                if text.as_str().is_empty() {
+                    self.report("names must not be empty".to_string(), location);
+                }
+                self.scope.push((*text, None));
+            }
+            Pattern::Tuple { first, second, .. } => {
+                self.pattern(first);
+                self.pattern(second);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Lists what is wrong with a program, in source order. An empty list means
+/// the engines can run it with `config`.
+pub fn validate(file: &File, config: InterpreterConfig) -> Vec<Violation> {
+    let mut validator = Validator {
+        config,
+        ..Validator::default()
+    };
+    validator.validate(&file.expression);
+    validator.violations
+}
@@ -0,0 +1,609 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+#[cfg(feature = "bigint")]
+use crate::bigint;
+use crate::config::{Coercion, InterpreterConfig};
+use crate::error::RuntimeError;
+use crate::interpreter;
+use crate::list::List;
+use crate::stats;
+use crate::symbol::Symbol;
+use crate::types::*;
+use crate::vm;
+
+/// A closure produced by one of the execution engines. Values never cross
+/// engines, so each engine only ever sees its own kind of closure, besides
+/// the natives that both of them can call.
+#[derive(Clone, Debug)]
+pub enum Closure {
+    Tree(Rc<interpreter::Closure>),
+    Bytecode(Rc<vm::Closure>),
+    Native(Rc<Native>),
+    /// Only produced with `--extensions`, see [`Partial`].
+    Partial(Rc<Partial>),
+}
+
+/// A closure called with fewer arguments than it takes, which waits for
+/// the rest. Calling it calls `callee` with `arguments` before the ones of
+/// the call, so `add(1)(2)` is `add(1, 2)`. `callee` is never a partial
+/// application itself.
+#[derive(Debug)]
+pub struct Partial {
+    pub callee: Closure,
+    pub arguments: Vec<Value>,
+}
+
+impl Partial {
+    /// `callee` given `arguments`, after the ones it already has when it
+    /// is a partial application itself.
+    pub fn new(callee: &Closure, arguments: Vec<Value>) -> Partial {
+        match callee {
+            Closure::Partial(partial) => Partial {
+                callee: partial.callee.clone(),
+                arguments: partial.arguments.iter().cloned().chain(arguments).collect(),
+            },
+            callee => Partial {
+                callee: callee.clone(),
+                arguments,
+            },
+        }
+    }
+}
+
+/// The Rust code behind a [`Native`].
+pub type NativeFn = dyn Fn(&[Value], &Location) -> Result<Value, RuntimeError>;
+
+/// A function implemented in Rust, bound to a name before the program
+/// starts. It receives its arguments, already checked to be `arity` many,
+/// and the location of the call for its errors.
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    pub function: Box<NativeFn>,
+    /// Whether calling it counts as an effect, like `print`, so that calls
+    /// to closures that reach it are not cached.
+    pub effects: bool,
+}
+
+impl Native {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value], &Location) -> Result<Value, RuntimeError> + 'static,
+    ) -> Native {
+        Native {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+            effects: false,
+        }
+    }
+
+    /// The same function, with calls to it counted as effects.
+    pub fn with_effects(self) -> Native {
+        Native {
+            effects: true,
+            ..self
+        }
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+/// A value of the program. It is two words wide: integers and booleans are
+/// held inline and everything else behind a single shared pointer, so
+/// copying a value between the stack, environments and caches never copies
+/// the string or tuple it holds.
+///
+/// This is an interim layout, not the single word of a NaN-boxed or
+/// tagged-pointer value: the tag takes a word of its own next to the
+/// payload. Packing both into eight bytes needs integers of the full i64
+/// range to be boxed when they do not fit beside the tag, and every match
+/// on `Value` to go through accessors instead.
+#[derive(Clone)]
+pub enum Value {
+    Boolean(bool),
+    String(Rc<String>),
+    Number(i64),
+    /// Integers outside the i64 range. Results that fit an i64 are always
+    /// narrowed back to `Number`.
+    #[cfg(feature = "bigint")]
+    BigInt(Rc<num_bigint::BigInt>),
+    Closure(Closure),
+    Tuple(Rc<(Value, Value)>),
+    /// Only produced by the builtins of `--extensions`.
+    List(List),
+    /// Only produced with `--extensions records`.
+    Record(Rc<HashMap<Symbol, Value>>),
+    /// A mutable cell, only produced with `--extensions refs`.
+    Ref(Rc<RefCell<Value>>),
+}
+
+const _: () = assert!(std::mem::size_of::<Value>() == 16);
+
+impl Drop for Value {
+    // Take nested tuples apart with a worklist so dropping them cannot
+    // overflow the stack.
+    fn drop(&mut self) {
+        let mut tuples = Vec::new();
+        take_tuples(self, &mut tuples);
+        while let Some(mut tuple) = tuples.pop() {
+            take_tuples(&mut tuple, &mut tuples);
+        }
+    }
+}
+
+/// Moves the tuples held by `value`, when it is the last owner of its pair,
+/// into `tuples`, leaving it nothing deep to drop.
+fn take_tuples(value: &mut Value, tuples: &mut Vec<Value>) {
+    if let Value::Tuple(pair) = value {
+        if let Some((first, second)) = Rc::get_mut(pair) {
+            for element in [first, second] {
+                if let Value::Tuple(_) = element {
+                    tuples.push(std::mem::replace(element, Value::Boolean(false)));
+                }
+            }
+        }
+    }
+}
+
+impl Value {
+    pub fn string(s: impl Into<String>) -> Value {
+        Value::String(Rc::new(s.into()))
+    }
+
+    pub fn tuple(first: Value, second: Value) -> Value {
+        Value::Tuple(Rc::new((first, second)))
+    }
+
+    /// The value as JSON, for tools that check results: integers as
+    /// numbers, strings and booleans as themselves, tuples as arrays of two
+    /// elements, lists as arrays and records as objects. Functions and refs
+    /// have no JSON counterpart and become `null`, which no other value of
+    /// the language does.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value as Json;
+        match self {
+            Value::Number(num) => Json::from(*num),
+            // Too large for a JSON number most readers can hold exactly.
+            #[cfg(feature = "bigint")]
+            Value::BigInt(num) => Json::String(num.to_string()),
+            Value::Boolean(b) => Json::Bool(*b),
+            Value::String(s) => Json::String(s.to_string()),
+            Value::Closure(_) | Value::Ref(_) => Json::Null,
+            Value::Tuple(pair) => Json::Array(vec![pair.0.to_json(), pair.1.to_json()]),
+            Value::List(list) => list.iter().map(|element| element.to_json()).collect(),
+            Value::Record(fields) => Json::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_json()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Boolean(_) => "bool",
+        Value::String(_) => "string",
+        Value::Number(_) => "int",
+        #[cfg(feature = "bigint")]
+        Value::BigInt(_) => "int",
+        Value::Closure(_) => "closure",
+        Value::Tuple(_) => "tuple",
+        Value::List(_) => "list",
+        Value::Record(_) => "record",
+        Value::Ref(_) => "ref",
+    }
+}
+
+pub fn type_mismatch(expected: &'static str, value: &Value, location: &Location) -> RuntimeError {
+    RuntimeError::TypeMismatch {
+        expected,
+        found: type_name(value),
+        location: location.clone(),
+    }
+}
+
+pub fn assert_int(value: &Value, location: &Location) -> Result<i64, RuntimeError> {
+    if let Value::Number(num) = value {
+        Ok(*num)
+    } else {
+        Err(type_mismatch("int", value, location))
+    }
+}
+
+pub fn assert_tuple<'a>(
+    value: &'a Value,
+    location: &Location,
+) -> Result<(&'a Value, &'a Value), RuntimeError> {
+    if let Value::Tuple(pair) = value {
+        Ok((&pair.0, &pair.1))
+    } else {
+        Err(type_mismatch("tuple", value, location))
+    }
+}
+
+/// The field `name` of the record `value`.
+pub fn field(value: &Value, name: Symbol, location: &Location) -> Result<Value, RuntimeError> {
+    let Value::Record(fields) = value else {
+        return Err(type_mismatch("record", value, location));
+    };
+    fields
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| RuntimeError::NoField {
+            name: name.to_string(),
+            location: location.clone(),
+        })
+}
+
+pub fn assert_ref<'a>(
+    value: &'a Value,
+    location: &Location,
+) -> Result<&'a RefCell<Value>, RuntimeError> {
+    if let Value::Ref(cell) = value {
+        Ok(cell)
+    } else {
+        Err(type_mismatch("ref", value, location))
+    }
+}
+
+pub fn assert_bool(value: &Value, location: &Location) -> Result<bool, RuntimeError> {
+    if let Value::Boolean(b) = value {
+        Ok(*b)
+    } else {
+        Err(type_mismatch("bool", value, location))
+    }
+}
+
+fn is_int(value: &Value) -> bool {
+    match value {
+        Value::Number(_) => true,
+        #[cfg(feature = "bigint")]
+        Value::BigInt(_) => true,
+        _ => false,
+    }
+}
+
+fn cast_to_string(
+    value: &Value,
+    coercion: Coercion,
+    location: &Location,
+) -> Result<String, RuntimeError> {
+    match value {
+        Value::Number(num) => Ok(num.to_string()),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(num) => Ok(num.to_string()),
+        Value::String(s) => Ok(s.to_string()),
+        Value::Boolean(b) if coercion >= Coercion::Booleans => Ok(b.to_string()),
+        _ if coercion == Coercion::All => Ok(value.to_string()),
+        _ => Err(type_mismatch("string or int", value, location)),
+    }
+}
+
+/// Joins `left` and `right`, for `+` on anything but two integers. Values
+/// other than integers and strings are only coerced next to a string, as
+/// far as `coercion` allows.
+fn concatenate(
+    left: &Value,
+    right: &Value,
+    coercion: Coercion,
+    location: &Location,
+) -> Result<Value, RuntimeError> {
+    let coercion = if matches!(left, Value::String(_)) || matches!(right, Value::String(_)) {
+        coercion
+    } else {
+        Coercion::Spec
+    };
+    let left_val = cast_to_string(left, coercion, location)?;
+    let right_val = cast_to_string(right, coercion, location)?;
+    stats::allocation();
+    Ok(Value::string(format!("{}{}", left_val, right_val)))
+}
+
+impl Closure {
+    /// Whether both are the same closure, as opposed to two closures
+    /// created from the same function.
+    fn ptr_eq(&self, other: &Closure) -> bool {
+        match (self, other) {
+            (Closure::Tree(l), Closure::Tree(r)) => Rc::ptr_eq(l, r),
+            (Closure::Bytecode(l), Closure::Bytecode(r)) => Rc::ptr_eq(l, r),
+            (Closure::Native(l), Closure::Native(r)) => Rc::ptr_eq(l, r),
+            (Closure::Partial(l), Closure::Partial(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+/// Structural equality. Tuples and lists are equal when their elements are,
+/// closures and references only equal themselves, and values of different
+/// types are never equal.
+pub(crate) fn is_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l == r,
+        // Big integers never fit an i64, so they never equal a `Number`.
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(l), Value::BigInt(r)) => l == r,
+        (Value::String(l), Value::String(r)) => l == r,
+        (Value::Boolean(l), Value::Boolean(r)) => l == r,
+        (Value::Closure(l), Value::Closure(r)) => l.ptr_eq(r),
+        (Value::Tuple(l), Value::Tuple(r)) => is_equal(&l.0, &r.0) && is_equal(&l.1, &r.1),
+        (Value::List(l), Value::List(r)) => {
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| is_equal(l, r))
+        }
+        (Value::Record(l), Value::Record(r)) => {
+            l.len() == r.len()
+                && l.iter()
+                    .all(|(name, l)| r.get(name).is_some_and(|r| is_equal(l, r)))
+        }
+        (Value::Ref(l), Value::Ref(r)) => Rc::ptr_eq(l, r),
+        _ => false,
+    }
+}
+
+fn closure_error(operation: &'static str, location: &Location) -> RuntimeError {
+    RuntimeError::Closure {
+        operation,
+        location: location.clone(),
+    }
+}
+
+/// Fails when comparing `left` and `right` would compare a closure, for
+/// configs that do not compare closures.
+fn check_comparable(left: &Value, right: &Value, location: &Location) -> Result<(), RuntimeError> {
+    match (left, right) {
+        (Value::Closure(_), _) | (_, Value::Closure(_)) => Err(closure_error("compare", location)),
+        (Value::Tuple(l), Value::Tuple(r)) => {
+            check_comparable(&l.0, &r.0, location)?;
+            check_comparable(&l.1, &r.1, location)
+        }
+        (Value::List(l), Value::List(r)) if l.len() == r.len() => l
+            .iter()
+            .zip(r.iter())
+            .try_for_each(|(l, r)| check_comparable(l, r, location)),
+        (Value::Record(l), Value::Record(r)) => {
+            l.iter().try_for_each(|(name, l)| match r.get(name) {
+                Some(r) => check_comparable(l, r, location),
+                None => Ok(()),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+fn contains_closure(value: &Value) -> bool {
+    match value {
+        Value::Closure(_) => true,
+        Value::Tuple(pair) => contains_closure(&pair.0) || contains_closure(&pair.1),
+        Value::List(list) => list.iter().any(contains_closure),
+        Value::Record(fields) => fields.values().any(contains_closure),
+        _ => false,
+    }
+}
+
+/// What `print` writes for `value`, failing on closures when `config`
+/// does not print them.
+pub fn show(
+    value: &Value,
+    config: InterpreterConfig,
+    location: &Location,
+) -> Result<String, RuntimeError> {
+    if !config.print_closures && contains_closure(value) {
+        return Err(closure_error("print", location));
+    }
+    Ok(value.to_string())
+}
+
+/// Whether `value` matches `pattern`, pushing what the names of the
+/// pattern bind onto `bindings`, left to right. Nothing pushed is taken
+/// back when the match fails.
+pub fn match_pattern(pattern: &Pattern, value: &Value, bindings: &mut Vec<Value>) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard { .. }, _) => true,
+        (Pattern::Var { .. }, _) => {
+            bindings.push(value.clone());
+            true
+        }
+        (
+            Pattern::Int {
+                value: expected, ..
+            },
+            Value::Number(n),
+        ) => expected == n,
+        (
+            Pattern::Str {
+                value: expected, ..
+            },
+            Value::String(s),
+        ) => expected == s.as_str(),
+        (
+            Pattern::Bool {
+                value: expected, ..
+            },
+            Value::Boolean(b),
+        ) => expected == b,
+        (Pattern::Tuple { first, second, .. }, Value::Tuple(pair)) => {
+            match_pattern(first, &pair.0, bindings) && match_pattern(second, &pair.1, bindings)
+        }
+        _ => false,
+    }
+}
+
+/// Applies an arithmetic operator to two integers. Overflow is reported at
+/// `location`, unless the `bigint` feature promotes the result instead.
+fn arithmetic(
+    left: &Value,
+    right: &Value,
+    op: &BinaryOp,
+    location: &Location,
+) -> Result<Value, RuntimeError> {
+    #[cfg(feature = "bigint")]
+    if bigint::is_big(left) || bigint::is_big(right) {
+        return bigint::arithmetic(left, right, op, location);
+    }
+
+    let left_val = assert_int(left, location)?;
+    let right_val = assert_int(right, location)?;
+    if right_val == 0 && matches!(op, BinaryOp::Div | BinaryOp::Rem) {
+        return Err(RuntimeError::DivisionByZero {
+            location: location.clone(),
+        });
+    }
+    let result = match op {
+        BinaryOp::Add => left_val.checked_add(right_val),
+        BinaryOp::Sub => left_val.checked_sub(right_val),
+        BinaryOp::Mul => left_val.checked_mul(right_val),
+        BinaryOp::Div => left_val.checked_div(right_val),
+        BinaryOp::Rem => left_val.checked_rem(right_val),
+        _ => unreachable!("not an arithmetic operator"),
+    };
+    match result {
+        Some(num) => Ok(Value::Number(num)),
+        #[cfg(feature = "bigint")]
+        None => bigint::arithmetic(left, right, op, location),
+        #[cfg(not(feature = "bigint"))]
+        None => Err(RuntimeError::IntegerOverflow {
+            location: location.clone(),
+        }),
+    }
+}
+
+fn compare(left: &Value, right: &Value, location: &Location) -> Result<Ordering, RuntimeError> {
+    #[cfg(feature = "bigint")]
+    if bigint::is_big(left) || bigint::is_big(right) {
+        return bigint::compare(left, right, location);
+    }
+
+    let left_val = assert_int(left, location)?;
+    let right_val = assert_int(right, location)?;
+    Ok(left_val.cmp(&right_val))
+}
+
+pub fn interpret_binary(
+    left: &Value,
+    right: &Value,
+    op: &BinaryOp,
+    config: InterpreterConfig,
+    location: &Location,
+) -> Result<Value, RuntimeError> {
+    let value = match op {
+        BinaryOp::Add => {
+            if is_int(left) && is_int(right) {
+                arithmetic(left, right, op, location)?
+            } else {
+                concatenate(left, right, config.coercion, location)?
+            }
+        }
+        BinaryOp::Eq | BinaryOp::Neq => {
+            if !config.compare_closures {
+                check_comparable(left, right, location)?;
+            }
+            Value::Boolean(is_equal(left, right) == (*op == BinaryOp::Eq))
+        }
+        BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            arithmetic(left, right, op, location)?
+        }
+        BinaryOp::Lt => Value::Boolean(compare(left, right, location)?.is_lt()),
+        BinaryOp::Gt => Value::Boolean(compare(left, right, location)?.is_gt()),
+        BinaryOp::Lte => Value::Boolean(compare(left, right, location)?.is_le()),
+        BinaryOp::Gte => Value::Boolean(compare(left, right, location)?.is_ge()),
+        BinaryOp::And => {
+            let left_val = assert_bool(left, location)?;
+            let right_val = assert_bool(right, location)?;
+            Value::Boolean(left_val && right_val)
+        }
+        BinaryOp::Or => {
+            let left_val = assert_bool(left, location)?;
+            let right_val = assert_bool(right, location)?;
+            Value::Boolean(left_val || right_val)
+        }
+    };
+    Ok(value)
+}
+
+/// The fields of a record sorted by name, the order they are shown in.
+pub fn sorted_fields(fields: &HashMap<Symbol, Value>) -> Vec<(Symbol, &Value)> {
+    let mut fields: Vec<(Symbol, &Value)> =
+        fields.iter().map(|(name, value)| (*name, value)).collect();
+    fields.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    fields
+}
+
+/// Formats values the way `print` shows them: integers in decimal,
+/// strings without quotes, `true` and `false`, tuples as `(a, b)`, records
+/// as `{x: 1, y: 2}` with their fields sorted by name, every function as
+/// `<#closure>` and every reference as `<#ref>`, as it may hold itself.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(num) => write!(f, "{}", num),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(num) => write!(f, "{}", num),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::String(s) => f.write_str(s),
+            Value::Closure(_) => f.write_str("<#closure>"),
+            Value::Ref(_) => f.write_str("<#ref>"),
+            Value::Tuple(pair) => write!(f, "({}, {})", pair.0, pair.1),
+            Value::List(list) => {
+                f.write_str("[")?;
+                for (i, element) in list.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_str("]")
+            }
+            Value::Record(fields) => {
+                f.write_str("{")?;
+                for (i, (name, value)) in sorted_fields(fields).into_iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Shows the variants as if the payloads were held inline, the way `rinha`
+/// reports the value of a program.
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Boolean(b) => f.debug_tuple("Boolean").field(b).finish(),
+            Value::String(s) => f.debug_tuple("String").field(s).finish(),
+            Value::Number(num) => f.debug_tuple("Number").field(num).finish(),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(num) => f.debug_tuple("BigInt").field(num).finish(),
+            Value::Closure(closure) => f.debug_tuple("Closure").field(closure).finish(),
+            Value::Tuple(pair) => f
+                .debug_tuple("Tuple")
+                .field(&pair.0)
+                .field(&pair.1)
+                .finish(),
+            Value::List(list) => f.debug_tuple("List").field(list).finish(),
+            Value::Record(fields) => f
+                .debug_map()
+                .entries(
+                    sorted_fields(fields)
+                        .into_iter()
+                        .map(|(n, v)| (n.as_str(), v)),
+                )
+                .finish(),
+            Value::Ref(cell) => f.debug_tuple("Ref").field(&cell.borrow()).finish(),
+        }
+    }
+}
@@ -0,0 +1,507 @@
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::Span;
+
+use crate::compiler::{Capture, Instr, Program};
+use crate::config::InterpreterConfig;
+use crate::depth;
+use crate::error::RuntimeError;
+use crate::fuel::Fuel;
+use crate::gc;
+use crate::interrupt;
+use crate::memo::{CallKey, Memo};
+use crate::memory;
+use crate::output::Output;
+use crate::stats;
+use crate::types::Location;
+use crate::value::{self, *};
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: usize,
+    pub captures: Vec<Value>,
+}
+
+impl Drop for Closure {
+    fn drop(&mut self) {
+        stats::closure_dropped();
+    }
+}
+
+pub(crate) struct Frame {
+    pub(crate) closure: Rc<Closure>,
+    pub(crate) ip: usize,
+    /// Stack index of the first argument; the callee sits right below it.
+    pub(crate) base: usize,
+    /// Cache key of the call that created this frame and the effect count
+    /// when it started, if its result may be memoized.
+    memo: Option<(CallKey, usize)>,
+    /// The `tracing` span of the call, open as long as the frame.
+    span: Span,
+}
+
+impl Frame {
+    pub(crate) fn new(closure: Rc<Closure>, ip: usize, base: usize) -> Frame {
+        Frame {
+            closure,
+            ip,
+            base,
+            memo: None,
+            span: Span::none(),
+        }
+    }
+}
+
+/// The span of a call to `closure` at `location` from the frame `caller`.
+fn call_span(caller: Option<&Frame>, closure: &Closure, location: &Location) -> Span {
+    tracing::trace_span!(
+        parent: caller.and_then(|frame| frame.span.id()),
+        "call",
+        function = closure.function,
+        start = location.start,
+    )
+}
+
+/// A program the VM stopped between two instructions, which [`run_until`]
+/// continues from and [`crate::checkpoint`] keeps on disk.
+pub struct Snapshot {
+    pub(crate) stack: Vec<Value>,
+    pub(crate) frames: Vec<Frame>,
+}
+
+/// How [`run_until`] ends when the program does not fail.
+pub enum Exit {
+    Finished(Value),
+    /// Stopped because it was asked to, as it entered a function.
+    Suspended(Snapshot),
+}
+
+struct Vm<'a> {
+    program: &'a Program,
+    output: &'a mut dyn Output,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    memo: Option<Memo>,
+    /// Number of prints and uses of references so far.
+    effects: usize,
+    /// Set from outside to stop the program at its next call.
+    stop: &'a AtomicBool,
+}
+
+/// A function being called: a closure of the program or a builtin, or,
+/// with partial application, a function given fewer arguments than it
+/// takes, which has already been replaced by the partial application it
+/// makes.
+enum Callee {
+    Bytecode(Rc<Closure>),
+    Native(Rc<Native>),
+    Partial,
+}
+
+fn assert_callee(value: Value, location: &Location) -> Result<Callee, RuntimeError> {
+    match &value {
+        Value::Closure(value::Closure::Bytecode(closure)) => Ok(Callee::Bytecode(closure.clone())),
+        Value::Closure(value::Closure::Native(native)) => Ok(Callee::Native(native.clone())),
+        value => Err(type_mismatch("closure", value, location)),
+    }
+}
+
+impl<'a> Vm<'a> {
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("vm stack underflow")
+    }
+
+    /// Checks the callee sitting below `argc` arguments and returns it,
+    /// with the number of arguments it is given. A partial application is
+    /// replaced by the function it applies, with the arguments it holds
+    /// put before the others. A callee that is not a function is reported
+    /// at `callee_location`, where it is written.
+    fn callee(
+        &mut self,
+        mut argc: usize,
+        location: &Location,
+        callee_location: &Location,
+    ) -> Result<(Callee, usize), RuntimeError> {
+        self.fuel.consume(location)?;
+        memory::check(location)?;
+        interrupt::check(location)?;
+        let slot = self.stack.len() - argc - 1;
+        if let Value::Closure(value::Closure::Partial(partial)) = &self.stack[slot] {
+            let partial = partial.clone();
+            self.stack[slot] = Value::Closure(partial.callee.clone());
+            self.stack
+                .splice(slot + 1..slot + 1, partial.arguments.iter().cloned());
+            argc += partial.arguments.len();
+        }
+        let callee = assert_callee(self.stack[slot].clone(), callee_location)?;
+        let arity = match &callee {
+            Callee::Bytecode(closure) => self.program.functions[closure.function].arity,
+            Callee::Native(native) => native.arity,
+            Callee::Partial => unreachable!(),
+        };
+        if argc < arity && self.config.partial_application {
+            let arguments = self.stack.split_off(slot + 1);
+            let Value::Closure(function) = &self.stack[slot] else {
+                unreachable!()
+            };
+            let partial = Partial::new(function, arguments);
+            self.stack[slot] = Value::Closure(value::Closure::Partial(Rc::new(partial)));
+            return Ok((Callee::Partial, argc));
+        }
+        if arity != argc {
+            return Err(RuntimeError::ArityMismatch {
+                expected: arity,
+                found: argc,
+                location: location.clone(),
+            });
+        }
+        Ok((callee, argc))
+    }
+
+    /// Replaces a builtin and the `argc` arguments above it with its result.
+    fn call_native(
+        &mut self,
+        native: &Native,
+        argc: usize,
+        location: &Location,
+    ) -> Result<(), RuntimeError> {
+        if native.effects {
+            self.effects += 1;
+        }
+        let base = self.stack.len() - argc;
+        let result = (native.function)(&self.stack[base..], location)?;
+        self.stack.truncate(base - 1);
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// Pops the current frame, leaving `result` for its caller. Returns
+    /// whether the program is done.
+    fn return_value(&mut self, result: &Value) -> bool {
+        let frame = self.frames.pop().unwrap();
+        if let (Some((key, effects)), Some(cache)) = (frame.memo, &mut self.memo) {
+            if effects == self.effects {
+                cache.insert(key, result.clone());
+            }
+        }
+        self.stack.truncate(frame.base - 1);
+        self.frames.is_empty()
+    }
+
+    fn current_closure(&self) -> &Closure {
+        &self.frames.last().unwrap().closure
+    }
+
+    /// The state of the program, taken right as it enters a function.
+    /// Calls being memoized are left out: their results are not cached.
+    fn suspend(&mut self) -> Exit {
+        let frames = self
+            .frames
+            .drain(..)
+            .map(|frame| Frame::new(frame.closure, frame.ip, frame.base))
+            .collect();
+        Exit::Suspended(Snapshot {
+            stack: std::mem::take(&mut self.stack),
+            frames,
+        })
+    }
+
+    fn run(&mut self) -> Result<Exit, RuntimeError> {
+        let program = self.program;
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let function = &program.functions[frame.closure.function];
+            let ip = frame.ip;
+            let base = frame.base;
+            frame.ip += 1;
+            let location = &function.locations[ip];
+
+            match &function.code[ip] {
+                Instr::Constant(index) => {
+                    let constant = &program.constants[*index];
+                    if let Value::String(_) = constant {
+                        stats::allocation();
+                    }
+                    self.stack.push(constant.clone());
+                }
+                Instr::LoadLocal(slot) => self.stack.push(self.stack[base + slot].clone()),
+                Instr::LoadCapture(index) => {
+                    let value = self.current_closure().captures[*index].clone();
+                    self.stack.push(value);
+                }
+                Instr::LoadCallee => {
+                    let closure = self.frames.last().unwrap().closure.clone();
+                    self.stack
+                        .push(Value::Closure(value::Closure::Bytecode(closure)));
+                }
+                Instr::Unbound(name) => {
+                    return Err(RuntimeError::UnboundVariable {
+                        name: name.to_string(),
+                        location: location.clone(),
+                    })
+                }
+                Instr::MakeClosure(index) => {
+                    let captures = program.functions[*index]
+                        .captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(slot) => self.stack[base + slot].clone(),
+                            Capture::Capture(i) => self.current_closure().captures[*i].clone(),
+                            Capture::Callee => Value::Closure(value::Closure::Bytecode(
+                                self.frames.last().unwrap().closure.clone(),
+                            )),
+                        })
+                        .collect();
+                    stats::closure_created();
+                    let closure = Closure {
+                        function: *index,
+                        captures,
+                    };
+                    self.stack
+                        .push(Value::Closure(value::Closure::Bytecode(Rc::new(closure))));
+                }
+                Instr::MakeTuple => {
+                    let second = self.pop();
+                    let first = self.pop();
+                    stats::allocation();
+                    self.stack.push(Value::tuple(first, second));
+                }
+                Instr::MakeRecord(names) => {
+                    let values = self.stack.split_off(self.stack.len() - names.len());
+                    stats::allocation();
+                    let fields = names.iter().copied().zip(values).collect();
+                    self.stack.push(Value::Record(Rc::new(fields)));
+                }
+                Instr::Field(name) => {
+                    let record = self.pop();
+                    self.stack.push(field(&record, *name, location)?);
+                }
+                Instr::MakeRef => {
+                    let value = self.pop();
+                    stats::allocation();
+                    self.effects += 1;
+                    self.stack.push(gc::new_ref(value));
+                }
+                Instr::Deref => {
+                    let reference = self.pop();
+                    let value = assert_ref(&reference, location)?.borrow().clone();
+                    self.effects += 1;
+                    self.stack.push(value);
+                }
+                Instr::Assign => {
+                    let value = self.pop();
+                    let reference = self.pop();
+                    *assert_ref(&reference, location)?.borrow_mut() = value.clone();
+                    self.effects += 1;
+                    self.stack.push(value);
+                }
+                Instr::First => {
+                    let tuple = self.pop();
+                    let (first, _) = assert_tuple(&tuple, location)?;
+                    self.stack.push(first.clone());
+                }
+                Instr::Second => {
+                    let tuple = self.pop();
+                    let (_, second) = assert_tuple(&tuple, location)?;
+                    self.stack.push(second.clone());
+                }
+                Instr::Unpack => {
+                    let tuple = self.pop();
+                    let (first, second) = assert_tuple(&tuple, location)?;
+                    self.stack.push(first.clone());
+                    self.stack.push(second.clone());
+                }
+                Instr::Print(n) => {
+                    let start = self.stack.len() - n;
+                    let line = self.stack[start..]
+                        .iter()
+                        .map(|value| value::show(value, self.config, location))
+                        .collect::<Result<Vec<_>, _>>()?
+                        .join(" ");
+                    self.output.print(&line);
+                    self.effects += 1;
+                    let last = self.pop();
+                    self.stack.truncate(start);
+                    self.stack.push(last);
+                }
+                Instr::Binary(op) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = interpret_binary(&left, &right, op, self.config, location)?;
+                    self.stack.push(value);
+                }
+                Instr::Jump(target) => self.frames.last_mut().unwrap().ip = *target,
+                Instr::JumpIfFalse(target) => {
+                    let condition = self.pop();
+                    if !assert_bool(&condition, location)? {
+                        self.frames.last_mut().unwrap().ip = *target;
+                    }
+                }
+                Instr::Match(pattern, target) => {
+                    let value = self.pop();
+                    let len = self.stack.len();
+                    if !match_pattern(pattern, &value, &mut self.stack) {
+                        self.stack.truncate(len);
+                        self.frames.last_mut().unwrap().ip = *target;
+                    }
+                }
+                Instr::NoMatch => {
+                    return Err(RuntimeError::NoMatch {
+                        location: location.clone(),
+                    })
+                }
+                Instr::Call(argc) => {
+                    let (closure, argc) =
+                        match self.callee(*argc, location, function.callee_location(ip))? {
+                            (Callee::Bytecode(closure), argc) => (closure, argc),
+                            (Callee::Native(native), argc) => {
+                                self.call_native(&native, argc, location)?;
+                                continue;
+                            }
+                            (Callee::Partial, _) => continue,
+                        };
+                    let base = self.stack.len() - argc;
+                    let mut memo = None;
+                    if let (Some(cache), true) =
+                        (&self.memo, program.functions[closure.function].pure)
+                    {
+                        let callee = value::Closure::Bytecode(closure.clone());
+                        let key = CallKey::new(&callee, &self.stack[base..]);
+                        if let Some(result) = cache.get(&key) {
+                            tracing::debug!(
+                                parent: self.frames.last().and_then(|frame| frame.span.id()),
+                                function = closure.function,
+                                "cache hit"
+                            );
+                            self.stack.truncate(base - 1);
+                            self.stack.push(result);
+                            continue;
+                        }
+                        memo = Some((key, self.effects));
+                    }
+                    // The frame of the program itself is not a call,
+                    // unless a tail call has replaced it.
+                    let main = self.frames[0].closure.function == program.main;
+                    depth::check(self.frames.len() + 1 - usize::from(main), location)?;
+                    let span = call_span(self.frames.last(), &closure, location);
+                    self.frames.push(Frame {
+                        closure,
+                        ip: 0,
+                        base,
+                        memo,
+                        span,
+                    });
+                    if self.stop.load(Ordering::Relaxed) {
+                        return Ok(self.suspend());
+                    }
+                }
+                Instr::TailCall(argc) => {
+                    let (closure, argc) =
+                        match self.callee(*argc, location, function.callee_location(ip))? {
+                            (Callee::Bytecode(closure), argc) => (closure, argc),
+                            // A builtin or a partial application returns right
+                            // away, so its result is the result of the current
+                            // frame.
+                            (callee, argc) => {
+                                if let Callee::Native(native) = callee {
+                                    self.call_native(&native, argc, location)?;
+                                }
+                                let result = self.pop();
+                                if self.return_value(&result) {
+                                    return Ok(Exit::Finished(result));
+                                }
+                                self.stack.push(result);
+                                continue;
+                            }
+                        };
+                    let start = self.stack.len() - argc - 1;
+                    self.stack.drain(base - 1..start);
+                    let caller = self.frames.len().checked_sub(2).map(|i| &self.frames[i]);
+                    let span = call_span(caller, &closure, location);
+                    let frame = self.frames.last_mut().unwrap();
+                    frame.closure = closure;
+                    frame.ip = 0;
+                    frame.span = span;
+                    if self.stop.load(Ordering::Relaxed) {
+                        return Ok(self.suspend());
+                    }
+                }
+                Instr::Slide(n) => {
+                    let top = self.pop();
+                    let len = self.stack.len();
+                    self.stack.truncate(len - n);
+                    self.stack.push(top);
+                }
+                Instr::Return => {
+                    let result = self.pop();
+                    if self.return_value(&result) {
+                        return Ok(Exit::Finished(result));
+                    }
+                    self.stack.push(result);
+                }
+            }
+        }
+    }
+}
+
+pub fn run(
+    program: &Program,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    output: &mut dyn Output,
+) -> Result<Value, RuntimeError> {
+    match run_until(
+        program,
+        None,
+        memoize,
+        config,
+        fuel,
+        output,
+        &AtomicBool::new(false),
+    )? {
+        Exit::Finished(value) => Ok(value),
+        Exit::Suspended(_) => unreachable!("nothing asked the program to stop"),
+    }
+}
+
+/// Runs `program` from the start, or from `snapshot` when one is given,
+/// until it finishes or `stop` is set, which it checks every time it enters
+/// a function. A run from a snapshot always gets to the next call, even
+/// when `stop` is already set.
+pub fn run_until(
+    program: &Program,
+    snapshot: Option<Snapshot>,
+    memoize: bool,
+    config: InterpreterConfig,
+    fuel: Fuel,
+    output: &mut dyn Output,
+    stop: &AtomicBool,
+) -> Result<Exit, RuntimeError> {
+    let snapshot = snapshot.unwrap_or_else(|| {
+        stats::closure_created();
+        let main = Rc::new(Closure {
+            function: program.main,
+            captures: Vec::new(),
+        });
+        Snapshot {
+            stack: vec![Value::Closure(value::Closure::Bytecode(main.clone()))],
+            frames: vec![Frame::new(main, 0, 1)],
+        }
+    });
+    let mut vm = Vm {
+        program,
+        output,
+        config,
+        fuel,
+        stack: snapshot.stack,
+        frames: snapshot.frames,
+        memo: memoize.then(Memo::default),
+        effects: 0,
+        stop,
+    };
+    vm.run()
+}
@@ -0,0 +1,116 @@
+//! `--watch`: runs a program again every time one of its files changes.
+//!
+//! Each run is a child process given the same arguments without
+//! `--watch`, so a program that fails or is stopped with ctrl-c ends like
+//! any other run while the watcher keeps going.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use rinha::error::SourceMap;
+use rinha::modules;
+
+use crate::cli::{Format, Options};
+
+/// How long to wait for more changes after one, as editors often write a
+/// file in several steps.
+const SETTLE: Duration = Duration::from_millis(100);
+
+pub fn run(options: &Options) -> ! {
+    let exe = env::current_exe().expect("Falha ao localizar o executável do rinha");
+    let args: Vec<String> = env::args().skip(1).filter(|arg| arg != "--watch").collect();
+    let (sender, events) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            eprintln!("error: cannot watch files: {}", err);
+            process::exit(1);
+        }
+    };
+    loop {
+        // The files are watched before the run starts, so that changes
+        // saved while it runs start the next one.
+        let files = files(options);
+        let dirs = watch(&mut watcher, &files);
+        while events.try_recv().is_ok() {}
+        // Clears the screen and moves the cursor to its top.
+        print!("\x1b[2J\x1b[H");
+        let _ = io::stdout().flush();
+        match Command::new(&exe).args(&args).status() {
+            Ok(status) if !status.success() => eprintln!("\n[{}]", status),
+            Ok(_) => {}
+            Err(err) => eprintln!("error: cannot run {}: {}", exe.display(), err),
+        }
+        eprintln!("\nwatching {} file(s) for changes", files.len());
+        wait(&events, &files);
+        for dir in dirs {
+            let _ = watcher.unwatch(&dir);
+        }
+    }
+}
+
+/// The file of the program and, with `--extensions modules`, the files it
+/// imports, as far as they can be read. They are looked up again before
+/// every run, as the imports may have changed.
+fn files(options: &Options) -> HashSet<PathBuf> {
+    let mut names = vec![options.input.clone()];
+    let source = fs::read_to_string(&options.input).ok();
+    let parsed = source
+        .filter(|_| options.syntax.modules && options.format == Format::Source)
+        .and_then(|source| rinha::parse_with(&source, &options.input, options.syntax).ok());
+    if let Some(file) = parsed {
+        let mut sources = SourceMap::new();
+        // Files read before an import failed are watched all the same.
+        let _ = modules::link(file, &options.input, options.syntax, &mut sources);
+        names.extend(sources.filenames().map(str::to_string));
+    }
+    names.iter().map(|name| absolute(Path::new(name))).collect()
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Starts watching the directories holding `files`, which it returns.
+/// Directories are watched rather than the files themselves, since editors
+/// that save by replacing a file would otherwise leave the old one watched.
+fn watch(watcher: &mut RecommendedWatcher, files: &HashSet<PathBuf>) -> HashSet<PathBuf> {
+    let dirs: HashSet<PathBuf> = files
+        .iter()
+        .filter_map(|file| file.parent())
+        .map(Path::to_path_buf)
+        .collect();
+    for dir in &dirs {
+        if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            eprintln!("error: cannot watch {}: {}", dir.display(), err);
+            process::exit(1);
+        }
+    }
+    dirs
+}
+
+/// Blocks until one of `files` changes, or returns right away if one
+/// already changed since they were watched.
+fn wait(events: &Receiver<notify::Result<Event>>, files: &HashSet<PathBuf>) {
+    // Runs read the files they watch, which is no reason to run again.
+    let changed = |event: &notify::Result<Event>| match event {
+        Ok(event) if !event.kind.is_access() => event
+            .paths
+            .iter()
+            .any(|path| files.contains(&absolute(path))),
+        _ => false,
+    };
+    while let Ok(event) = events.recv() {
+        if changed(&event) {
+            break;
+        }
+    }
+    while events.recv_timeout(SETTLE).is_ok() {}
+}
@@ -0,0 +1,275 @@
+//! Checks integer and boolean operators against the same operations in
+//! Rust on randomly generated expressions, with every engine and with and
+//! without the optimizer.
+
+use proptest::prelude::*;
+use rinha::{optimizer, Engine, Fuel, InterpreterConfig, Value};
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Int(i64),
+    Bool(bool),
+    Binary(Box<Expr>, &'static str, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Expected {
+    Int(i64),
+    Bool(bool),
+    Error(&'static str),
+}
+
+impl Expr {
+    fn to_source(&self) -> String {
+        match self {
+            Expr::Int(n) if *n < 0 => format!("({})", n),
+            Expr::Int(n) => n.to_string(),
+            Expr::Bool(b) => b.to_string(),
+            Expr::Binary(lhs, op, rhs) => {
+                format!("({} {} {})", lhs.to_source(), op, rhs.to_source())
+            }
+        }
+    }
+
+    /// What the interpreter should compute: checked i64 arithmetic,
+    /// short-circuiting `&&` and `||`, and errors named like the
+    /// interpreter's.
+    fn eval(&self) -> Expected {
+        let Expr::Binary(lhs, op, rhs) = self else {
+            return match self {
+                Expr::Int(n) => Expected::Int(*n),
+                Expr::Bool(b) => Expected::Bool(*b),
+                Expr::Binary(..) => unreachable!(),
+            };
+        };
+        let left = lhs.eval();
+        match (&left, *op) {
+            (Expected::Error(_), _) => return left,
+            (Expected::Bool(false), "&&") => return left,
+            (Expected::Bool(true), "||") => return left,
+            _ => {}
+        }
+        let right = rhs.eval();
+        match (left, right) {
+            (_, Expected::Error(err)) => Expected::Error(err),
+            (Expected::Int(l), Expected::Int(r)) => {
+                let result = match *op {
+                    "/" | "%" if r == 0 => return Expected::Error("division by zero"),
+                    "+" => l.checked_add(r),
+                    "-" => l.checked_sub(r),
+                    "*" => l.checked_mul(r),
+                    "/" => l.checked_div(r),
+                    "%" => l.checked_rem(r),
+                    "==" => return Expected::Bool(l == r),
+                    "!=" => return Expected::Bool(l != r),
+                    "<" => return Expected::Bool(l < r),
+                    ">" => return Expected::Bool(l > r),
+                    "<=" => return Expected::Bool(l <= r),
+                    ">=" => return Expected::Bool(l >= r),
+                    _ => unreachable!("{} is not an integer operator", op),
+                };
+                result.map_or(Expected::Error("integer overflow"), Expected::Int)
+            }
+            (Expected::Bool(l), Expected::Bool(r)) => match *op {
+                "&&" | "||" => Expected::Bool(r),
+                "==" => Expected::Bool(l == r),
+                "!=" => Expected::Bool(l != r),
+                _ => unreachable!("{} is not a boolean operator", op),
+            },
+            _ => unreachable!("operands of different types"),
+        }
+    }
+}
+
+fn int_literal() -> impl Strategy<Value = i64> {
+    prop_oneof![
+        4 => -10i64..10,
+        2 => any::<i64>(),
+        1 => Just(i64::MAX),
+        1 => Just(i64::MIN),
+    ]
+}
+
+/// Expressions of type `int` when `boolean` is false and `bool` otherwise.
+fn expr(boolean: bool, depth: u32) -> BoxedStrategy<Expr> {
+    let leaf = if boolean {
+        any::<bool>().prop_map(Expr::Bool).boxed()
+    } else {
+        int_literal().prop_map(Expr::Int).boxed()
+    };
+    if depth == 0 {
+        return leaf;
+    }
+    let binary = |boolean, ops: &'static [&'static str]| {
+        (
+            expr(boolean, depth - 1),
+            prop::sample::select(ops),
+            expr(boolean, depth - 1),
+        )
+            .prop_map(|(lhs, op, rhs)| Expr::Binary(Box::new(lhs), op, Box::new(rhs)))
+    };
+    if boolean {
+        prop_oneof![
+            1 => leaf,
+            2 => binary(false, &["==", "!=", "<", ">", "<=", ">="]),
+            2 => binary(true, &["&&", "||", "==", "!="]),
+        ]
+        .boxed()
+    } else {
+        prop_oneof![
+            1 => leaf,
+            3 => binary(false, &["+", "-", "*", "/", "%"]),
+        ]
+        .boxed()
+    }
+}
+
+fn actual(source: &str, engine: Engine, optimize: bool) -> Expected {
+    let mut file = rinha::parse(source, "expr.rinha").unwrap();
+    if optimize {
+        file = optimizer::optimize(&file);
+    }
+    let mut lines = Vec::new();
+    match engine.run(
+        &file,
+        false,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        &mut lines,
+    ) {
+        Ok(Value::Number(n)) => Expected::Int(n),
+        Ok(Value::Boolean(b)) => Expected::Bool(b),
+        Ok(value) => panic!("unexpected value {:?}", value),
+        Err(err) => match err.to_string().as_str() {
+            "division by zero" => Expected::Error("division by zero"),
+            "integer overflow" => Expected::Error("integer overflow"),
+            message => panic!("unexpected error: {}", message),
+        },
+    }
+}
+
+fn check(expr: &Expr) -> Result<(), TestCaseError> {
+    let expected = expr.eval();
+    // Integers that overflow become big integers instead.
+    if cfg!(feature = "bigint") && expected == Expected::Error("integer overflow") {
+        return Ok(());
+    }
+    let body = expr.to_source();
+    // Inside a function the JIT and the VM run their compiled code.
+    let sources = [body.clone(), format!("let f = fn () => {};\nf()", body)];
+    for source in &sources {
+        for engine in [Engine::Tree, Engine::Vm, Engine::Jit] {
+            for optimize in [false, true] {
+                prop_assert_eq!(
+                    &actual(source, engine, optimize),
+                    &expected,
+                    "{:?} (optimized: {}) on {}",
+                    engine,
+                    optimize,
+                    source
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn literals_cover_the_range_of_i64() {
+    for engine in [Engine::Tree, Engine::Vm] {
+        let min = actual("-9223372036854775808", engine, false);
+        assert_eq!(min, Expected::Int(i64::MIN), "{}", engine.name());
+    }
+    for source in [
+        "9223372036854775808",
+        "-9223372036854775809",
+        "99999999999999999999",
+    ] {
+        let err = rinha::parse(source, "arithmetic.rinha").unwrap_err();
+        assert!(err.message.ends_with("is too large"), "{}", err.message);
+    }
+    let err = rinha::parse("1 + 9223372036854775808", "arithmetic.rinha").unwrap_err();
+    assert_eq!(
+        err.message,
+        "integer literal 9223372036854775808 is too large"
+    );
+    assert_eq!((err.location.start, err.location.end), (4, 23));
+}
+
+#[test]
+fn integers_are_exact_and_division_truncates() {
+    for engine in Engine::ALL {
+        // 2^53 + 1 has no exact `f64`.
+        let big = actual("9007199254740992 + 1", engine, false);
+        assert_eq!(big, Expected::Int(9007199254740993), "{}", engine.name());
+        assert_eq!(actual("-7 / 2", engine, false), Expected::Int(-3));
+        assert_eq!(actual("-7 % 2", engine, false), Expected::Int(-1));
+    }
+}
+
+#[test]
+fn and_and_or_only_evaluate_the_right_operand_when_needed() {
+    for engine in Engine::ALL {
+        for optimize in [false, true] {
+            let skipped = |source| actual(source, engine, optimize);
+            assert_eq!(skipped("false && 1 / 0 == 0"), Expected::Bool(false));
+            assert_eq!(skipped("true || 1 / 0 == 0"), Expected::Bool(true));
+            let evaluated = Expected::Error("division by zero");
+            assert_eq!(skipped("true && 1 / 0 == 0"), evaluated);
+            assert_eq!(skipped("false || 1 / 0 == 0"), evaluated);
+        }
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+#[test]
+fn overflow_is_an_error() {
+    for engine in Engine::ALL {
+        for source in [
+            "9223372036854775807 + 1",
+            "-9223372036854775807 - 2",
+            "4611686018427387904 * 2",
+            "-9223372036854775808 / -1",
+        ] {
+            let overflow = Expected::Error("integer overflow");
+            assert_eq!(actual(source, engine, false), overflow, "{}", source);
+        }
+    }
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn overflow_promotes_to_big_integers() {
+    let fact = "let fact = fn (n) => if (n == 0) { 1 } else { n * fact(n - 1) };\nfact(25)";
+    for engine in Engine::ALL {
+        let value = |source| {
+            let file = rinha::parse(source, "bigint.rinha").unwrap();
+            let config = InterpreterConfig::STRICT;
+            let mut lines = Vec::new();
+            engine
+                .run(&file, false, config, Fuel::unlimited(), &mut lines)
+                .unwrap()
+        };
+        let big = value(fact).to_string();
+        assert_eq!(big, "15511210043330985984000000", "{}", engine.name());
+        // Big integers that fit again become machine integers.
+        let narrowed = value("(9223372036854775807 + 1) - 1");
+        assert!(
+            matches!(narrowed, Value::Number(i64::MAX)),
+            "{}",
+            engine.name()
+        );
+    }
+}
+
+proptest! {
+    #[test]
+    fn integer_expressions(expr in expr(false, 4)) {
+        check(&expr)?;
+    }
+
+    #[test]
+    fn boolean_expressions(expr in expr(true, 4)) {
+        check(&expr)?;
+    }
+}
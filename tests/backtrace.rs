@@ -0,0 +1,80 @@
+//! Checks the calls a failed run reports as its backtrace, and how
+//! `rinha` prints them.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use rinha::interpreter::{self, CallSite};
+use rinha::{Fuel, InterpreterConfig};
+
+const SOURCE: &str = "let fail = fn (n) => if (n == 0) { 1 / 0 } else { 1 + fail(n - 1) };
+let work = fn (n) => 0 + fail(n);
+let ok = fn (n) => n;
+ok(3) + work(2)";
+
+fn backtrace(parallel: bool) -> Vec<CallSite> {
+    let file = rinha::parse(SOURCE, "backtrace.rinha").unwrap();
+    let (result, backtrace) = interpreter::interpret_file_backtrace(
+        &file,
+        false,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        parallel,
+        &mut Vec::new(),
+    );
+    assert!(result.is_err());
+    backtrace
+}
+
+fn calls(backtrace: &[CallSite]) -> Vec<(Option<&str>, usize)> {
+    backtrace
+        .iter()
+        .map(|call| (call.name.as_deref(), call.location.start))
+        .collect()
+}
+
+#[test]
+fn lists_the_calls_active_at_the_error() {
+    let backtrace = backtrace(false);
+    assert_eq!(
+        calls(&backtrace),
+        [
+            (Some("work"), 133),
+            (Some("fail"), 94),
+            (Some("fail"), 54),
+            (Some("fail"), 54),
+        ]
+    );
+}
+
+#[test]
+fn keeps_the_calls_of_a_worker_that_failed() {
+    // `work(2)` runs on a worker, where every call of the backtrace is made.
+    assert_eq!(calls(&backtrace(true)), calls(&backtrace(false)));
+}
+
+#[test]
+fn prints_the_innermost_calls_first() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["--source", "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let source = SOURCE.replace("work(2)", "work(12)");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let (_, backtrace) = stderr.split_once("backtrace:\n").unwrap();
+    // Only the innermost ten of the calls of `fail` are listed.
+    let expected = format!(
+        "{}  ... and 3 more\n",
+        "  fail (<stdin>:1:12) called at <stdin>:1:55\n".repeat(10)
+    );
+    assert_eq!(backtrace, expected);
+}
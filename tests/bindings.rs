@@ -0,0 +1,147 @@
+//! Programs made of many consecutive bindings, which every stage handles
+//! in a loop rather than nesting a call for each one.
+
+use rinha::error::SourceMap;
+use rinha::parser::Syntax;
+use rinha::{
+    complexity, lint, modules, optimizer, packed, peval, stats, typecheck, validate, Engine, Fuel,
+    InterpreterConfig,
+};
+
+const BINDINGS: usize = 20_000;
+
+/// A program binding `BINDINGS` names in every way there is, with the
+/// number it prints.
+fn program() -> (String, usize) {
+    let mut printed = 0;
+    let mut source = String::from("let x0 = 0;\n");
+    for i in 1..BINDINGS {
+        match i % 4 {
+            0 => {
+                source.push_str(&format!("let x{} = x{} + 1;\n", i, i - 1));
+                printed += 1;
+            }
+            1 => source.push_str(&format!("let x{} = x{};\n", i, i - 1)),
+            2 => {
+                source.push_str(&format!("let (x{}, _) = (x{} + 1, 0);\n", i, i - 1));
+                printed += 1;
+            }
+            _ => source.push_str(&format!(
+                "let f{} = fn () => x{};\nlet x{} = f{}();\n",
+                i,
+                i - 1,
+                i,
+                i
+            )),
+        }
+    }
+    source.push_str(&format!("print(x{})", BINDINGS - 1));
+    (source, printed)
+}
+
+#[test]
+fn long_chains_of_let_do_not_overflow_the_stack() {
+    let (source, expected) = program();
+    let file = rinha::parse(&source, "bindings.rinha").unwrap();
+    assert!(validate::validate(&file, InterpreterConfig::STRICT).is_empty());
+    let optimized = optimizer::optimize(&file);
+    let specialized = peval::specialize(&optimized, InterpreterConfig::STRICT);
+    let printed = rinha::parse(&specialized.expression.to_source(), "bindings.rinha").unwrap();
+    let expected = expected.to_string();
+    for file in [&file, &optimized, &specialized, &printed] {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut lines = Vec::new();
+            engine
+                .run(
+                    file,
+                    false,
+                    InterpreterConfig::STRICT,
+                    Fuel::unlimited(),
+                    &mut lines,
+                )
+                .unwrap();
+            assert_eq!(lines, [expected.as_str()], "{}", engine.name());
+        }
+    }
+}
+
+#[test]
+fn binding_names_and_literals_copies_no_environment() {
+    let mut source = String::new();
+    for i in 0..1000 {
+        source.push_str(&format!("let x{} = {};\n", i, i));
+    }
+    source.push_str("x999");
+    let file = rinha::parse(&source, "bindings.rinha").unwrap();
+    stats::reset();
+    let mut lines = Vec::new();
+    Engine::Tree
+        .run(
+            &file,
+            false,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            &mut lines,
+        )
+        .unwrap();
+    assert!(stats::snapshot().env_clones < 10);
+}
+
+/// `count` bindings of `x` to the next number, as source and as the JSON
+/// AST, which has no other way to write them than nested in each other.
+fn numbers(count: usize) -> (String, String) {
+    let location = r#"{"start":0,"end":0,"filename":"numbers.rinha"}"#;
+    let mut source = String::new();
+    let mut json = String::new();
+    for i in 0..count {
+        source.push_str(&format!("let x = {};\n", i));
+        json.push_str(&format!(
+            r#"{{"kind":"Let","name":{{"text":"x","location":{0}}},"value":{{"kind":"Int","value":{1},"location":{0}}},"next":"#,
+            location, i
+        ));
+    }
+    source.push_str("print(x)");
+    json.push_str(&format!(
+        r#"{{"kind":"Print","value":{{"kind":"Var","text":"x","location":{0}}},"location":{0}}}"#,
+        location
+    ));
+    json.push_str(&format!(r#","location":{}}}"#, location).repeat(count));
+    let json = format!(
+        r#"{{"name":"numbers.rinha","expression":{},"location":{}}}"#,
+        json, location
+    );
+    (source, json)
+}
+
+#[test]
+fn every_command_takes_100k_bindings() {
+    let (source, json) = numbers(100_000);
+    let file = rinha::parse_with(&source, "numbers.rinha", Syntax::all()).unwrap();
+    // What `rinha check`, `lint` and `stats` load.
+    let file = modules::link(file, "numbers.rinha", Syntax::all(), &mut SourceMap::new()).unwrap();
+    // `rinha check` and the language server.
+    assert_eq!(typecheck::check(&file).unwrap().to_string(), "int");
+    // `rinha lint`.
+    assert!(lint::lint(&file, InterpreterConfig::STRICT).is_empty());
+    // `rinha stats`.
+    complexity::analyze(&file);
+    // `rinha pack`, and running what it wrote.
+    let unpacked = packed::unpack(&packed::pack(&file)).unwrap();
+    // Reading the JSON AST, and writing it back. `rinha emit-ast` indents
+    // it, which takes room as deep as the program is long.
+    let loaded = rinha::parse_json(&json).unwrap();
+    let written = rinha::parse_json(&serde_json::to_string(&file).unwrap()).unwrap();
+    for file in [&file, &unpacked, &loaded, &written] {
+        let mut lines = Vec::new();
+        Engine::Vm
+            .run(
+                file,
+                false,
+                InterpreterConfig::STRICT,
+                Fuel::unlimited(),
+                &mut lines,
+            )
+            .unwrap();
+        assert_eq!(lines, ["99999"]);
+    }
+}
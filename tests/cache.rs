@@ -0,0 +1,78 @@
+//! Checks that programs stored in a cache run the same once loaded back.
+
+use std::fs;
+use std::process;
+
+use rinha::cache::Cache;
+use rinha::parser::Syntax;
+use rinha::{compiler, optimizer, vm, Fuel, InterpreterConfig};
+
+const SOURCE: &str = r#"
+let sum = fn (list) => {
+  if (length(list) == 0) { 0 } else { head(list) + sum(tail(list)) }
+};
+let _ = print("sum: " + sum(cons(1, cons(2, cons(3, nil)))));
+(true, "done")
+"#;
+
+fn run(program: &compiler::Program) -> Vec<String> {
+    let mut lines = Vec::new();
+    let value = vm::run(
+        program,
+        true,
+        InterpreterConfig::EXTENDED,
+        Fuel::unlimited(),
+        &mut lines,
+    )
+    .unwrap();
+    lines.push(value.to_string());
+    lines
+}
+
+#[test]
+fn stored_programs_run_the_same() {
+    let dir = std::env::temp_dir().join(format!("rinha-cache-{}", process::id()));
+    let cache = Cache::new(&dir);
+    let key = Cache::key("cache.rinha", SOURCE, false, Syntax::default(), true);
+    assert!(cache.load(&key, SOURCE).is_none());
+
+    let file = rinha::parse(SOURCE, "cache.rinha").unwrap();
+    let program = compiler::compile(&optimizer::optimize(&file), InterpreterConfig::EXTENDED);
+    cache.store(&key, SOURCE, &program).unwrap();
+    let loaded = cache.load(&key, SOURCE).unwrap();
+    assert_eq!(run(&loaded), run(&program));
+    assert_eq!(run(&loaded), ["sum: 6", "(true, done)"]);
+
+    // A key shared with other contents, as when two inputs collide, does
+    // not load the program of the first.
+    assert!(cache.load(&key, "print(1)").is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keys_cover_everything_the_bytecode_depends_on() {
+    let key = Cache::key("cache.rinha", SOURCE, false, Syntax::default(), true);
+    // Keys are the same from one build of rinha to the next.
+    assert_eq!(
+        key,
+        Cache::key("cache.rinha", SOURCE, false, Syntax::default(), true)
+    );
+    assert_eq!(key.len(), 16);
+    // Errors point into the file the program was compiled from.
+    assert_ne!(
+        key,
+        Cache::key("other.rinha", SOURCE, false, Syntax::default(), true)
+    );
+    assert_ne!(
+        key,
+        Cache::key("cache.rinha", SOURCE, true, Syntax::default(), true)
+    );
+    assert_ne!(
+        key,
+        Cache::key("cache.rinha", SOURCE, false, Syntax::default(), false)
+    );
+    assert_ne!(
+        key,
+        Cache::key("cache.rinha", SOURCE, false, Syntax::all(), true)
+    );
+}
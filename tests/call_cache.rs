@@ -0,0 +1,57 @@
+//! Checks the cache each call keeps of the function it went to last.
+
+use std::rc::Rc;
+
+use rinha::types::{CallCache, Term};
+use rinha::{Engine, Fuel, InterpreterConfig};
+
+fn body(source: &str) -> Rc<Term> {
+    rinha::parse(source, "call_cache.rinha").unwrap().expression
+}
+
+#[test]
+fn holds_the_last_body_set() {
+    let (first, second) = (body("1"), body("print(2)"));
+    let cache = CallCache::default();
+    assert_eq!(cache.get(&first), None);
+    cache.set(&first, true);
+    assert_eq!(cache.get(&first), Some(true));
+    assert_eq!(cache.get(&second), None);
+    cache.set(&second, false);
+    assert_eq!(cache.get(&second), Some(false));
+    assert_eq!(cache.get(&first), None);
+    // Copies of a term check their calls again.
+    assert_eq!(cache.clone().get(&second), None);
+}
+
+#[test]
+fn calls_going_to_other_functions_check_them_again() {
+    let run = |source| {
+        let file = rinha::parse(source, "call_cache.rinha").unwrap();
+        let mut lines = Vec::new();
+        let result = Engine::Tree.run(
+            &file,
+            true,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            &mut lines,
+        );
+        (result.map(|value| value.to_string()), lines)
+    };
+    // The same call checks the arity of each function it goes to.
+    let source = "let apply = fn (g) => g(1);
+let one = fn (x) => x;
+let two = fn (x, y) => x;
+apply(one) + apply(two)";
+    let (result, _) = run(source);
+    let err = result.unwrap_err().to_string();
+    assert_eq!(err, "expected 2 arguments but instead got 1");
+    // And only caches the results of those that are pure.
+    let source = "let apply = fn (g, x) => g(x);
+let same = fn (x) => x;
+let noisy = fn (x) => print(x);
+apply(same, 1) + apply(noisy, 2) + apply(noisy, 2)";
+    let (result, lines) = run(source);
+    assert_eq!(result.unwrap(), "5");
+    assert_eq!(lines, ["2", "2"]);
+}
@@ -0,0 +1,61 @@
+//! Checks the functions C programs call, as a C program would call them.
+
+#![cfg(feature = "capi")]
+
+use std::ffi::{CStr, CString};
+
+use rinha::capi::{rinha_free_result, rinha_run_json};
+
+fn run(json: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let json = CString::new(json).unwrap();
+    let text = |text: *mut std::ffi::c_char| {
+        (!text.is_null()).then(|| {
+            unsafe { CStr::from_ptr(text) }
+                .to_str()
+                .unwrap()
+                .to_string()
+        })
+    };
+    unsafe {
+        let result = rinha_run_json(json.as_ptr());
+        let texts = (
+            text((*result).output),
+            text((*result).value),
+            text((*result).error),
+        );
+        rinha_free_result(result);
+        texts
+    }
+}
+
+#[test]
+fn runs_json_asts() {
+    let file = rinha::parse("let x = print(20); x + 22", "capi.rinha").unwrap();
+    let (output, value, error) = run(&file.to_json());
+    assert_eq!(output.as_deref(), Some("20\n"));
+    assert_eq!(value.as_deref(), Some("42"));
+    assert_eq!(error, None);
+}
+
+#[test]
+fn reports_errors_instead_of_a_value() {
+    let file = rinha::parse("let _ = print(1); 1 / 0", "capi.rinha").unwrap();
+    let (output, value, error) = run(&file.to_json());
+    assert_eq!(output.as_deref(), Some("1\n"));
+    assert_eq!(value, None);
+    assert_eq!(error.as_deref(), Some("division by zero"));
+
+    let (_, value, error) = run("[]");
+    assert_eq!(value, None);
+    assert!(error.unwrap().starts_with("invalid JSON AST: "));
+}
+
+#[test]
+fn null_is_an_error_and_frees_nothing() {
+    unsafe {
+        let result = rinha_run_json(std::ptr::null());
+        assert!(!(*result).error.is_null());
+        rinha_free_result(result);
+        rinha_free_result(std::ptr::null_mut());
+    }
+}
@@ -0,0 +1,94 @@
+//! Checks that a program stopped by the VM and loaded back from a
+//! checkpoint goes on exactly as if it had never stopped.
+
+use std::fs;
+use std::process;
+use std::sync::atomic::AtomicBool;
+
+use rinha::parser::{self, Syntax};
+use rinha::vm::{self, Exit};
+use rinha::{checkpoint, compiler, Fuel, InterpreterConfig};
+
+const SOURCE: &str = r#"
+let counter = ref(0);
+let tick = fn () => { counter := !counter + 1 };
+let self = ref(fn (n) => { n });
+let _ = self := fn (n) => { if (n == 0) { !counter } else { let _ = tick(); (!self)(n - 1) } };
+let shared = ("shared", cons(1, cons(2, nil)));
+let point = { x: shared, y: shared };
+let sum = fn (list) => { if (length(list) == 0) { 0 } else { head(list) + sum(tail(list)) } };
+let _ = print((!self)(5));
+let _ = print(sum(cons(3, second(point.x))));
+(point, first(point.y) + " again")
+"#;
+
+fn compile() -> compiler::Program {
+    let file = parser::parse_with(SOURCE, "checkpoint.rinha", Syntax::all()).unwrap();
+    compiler::compile(&file, InterpreterConfig::EXTENDED)
+}
+
+#[test]
+fn resumed_programs_run_the_same() {
+    let program = compile();
+    let mut expected = Vec::new();
+    let value = vm::run(
+        &program,
+        true,
+        InterpreterConfig::EXTENDED,
+        Fuel::unlimited(),
+        &mut expected,
+    )
+    .unwrap();
+    expected.push(value.to_string());
+
+    // Stop at every function the program enters and continue from a
+    // checkpoint each time.
+    let path = std::env::temp_dir().join(format!("rinha-checkpoint-{}.json", process::id()));
+    let stop = AtomicBool::new(true);
+    let mut lines = Vec::new();
+    let mut stops = 0;
+    let value = loop {
+        let (program, snapshot) = if stops == 0 {
+            (compile(), None)
+        } else {
+            let (program, snapshot) = checkpoint::load(&path).unwrap();
+            (program, Some(snapshot))
+        };
+        let exit = vm::run_until(
+            &program,
+            snapshot,
+            true,
+            InterpreterConfig::EXTENDED,
+            Fuel::unlimited(),
+            &mut lines,
+            &stop,
+        );
+        match exit.unwrap() {
+            Exit::Finished(value) => break value,
+            Exit::Suspended(snapshot) => {
+                checkpoint::save(&path, &program, &snapshot).unwrap();
+                stops += 1;
+            }
+        }
+    };
+    lines.push(value.to_string());
+    fs::remove_file(&path).unwrap();
+    assert!(stops > 10, "{} stops", stops);
+    assert_eq!(lines, expected);
+    assert_eq!(
+        lines,
+        [
+            "5",
+            "6",
+            "({x: (shared, [1, 2]), y: (shared, [1, 2])}, shared again)"
+        ]
+    );
+}
+
+#[test]
+fn broken_checkpoints_are_rejected() {
+    let path = std::env::temp_dir().join(format!("rinha-broken-{}.json", process::id()));
+    fs::write(&path, "{\"version\": \"0.0.0\"}").unwrap();
+    assert!(checkpoint::load(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
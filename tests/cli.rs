@@ -0,0 +1,290 @@
+//! Checks the options of `rinha run` and `rinha eval` by running the
+//! binary, mostly on programs read from stdin.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// Runs `rinha <args> --source -` with `source` on stdin.
+fn rinha(args: &[&str], source: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(args)
+        .args(["--source", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+fn stderr(output: &Output) -> &str {
+    std::str::from_utf8(&output.stderr).unwrap()
+}
+
+#[test]
+fn runtime_errors_are_diagnostics_rather_than_panics() {
+    let programs = [
+        ("print(1 + true)", "expected string or int but found bool"),
+        ("print(x)", "cannot find variable x"),
+        (
+            "let f = fn (a) => a;\nf(1, 2)",
+            "expected 1 arguments but instead got 2",
+        ),
+    ];
+    for (source, message) in programs {
+        let output = rinha(&[], source);
+        assert_eq!(output.status.code(), Some(1), "{}", source);
+        let stderr = stderr(&output);
+        assert!(
+            stderr.starts_with(&format!("error: {}\n", message)),
+            "{}",
+            stderr
+        );
+        assert!(!stderr.contains("panicked"), "{}", stderr);
+    }
+}
+
+#[test]
+fn no_memo_turns_off_the_cache_of_calls() {
+    let fib =
+        "let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };\nprint(fib(25))";
+    let output = rinha(&["--quiet", "--max-steps", "1000"], fib);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "75025\n");
+    let output = rinha(&["--quiet", "--max-steps", "1000", "--no-memo"], fib);
+    assert!(stderr(&output).starts_with("error: execution budget exceeded\n"));
+}
+
+#[test]
+fn max_steps_stops_programs_that_loop_forever() {
+    let source = "let loop = fn (n) => loop(n + 1);\nloop(0)";
+    for engine in ["tree", "vm", "jit"] {
+        let engine = format!("--engine={}", engine);
+        let output = rinha(&[&engine, "--max-steps", "10000"], source);
+        assert_eq!(output.status.code(), Some(1), "{}", engine);
+        let stderr = stderr(&output);
+        assert!(
+            stderr.starts_with("error: execution budget exceeded\n"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn trace_logs_every_term_indented_by_call_depth() {
+    let source = "let f = fn (n) => if (n == 0) { 0 } else { f(n - 1) };\nf(1)";
+    let expected = "[0] Var <stdin>:55..56 => <#closure>
+[0] Int <stdin>:57..58 => 1
+  [1] Var <stdin>:22..23 => 1
+  [1] Int <stdin>:27..28 => 0
+  [1] Binary <stdin>:22..28 => false
+  [1] Var <stdin>:43..44 => <#closure>
+  [1] Var <stdin>:45..46 => 1
+  [1] Int <stdin>:49..50 => 1
+  [1] Binary <stdin>:45..50 => 0
+    [2] Var <stdin>:22..23 => 0
+    [2] Int <stdin>:27..28 => 0
+    [2] Binary <stdin>:22..28 => true
+    [2] Int <stdin>:32..33 => 0
+    [2] If <stdin>:18..53 => 0
+  [1] Call <stdin>:43..51 => 0
+  [1] If <stdin>:18..53 => 0
+[0] Call <stdin>:55..59 => 0
+[0] Let <stdin>:0..59 => 0
+";
+    let output = rinha(&["--quiet", "--trace"], source);
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), expected);
+
+    let path = std::env::temp_dir().join(format!("rinha-trace-{}.log", std::process::id()));
+    let trace = format!("--trace={}", path.display());
+    let output = rinha(&["--quiet", &trace], source);
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn profile_reports_the_calls_of_each_function() {
+    let source = "let f = fn (n) => if (n == 0) { 0 } else { f(n - 1) };\nprint(f(3))";
+    let output = rinha(&["--quiet", "--profile"], source);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "0\n");
+    let lines: Vec<_> = stderr(&output).lines().collect();
+    assert_eq!(lines.len(), 2, "{:?}", lines);
+    assert_eq!(lines[0], "     calls          time  function");
+    let columns: Vec<_> = lines[1].split_whitespace().collect();
+    assert_eq!(columns[0], "4");
+    assert_eq!(columns[2..], ["f", "at", "<stdin>:8..53"]);
+}
+
+#[test]
+fn stats_counts_calls_and_the_deepest_recursion() {
+    let source = "let f = fn (n) => if (n == 0) { 0 } else { 1 + f(n - 1) };\nprint(f(10))";
+    let output = rinha(&["--quiet", "--stats"], source);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "10\n");
+    let stats: Vec<_> = stderr(&output)
+        .lines()
+        .map(|line| {
+            let (name, count) = line.split_once(':').unwrap();
+            (name, count.trim().parse::<u64>().unwrap())
+        })
+        .collect();
+    let names: Vec<_> = stats.iter().map(|(name, _)| *name).collect();
+    let expected = [
+        "allocations",
+        "env clones",
+        "peak closures",
+        "calls",
+        "peak depth",
+    ];
+    assert_eq!(names, expected);
+    assert_eq!(
+        stats[2..],
+        [("peak closures", 1), ("calls", 11), ("peak depth", 11)]
+    );
+}
+
+#[test]
+fn timeout_stops_programs_that_run_too_long() {
+    let source = "let loop = fn (n) => loop(n + 1);\nloop(0)";
+    let start = Instant::now();
+    let output = rinha(&["--timeout", "0.5"], source);
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "error: time limit exceeded\n");
+}
+
+#[test]
+fn max_memory_stops_programs_that_use_too_much() {
+    // Every call keeps a longer chain of tuples alive.
+    let source =
+        "let grow = fn (n, acc) => if (n == 0) { acc } else { grow(n - 1, (n, acc)) };\ngrow(100000000, 0)";
+    let output = rinha(&["--max-memory", "20"], source);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.starts_with("error: memory limit exceeded\n"),
+        "{}",
+        stderr
+    );
+    // Programs within the limit run as usual.
+    let output = rinha(&["--quiet", "--max-memory", "20"], "print(1)");
+    assert_eq!(stdout(&output), "1\n");
+}
+
+#[test]
+fn output_printed_before_an_error_is_flushed() {
+    // `print` gives back what it printed.
+    let source = "let x = print(print(1) + 1);\nprint(x / 0)";
+    let output = rinha(&["--quiet"], source);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "1\n2\n");
+}
+
+#[test]
+fn line_buffered_writes_each_line_as_it_is_printed() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["--line-buffered", "--timeout", "30", "--source", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let source = "let loop = fn (n) => loop(n + 1);\nlet _ = print(\"started\");\nloop(0)";
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    // The program runs until it times out, and only then would a
+    // buffered line be written.
+    let start = Instant::now();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(20));
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(line, "started\n");
+}
+
+#[test]
+fn json_result_gives_one_object_for_the_whole_run() {
+    let output = rinha(&["--json-result"], "let _ = print(\"hi\");\n(1, 2)");
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_str(stdout(&output)).unwrap();
+    assert_eq!(result["stdout"], "hi\n");
+    assert_eq!(result["result"], "(1, 2)");
+    assert_eq!(result["status"], "ok");
+    assert!(result["elapsed_ms"].is_u64());
+    assert_eq!(stderr(&output), "");
+
+    let output = rinha(&["--json-result"], "let _ = print(1);\n1 / 0");
+    assert_eq!(output.status.code(), Some(1));
+    let result: serde_json::Value = serde_json::from_str(stdout(&output)).unwrap();
+    assert_eq!(result["stdout"], "1\n");
+    assert_eq!(result["result"], "division by zero");
+    assert_eq!(result["status"], "error");
+}
+
+#[test]
+fn quiet_leaves_only_what_the_program_prints() {
+    let source = "let _ = print(1);\n(2, 3)";
+    let output = rinha(&["--color", "never"], source);
+    assert_eq!(stdout(&output), "1\nvalue: Tuple(Number(2), Number(3))\n");
+    let output = rinha(&["--quiet"], source);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "1\n");
+    assert_eq!(stderr(&output), "");
+    for other in ["--json-result", "--log-json"] {
+        let output = rinha(&["--quiet", other], source);
+        assert_eq!(output.status.code(), Some(2), "{}", other);
+        assert_eq!(stdout(&output), "", "{}", other);
+    }
+}
+
+#[test]
+fn eval_runs_the_source_given_as_an_argument() {
+    let eval = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rinha"))
+            .arg("eval")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let output = eval(&["--quiet", "let x = 2; print(x * 21)"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "42\n");
+    // Options apply as they do to files.
+    let output = eval(&[
+        "--quiet",
+        "--engine=vm",
+        "--max-steps",
+        "1",
+        "let f = fn (x) => f(x); f(1)",
+    ]);
+    assert!(stderr(&output).starts_with("error: execution budget exceeded\n"));
+    let output = eval(&["let x = ;"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains(" --> <eval>:1:9\n"),
+        "{}",
+        stderr(&output)
+    );
+}
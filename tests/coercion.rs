@@ -0,0 +1,106 @@
+//! Checks `+` on every pair of kinds of values with each coercion. The
+//! config is global, so these tests have a binary of their own.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rinha::config::Coercion;
+use rinha::list::List;
+use rinha::parser::{self, Syntax};
+use rinha::symbol::Symbol;
+use rinha::types::BinaryOp;
+use rinha::{InterpreterConfig, Value};
+
+fn eval(source: &str) -> Value {
+    let file = parser::parse_with(source, "coercion.rinha", Syntax::all()).unwrap();
+    rinha::interpret(&file).unwrap()
+}
+
+/// One value of each kind, in the order of the rows and columns of the
+/// tables below.
+fn values() -> Vec<Value> {
+    let record = HashMap::from([(Symbol::intern("x"), Value::Number(1))]);
+    vec![
+        Value::Number(1),
+        Value::string("a"),
+        Value::Boolean(true),
+        Value::tuple(Value::Number(1), Value::Number(2)),
+        eval("fn () => 1"),
+        Value::List(List::cons(Value::Number(1), List::default())),
+        Value::Record(Rc::new(record)),
+        eval("ref(1)"),
+    ]
+}
+
+// What `left + right` gives, with `left` picking the row and `right` the
+// column: `I` is a sum, `C` the values joined as shown and `E` an error.
+//
+//   int str bool tuple closure list record ref
+const SPEC: [&str; 8] = [
+    "ICEEEEEE", // int
+    "CCEEEEEE", // str
+    "EEEEEEEE", // bool
+    "EEEEEEEE", // tuple
+    "EEEEEEEE", // closure
+    "EEEEEEEE", // list
+    "EEEEEEEE", // record
+    "EEEEEEEE", // ref
+];
+
+const BOOLEANS: [&str; 8] = [
+    "ICEEEEEE", // int
+    "CCCEEEEE", // str
+    "ECEEEEEE", // bool
+    "EEEEEEEE", // tuple
+    "EEEEEEEE", // closure
+    "EEEEEEEE", // list
+    "EEEEEEEE", // record
+    "EEEEEEEE", // ref
+];
+
+const ALL: [&str; 8] = [
+    "ICEEEEEE", // int
+    "CCCCCCCC", // str
+    "ECEEEEEE", // bool
+    "ECEEEEEE", // tuple
+    "ECEEEEEE", // closure
+    "ECEEEEEE", // list
+    "ECEEEEEE", // record
+    "ECEEEEEE", // ref
+];
+
+#[test]
+fn follows_the_table_of_each_coercion() {
+    let values = values();
+    let file = rinha::parse("1 + 1", "coercion.rinha").unwrap();
+    let location = file.expression.location();
+    for (coercion, table) in [
+        (Coercion::Spec, SPEC),
+        (Coercion::Booleans, BOOLEANS),
+        (Coercion::All, ALL),
+    ] {
+        let config = InterpreterConfig {
+            coercion,
+            ..InterpreterConfig::EXTENDED
+        };
+        for (row, left) in table.iter().zip(&values) {
+            for (cell, right) in row.chars().zip(&values) {
+                let result =
+                    rinha::value::interpret_binary(left, right, &BinaryOp::Add, config, location);
+                let pair = format!("{:?}: {} + {}", coercion, left, right);
+                match cell {
+                    'I' => assert!(matches!(result, Ok(Value::Number(2))), "{}", pair),
+                    'C' => {
+                        let joined = result.unwrap_or_else(|err| panic!("{}: {}", pair, err));
+                        assert!(matches!(joined, Value::String(_)), "{}", pair);
+                        assert_eq!(joined.to_string(), format!("{}{}", left, right), "{}", pair);
+                    }
+                    _ => {
+                        let err = result.expect_err(&pair);
+                        assert!(err.to_string().contains("string or int"), "{}", pair);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use rinha::error::{diagnostic, SourceMap};
+use rinha::types::Location;
+
+fn division() -> (SourceMap, Location) {
+    let mut sources = SourceMap::new();
+    sources.add("color.rinha", "let x = 1;\nx / 0");
+    let location = Location {
+        start: 11,
+        end: 16,
+        filename: "color.rinha".to_string(),
+    };
+    (sources, location)
+}
+
+#[test]
+fn plain_diagnostics_have_no_escape_codes() {
+    let (sources, location) = division();
+    let text = diagnostic(&sources, &"division by zero", &location, false);
+    assert_eq!(
+        text,
+        "error: division by zero\n \
+         --> color.rinha:2:1\n  \
+         |\n\
+         2 | x / 0\n  \
+         | ^^^^^\n"
+    );
+}
+
+#[test]
+fn colored_diagnostics_mark_the_label_and_the_span() {
+    let (sources, location) = division();
+    let text = diagnostic(&sources, &"division by zero", &location, true);
+    assert!(text.starts_with("\x1b[1;31merror\x1b[0m: division by zero\n"));
+    assert!(text.contains("\x1b[4mx / 0\x1b[0m"), "{:?}", text);
+    assert!(text.contains("\x1b[1;31m^^^^^\x1b[0m"), "{:?}", text);
+}
@@ -0,0 +1,29 @@
+//! Helpers shared by the tests of optimizer passes.
+
+use rinha::{Engine, File, Fuel, InterpreterConfig};
+
+/// What `file` prints when the tree-walker runs it, and its value as
+/// `print` shows it or the error that stopped it.
+fn run(file: &File) -> (Vec<String>, String) {
+    let mut lines = Vec::new();
+    let result = match Engine::Tree.run(
+        file,
+        false,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        &mut lines,
+    ) {
+        Ok(value) => value.to_string(),
+        Err(err) => format!("error: {}", err),
+    };
+    (lines, result)
+}
+
+/// Applies `pass` to `source`, checking that the program runs the same as
+/// before, and gives back the new program as source.
+pub fn transformed(source: &str, pass: impl Fn(&File) -> File) -> String {
+    let file = rinha::parse(source, "pass.rinha").unwrap();
+    let transformed = pass(&file);
+    assert_eq!(run(&transformed), run(&file), "{}", source);
+    transformed.expression.to_source()
+}
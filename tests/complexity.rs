@@ -0,0 +1,40 @@
+//! Checks what `rinha stats` reports about the shape of a program.
+
+use rinha::complexity;
+
+fn analyze(source: &str) -> complexity::Report {
+    complexity::analyze(&rinha::parse(source, "stats.rinha").unwrap())
+}
+
+#[test]
+fn counts_terms_by_kind() {
+    let report = analyze("let add = fn (a, b) => { a + b }; print(add(1, 2))");
+    let kinds: Vec<_> = report.kinds.iter().map(|(k, n)| (*k, *n)).collect();
+    assert_eq!(
+        kinds,
+        [
+            ("Binary", 1),
+            ("Call", 1),
+            ("Function", 1),
+            ("Int", 2),
+            ("Let", 1),
+            ("Print", 1),
+            ("Var", 3),
+        ]
+    );
+    assert_eq!(report.nodes(), 10);
+    assert_eq!(report.variables, 3);
+    assert_eq!(report.closures, 1);
+}
+
+#[test]
+fn depth_counts_the_root() {
+    assert_eq!(analyze("1").max_depth, 1);
+    assert_eq!(analyze("(1, (2, 3))").max_depth, 3);
+}
+
+#[test]
+fn names_are_counted_once() {
+    let report = analyze("let x = 1; let f = fn (x) => { x }; let x = f(x); x");
+    assert_eq!(report.variables, 2);
+}
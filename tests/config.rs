@@ -0,0 +1,191 @@
+//! Checks what each config changes. Each run takes its own config, so
+//! these tests run side by side.
+
+use rinha::error::RuntimeError;
+use rinha::{conformance, lint, Engine, Fuel, InterpreterConfig, Value};
+
+fn run(source: &str, engine: Engine, config: InterpreterConfig) -> Result<Value, RuntimeError> {
+    run_printing(source, engine, config, &mut Vec::new())
+}
+
+fn run_printing(
+    source: &str,
+    engine: Engine,
+    config: InterpreterConfig,
+    lines: &mut Vec<String>,
+) -> Result<Value, RuntimeError> {
+    let file = rinha::parse(source, "config.rinha").unwrap();
+    engine.run(&file, false, config, Fuel::unlimited(), lines)
+}
+
+#[test]
+fn coerces_booleans_only_when_extended() {
+    let source = "let done = 1 < 2; \"done: \" + done";
+    let file = rinha::parse(source, "config.rinha").unwrap();
+
+    assert_eq!(InterpreterConfig::default(), InterpreterConfig::STRICT);
+    let config = InterpreterConfig::STRICT;
+    for engine in [Engine::Tree, Engine::Vm] {
+        let err = run(source, engine, config).unwrap_err();
+        assert!(err.to_string().contains("string or int"), "{}", err);
+    }
+    assert_eq!(lint::lint(&file, config).len(), 1);
+
+    let config = InterpreterConfig::EXTENDED;
+    for engine in [Engine::Tree, Engine::Vm] {
+        let value = run(source, engine, config).unwrap();
+        assert_eq!(value.to_string(), "done: true", "{}", engine.name());
+        // Tuples still cannot be joined to strings.
+        assert!(run("\"pair: \" + (1, 2)", engine, config).is_err());
+    }
+    assert!(lint::lint(&file, config).is_empty());
+}
+
+#[test]
+fn compares_and_prints_closures_as_configured() {
+    let compare = "let f = fn () => 1; (1, f) == (1, f)";
+    let show = "let f = fn () => 1; print((1, f))";
+
+    let config = InterpreterConfig::STRICT;
+    for engine in [Engine::Tree, Engine::Vm] {
+        assert!(matches!(
+            run(compare, engine, config),
+            Ok(Value::Boolean(true))
+        ));
+        let mut lines = Vec::new();
+        run_printing(show, engine, config, &mut lines).unwrap();
+        assert_eq!(lines, ["(1, <#closure>)"], "{}", engine.name());
+    }
+
+    let config = InterpreterConfig {
+        compare_closures: false,
+        print_closures: false,
+        ..InterpreterConfig::STRICT
+    };
+    for engine in [Engine::Tree, Engine::Vm] {
+        let err = run(compare, engine, config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot compare closures",
+            "{}",
+            engine.name()
+        );
+        let err = run(show, engine, config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot print closures",
+            "{}",
+            engine.name()
+        );
+        // Values without closures compare and print as before.
+        assert!(matches!(
+            run("(1, 2) == (1, 2)", engine, config),
+            Ok(Value::Boolean(true))
+        ));
+        assert!(run("print((1, 2))", engine, config).is_ok());
+    }
+}
+
+#[test]
+fn applies_functions_partially_only_when_asked() {
+    let source = "let add = fn (a, b, c) => a + b + c;
+        let inc = add(1);
+        let twice = fn (f, x) => f(f(x, 0), 0);
+        let _ = print(inc(2)(3));
+        let _ = print(inc(2, 3));
+        let _ = print(twice(inc, 5));
+        (add(1)()(2) == add(1, 2), add(1)(2, 3, 4))";
+
+    let config = InterpreterConfig::STRICT;
+    for engine in [Engine::Tree, Engine::Vm] {
+        let err = run(source, engine, config).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                RuntimeError::ArityMismatch {
+                    expected: 3,
+                    found: 1,
+                    ..
+                }
+            ),
+            "{}: {}",
+            engine.name(),
+            err
+        );
+    }
+
+    let config = InterpreterConfig {
+        partial_application: true,
+        ..InterpreterConfig::EXTENDED
+    };
+    for engine in [Engine::Tree, Engine::Vm] {
+        let mut lines = Vec::new();
+        let err = run_printing(source, engine, config, &mut lines).unwrap_err();
+        assert_eq!(lines, ["6", "6", "7"], "{}", engine.name());
+        // Too many arguments still fail, counting the ones given before.
+        assert!(
+            matches!(
+                err,
+                RuntimeError::ArityMismatch {
+                    expected: 3,
+                    found: 4,
+                    ..
+                }
+            ),
+            "{}: {}",
+            engine.name(),
+            err
+        );
+        let value = run("let add = fn (a, b) => a + b; add(1)", engine, config).unwrap();
+        assert_eq!(value.to_string(), "<#closure>");
+    }
+}
+
+#[test]
+fn reads_the_fixed_clock_when_there_is_one() {
+    let source = "let start = now();
+        let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };
+        let _ = fib(15);
+        (start, now() - start)";
+    let file = rinha::parse(source, "config.rinha").unwrap();
+    let run = |engine: Engine, config| {
+        engine
+            .run(&file, true, config, Fuel::unlimited(), &mut Vec::new())
+            .unwrap()
+    };
+
+    let config = InterpreterConfig {
+        clock: Some(1500),
+        ..InterpreterConfig::EXTENDED
+    };
+    for engine in [Engine::Tree, Engine::Vm] {
+        let value = run(engine, config);
+        assert_eq!(value.to_string(), "(1500, 0)", "{}", engine.name());
+    }
+
+    let config = InterpreterConfig::EXTENDED;
+    for engine in [Engine::Tree, Engine::Vm] {
+        let value = run(engine, config);
+        let Value::Tuple(pair) = &value else {
+            panic!("{}: {}", engine.name(), value)
+        };
+        assert!(matches!(pair.1, Value::Number(elapsed) if elapsed >= 0));
+    }
+}
+
+#[test]
+fn conformance_runs_with_the_config_of_rinha_test() {
+    // `rinha test` runs with every extension but partial application, so
+    // calls with too few arguments still fail.
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    for engine in [Engine::Tree, Engine::Vm] {
+        let cases = conformance::run_dir(&dir, engine).unwrap();
+        let failed: Vec<_> = cases.iter().filter(|case| !case.passed()).collect();
+        assert!(
+            failed.is_empty(),
+            "{}: {:?}",
+            engine.name(),
+            failed.iter().map(|case| &case.path).collect::<Vec<_>>()
+        );
+    }
+}
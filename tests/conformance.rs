@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use rinha::conformance::{self, Outcome};
+use rinha::Engine;
+
+fn check(engine: Engine) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let cases = conformance::run_dir(&dir, engine).unwrap();
+    assert!(!cases.is_empty());
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        match &case.outcome {
+            Outcome::Pass => {}
+            Outcome::Fail { expected, actual } => failures.push(format!(
+                "{}:\n--- expected\n{}\n--- actual\n{}",
+                case.path.display(),
+                expected.trim_end(),
+                actual
+            )),
+            Outcome::Broken(message) => {
+                failures.push(format!("{}: {}", case.path.display(), message))
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn tree_walker() {
+    check(Engine::Tree);
+}
+
+#[test]
+fn vm() {
+    check(Engine::Vm);
+}
+
+#[test]
+fn jit() {
+    check(Engine::Jit);
+}
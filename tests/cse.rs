@@ -0,0 +1,64 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use rinha::cse;
+
+/// Eliminates the common subexpressions of `source`, checking that it runs
+/// the same as before.
+fn eliminated(source: &str) -> String {
+    common::transformed(source, cse::eliminate)
+}
+
+#[test]
+fn binds_repeated_calls_once() {
+    let source = eliminated(
+        "let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 1) };\nfib(15)",
+    );
+    assert_eq!(source.matches("fib(").count(), 2, "{}", source);
+
+    let source = eliminated("let x = 4;\n(x * x + 1, x * x + 1)");
+    assert_eq!(source.matches("x * x").count(), 1, "{}", source);
+}
+
+#[test]
+fn leaves_terms_with_effects() {
+    let source = eliminated("let loud = fn (x) => print(x);\nloud(1) + loud(1)");
+    assert_eq!(source.matches("loud(1)").count(), 2, "{}", source);
+
+    let source = eliminated("let make = fn (x) => fn (y) => x;\nmake(1) == make(1)");
+    assert_eq!(source.matches("make(1)").count(), 2, "{}", source);
+
+    // Builtins and parameters may do anything.
+    eliminated("let f = fn (g) => g(1) + g(1);\nf(fn (x) => print(x))");
+}
+
+#[test]
+fn only_binds_terms_that_always_run_first() {
+    // The copy in a branch does not always run.
+    let source = eliminated("let x = 3;\n(x / 1) + (if (x < 0) { x / 1 } else { 0 })");
+    assert_eq!(source.matches("x / 1").count(), 2, "{}", source);
+
+    // Dividing by zero must still fail before the loop starts.
+    let source =
+        eliminated("let loop = fn (n) => loop(n);\nlet z = 0;\n(1 / z + loop(1), loop(1))");
+    assert_eq!(source.matches("loop(1)").count(), 2, "{}", source);
+}
+
+#[test]
+fn keeps_the_meaning_of_names() {
+    let source = eliminated("let x = 1;\n(x + 1, let x = 2; x + 1)");
+    assert_eq!(source.matches("x + 1").count(), 2, "{}", source);
+}
+
+#[test]
+fn long_chains_are_rewritten_quickly() {
+    // Each term was once compared as source with every other one.
+    for term in ["1", "x * x"] {
+        let source = format!("let x = 3;\nprint({})", vec![term; 1000].join(" + "));
+        let file = rinha::parse(&source, "chain.rinha").unwrap();
+        let start = Instant::now();
+        cse::eliminate(&file);
+        assert!(start.elapsed() < Duration::from_secs(2), "{}", term);
+    }
+}
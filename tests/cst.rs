@@ -0,0 +1,120 @@
+//! Checks that syntax trees keep every byte of their text and that
+//! editing one gives the tree read from scratch from the edited text.
+
+use proptest::prelude::*;
+use rinha::cst::{Cst, NodeKind};
+use rinha::lexer::TokenKind;
+use rinha::parser::Syntax;
+
+const PROGRAM: &str = "// Sums the numbers up to n.
+let sum = fn (n) => {
+  if (n == 0) { 0 } else { n + sum(n - 1) }
+};
+let (a, b) = (1, /* two */ 2);
+let total = let x = sum(10); x + a;
+print(total + b)
+";
+
+/// What a tree holds, to compare trees read in different ways.
+fn summary(cst: &Cst) -> String {
+    let items: Vec<_> = cst
+        .items()
+        .iter()
+        .map(|item| (item.range(), item.kind()))
+        .collect();
+    let tokens: Vec<_> = cst.tokens().collect();
+    let errors: Vec<_> = cst
+        .errors()
+        .into_iter()
+        .map(|err| (err.message, err.location.start, err.location.end))
+        .collect();
+    let file = match cst.file() {
+        Ok(file) => serde_json::to_string(&file).unwrap(),
+        Err(err) => err.message,
+    };
+    format!("{:?}\n{:?}\n{:?}\n{}", items, tokens, errors, file)
+}
+
+#[test]
+fn tokens_hold_every_byte_of_the_text() {
+    let text = "let s = \"unterminated; /* open\n\t1 + ç";
+    for source in [PROGRAM, text, ""] {
+        let cst = Cst::parse(source, "cst.rinha", Syntax::all());
+        let joined: String = cst.tokens().map(|token| &source[token.range]).collect();
+        assert_eq!(joined, source);
+    }
+    let cst = Cst::parse(text, "cst.rinha", Syntax::all());
+    assert!(cst.tokens().any(|token| token.kind == TokenKind::Error));
+}
+
+#[test]
+fn items_are_the_top_level_bindings_and_the_term() {
+    let cst = Cst::parse(PROGRAM, "cst.rinha", Syntax::all());
+    let kinds: Vec<_> = cst.items().iter().map(|item| item.kind()).collect();
+    assert_eq!(
+        kinds,
+        [
+            NodeKind::Binding,
+            NodeKind::Binding,
+            NodeKind::Binding,
+            NodeKind::Term
+        ]
+    );
+    let file = rinha::parse_with(PROGRAM, "cst.rinha", Syntax::all()).unwrap();
+    assert_eq!(
+        serde_json::to_string(&cst.file().unwrap()).unwrap(),
+        serde_json::to_string(&file).unwrap()
+    );
+}
+
+#[test]
+fn each_item_reports_its_first_error() {
+    let cst = Cst::parse(
+        "let a = ;\nlet b = 1;\nlet c = );\nb",
+        "cst.rinha",
+        Syntax::all(),
+    );
+    let errors: Vec<_> = cst.errors().iter().map(|err| err.location.start).collect();
+    assert_eq!(errors, [8, 29]);
+    assert!(cst.file().is_err());
+}
+
+#[test]
+fn edits_read_again_only_the_items_they_touch() {
+    let mut cst = Cst::parse(PROGRAM, "cst.rinha", Syntax::all());
+    let at = PROGRAM.find("sum(10)").unwrap() + 4;
+    let read = cst.edit(at..at + 2, "20");
+    assert_eq!(read, 2..3);
+    let text = PROGRAM.replace("sum(10)", "sum(20)");
+    assert_eq!(
+        summary(&cst),
+        summary(&Cst::parse(&text, "cst.rinha", Syntax::all()))
+    );
+}
+
+proptest! {
+    #[test]
+    fn edits_give_the_tree_of_the_edited_text(
+        edits in prop::collection::vec(
+            (0..PROGRAM.len(), 0usize..8, prop::sample::select(vec![
+                "", ";", "let y = 1;", "{", "}", "(", ")", "\"", "/*", "*/",
+                "//", "\n", "x", "42", " + ", "let",
+            ])),
+            1..6,
+        )
+    ) {
+        let mut cst = Cst::parse(PROGRAM, "cst.rinha", Syntax::all());
+        let mut text = PROGRAM.to_string();
+        for (start, len, replacement) in edits {
+            let start = start.min(text.len());
+            let end = (start + len).min(text.len());
+            text.replace_range(start..end, replacement);
+            cst.edit(start..end, replacement);
+            prop_assert_eq!(cst.text(), text.as_str());
+            prop_assert_eq!(
+                summary(&cst),
+                summary(&Cst::parse(&text, "cst.rinha", Syntax::all()))
+            );
+        }
+    }
+}
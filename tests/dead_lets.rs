@@ -0,0 +1,61 @@
+mod common;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use rinha::optimizer;
+
+/// Removes the dead bindings of `source`, checking that it runs the same
+/// as before.
+fn removed(source: &str) -> String {
+    common::transformed(source, optimizer::remove_dead_lets)
+}
+
+#[test]
+fn removes_unused_bindings_without_effects() {
+    let source = "let unused = 3;\nlet f = fn (x) => x;\nlet t = (1, \"two\");\nprint(3)";
+    assert_eq!(removed(source), "print(3)");
+    // A binding only used by a dead one is dead too.
+    assert_eq!(removed("let a = 1;\nlet b = a;\n2"), "2");
+}
+
+#[test]
+fn keeps_bindings_that_are_used_or_have_effects() {
+    for source in [
+        "let a = 1;\na",
+        "let _ = print(1);\n2",
+        // Operators may fail, as here.
+        "let x = 1 / 0;\n2",
+        "let x = 1 + 2;\n2",
+        // Calls may not return.
+        "let f = fn (n) => n;\nlet x = f(1);\n2",
+    ] {
+        assert_eq!(
+            removed(source),
+            rinha::parse(source, "dead.rinha")
+                .unwrap()
+                .expression
+                .to_source()
+        );
+    }
+}
+
+#[test]
+fn show_opt_prints_the_optimized_program() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["--show-opt", "--source", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let source = "let unused = 3;\nprint(3)";
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "print(3)\n");
+}
@@ -0,0 +1,48 @@
+//! Checks where `rinha debug` stops, driving it through stdin.
+
+use std::fs;
+use std::io::Write;
+use std::process::{self, Command, Stdio};
+
+/// Runs `rinha debug` on `source` with `commands`, returning what it
+/// printed.
+fn debug(source: &str, commands: &str) -> String {
+    let path = std::env::temp_dir().join(format!("rinha-debug-{}.rinha", process::id()));
+    fs::write(&path, source).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .arg("debug")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn breakpoints_stop_once_for_terms_starting_together() {
+    // `n + 1` and `n` both start at offset 18, and each call of `f` stops
+    // there once.
+    let source = "let f = fn (n) => n + 1;\nprint(f(2) + f(1))";
+    let output = debug(source, "break 18\ncontinue\ncontinue\ncontinue\n");
+    let stops: Vec<_> = output
+        .lines()
+        .filter_map(|line| line.strip_prefix("(debug) "))
+        .filter(|line| line.contains(":18.."))
+        .collect();
+    assert_eq!(stops.len(), 2, "{}", output);
+    assert!(
+        stops.iter().all(|stop| stop.starts_with("Binary ")),
+        "{}",
+        output
+    );
+}
@@ -0,0 +1,99 @@
+//! Checks the source excerpts diagnostics quote.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use rinha::error::{diagnostic, SourceMap};
+use rinha::types::Location;
+
+fn at(start: usize, end: usize) -> Location {
+    Location {
+        start,
+        end,
+        filename: "diagnostics.rinha".to_string(),
+    }
+}
+
+fn sources(source: &str) -> SourceMap {
+    let mut sources = SourceMap::new();
+    sources.add("diagnostics.rinha", source);
+    sources
+}
+
+#[test]
+fn columns_count_characters() {
+    let source = "let s = \"ação\" + (1, 2);";
+    let sources = sources(source);
+    let start = source.find('(').unwrap();
+    let location = at(start, start + 6);
+    assert_eq!(sources.position(&location), "diagnostics.rinha:1:18");
+    let text = diagnostic(&sources, &"mismatch", &location, false);
+    assert_eq!(
+        text,
+        "error: mismatch\n \
+         --> diagnostics.rinha:1:18\n  \
+         |\n\
+         1 | let s = \"ação\" + (1, 2);\n  \
+         |                  ^^^^^^\n"
+    );
+}
+
+#[test]
+fn spans_over_several_lines_mark_the_rest_of_the_first() {
+    let source = "let f = fn (x) => {\n  x\n};\nf";
+    let text = diagnostic(&sources(source), &"oops", &at(8, 25), false);
+    assert!(
+        text.ends_with("1 | let f = fn (x) => {\n  |         ^^^^^^^^^^^\n"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn the_gutter_fits_the_line_number() {
+    let source = format!("{}1 / 0", "\n".repeat(11));
+    let text = diagnostic(&sources(&source), &"division by zero", &at(11, 16), false);
+    assert_eq!(
+        text,
+        "error: division by zero\n  \
+         --> diagnostics.rinha:12:1\n   \
+         |\n\
+         12 | 1 / 0\n   \
+         | ^^^^^\n"
+    );
+}
+
+#[test]
+fn unknown_sources_show_the_span_of_bytes() {
+    let location = at(3, 7);
+    let sources = SourceMap::new();
+    assert_eq!(sources.position(&location), "diagnostics.rinha:3..7");
+    let text = diagnostic(&sources, &"oops", &location, false);
+    assert_eq!(text, "error: oops\n  --> diagnostics.rinha:3..7\n");
+}
+
+#[test]
+fn parse_errors_quote_the_source() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["--source", "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"let x = ;\nx")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error: expected expression but found ;\n \
+         --> <stdin>:1:9\n  \
+         |\n\
+         1 | let x = ;\n  \
+         |         ^\n"
+    );
+}
@@ -0,0 +1,77 @@
+//! Checks how `rinha difftest` compares the runs of each engine.
+
+use std::process::Command;
+
+use rinha::difftest::{self, Run};
+use rinha::{Engine, Fuel, InterpreterConfig};
+
+fn run(engine: Engine, lines: &[&str]) -> Run {
+    let lines = lines.iter().map(|line| line.to_string()).collect();
+    Run { engine, lines }
+}
+
+#[test]
+fn engines_agree_on_prints_values_and_errors() {
+    let source = "let f = fn (n) => if (n < 2) { n } else { f(n - 1) + f(n - 2) };
+let _ = print(f(10));
+(f(5), 1 / 0)";
+    let file = rinha::parse(source, "difftest.rinha").unwrap();
+    let runs = difftest::run_all(&file, true, InterpreterConfig::STRICT, Fuel::unlimited());
+    let engines: Vec<_> = runs.iter().map(|run| run.engine).collect();
+    assert_eq!(engines, Engine::ALL);
+    let start = source.find("1 / 0").unwrap();
+    let error = format!(
+        "error: division by zero at difftest.rinha:{}..{}",
+        start,
+        start + 5
+    );
+    assert_eq!(runs[0].lines, ["55".to_string(), error]);
+    assert!(difftest::divergence(&runs).is_none());
+}
+
+#[test]
+fn finds_the_first_line_that_differs() {
+    let runs = [
+        run(Engine::Tree, &["1", "2", "value: 3"]),
+        run(Engine::Vm, &["1", "4", "value: 3"]),
+        run(Engine::Jit, &["1", "2", "value: 3"]),
+    ];
+    let divergence = difftest::divergence(&runs).unwrap();
+    assert_eq!(divergence.line, 1);
+    let lines: Vec<_> = divergence
+        .lines
+        .iter()
+        .map(|(engine, line)| (*engine, line.as_deref()))
+        .collect();
+    let expected = [
+        (Engine::Tree, Some("2")),
+        (Engine::Vm, Some("4")),
+        (Engine::Jit, Some("2")),
+    ];
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn runs_that_end_early_differ_where_they_end() {
+    let runs = [
+        run(Engine::Tree, &["1", "value: 1"]),
+        run(Engine::Vm, &["1"]),
+    ];
+    let divergence = difftest::divergence(&runs).unwrap();
+    assert_eq!(divergence.line, 1);
+    assert_eq!(divergence.lines[1].1, None);
+}
+
+#[test]
+fn reports_agreement() {
+    let fib = concat!(env!("CARGO_MANIFEST_DIR"), "/files/fib.rinha");
+    let output = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["difftest", fib])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "tree, vm, jit agree on 2 lines\n"
+    );
+}
@@ -0,0 +1,90 @@
+//! Locks down how values are shown by `print`, which must match the
+//! reference implementation exactly.
+
+use rinha::{Engine, Fuel, InterpreterConfig, Value};
+
+fn value(source: &str, engine: Engine) -> Value {
+    let file = rinha::parse(source, "display.rinha").unwrap();
+    let mut lines = Vec::new();
+    engine
+        .run(
+            &file,
+            true,
+            InterpreterConfig::EXTENDED,
+            Fuel::unlimited(),
+            &mut lines,
+        )
+        .unwrap()
+}
+
+#[test]
+fn integers() {
+    assert_eq!(Value::Number(0).to_string(), "0");
+    assert_eq!(Value::Number(3).to_string(), "3");
+    assert_eq!(Value::Number(-42).to_string(), "-42");
+    assert_eq!(Value::Number(i64::MAX).to_string(), "9223372036854775807");
+    assert_eq!(Value::Number(i64::MIN).to_string(), "-9223372036854775808");
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn big_integers() {
+    let value = value("9223372036854775807 * 10", Engine::Tree);
+    assert_eq!(value.to_string(), "92233720368547758070");
+}
+
+#[test]
+fn booleans() {
+    assert_eq!(Value::Boolean(true).to_string(), "true");
+    assert_eq!(Value::Boolean(false).to_string(), "false");
+}
+
+#[test]
+fn strings_are_not_quoted() {
+    assert_eq!(Value::string("").to_string(), "");
+    assert_eq!(Value::string("say \"hi\"\n").to_string(), "say \"hi\"\n");
+}
+
+#[test]
+fn tuples() {
+    let pair = Value::tuple(Value::Number(1), Value::string("two"));
+    assert_eq!(pair.to_string(), "(1, two)");
+    let nested = Value::tuple(pair.clone(), Value::tuple(Value::Boolean(true), pair));
+    assert_eq!(nested.to_string(), "((1, two), (true, (1, two)))");
+}
+
+#[test]
+fn closures() {
+    for engine in Engine::ALL {
+        assert_eq!(value("fn (x) => x", engine).to_string(), "<#closure>");
+        assert_eq!(value("head", engine).to_string(), "<#closure>");
+        let pair = value("(fn () => 1, 2)", engine);
+        assert_eq!(pair.to_string(), "(<#closure>, 2)");
+    }
+}
+
+#[test]
+fn lists() {
+    assert_eq!(value("nil", Engine::Tree).to_string(), "[]");
+    let list = value("cons(1, cons((2, \"3\"), nil))", Engine::Tree);
+    assert_eq!(list.to_string(), "[1, (2, 3)]");
+}
+
+#[test]
+fn print_shows_values_the_same_way() {
+    let source = "let _ = print((fn (x) => x, (-1, true))); print(\"a\" + 1)";
+    let file = rinha::parse(source, "display.rinha").unwrap();
+    for engine in Engine::ALL {
+        let mut lines = Vec::new();
+        engine
+            .run(
+                &file,
+                true,
+                InterpreterConfig::STRICT,
+                Fuel::unlimited(),
+                &mut lines,
+            )
+            .unwrap();
+        assert_eq!(lines, ["(<#closure>, (-1, true))", "a1"], "{:?}", engine);
+    }
+}
@@ -0,0 +1,49 @@
+//! Checks that values nested as deep as a program can build them are
+//! freed without overflowing the stack.
+
+use rinha::{Engine, Fuel, InterpreterConfig, Value};
+
+const DEPTH: usize = 1_000_000;
+
+#[test]
+fn deep_tuples_drop_in_a_loop() {
+    let mut value = Value::Number(0);
+    for i in 0..DEPTH {
+        value = Value::tuple(Value::Number(i as i64), value);
+    }
+    drop(value);
+}
+
+#[test]
+fn shared_tuples_outlive_the_ones_dropped() {
+    let inner = Value::tuple(Value::Number(1), Value::Number(2));
+    let mut value = inner.clone();
+    for i in 0..DEPTH {
+        value = Value::tuple(Value::Number(i as i64), value);
+    }
+    drop(value);
+    assert_eq!(inner.to_string(), "(1, 2)");
+}
+
+#[test]
+fn programs_drop_the_deep_tuples_they_build() {
+    let source = format!(
+        "let build = fn (n, acc) => if (n == 0) {{ acc }} else {{ build(n - 1, (n, acc)) }};
+         first(build({}, 0))",
+        DEPTH
+    );
+    let file = rinha::parse(&source, "drop.rinha").unwrap();
+    for engine in [Engine::Tree, Engine::Vm] {
+        let mut lines = Vec::new();
+        let value = engine
+            .run(
+                &file,
+                false,
+                InterpreterConfig::STRICT,
+                Fuel::unlimited(),
+                &mut lines,
+            )
+            .unwrap();
+        assert_eq!(value.to_string(), "1", "{}", engine.name());
+    }
+}
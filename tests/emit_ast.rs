@@ -0,0 +1,30 @@
+//! Checks that `rinha emit-ast` writes the JSON the reference parser does.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn emits_the_ast_of_the_reference_parser() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("files");
+    // `function` reads `a + b + c` the other way around, see
+    // `tests/parser.rs`.
+    for name in ["fib", "hello", "let"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_rinha"))
+            .current_dir(&dir)
+            .arg("emit-ast")
+            .arg(format!("{}.rinha", name))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", name);
+        let emitted: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        // The reference files were made from `examples/`.
+        let reference = fs::read_to_string(dir.join(name).with_extension("json"))
+            .unwrap()
+            .replace("examples/", "");
+        let reference: serde_json::Value = serde_json::from_str(&reference).unwrap();
+        assert_eq!(emitted["name"], reference["name"], "{}", name);
+        assert_eq!(emitted["expression"], reference["expression"], "{}", name);
+        rinha::parse_json(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    }
+}
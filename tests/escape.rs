@@ -0,0 +1,64 @@
+use rinha::{stats, Engine, Fuel, InterpreterConfig};
+
+/// Runs `source` with both engines, checking that they agree, and gives
+/// back what it printed and how many values the VM allocated.
+fn run(source: &str) -> (Vec<String>, u64) {
+    let file = rinha::parse(source, "escape.rinha").unwrap();
+    let mut expected = Vec::new();
+    let value = Engine::Tree
+        .run(
+            &file,
+            false,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            &mut expected,
+        )
+        .map(|value| value.to_string());
+    stats::reset();
+    let mut lines = Vec::new();
+    let result = Engine::Vm
+        .run(
+            &file,
+            false,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            &mut lines,
+        )
+        .map(|value| value.to_string());
+    let allocations = stats::snapshot().allocations;
+    assert_eq!(lines, expected, "{}", source);
+    assert_eq!(
+        result.map_err(|err| err.to_string()),
+        value.map_err(|err| err.to_string()),
+        "{}",
+        source
+    );
+    (lines, allocations)
+}
+
+#[test]
+fn tuples_only_taken_apart_are_never_built() {
+    let (lines, allocations) = run("let sum = fn (n, acc) => if (n == 0) { acc } else {\n\
+           let p = (n - 1, acc + n);\n\
+           sum(first(p), second(p))\n\
+         };\n\
+         let _ = print(sum(100, 0));\n\
+         let (a, b) = (1, 2);\n\
+         print(first((a, print(b))) + second((3, 4)))");
+    assert_eq!(lines, ["5050", "2", "5"]);
+    // Only the program and `sum` are allocated.
+    assert_eq!(allocations, 2);
+}
+
+#[test]
+fn tuples_that_escape_are_built() {
+    let (_, allocations) = run("let p = (1, 2);\nlet f = fn () => first(p);\n(f(), p)");
+    assert_eq!(allocations, 4);
+
+    // The inner `p` is used whole, the outer one is only taken apart.
+    let (lines, allocations) = run("let p = (1, 2);\n\
+         let q = (let p = (3, 4); p);\n\
+         print((first(p), first(q)))");
+    assert_eq!(lines, ["(1, 3)"]);
+    assert_eq!(allocations, 3);
+}
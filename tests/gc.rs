@@ -0,0 +1,41 @@
+//! Checks that reference cycles a program leaves behind are freed.
+
+use rinha::parser::{self, Syntax};
+use rinha::{gc, stats, Engine, Fuel, InterpreterConfig};
+
+const KNOT: &str = "
+let cell = ref(0);
+let _ = cell := fn () => !cell;
+0
+";
+
+fn leave_cycle(engine: Engine) {
+    let syntax = Syntax {
+        refs: true,
+        ..Syntax::default()
+    };
+    let file = parser::parse_with(KNOT, "gc.rinha", syntax).unwrap();
+    let mut lines = Vec::new();
+    engine
+        .run(
+            &file,
+            false,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            &mut lines,
+        )
+        .unwrap();
+}
+
+#[test]
+fn frees_cycles_after_a_run() {
+    for engine in [Engine::Tree, Engine::Vm] {
+        gc::collect();
+        stats::reset();
+        leave_cycle(engine);
+        assert_eq!(stats::snapshot().live_closures, 1, "{}", engine.name());
+        assert_eq!(gc::collect(), 1, "{}", engine.name());
+        assert_eq!(stats::snapshot().live_closures, 0, "{}", engine.name());
+        assert_eq!(gc::collect(), 0, "{}", engine.name());
+    }
+}
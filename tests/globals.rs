@@ -0,0 +1,125 @@
+use std::rc::Rc;
+
+use rinha::interpreter::{Arena, Session};
+use rinha::resolver::resolve_program;
+use rinha::types::{Slot, Term};
+use rinha::{Engine, Fuel, InterpreterConfig};
+
+/// The function bound by the last `let` of the chain starting at `term`.
+fn last_function(term: &Rc<Term>) -> &Term {
+    let mut term = term;
+    let mut function = None;
+    while let Some(next) = term.next() {
+        if let Term::Let { value, .. } = term.as_ref() {
+            function = Some(value.as_ref());
+        }
+        term = next;
+    }
+    function.unwrap()
+}
+
+#[test]
+fn functions_at_the_start_are_not_captured() {
+    let file = rinha::parse(
+        "let double = fn (x) => x * 2;\n\
+         let quad = fn (x) => double(double(x));\n\
+         quad(3)",
+        "globals.rinha",
+    )
+    .unwrap();
+    let resolved = resolve_program(&file.expression, 5);
+    let Term::Function {
+        captures, value, ..
+    } = last_function(&resolved)
+    else {
+        panic!("not a function")
+    };
+    assert!(captures.is_empty());
+    let Term::Call { callee, .. } = value.as_ref() else {
+        panic!("not a call")
+    };
+    assert!(matches!(
+        callee.as_ref(),
+        Term::Var {
+            index: Some(Slot::Global(5)),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn functions_after_other_bindings_are_captured() {
+    let file = rinha::parse(
+        "let n = 2;\nlet add = fn (x) => x + n;\nlet twice = fn (x) => add(add(x));\ntwice(1)",
+        "globals.rinha",
+    )
+    .unwrap();
+    let resolved = resolve_program(&file.expression, 0);
+    let Term::Function { captures, .. } = last_function(&resolved) else {
+        panic!("not a function")
+    };
+    assert_eq!(captures.len(), 1);
+}
+
+#[test]
+fn programs_run_the_same_with_the_table() {
+    let sources = [
+        "let f = fn (x) => x + 1;\n\
+         let g = fn (x) => f(x) * 2;\n\
+         let f = fn (x) => g(x) + 100;\n\
+         print(f(3))",
+        "let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };\n\
+         let show = fn (n) => print(fib(n));\n\
+         let x = show(15);\n\
+         let g = fn () => show(x - 600);\n\
+         g()",
+        "let make = fn (x) => fn () => x;\nlet one = make(1);\nprint(one())",
+    ];
+    for source in sources {
+        let file = rinha::parse(source, "globals.rinha").unwrap();
+        let mut tree = Vec::new();
+        let mut vm = Vec::new();
+        Engine::Tree
+            .run(
+                &file,
+                false,
+                InterpreterConfig::STRICT,
+                Fuel::unlimited(),
+                &mut tree,
+            )
+            .unwrap();
+        Engine::Vm
+            .run(
+                &file,
+                false,
+                InterpreterConfig::STRICT,
+                Fuel::unlimited(),
+                &mut vm,
+            )
+            .unwrap();
+        assert_eq!(tree, vm, "{}", source);
+    }
+}
+
+#[test]
+fn later_evaluations_add_to_the_table() {
+    let arena = Arena::new();
+    let mut lines = Vec::new();
+    let mut session = Session::new(
+        &arena,
+        false,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        &mut lines,
+    );
+    let file = rinha::parse("let f = fn (x) => x + 1;\nprint(f(1))", "globals.rinha").unwrap();
+    session.eval(&file.expression).unwrap();
+    let file = rinha::parse(
+        "let g = fn (x) => x * 10;\nlet h = fn (x) => g(x) + 1;\nprint(h(2))",
+        "globals.rinha",
+    )
+    .unwrap();
+    session.eval(&file.expression).unwrap();
+    drop(session);
+    assert_eq!(lines, ["2", "21"]);
+}
@@ -0,0 +1,94 @@
+//! Checks the limits `rinha http` puts on each request, talking to it over
+//! a socket.
+
+#![cfg(feature = "server")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// A server on a free port, stopped when dropped.
+struct Server {
+    child: Child,
+    address: String,
+}
+
+impl Server {
+    fn start(args: &[&str]) -> Server {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = format!("127.0.0.1:{}", port);
+        let child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+            .args(["http", "--listen", &address])
+            .args(args)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        for _ in 0..100 {
+            if TcpStream::connect(&address).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Server { child, address }
+    }
+
+    /// Sends `body` to `POST /run`, returning the status and the body of
+    /// the response.
+    fn post(&self, body: &[u8]) -> (u16, String) {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        let head = format!(
+            "POST /run HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.address,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        // The server may answer before reading all of a body it refuses.
+        let _ = stream.write_all(body);
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn ast(source: &str) -> Vec<u8> {
+    rinha::parse(source, "http.rinha")
+        .unwrap()
+        .to_json()
+        .into_bytes()
+}
+
+#[test]
+fn interrupts_programs_that_run_too_long_and_goes_on() {
+    let server = Server::start(&["--timeout", "0.5"]);
+    let (status, body) = server.post(&ast("let f = fn (n) => f(n + 1); f(0)"));
+    assert_eq!(status, 200);
+    assert!(body.contains("\"status\":\"error\""), "{}", body);
+    assert!(body.contains("interrupted after"), "{}", body);
+
+    let (status, body) = server.post(&ast("let _ = print(1); 2"));
+    assert_eq!(status, 200);
+    assert!(body.contains("\"status\":\"ok\""), "{}", body);
+}
+
+#[test]
+fn refuses_bodies_larger_than_the_limit() {
+    let server = Server::start(&[]);
+    let (status, body) = server.post(&vec![b' '; (16 << 20) + 1]);
+    assert_eq!(status, 413, "{}", body);
+    assert!(body.contains("larger than"), "{}", body);
+}
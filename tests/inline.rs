@@ -0,0 +1,56 @@
+mod common;
+
+use rinha::inline;
+
+/// Inlines `source`, checking that it runs the same as before.
+fn inlined(source: &str) -> String {
+    common::transformed(source, inline::inline)
+}
+
+#[test]
+fn inlines_small_helpers() {
+    let source =
+        inlined("let double = fn (x) => x * 2;\nlet inc = fn (x) => x + 1;\ndouble(inc(20))");
+    assert!(!source.contains("double("), "{}", source);
+    assert!(!source.contains("inc("), "{}", source);
+}
+
+#[test]
+fn keeps_the_meaning_of_names() {
+    // The helper uses a `y` that is bound again before the call.
+    let source = inlined("let y = 1;\nlet add = fn (x) => x + y;\nlet y = 2;\nadd(10)");
+    assert!(source.contains("add(10)"), "{}", source);
+
+    // Arguments mention names the parameters are called.
+    inlined("let sub = fn (x, y) => x - y;\nlet x = 1;\nlet y = 5;\nsub(y, x)");
+
+    // A parameter shadowed inside the body is left alone there.
+    inlined("let f = fn (x) => (x, let x = 7; x);\nf(1)");
+}
+
+#[test]
+fn evaluates_arguments_in_order() {
+    inlined("let pair = fn (a, b) => (b, a);\npair(print(1), print(2))");
+}
+
+#[test]
+fn leaves_recursive_large_and_misapplied_functions() {
+    let source =
+        inlined("let count = fn (n) => if (n == 0) { 0 } else { count(n - 1) };\ncount(3)");
+    assert!(source.contains("count(3)"), "{}", source);
+
+    let source =
+        inlined("let big = fn (n) => n + n + n + n + n + n + n + n + n + n + n + n + n;\nbig(1)");
+    assert!(source.contains("big(1)"), "{}", source);
+
+    let source = inlined("let f = fn (a, b) => a;\nf(1)");
+    assert!(source.contains("f(1)"), "{}", source);
+}
+
+#[test]
+fn renames_parameters_to_names_a_program_can_spell() {
+    // `__x_1` is taken, so the parameter gets the next fresh name.
+    let source = inlined("let __x_1 = 5;\nlet double = fn (x) => x * 2;\ndouble(__x_1)");
+    assert!(source.contains("__x_2 * 2"), "{}", source);
+    rinha::parse(&source, "inlined.rinha").unwrap();
+}
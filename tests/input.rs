@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+
+use rinha::error::RuntimeError;
+use rinha::{input, Engine, Fuel, InterpreterConfig};
+
+fn lines(lines: &[&str]) -> VecDeque<String> {
+    lines.iter().map(|line| line.to_string()).collect()
+}
+
+#[test]
+fn programs_read_the_lines_they_are_given() {
+    // `get` looks pure, so only the read it reaches keeps its calls from
+    // being cached.
+    let source = "let ask = fn () => read_line();
+        let get = fn (x) => ask();
+        let _ = print(\"name?\");
+        let name = get(1);
+        let age = str_to_int(get(1));
+        (name, age + 1)";
+    let file = rinha::parse(source, "input.rinha").unwrap();
+    for engine in Engine::ALL {
+        input::set(lines(&["Ada", "36"]));
+        let mut printed = Vec::new();
+        let value = engine
+            .run(
+                &file,
+                true,
+                InterpreterConfig::EXTENDED,
+                Fuel::unlimited(),
+                &mut printed,
+            )
+            .unwrap();
+        assert_eq!(printed, ["name?"]);
+        assert_eq!(value.to_string(), "(Ada, 37)", "{}", engine.name());
+
+        let err = engine
+            .run(
+                &file,
+                true,
+                InterpreterConfig::EXTENDED,
+                Fuel::unlimited(),
+                &mut printed,
+            )
+            .unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::Builtin { name, .. } if name == "read_line"),
+            "{}: {:?}",
+            engine.name(),
+            err
+        );
+        assert_eq!(err.to_string(), "read_line: end of input");
+    }
+}
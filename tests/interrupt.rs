@@ -0,0 +1,47 @@
+//! Interrupting is global to the process, so these tests have a binary of
+//! their own.
+
+use rinha::error::RuntimeError;
+use rinha::{interrupt, stats, Engine, Fuel, InterpreterConfig};
+
+#[test]
+fn interrupted_programs_stop_at_the_next_call() {
+    let file = rinha::parse(
+        "let _ = print(1);\nlet f = fn (x) => x + 1;\nprint(f(2))",
+        "interrupt.rinha",
+    )
+    .unwrap();
+    interrupt::interrupt();
+    for engine in [Engine::Tree, Engine::Vm] {
+        stats::reset();
+        let mut lines = Vec::new();
+        let result = engine.run(
+            &file,
+            false,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            &mut lines,
+        );
+        assert_eq!(lines, ["1"], "{}", engine.name());
+        match result {
+            Err(RuntimeError::Interrupted { calls, location }) => {
+                assert_eq!(calls, 0);
+                assert_eq!(location.start, 49);
+            }
+            result => panic!("{}: {:?}", engine.name(), result),
+        }
+    }
+
+    interrupt::reset();
+    let mut lines = Vec::new();
+    Engine::Tree
+        .run(
+            &file,
+            false,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            &mut lines,
+        )
+        .unwrap();
+    assert_eq!(lines, ["1", "3"]);
+}
@@ -0,0 +1,65 @@
+//! Checks the JSON that `--json-result` gives for the value of a program.
+
+use rinha::parser::{self, Syntax};
+use rinha::{Engine, Fuel, InterpreterConfig};
+use serde_json::json;
+
+fn value_json(source: &str) -> Vec<serde_json::Value> {
+    let file = parser::parse_with(source, "json.rinha", Syntax::all()).unwrap();
+    Engine::ALL
+        .iter()
+        .map(|engine| {
+            let mut lines = Vec::new();
+            let value = engine
+                .run(
+                    &file,
+                    true,
+                    InterpreterConfig::EXTENDED,
+                    Fuel::unlimited(),
+                    &mut lines,
+                )
+                .unwrap();
+            value.to_json()
+        })
+        .collect()
+}
+
+fn assert_json(source: &str, expected: serde_json::Value) {
+    for value in value_json(source) {
+        assert_eq!(value, expected, "{}", source);
+    }
+}
+
+#[test]
+fn scalars() {
+    assert_json("1 + 2", json!(3));
+    assert_json("-7", json!(-7));
+    assert_json("\"say \" + \"hi\"", json!("say hi"));
+    assert_json("1 < 2", json!(true));
+}
+
+#[test]
+fn nested_tuples_are_arrays() {
+    assert_json(
+        "(1, (\"two\", (false, 4)))",
+        json!([1, ["two", [false, 4]]]),
+    );
+}
+
+#[test]
+fn lists_and_records() {
+    assert_json("cons(1, cons(2, nil))", json!([1, 2]));
+    assert_json("{y: (1, 2), x: \"a\"}", json!({"x": "a", "y": [1, 2]}));
+}
+
+#[test]
+fn functions_and_refs_are_null() {
+    assert_json("fn (x) => { x }", json!(null));
+    assert_json("(ref(1), head)", json!([null, null]));
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn big_integers_are_strings() {
+    assert_json("9223372036854775807 * 10", json!("92233720368547758070"));
+}
@@ -0,0 +1,97 @@
+//! Runs what the `json_ast` fuzz target does on broken copies of the
+//! example ASTs: loading them may fail and running them may stop with an
+//! error, but nothing may panic.
+
+use std::fs;
+use std::path::Path;
+
+use rinha::{lint, optimizer, typecheck, Engine, Fuel, InterpreterConfig};
+use serde_json::Value;
+
+/// What the fuzz target does with `json`. Gives whether it loaded.
+fn check(json: &str) -> bool {
+    let Ok(file) = rinha::parse_json(json) else {
+        return false;
+    };
+    let _ = typecheck::check(&file);
+    let _ = lint::lint(&file, InterpreterConfig::STRICT);
+    let _ = file.expression.to_source();
+    let file = optimizer::optimize(&file);
+    for engine in [Engine::Tree, Engine::Vm] {
+        let mut lines = Vec::new();
+        let config = InterpreterConfig::STRICT;
+        let _ = engine.run(&file, true, config, Fuel::limited(10_000), &mut lines);
+    }
+    true
+}
+
+/// Copies of `value` with one field of one object taken out, or its value
+/// replaced by each of `replacements`.
+fn mutations(value: &Value, replacements: &[Value]) -> Vec<Value> {
+    let mut mutated = Vec::new();
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                let mut without = fields.clone();
+                without.remove(key);
+                mutated.push(Value::Object(without));
+                for replacement in replacements {
+                    let mut replaced = fields.clone();
+                    replaced.insert(key.clone(), replacement.clone());
+                    mutated.push(Value::Object(replaced));
+                }
+                for inner in mutations(field, replacements) {
+                    let mut replaced = fields.clone();
+                    replaced.insert(key.clone(), inner);
+                    mutated.push(Value::Object(replaced));
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                for inner in mutations(item, replacements) {
+                    let mut replaced = items.clone();
+                    replaced[i] = inner;
+                    mutated.push(Value::Array(replaced));
+                }
+            }
+        }
+        _ => {}
+    }
+    mutated
+}
+
+fn examples() -> Vec<String> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("files");
+    ["fib", "function", "hello", "let"]
+        .iter()
+        .map(|name| fs::read_to_string(dir.join(name).with_extension("json")).unwrap())
+        .collect()
+}
+
+#[test]
+fn truncated_asts_are_errors() {
+    for json in examples() {
+        assert!(check(&json));
+        for end in (0..json.trim_end().len()).filter(|&end| json.is_char_boundary(end)) {
+            assert!(!check(&json[..end]), "{}", &json[..end]);
+        }
+    }
+}
+
+#[test]
+fn asts_with_missing_or_wrong_fields_do_not_panic() {
+    let replacements = [
+        Value::Null,
+        Value::from(-1),
+        Value::from(u64::MAX),
+        Value::from("Var"),
+        Value::from(Vec::<Value>::new()),
+    ];
+    for json in examples() {
+        let value: Value = serde_json::from_str(&json).unwrap();
+        for mutated in mutations(&value, &replacements) {
+            check(&mutated.to_string());
+        }
+    }
+}
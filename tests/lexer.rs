@@ -0,0 +1,60 @@
+use rinha::lexer::{self, TokenKind};
+
+#[test]
+fn tokens_cover_the_source_in_order() {
+    let source = "let x = \"a\"; /* note */ print(x)\n// end";
+    let tokens = lexer::tokenize(source);
+    let joined: String = tokens
+        .iter()
+        .map(|(_, range)| &source[range.clone()])
+        .collect();
+    assert_eq!(joined, source);
+    let kinds: Vec<_> = tokens
+        .iter()
+        .filter(|(kind, _)| *kind != TokenKind::Whitespace)
+        .map(|(kind, _)| kind.name())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "keyword",
+            "ident",
+            "punctuation",
+            "string",
+            "punctuation",
+            "comment",
+            "keyword",
+            "punctuation",
+            "ident",
+            "punctuation",
+            "comment"
+        ]
+    );
+}
+
+#[test]
+fn rejected_text_is_an_error_token() {
+    let tokens = lexer::tokenize("1 # \"open");
+    let kinds: Vec<_> = tokens.iter().map(|(kind, _)| *kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Int,
+            TokenKind::Whitespace,
+            TokenKind::Error,
+            TokenKind::Whitespace,
+            TokenKind::Error
+        ]
+    );
+    assert_eq!(tokens[4].1, 4..9);
+}
+
+#[test]
+fn html_escapes_the_text_of_tokens() {
+    assert_eq!(
+        lexer::html("a < \"&\""),
+        "<pre class=\"rinha\"><span class=\"rinha-ident\">a</span> \
+         <span class=\"rinha-punctuation\">&lt;</span> \
+         <span class=\"rinha-string\">&quot;&amp;&quot;</span></pre>\n"
+    );
+}
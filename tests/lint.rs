@@ -0,0 +1,59 @@
+//! Checks the warnings of `rinha lint`, which never stop a program.
+
+use std::process::Command;
+
+use rinha::{lint, InterpreterConfig};
+
+/// The warnings for `source`, with the offset each one starts at.
+fn warnings(source: &str) -> Vec<(String, usize)> {
+    let file = rinha::parse(source, "lint.rinha").unwrap();
+    lint::lint(&file, InterpreterConfig::STRICT)
+        .into_iter()
+        .map(|warning| (warning.message, warning.location.start))
+        .collect()
+}
+
+#[test]
+fn flags_likely_runtime_errors() {
+    let source = "let f = fn (a) => a;
+let x = f(1, 2);
+let y = fn () => 1(2);
+print((1, 2) < (3, 4))";
+    let expected = [
+        ("expected 1 arguments but instead got 2", 29),
+        ("expected closure but found int", 55),
+        ("expected int but found tuple", 67),
+    ];
+    let expected: Vec<_> = expected
+        .into_iter()
+        .map(|(message, start)| (message.to_string(), start))
+        .collect();
+    assert_eq!(warnings(source), expected);
+}
+
+#[test]
+fn passes_programs_that_run() {
+    let source = "let f = fn (a, b) => if (a < b) { (a, b) } else { f(b, a) };
+print(f(2, 1))";
+    assert!(warnings(source).is_empty());
+}
+
+#[test]
+fn lint_flag_warns_and_still_runs() {
+    let path = std::env::temp_dir().join(format!("rinha-lint-{}.rinha", std::process::id()));
+    std::fs::write(&path, "let g = fn () => 1(2);\nprint(3)").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["--lint", "--quiet"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("warning: expected closure but found int\n"),
+        "{}",
+        stderr
+    );
+}
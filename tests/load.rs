@@ -0,0 +1,42 @@
+//! Checks `rinha::load` and `rinha::run_loaded`, which the Python, Node.js
+//! and C bindings read and run programs with.
+
+#[test]
+fn reads_source_and_json_asts() {
+    let source = rinha::load("1 + 2", "load.rinha").unwrap();
+    let json = rinha::load(&source.to_json(), "load.rinha").unwrap();
+    for file in [source, json] {
+        assert!(matches!(
+            rinha::interpret(&file),
+            Ok(rinha::Value::Number(3))
+        ));
+    }
+}
+
+#[test]
+fn fails_with_the_first_error() {
+    let err = rinha::load("let", "load.rinha").unwrap_err();
+    assert_eq!(
+        err,
+        "load.rinha:3: expected identifier but found end of file"
+    );
+    let err = rinha::load("{ \"name\": 1 }", "load.rinha").unwrap_err();
+    assert!(err.starts_with("invalid JSON AST: "), "{}", err);
+}
+
+#[test]
+fn reads_only_json_asts_when_asked() {
+    let source = rinha::load("1 + 2", "load.rinha").unwrap();
+    assert!(rinha::load_json(&source.to_json()).is_ok());
+    let err = rinha::load_json("1 + 2").unwrap_err();
+    assert!(err.starts_with("invalid JSON AST: "), "{}", err);
+}
+
+#[test]
+fn runs_loaded_programs_with_their_output() {
+    let file = rinha::load("let _ = print(1); 1 + 2", "load.rinha").unwrap();
+    let mut lines = Vec::new();
+    let value = rinha::run_loaded(&file, &mut lines).unwrap();
+    assert_eq!(lines, ["1"]);
+    assert!(matches!(value, rinha::Value::Number(3)));
+}
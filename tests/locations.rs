@@ -0,0 +1,78 @@
+//! Checks that every engine reports a runtime error at the same place.
+
+use rinha::parser::{self, Syntax};
+use rinha::{Engine, Fuel, InterpreterConfig};
+
+const PROGRAMS: [&str; 19] = [
+    "let x = first(1); x",
+    "let x = second(true); x",
+    "let f = fn (a) => first(a); f(1)",
+    "print(first(1))",
+    "let x = 1; x(2)",
+    "let f = fn (a) => a; f(1, 2)",
+    "if (1) { 2 } else { 3 }",
+    "let x = 1 && true; x",
+    "let x = true && 1; x",
+    "let (a, b) = 1; a",
+    "let x = 1 / 0; x",
+    "let x = 1 + (2, 3); x",
+    "let x = y; x",
+    "let x = match (1) { 2 => 3 }; x",
+    "let x = {a: 1}.b; x",
+    "let x = 1.a; x",
+    "let x = !1; x",
+    "let r = 1; r := 2",
+    "let x = 5; !x",
+];
+
+#[test]
+fn engines_report_errors_at_the_same_place() {
+    for source in PROGRAMS {
+        let file = parser::parse_with(source, "locations.rinha", Syntax::all()).unwrap();
+        let locations: Vec<_> = Engine::ALL
+            .iter()
+            .map(|engine| {
+                let err = engine
+                    .run(
+                        &file,
+                        false,
+                        InterpreterConfig::EXTENDED,
+                        Fuel::unlimited(),
+                        &mut Vec::new(),
+                    )
+                    .unwrap_err();
+                let location = err.location();
+                (location.start, location.end)
+            })
+            .collect();
+        assert!(
+            locations.iter().all(|location| *location == locations[0]),
+            "{}: {:?}",
+            source,
+            locations
+        );
+    }
+}
+
+#[test]
+fn projections_point_at_what_is_not_a_tuple() {
+    let file = rinha::parse("let x = first(1); x", "locations.rinha").unwrap();
+    for engine in Engine::ALL {
+        let err = engine
+            .run(
+                &file,
+                false,
+                InterpreterConfig::STRICT,
+                Fuel::unlimited(),
+                &mut Vec::new(),
+            )
+            .unwrap_err();
+        let location = err.location();
+        assert_eq!(
+            (location.start, location.end),
+            (14, 15),
+            "{}",
+            engine.name()
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Checks `rinha lsp` by talking to it over stdin and stdout.
+
+#![cfg(feature = "lsp")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{json, Value};
+
+const URI: &str = "file:///lsp.rinha";
+
+struct Client {
+    child: Child,
+    /// Taken when stopping, so the server sees its input end.
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Client {
+            child,
+            stdin,
+            stdout,
+        };
+        client.request(1, "initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        let stdin = self.stdin.as_mut().unwrap();
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Sends a request and gives back its result, skipping notifications
+    /// the server sends in between.
+    fn request(&mut self, id: u64, method: &str, params: Value) -> Value {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                return message["result"].clone();
+            }
+        }
+    }
+
+    /// Opens `text` and gives back the diagnostics published for it.
+    fn open(&mut self, text: &str) -> Value {
+        let document = json!({ "uri": URI, "languageId": "rinha", "version": 1, "text": text });
+        self.notify("textDocument/didOpen", json!({ "textDocument": document }));
+        loop {
+            let message = self.receive();
+            if message["method"] == "textDocument/publishDiagnostics" {
+                return message["params"]["diagnostics"].clone();
+            }
+        }
+    }
+
+    fn at(line: u32, character: u32) -> Value {
+        json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        })
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.request(0, "shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        self.stdin = None;
+        self.child.wait().unwrap();
+    }
+}
+
+fn range(start: (u32, u32), end: (u32, u32)) -> Value {
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+#[test]
+fn publishes_parse_errors() {
+    let mut client = Client::start();
+    let diagnostics = client.open("let x = ;\nx");
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1, "{}", diagnostics);
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["range"], range((0, 8), (0, 9)));
+}
+
+#[test]
+fn hovers_with_types_and_finds_definitions() {
+    let mut client = Client::start();
+    let diagnostics = client.open("let double = fn (x) => x * 2;\nprint(double(1))");
+    assert_eq!(diagnostics, json!([]));
+
+    let hover = client.request(2, "textDocument/hover", Client::at(1, 8));
+    let contents = hover["contents"]["value"].as_str().unwrap();
+    assert!(contents.contains("double: "), "{}", contents);
+    assert_eq!(hover["range"], range((1, 6), (1, 12)));
+
+    let definition = client.request(3, "textDocument/definition", Client::at(1, 8));
+    assert_eq!(definition["uri"], URI);
+    assert_eq!(definition["range"], range((0, 4), (0, 10)));
+}
@@ -0,0 +1,45 @@
+//! Checks that calls to pure functions are cached unless memoization is
+//! turned off, and that calls which print are not.
+
+use rinha::error::RuntimeError;
+use rinha::{Engine, Fuel, InterpreterConfig, Value};
+
+fn run(
+    source: &str,
+    engine: Engine,
+    memoize: bool,
+    fuel: Fuel,
+) -> (Result<Value, RuntimeError>, Vec<String>) {
+    let file = rinha::parse(source, "memo.rinha").unwrap();
+    let mut lines = Vec::new();
+    let result = engine.run(&file, memoize, InterpreterConfig::STRICT, fuel, &mut lines);
+    (result, lines)
+}
+
+#[test]
+fn repeated_calls_to_pure_functions_are_cached() {
+    let fib = "let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };\nfib(25)";
+    for engine in Engine::ALL {
+        // Without the cache, `fib(25)` makes a quarter of a million calls.
+        let (result, _) = run(fib, engine, true, Fuel::limited(1000));
+        assert_eq!(result.unwrap().to_string(), "75025", "{}", engine.name());
+        let (result, _) = run(fib, engine, false, Fuel::limited(1000));
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "execution budget exceeded",
+            "{}",
+            engine.name()
+        );
+    }
+}
+
+#[test]
+fn calls_that_print_are_not_cached() {
+    let source = "let f = fn (x) => print(x);\nlet a = f(1);\nlet b = f(1);\na + b";
+    for engine in Engine::ALL {
+        let (result, lines) = run(source, engine, true, Fuel::unlimited());
+        assert_eq!(result.unwrap().to_string(), "2", "{}", engine.name());
+        assert_eq!(lines, ["1", "1"], "{}", engine.name());
+    }
+}
@@ -0,0 +1,68 @@
+//! Checks that packed ASTs load back into exactly the program they were
+//! made from.
+
+use std::fs;
+use std::path::Path;
+
+use rinha::packed::{self, MAGIC};
+use rinha::parser::{self, Syntax};
+
+#[test]
+fn conformance_programs_round_trip() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut count = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("rinha") {
+            continue;
+        }
+        let contents = fs::read_to_string(&path).unwrap();
+        let name = path.display().to_string();
+        let Ok(file) = parser::parse_with(&contents, &name, Syntax::all()) else {
+            continue;
+        };
+        let bytes = packed::pack(&file);
+        assert!(packed::is_packed(&bytes));
+        let unpacked = packed::unpack(&bytes).unwrap();
+        assert_eq!(unpacked.to_json(), file.to_json(), "{}", name);
+        count += 1;
+    }
+    assert!(count > 0);
+}
+
+#[test]
+fn strings_are_written_once() {
+    let source = "let message = \"hello\"; (message, (message, \"hello\"))";
+    let bytes = packed::pack(&rinha::parse(source, "strings.rinha").unwrap());
+    let count = bytes.windows(5).filter(|w| w == b"hello").count();
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn extreme_integers_round_trip() {
+    let source = "(9223372036854775807, (-9223372036854775807 - 1, -1))";
+    let file = rinha::parse(source, "ints.rinha").unwrap();
+    let unpacked = packed::unpack(&packed::pack(&file)).unwrap();
+    assert_eq!(unpacked.to_json(), file.to_json());
+}
+
+#[test]
+fn broken_input_is_rejected() {
+    let file = rinha::parse("print(1 + 2)", "broken.rinha").unwrap();
+    let bytes = packed::pack(&file);
+
+    let err = packed::unpack(b"{\"name\": \"x\"}").unwrap_err();
+    assert_eq!(err.to_string(), "not a packed program at byte offset 0");
+
+    let mut newer = bytes.clone();
+    newer[MAGIC.len()] = 99;
+    let err = packed::unpack(&newer).unwrap_err();
+    assert_eq!(err.to_string(), "unsupported version 99 at byte offset 4");
+
+    for len in MAGIC.len()..bytes.len() {
+        assert!(packed::unpack(&bytes[..len]).is_err(), "{} bytes", len);
+    }
+    let mut longer = bytes.clone();
+    longer.push(0);
+    assert!(packed::unpack(&longer).is_err());
+}
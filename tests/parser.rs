@@ -0,0 +1,50 @@
+//! Checks the parser of `.rinha` source against the reference parser.
+
+use std::fs;
+use std::path::Path;
+
+/// Examples the reference parser gives the same tree for. It reads
+/// `a + b + c` as `a + (b + c)` where we read it left-associatively, so
+/// `function` is left out, and `add` and `if` are broken on purpose.
+const EXAMPLES: [&str; 3] = ["fib", "hello", "let"];
+
+#[test]
+fn parses_the_examples_as_the_reference_parser_does() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("files");
+    for name in EXAMPLES {
+        let path = dir.join(name);
+        let json = fs::read_to_string(path.with_extension("json")).unwrap();
+        let expected = rinha::parse_json(&json).unwrap();
+        let source = fs::read_to_string(path.with_extension("rinha")).unwrap();
+        let filename = &expected.expression.location().filename;
+        let parsed = rinha::parse(&source, filename).unwrap();
+        assert_eq!(
+            format!("{:?}", parsed.expression),
+            format!("{:?}", expected.expression),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn reads_binary_operators_by_precedence_and_from_the_left() {
+    let file = rinha::parse("print(10 - 2 - 3 * 2 == 2 || false)", "parser.rinha").unwrap();
+    let mut lines = Vec::new();
+    rinha::Engine::Tree
+        .run(
+            &file,
+            false,
+            rinha::InterpreterConfig::STRICT,
+            rinha::Fuel::unlimited(),
+            &mut lines,
+        )
+        .unwrap();
+    assert_eq!(lines, ["true"]);
+}
+
+#[test]
+fn reports_where_source_stops_making_sense() {
+    let err = rinha::parse("let x = ;\nx", "parser.rinha").unwrap_err();
+    assert_eq!(err.message, "expected expression but found ;");
+    assert_eq!((err.location.start, err.location.end), (8, 9));
+}
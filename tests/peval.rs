@@ -0,0 +1,71 @@
+use rinha::error::RuntimeError;
+use rinha::{optimizer, peval, Engine, File, Fuel, InterpreterConfig, Value};
+
+fn run(file: &File) -> (Vec<String>, Result<Value, RuntimeError>) {
+    let mut lines = Vec::new();
+    let result = Engine::Vm.run(
+        file,
+        true,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        &mut lines,
+    );
+    (lines, result)
+}
+
+fn specialize(source: &str) -> File {
+    let file = rinha::parse(source, "peval.rinha").unwrap();
+    let specialized = peval::specialize(&optimizer::optimize(&file), InterpreterConfig::STRICT);
+    optimizer::remove_dead_lets(&specialized)
+}
+
+#[test]
+fn replaces_closed_calls_with_their_values() {
+    let file = specialize(
+        "let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };\n\
+         let _ = print(\"fib(30) = \" + fib(30));\n\
+         let f = fn (x) => fib(x) + fib(10);\n\
+         (f, f(3))",
+    );
+    let source = file.expression.to_source();
+    assert!(source.contains("print(\"fib(30) = 832040\")"), "{}", source);
+    // Calls on parameters stay, while closed calls inside functions go.
+    assert!(source.contains("fib(x) + 55"), "{}", source);
+    // The call inlined and then evaluated leaves no binding behind.
+    assert!(source.ends_with("57)"), "{}", source);
+    assert!(!source.contains("__x"), "{}", source);
+}
+
+#[test]
+fn leaves_calls_that_cannot_run_ahead_of_time() {
+    let source = "let loud = fn (x) => print(x);\n\
+                  let forever = fn (n) => forever(n + 1);\n\
+                  let half = fn (n) => n / 0;\n\
+                  let _ = loud(1);\n\
+                  let _ = half(4);\n\
+                  forever(0)";
+    let file = specialize(source);
+    let printed = file.expression.to_source();
+    // The first two are inlined, but not evaluated.
+    for call in ["print(", " / 0", "forever(0)"] {
+        assert!(printed.contains(call), "{}", printed);
+    }
+}
+
+#[test]
+fn respects_shadowing() {
+    let source = "let x = 1;\n\
+                  let x = print(5);\n\
+                  let get = fn () => x + 1;\n\
+                  let f = fn () => 10;\n\
+                  let g = fn () => f();\n\
+                  let f = fn () => 20;\n\
+                  (get(), g() + f())";
+    let original = rinha::parse(source, "peval.rinha").unwrap();
+    let file = specialize(source);
+    let (lines, result) = run(&file);
+    let (expected_lines, expected) = run(&original);
+    assert_eq!(lines, expected_lines);
+    assert_eq!(result.unwrap().to_string(), expected.unwrap().to_string());
+    assert!(file.expression.to_source().contains("x + 1"));
+}
@@ -0,0 +1,40 @@
+//! Checks what the browser playground hands back for a program.
+
+#![cfg(feature = "playground")]
+
+use rinha::playground;
+
+#[test]
+fn gives_what_was_printed_and_the_value() {
+    let mut lines = Vec::new();
+    let run = playground::run_program("let _ = print(1);\n(2, 3)", Some(&mut lines));
+    assert_eq!(run.stdout, "1\n");
+    assert_eq!(run.result.as_deref(), Some("(2, 3)"));
+    assert_eq!(run.error, None);
+    // The sink sees each line as it is printed.
+    assert_eq!(lines, ["1"]);
+}
+
+#[test]
+fn errors_are_diagnostics() {
+    let run = playground::run_program("let _ = print(1);\n1 / 0", None);
+    assert_eq!(run.stdout, "1\n");
+    assert_eq!(run.result, None);
+    assert_eq!(
+        run.error.as_deref(),
+        Some(
+            "error: division by zero\n \
+             --> playground.rinha:2:1\n  \
+             |\n\
+             2 | 1 / 0\n  \
+             | ^^^^^\n"
+        )
+    );
+    let run = playground::run_program("let x = ;", None);
+    let error = run.error.unwrap();
+    assert!(
+        error.starts_with("error: expected expression but found ;\n"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,70 @@
+//! `print` with several values, which only runs with `--extensions`.
+
+use rinha::{packed, validate, Engine, Fuel, InterpreterConfig};
+
+const SOURCE: &str = "let x = print(1, \"and\", (2, true)); x";
+
+#[test]
+fn prints_its_values_on_one_line() {
+    let file = rinha::parse(SOURCE, "print.rinha").unwrap();
+    for engine in Engine::ALL {
+        let mut lines = Vec::new();
+        let value = engine
+            .run(
+                &file,
+                true,
+                InterpreterConfig::EXTENDED,
+                Fuel::unlimited(),
+                &mut lines,
+            )
+            .unwrap();
+        assert_eq!(lines, ["1 and (2, true)"], "{}", engine.name());
+        assert_eq!(value.to_string(), "(2, true)");
+    }
+}
+
+#[test]
+fn needs_extensions() {
+    let file = rinha::parse(SOURCE, "print.rinha").unwrap();
+    let violations = validate::validate(&file, InterpreterConfig::STRICT);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(
+        violations[0].message,
+        "`print` takes one value without `--extensions`"
+    );
+    assert!(validate::validate(
+        &rinha::parse("print(1)", "print.rinha").unwrap(),
+        InterpreterConfig::STRICT
+    )
+    .is_empty());
+}
+
+#[test]
+fn single_values_keep_the_reference_json() {
+    let single = rinha::parse("print(1)", "print.rinha").unwrap();
+    let json: serde_json::Value = serde_json::from_str(&single.to_json()).unwrap();
+    assert_eq!(json["expression"]["value"]["value"], 1);
+    assert!(json["expression"].get("values").is_none());
+
+    let several = rinha::parse(SOURCE, "print.rinha").unwrap();
+    let json: serde_json::Value = serde_json::from_str(&several.to_json()).unwrap();
+    assert_eq!(json["expression"]["value"]["values"][0]["value"], 1);
+
+    for file in [single, several] {
+        let loaded = rinha::parse_json(&file.to_json()).unwrap();
+        assert_eq!(loaded.to_json(), file.to_json());
+        let unpacked = packed::unpack(&packed::pack(&file)).unwrap();
+        assert_eq!(unpacked.to_json(), file.to_json());
+        assert_eq!(loaded.expression.to_source(), file.expression.to_source());
+    }
+
+    let empty = r#"{"name": "e", "expression": {"kind": "Print", "values": [],
+        "location": {"start": 0, "end": 0, "filename": "e"}},
+        "location": {"start": 0, "end": 0, "filename": "e"}}"#;
+    let err = rinha::parse_json(empty).unwrap_err();
+    assert!(
+        err.message.contains("empty field `values`"),
+        "{}",
+        err.message
+    );
+}
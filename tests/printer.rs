@@ -0,0 +1,97 @@
+//! Checks that printed terms read back as the same program, and the
+//! source `rinha fmt` prints with them.
+
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command, Output};
+
+use rinha::parser::Syntax;
+
+/// Prints `source` and checks that parsing what it printed gives the same
+/// source back.
+fn reprinted(source: &str) -> String {
+    let file = rinha::parse_with(source, "printer.rinha", Syntax::all()).unwrap();
+    let printed = file.expression.to_source();
+    let reparsed = rinha::parse_with(&printed, "printer.rinha", Syntax::all()).unwrap();
+    assert_eq!(reparsed.expression.to_source(), printed, "{}", source);
+    printed
+}
+
+#[test]
+fn keeps_the_parentheses_operators_need() {
+    assert_eq!(reprinted("(1 + 2) * 3"), "(1 + 2) * 3");
+    assert_eq!(reprinted("1 - (2 - 3)"), "1 - (2 - 3)");
+    assert_eq!(reprinted("(1 - 2) - 3"), "1 - 2 - 3");
+    assert_eq!(reprinted("a || b && c"), "a || b && c");
+    assert_eq!(reprinted("(a || b) && c"), "(a || b) && c");
+}
+
+#[test]
+fn conformance_programs_read_back_the_same() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "rinha") {
+            reprinted(&fs::read_to_string(&path).unwrap());
+        }
+    }
+}
+
+#[test]
+fn fmt_from_json_prints_the_source_of_an_ast() {
+    let json = Path::new(env!("CARGO_MANIFEST_DIR")).join("files/fib.json");
+    let output = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["fmt", "--from-json"])
+        .arg(json)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let expected = "let fib = fn (n) => {
+  if (n < 2) {
+    n
+  } else {
+    fib(n - 1) + fib(n - 2)
+  }
+};
+print(\"fib: \" + fib(10))
+";
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+/// Runs `rinha fmt <args>` on a file holding `source`.
+fn fmt(args: &[&str], source: &str) -> Output {
+    let path = std::env::temp_dir().join(format!("rinha-fmt-{}.rinha", process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .arg("fmt")
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn fmt_prints_formatted_source_and_check_tells_whether_it_is() {
+    let call = "f(100000000000000, 200000000000000000, 3000000000000000000)";
+    let source = format!("let f=fn(a,b,c)=>{{a}};print({}+{})", call, call);
+    // The second call goes past the end of the line, so it is split.
+    let formatted = format!(
+        "let f = fn (a, b, c) => {{
+  a
+}};
+print({} + f(
+  100000000000000,
+  200000000000000000,
+  3000000000000000000
+))
+",
+        call
+    );
+    let output = fmt(&[], &source);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), formatted);
+    assert_eq!(fmt(&["--check"], &source).status.code(), Some(1));
+    assert!(fmt(&["--check"], &formatted).status.success());
+}
@@ -0,0 +1,66 @@
+//! Checks the calls that `--profile` counts and the stacks of calls that
+//! `--flamegraph` writes.
+
+use rinha::{interpreter, Fuel, InterpreterConfig};
+
+const PROGRAM: &str = "let square = fn (x) => { x * x };
+let sum = fn (n) => { if (n == 0) { 0 } else { square(n) + sum(n - 1) } };
+print(sum(200))";
+
+#[test]
+fn stacks_follow_calls_and_fold_recursion() {
+    let file = rinha::parse(PROGRAM, "profile.rinha").unwrap();
+    let (result, profile) = interpreter::interpret_file_profiled(
+        &file,
+        false,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        &mut Vec::new(),
+    );
+    result.unwrap();
+    let stacks: Vec<_> = profile
+        .stacks
+        .iter()
+        .map(|stack| stack.frames.join(";"))
+        .collect();
+    assert_eq!(
+        stacks,
+        [
+            "sum at profile.rinha:44..107",
+            "sum at profile.rinha:44..107;square at profile.rinha:13..32",
+        ]
+    );
+    for line in profile.folded().lines() {
+        let (frames, micros) = line.rsplit_once(' ').unwrap();
+        assert!(stacks.contains(&frames.to_string()), "{}", line);
+        assert!(micros.parse::<u64>().unwrap() > 0, "{}", line);
+    }
+}
+
+#[test]
+fn entries_count_the_calls_of_each_function_slowest_first() {
+    let file = rinha::parse(PROGRAM, "profile.rinha").unwrap();
+    let (result, profile) = interpreter::interpret_file_profiled(
+        &file,
+        false,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        &mut Vec::new(),
+    );
+    result.unwrap();
+    let entries: Vec<_> = profile
+        .entries
+        .iter()
+        .map(|entry| {
+            let name = entry.name.map(|name| name.to_string());
+            (name, entry.location.start, entry.calls)
+        })
+        .collect();
+    // The time of `sum` includes the calls it makes to `square`.
+    let expected = [
+        (Some("sum".to_string()), 44, 201),
+        (Some("square".to_string()), 13, 200),
+    ];
+    assert_eq!(entries, expected);
+    assert!(profile.entries[0].time >= profile.entries[1].time);
+}
@@ -0,0 +1,63 @@
+//! The generator of `random` is global to the process, so these tests have
+//! a binary of their own.
+
+use rinha::{peval, random, Engine, Fuel, InterpreterConfig};
+
+const DRAWS: &str = "let draw = fn (n) => random(n);
+    let draws = fn (k) => if (k == 0) { nil } else { cons(draw(6), draws(k - 1)) };
+    draws(20)";
+
+fn run(source: &str, engine: Engine) -> String {
+    let file = rinha::parse(source, "random.rinha").unwrap();
+    engine
+        .run(
+            &file,
+            true,
+            InterpreterConfig::EXTENDED,
+            Fuel::unlimited(),
+            &mut Vec::new(),
+        )
+        .map_or_else(|err| err.to_string(), |value| value.to_string())
+}
+
+#[test]
+fn seeded_runs_draw_the_same_numbers() {
+    random::seed(42);
+    let first = run(DRAWS, Engine::Tree);
+    for engine in Engine::ALL {
+        random::seed(42);
+        assert_eq!(run(DRAWS, engine), first, "{}", engine.name());
+    }
+    let numbers: Vec<i64> = first
+        .trim_matches(['[', ']'])
+        .split(", ")
+        .map(|n| n.parse().unwrap())
+        .collect();
+    assert_eq!(numbers.len(), 20);
+    assert!(numbers.iter().all(|n| (0..6).contains(n)), "{}", first);
+    // Memoized calls to `draw` would give the same number every time.
+    assert!(numbers.iter().any(|&n| n != numbers[0]), "{}", first);
+
+    random::seed(7);
+    assert_ne!(run(DRAWS, Engine::Vm), first);
+}
+
+#[test]
+fn bounds_must_be_positive() {
+    for engine in [Engine::Tree, Engine::Vm] {
+        assert_eq!(
+            run("random(0)", engine),
+            "random: the bound must be positive, found 0"
+        );
+    }
+}
+
+#[test]
+fn draws_are_not_made_ahead_of_time() {
+    let file = rinha::parse("let f = fn (n) => random(n); f(1000)", "random.rinha").unwrap();
+    let specialized = peval::specialize(&file, InterpreterConfig::EXTENDED);
+    assert_eq!(
+        specialized.expression.to_source(),
+        file.expression.to_source()
+    );
+}
@@ -0,0 +1,89 @@
+//! Checks how functions recurse and that the engines stop recursion at the
+//! same depth. The limit is global, so these tests have a binary of their
+//! own.
+
+use rinha::error::RuntimeError;
+use rinha::{depth, Engine, Fuel, InterpreterConfig, Value};
+
+fn run(source: &str, engine: Engine) -> Result<Value, RuntimeError> {
+    let file = rinha::parse(source, "recursion.rinha").unwrap();
+    let mut lines = Vec::new();
+    engine.run(
+        &file,
+        false,
+        InterpreterConfig::STRICT,
+        Fuel::unlimited(),
+        &mut lines,
+    )
+}
+
+fn sum(n: i64) -> String {
+    format!(
+        "let sum = fn (n) => if (n == 0) {{ 0 }} else {{ n + sum(n - 1) }};\nprint(sum({}))",
+        n
+    )
+}
+
+#[test]
+fn limits_nested_calls() {
+    depth::set_limit(1000);
+    for engine in [Engine::Tree, Engine::Vm] {
+        // `sum(n)` nests n + 1 calls.
+        assert!(run(&sum(999), engine).is_ok(), "{}", engine.name());
+        let source = sum(1000);
+        let err = run(&source, engine).unwrap_err();
+        assert_eq!(err.to_string(), "maximum recursion depth 1000 exceeded");
+        let call = source.find("sum(n - 1)").unwrap();
+        assert_eq!(err.location().start, call, "{}", engine.name());
+
+        // Calls in tail position do not nest.
+        let count = "let count = fn (n) => if (n == 0) { 0 } else { count(n - 1) };\ncount(5000)";
+        assert!(run(count, engine).is_ok(), "{}", engine.name());
+    }
+}
+
+#[test]
+fn functions_call_themselves_but_not_later_bindings() {
+    let own = "let f = fn (x) => {
+          let fact = fn (n) => if (n == 0) { 1 } else { n * fact(n - 1) };
+          let fact2 = fact;
+          let fact = fn (n) => 0;
+          fact2(x) + fact(x)
+        };
+        f(5)";
+    // Functions that call one another take each other as arguments.
+    let passed = "let even = fn (n, odd) => if (n == 0) { true } else { odd(n - 1, even) };
+        let odd = fn (n, even) => if (n == 0) { false } else { even(n - 1, odd) };
+        (even(10, odd), odd(7, even))";
+    let later = "let even = fn (n) => if (n == 0) { true } else { odd(n - 1) };
+        let odd = fn (n) => if (n == 0) { false } else { even(n - 1) };
+        even(10)";
+    for engine in Engine::ALL {
+        let value = run(own, engine).unwrap();
+        assert_eq!(value.to_string(), "120", "{}", engine.name());
+        let value = run(passed, engine).unwrap();
+        assert_eq!(value.to_string(), "(true, true)", "{}", engine.name());
+        let err = run(later, engine).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot find variable odd",
+            "{}",
+            engine.name()
+        );
+    }
+}
+
+#[test]
+fn calls_in_tail_position_run_in_constant_space() {
+    depth::set_limit(1000);
+    // The tail call is behind a `let` and in both branches of an `if`.
+    let source = "let loop = fn (n, acc) => {
+          let next = n - 1;
+          if (n == 0) { acc } else { if (n % 2 == 0) { loop(next, acc + n) } else { loop(next, acc) } }
+        };
+        loop(100000, 0)";
+    for engine in Engine::ALL {
+        let value = run(source, engine).unwrap();
+        assert_eq!(value.to_string(), "2500050000", "{}", engine.name());
+    }
+}
@@ -0,0 +1,47 @@
+//! Checks how `rinha repl` reports errors, driving it through stdin.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Feeds `input` to the REPL, returning what it wrote to stderr.
+fn errors(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn quotes_the_input_a_runtime_error_points_into() {
+    let stderr = errors("let f = fn (x) => x / 0;\n1 +\n2\nf(1)\n");
+    assert!(stderr.contains(" --> <repl:1>:1:19\n"), "{}", stderr);
+    assert!(
+        stderr.contains("1 | let f = fn (x) => x / 0;\n"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("  |                   ^^^^^\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn quotes_inputs_that_do_not_parse() {
+    let stderr = errors("let\n= 2\n");
+    assert!(stderr.contains(" --> <repl:1>:2:1\n"), "{}", stderr);
+    assert!(stderr.contains("2 | = 2\n"), "{}", stderr);
+}
@@ -0,0 +1,44 @@
+//! Checks the slots the resolver gives variables.
+
+use rinha::resolver;
+
+/// Each variable in `source`, in the order they appear, with the slot the
+/// resolver gives it, such as `a Local(1)`.
+fn slots(source: &str) -> Vec<String> {
+    let file = rinha::parse(source, "resolver.rinha").unwrap();
+    let debug = format!("{:?}", resolver::resolve(&file.expression));
+    debug
+        .split("Var { text: \"")
+        .skip(1)
+        .map(|var| {
+            let (name, rest) = var.split_once("\", index: ").unwrap();
+            let slot = &rest[..rest.find(", location").unwrap()];
+            let slot = slot
+                .strip_prefix("Some(")
+                .map_or(slot, |slot| &slot[..slot.len() - 1]);
+            format!("{} {}", name, slot)
+        })
+        .collect()
+}
+
+#[test]
+fn variables_count_the_bindings_since_theirs() {
+    let source = "let a = 1;\nlet b = 2;\nlet a = a + b;\nf(b) + a";
+    // `f` is bound outside the program, and is looked up by name.
+    let expected = [
+        "a Local(1)",
+        "b Local(0)",
+        "f None",
+        "b Local(1)",
+        "a Local(0)",
+    ];
+    assert_eq!(slots(source), expected);
+}
+
+#[test]
+fn functions_count_their_captures_below_their_own_bindings() {
+    // Inside `f`, `x` comes first, then `f` itself, then what it captures.
+    let source = "let a = 1;\nlet f = fn (x) => x + a;\nf(a)";
+    let expected = ["x Local(0)", "a Local(2)", "f Local(0)", "a Local(1)"];
+    assert_eq!(slots(source), expected);
+}
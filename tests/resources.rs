@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use rinha::resources::Resources;
+use rinha::{stats, Engine, Fuel, InterpreterConfig};
+
+#[test]
+fn both_engines_report_the_same_calls_and_depth() {
+    let source = "let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };\nfib(10)";
+    let file = rinha::parse(source, "resources.rinha").unwrap();
+    for engine in [Engine::Tree, Engine::Vm] {
+        stats::reset();
+        let mut lines = Vec::new();
+        engine
+            .run(
+                &file,
+                false,
+                InterpreterConfig::STRICT,
+                Fuel::unlimited(),
+                &mut lines,
+            )
+            .unwrap();
+        let resources = Resources::measure(Duration::from_millis(5));
+        // fib(10) makes 177 calls, nesting as deep as fib(1).
+        assert_eq!(resources.steps, 177, "{}", engine.name());
+        assert_eq!(resources.max_depth, 10, "{}", engine.name());
+
+        let json = resources.to_json();
+        assert_eq!(json["wall_ms"], 5);
+        assert_eq!(json["steps"], 177);
+        if cfg!(target_os = "linux") {
+            assert!(json["peak_rss_kb"].as_u64().unwrap() > 0);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Checks `rinha serve`, which answers each line of stdin with the result
+//! of running the program on it.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use base64::Engine as _;
+use serde_json::Value;
+
+/// Sends `lines` to `rinha serve` and gives back its answers.
+fn serve(lines: &[String]) -> Vec<Value> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .arg("serve")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn base64(source: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(source)
+}
+
+/// The AST of `source` as JSON on a single line.
+fn json(source: &str) -> String {
+    let file = rinha::parse(source, "serve.rinha").unwrap();
+    let json: Value = serde_json::from_str(&file.to_json()).unwrap();
+    json.to_string()
+}
+
+#[test]
+fn answers_each_program_on_its_own_line() {
+    let answers = serve(&[
+        json("let x = print(40);\nx + 2"),
+        base64("let _ = print(\"hi\");\n1 / 0"),
+        "!".to_string(),
+        base64("print(x)"),
+    ]);
+    assert_eq!(answers.len(), 4);
+    assert_eq!(answers[0]["stdout"], "40\n");
+    assert_eq!(answers[0]["result"], "42");
+    assert_eq!(answers[0]["status"], "ok");
+    assert_eq!(answers[1]["stdout"], "hi\n");
+    assert_eq!(answers[1]["result"], "division by zero");
+    assert_eq!(answers[1]["status"], "error");
+    assert_eq!(answers[2]["status"], "error");
+    let result = answers[2]["result"].as_str().unwrap();
+    assert!(result.starts_with("invalid base64: "), "{}", result);
+    // Nothing is left over from the programs before.
+    assert_eq!(answers[3]["status"], "error");
+    assert_eq!(answers[3]["stdout"], "");
+}
@@ -0,0 +1,75 @@
+//! Runs the programs in `tests/corpus` and snapshots what they print and
+//! their final value. Every engine must produce the same snapshot, as must
+//! the tree-walker evaluating operands in parallel.
+//!
+//! After an intended change in behavior, review and accept the new
+//! snapshots with `cargo insta review`.
+
+use std::fs;
+use std::path::Path;
+
+use rinha::error::RuntimeError;
+use rinha::interpreter;
+use rinha::{Engine, File, Fuel, InterpreterConfig, Value};
+
+fn show(
+    path: &Path,
+    run: impl FnOnce(&File, &mut Vec<String>) -> Result<Value, RuntimeError>,
+) -> String {
+    let source = fs::read_to_string(path).unwrap();
+    let file = rinha::parse(&source, "program.rinha").unwrap();
+    let mut lines = Vec::new();
+    let result = run(&file, &mut lines);
+    match result {
+        Ok(value) => lines.push(format!("value: {:?}", value)),
+        Err(err) => lines.push(format!("error: {}", err)),
+    }
+    lines.join("\n")
+}
+
+fn run(path: &Path, engine: Engine) -> String {
+    show(path, |file, lines| {
+        engine.run(
+            file,
+            true,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            lines,
+        )
+    })
+}
+
+fn run_parallel(path: &Path) -> String {
+    show(path, |file, lines| {
+        interpreter::interpret_file_parallel(
+            file,
+            true,
+            InterpreterConfig::STRICT,
+            Fuel::unlimited(),
+            lines,
+        )
+    })
+}
+
+#[test]
+fn corpus() {
+    insta::glob!("corpus/*.rinha", |path| {
+        let output = run(path, Engine::Tree);
+        for engine in [Engine::Vm, Engine::Jit] {
+            assert_eq!(
+                run(path, engine),
+                output,
+                "{:?} differs from the tree-walker on {}",
+                engine,
+                path.display()
+            );
+        }
+        assert_eq!(
+            run_parallel(path),
+            output,
+            "parallel evaluation differs from the tree-walker on {}",
+            path.display()
+        );
+        insta::assert_snapshot!(output);
+    });
+}
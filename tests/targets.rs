@@ -0,0 +1,165 @@
+//! Checks that the programs `rinha build` writes print what the
+//! tree-walker prints.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use rinha::{output, Engine, Fuel, InterpreterConfig};
+
+/// The examples in `files/` that parse, with what the tree-walker prints
+/// for each. A couple of them are broken on purpose.
+fn examples() -> Vec<(PathBuf, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("files");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rinha"))
+        .collect();
+    paths.sort();
+    let examples: Vec<_> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let source = fs::read_to_string(&path).unwrap();
+            let file = rinha::parse(&source, &path.to_string_lossy()).ok()?;
+            let mut lines = Vec::new();
+            Engine::Tree
+                .run(
+                    &file,
+                    false,
+                    InterpreterConfig::STRICT,
+                    Fuel::unlimited(),
+                    &mut lines,
+                )
+                .unwrap();
+            let stdout = output::joined(&lines);
+            Some((path, stdout))
+        })
+        .collect();
+    assert!(!examples.is_empty());
+    examples
+}
+
+/// Runs `rinha build --target <target>` on `input`, writing `output`.
+fn build(target: &str, input: &Path, output: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["build", "--target", target, "-o"])
+        .arg(output)
+        .arg(input)
+        .status()
+        .unwrap();
+    assert!(status.success(), "{}", input.display());
+}
+
+fn stdout(command: &mut Command) -> String {
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{:?}", command);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn c_programs_print_what_the_tree_walker_prints() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping: no `cc` to build the C programs with");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("rinha-c-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (path, expected) in examples() {
+        let name = path.file_stem().unwrap();
+        let source = dir.join(name).with_extension("c");
+        let binary = dir.join(name);
+        build("c", &path, &source);
+        let status = Command::new("cc")
+            .arg("-O2")
+            .arg(&source)
+            .arg("-o")
+            .arg(&binary)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{}", source.display());
+        assert_eq!(
+            stdout(&mut Command::new(&binary)),
+            expected,
+            "{}",
+            path.display()
+        );
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Conformance programs the WebAssembly target cannot run: it has no
+/// builtins or imports, and deep recursion needs more host stack than
+/// node gives by default.
+#[cfg(feature = "wasm")]
+const WASM_UNSUPPORTED: [&str; 5] = [
+    "deep_recursion",
+    "import_cycle",
+    "lists",
+    "modules",
+    "string_builtins",
+];
+
+#[cfg(feature = "wasm")]
+#[test]
+fn wasm_modules_pass_the_conformance_files_in_node() {
+    if Command::new("node").arg("--version").output().is_err() {
+        eprintln!("skipping: no `node` to run the modules with");
+        return;
+    }
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let shim = root.join("runtime/rinha.mjs");
+    let dir = std::env::temp_dir().join(format!("rinha-wasm-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut paths: Vec<_> = fs::read_dir(root.join("tests/conformance"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "rinha" || ext == "json")
+        })
+        .collect();
+    paths.sort();
+    let mut ran = 0;
+    for path in paths {
+        let name = path.file_stem().unwrap();
+        if WASM_UNSUPPORTED.contains(&name.to_str().unwrap()) {
+            continue;
+        }
+        let module = dir.join(name).with_extension("wasm");
+        // Programs `rinha build` rejects, like those using records, have
+        // nothing to run.
+        let built = Command::new(env!("CARGO_BIN_EXE_rinha"))
+            .args(["build", "--target", "wasm", "-o"])
+            .arg(&module)
+            .arg(&path)
+            .output()
+            .unwrap();
+        if !built.status.success() {
+            continue;
+        }
+        // Programs stopping with an error exit with a failure status.
+        let ran_module = Command::new("node")
+            .arg(&shim)
+            .arg(&module)
+            .output()
+            .unwrap();
+        let actual = String::from_utf8(ran_module.stdout).unwrap();
+        let expected = fs::read_to_string(path.with_extension("expected")).unwrap();
+        let (actual, expected): (Vec<_>, Vec<_>) =
+            (actual.lines().collect(), expected.lines().collect());
+        assert_eq!(actual.len(), expected.len(), "{}", path.display());
+        for (actual, expected) in actual.iter().zip(&expected) {
+            // The module only reports the kind of an error, not the
+            // values involved.
+            if expected.starts_with("error: ") {
+                assert!(actual.starts_with("error: "), "{}", path.display());
+            } else {
+                assert_eq!(actual, expected, "{}", path.display());
+            }
+        }
+        ran += 1;
+    }
+    assert!(ran > 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,94 @@
+//! Checks the spans and events that both engines send to `tracing`.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use rinha::{Engine, Fuel, InterpreterConfig};
+use tracing_subscriber::fmt::format::FmtSpan;
+
+const PROGRAM: &str = "
+let fib = fn (n) => { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+let count = ref(0);
+let _ = count := fib(4);
+print(!count)
+";
+
+/// Lines logged, shared with the subscriber writing them.
+#[derive(Clone, Default)]
+struct Log(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Log {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What running `PROGRAM` with `engine` logs as JSON.
+fn log(engine: Engine) -> Vec<serde_json::Value> {
+    let log = Log::default();
+    let writer = log.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_max_level(tracing::Level::TRACE)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(move || writer.clone())
+        .finish();
+    let file = rinha::parse_with(PROGRAM, "tracing.rinha", rinha::parser::Syntax::all()).unwrap();
+    tracing::subscriber::with_default(subscriber, || {
+        engine
+            .run(
+                &file,
+                true,
+                InterpreterConfig::EXTENDED,
+                Fuel::unlimited(),
+                &mut Vec::new(),
+            )
+            .unwrap();
+    });
+    let text = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    text.lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn messages(log: &[serde_json::Value]) -> Vec<&str> {
+    log.iter()
+        .map(|line| line["fields"]["message"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn the_tree_walker_logs_calls_bindings_and_cache_hits() {
+    let log = log(Engine::Tree);
+    let messages = messages(&log);
+    // fib(4) runs fib for 0 to 4 once each and finds fib(2) and fib(3) in
+    // the cache the second time.
+    assert_eq!(messages.iter().filter(|m| **m == "close").count(), 5);
+    assert_eq!(messages.iter().filter(|m| **m == "cache hit").count(), 2);
+    assert!(messages.contains(&"assign"));
+    let bound: Vec<_> = log
+        .iter()
+        .filter(|line| line["fields"]["message"] == "bind")
+        .map(|line| line["fields"]["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(bound, ["fib", "count", "_"]);
+    let close = log
+        .iter()
+        .find(|line| line["fields"]["message"] == "close")
+        .unwrap();
+    assert_eq!(close["span"]["name"], "call");
+    assert_eq!(close["span"]["function"], "fib");
+}
+
+#[test]
+fn the_vm_logs_calls_and_cache_hits() {
+    let log = log(Engine::Vm);
+    let messages = messages(&log);
+    assert_eq!(messages.iter().filter(|m| **m == "close").count(), 5);
+    assert_eq!(messages.iter().filter(|m| **m == "cache hit").count(), 2);
+}
@@ -0,0 +1,41 @@
+//! Checks how `rinha check` types `+`, which adds integers and joins
+//! strings alike.
+
+use rinha::typecheck;
+
+fn check(source: &str) -> Result<String, String> {
+    let file = rinha::parse(source, "typecheck.rinha").unwrap();
+    typecheck::check(&file)
+        .map(|ty| ty.to_string())
+        .map_err(|err| err.message)
+}
+
+#[test]
+fn functions_that_add_take_integers_or_strings() {
+    assert_eq!(
+        check("let f = fn (x) => x + 1; f(\"a\")").as_deref(),
+        Ok("string")
+    );
+    assert_eq!(
+        check("let f = fn (x) => x + 1; (f(2), f(\"a\"))").as_deref(),
+        Ok("(int, string)")
+    );
+    assert_eq!(
+        check("let f = fn (x, y) => x + y; let g = fn (a) => f(a, 1); (g(\"s\"), g(1))").as_deref(),
+        Ok("(string, int)")
+    );
+    // With nothing to tell, the operands are integers.
+    assert_eq!(check("fn (x) => x + x").as_deref(), Ok("fn (int) => int"));
+}
+
+#[test]
+fn additions_still_reject_other_types() {
+    assert_eq!(
+        check("let f = fn (x) => x + 1; f(true)").unwrap_err(),
+        "expected string or int but found bool"
+    );
+    assert_eq!(
+        check("let f = fn (x) => { let y = x + 1; y * 2 }; f(\"a\")").unwrap_err(),
+        "expected int but found string"
+    );
+}
@@ -0,0 +1,48 @@
+//! Checks that `--watch` runs a program again when a file it imports
+//! changes.
+
+#![cfg(feature = "watch")]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{self, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn runs_again_when_an_import_changes() {
+    let dir = std::env::temp_dir().join(format!("rinha-watch-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.rinha");
+    let lib = dir.join("lib.rinha");
+    fs::write(&main, "print(import(\"lib.rinha\"))").unwrap();
+    fs::write(&lib, "1").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rinha"))
+        .args(["--watch", "--quiet", "--extensions", "modules"])
+        .arg(&main)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in stdout.lines() {
+            // Each run starts by clearing the screen.
+            let line = line.unwrap().replace("\x1b[2J\x1b[H", "");
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let timeout = Duration::from_secs(30);
+    let first = lines.recv_timeout(timeout);
+    fs::write(&lib, "2").unwrap();
+    let second = lines.recv_timeout(timeout);
+    child.kill().unwrap();
+    child.wait().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(first.as_deref(), Ok("1"));
+    assert_eq!(second.as_deref(), Ok("2"));
+}